@@ -0,0 +1,57 @@
+//! Compares the `/proc` reads each `--enrich` level makes per target, for
+//! the PID counts gracekill is typically pointed at.
+//!
+//! Run with `cargo bench --bench enrich`.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use gracekill::{ProcessController, System};
+
+const ROUNDS: u32 = 50;
+
+/// Average time of one call to `f`.
+fn time(mut f: impl FnMut()) -> Duration {
+    f(); // warm up
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        f();
+    }
+    start.elapsed() / ROUNDS
+}
+
+fn main() {
+    // Live PIDs, repeated if there are fewer than wanted, so that every read
+    // finds something to read
+    let live = gracekill::procfs::pids().expect("failed to list /proc");
+    for count in [10, 100, 1000] {
+        let pids: Vec<u32> = live.iter().copied().cycle().take(count).collect();
+
+        let mut system = System;
+        let off = time(|| {
+            for &pid in &pids {
+                black_box(system.is_running(pid));
+            }
+        });
+        // A liveness check, the command name for --report and the zombie check
+        let basic = time(|| {
+            for &pid in &pids {
+                black_box(system.is_running(pid));
+                black_box(gracekill::procfs::comm(pid));
+                black_box(gracekill::procfs::stat(pid).ok());
+            }
+        });
+        // Everything basic reads, plus the owner and command line
+        let full = time(|| {
+            for &pid in &pids {
+                black_box(system.is_running(pid));
+                black_box(gracekill::procfs::comm(pid));
+                black_box(gracekill::procfs::stat(pid).ok());
+                black_box(gracekill::procfs::uid(pid).ok());
+                black_box(gracekill::procfs::cmdline(pid));
+            }
+        });
+
+        println!("{count:>5} PIDs: off {off:>10.1?}   basic {basic:>10.1?}   full {full:>10.1?}");
+    }
+}
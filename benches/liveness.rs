@@ -0,0 +1,54 @@
+//! Compares per-PID `kill(pid, 0)` liveness checks against a single `/proc`
+//! scan, for the PID counts gracekill is typically pointed at.
+//!
+//! Run with `cargo bench --bench liveness`.
+
+use std::collections::HashSet;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use gracekill::{ProcessController, System};
+
+const ROUNDS: u32 = 200;
+
+/// Average time of one call to `f`.
+fn time(mut f: impl FnMut()) -> Duration {
+    f(); // warm up
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        f();
+    }
+    start.elapsed() / ROUNDS
+}
+
+fn main() {
+    // A mix of live PIDs (whatever is running) and PIDs that almost certainly
+    // don't exist, so both outcomes of the check are exercised.
+    let live = gracekill::procfs::pids().expect("failed to list /proc");
+    for count in [10, 100, 1000] {
+        let pids: Vec<u32> = live
+            .iter()
+            .copied()
+            .chain(4_000_000..)
+            .take(count)
+            .collect();
+
+        let mut system = System;
+        let signal0 = time(|| {
+            for &pid in &pids {
+                black_box(system.is_running(pid));
+            }
+        });
+        let scan = time(|| {
+            let running: HashSet<u32> = gracekill::procfs::pids()
+                .expect("failed to list /proc")
+                .into_iter()
+                .collect();
+            for pid in &pids {
+                black_box(running.contains(pid));
+            }
+        });
+
+        println!("{count:>5} PIDs: kill(pid, 0) {signal0:>10.1?}   /proc scan {scan:>10.1?}");
+    }
+}
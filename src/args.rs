@@ -0,0 +1,1584 @@
+//! Command-line parsing.
+
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use gracekill::log::{self, DurationFormat, Level};
+use gracekill::{MissingHandler, PollSchedule, Signal, Stage};
+
+use crate::container;
+use crate::deadline::{IfPast, OverBudget, parse_deadline};
+use crate::duration::{bounded, parse_duration};
+use crate::enrich::Enrich;
+use crate::error_format::ErrorFormat;
+use crate::liveness::Liveness;
+use crate::policy::Policy;
+use crate::shutdown::Method;
+use crate::size::parse_size;
+use crate::stage::{self, Block};
+use crate::stdout::Epipe;
+use crate::threshold::Thresholds;
+use crate::tree_order::TreeOrder;
+use crate::{port, stdin_cmd, stream, then_exec, watch};
+
+const DEFAULT_GRACE_SECONDS: u64 = gracekill::DEFAULT_GRACE.as_secs();
+/// The longest command name the kernel keeps: `TASK_COMM_LEN` less the NUL.
+const MAX_COMM: usize = 15;
+
+pub fn print_usage(program: &str) {
+    eprintln!("Usage: {program} [options] [--] <pid>[,pid...]");
+    eprintln!();
+    eprintln!("Arguments:");
+    eprintln!("  @file                  Read more arguments from file, separated by whitespace");
+    eprintln!("  pid                    Process ID(s) to kill (comma or space separated)");
+    eprintln!("  -pgid                  After --, a negative ID targets a process group (Linux)");
+    eprintln!("  pid/SIG                Send this PID SIG instead of --signal, e.g. 1234/INT");
+    eprintln!();
+    print_options();
+    eprintln!();
+    print_output_options();
+    eprintln!();
+    print_target_options();
+    eprintln!();
+    print_liveness();
+    eprintln!();
+    eprintln!("Environment:");
+    eprintln!(
+        "  {}                           Log level: error, warn, summary, info or debug (default: info)",
+        log::ENV_VAR
+    );
+    eprintln!(
+        "  GRACEKILL_LOCK_DIR                      Where the per-PID lock files go (default: /run/gracekill)"
+    );
+    eprintln!();
+    eprintln!("Examples:");
+    eprintln!("  {program} 1234 5678");
+    eprintln!("  {program} -g 10 1234 5678");
+    eprintln!("  {program} --grace-seconds 30 1234,5678,9012");
+    eprintln!("  {program} -g 10 -- -1234");
+    eprintln!("  {program} 1234/INT 5678/TERM");
+    eprintln!("  {program} --escalate TERM:30s,QUIT:5s,KILL 1234");
+}
+
+fn print_options() {
+    eprintln!("Options:");
+    eprintln!(
+        "  -g, --grace-seconds                    Grace period in seconds (default: {DEFAULT_GRACE_SECONDS})"
+    );
+    eprintln!(
+        "  -s, --signal <SIGNAL>                  Signal asking processes to exit (default: TERM)"
+    );
+    eprintln!(
+        "  --kill-signal <SIGNAL>                 Signal sent after the grace period (default: KILL)"
+    );
+    eprintln!(
+        "  --abort-with-core                      Kill with SIGABRT, raising the core size limit first (Linux)"
+    );
+    eprintln!(
+        "  --kill-compat                          Also accept kill(1)-style -TERM, -15 and -s 15 signals"
+    );
+    eprintln!(
+        "  --by <TIME>                            Grace period until TIME, HH:MM or RFC 3339, instead of -g"
+    );
+    eprintln!(
+        "  --if-past <kill|error>                 With --by, when TIME has passed (default: kill)"
+    );
+    eprintln!(
+        "  --if-over-budget <error|clamp>         With --by, when the --escalate waits outlast TIME (default: error)"
+    );
+    eprintln!(
+        "  --grace-from-env <VAR>                 Take each target's grace period from VAR in its environment (Linux)"
+    );
+    eprintln!(
+        "  --policy <PATTERN:SIGNAL:GRACE>        Signal and grace period for targets whose name matches (Linux)"
+    );
+    eprintln!(
+        "  --escalate <SIGNAL[:DURATION],...>     Signals to try in order; the last one is the kill"
+    );
+    eprintln!(
+        "  --stage <stage{{signal=S,grace=D,...}}> One step of the kill, with retries=N and jitter=D (repeatable)"
+    );
+    eprintln!(
+        "  --double-tap                           Send the signal again halfway through the grace period"
+    );
+    eprintln!(
+        "  --grace-per-signal-class <SIG=DUR,...> Default wait after each signal, e.g. TERM=30s,QUIT=5s"
+    );
+    print_wait_options();
+    eprintln!("  --exit-non-zero-if-sigkill-required   Exit with code 3 if SIGKILL was required");
+    eprintln!(
+        "  --escalate-privilege <CMD>             Retry signals refused with EPERM as '<CMD> kill ...'"
+    );
+    eprintln!(
+        "  --follow-daemon                        Follow targets that double-fork into a daemon (Linux)"
+    );
+    eprintln!(
+        "  --freeze                               Freeze the targets' cgroups so they can't fork away from the kill (Linux)"
+    );
+    eprintln!(
+        "  --diagnose                             Warn about targets that appear to ignore the signal (Linux)"
+    );
+    eprintln!(
+        "  --require-handler <warn|escalate>      Check targets have a handler for the signal first (Linux)"
+    );
+    eprintln!("  --rate <N>                             Send at most N signals per second");
+    eprintln!(
+        "  --per-wave-grace                       Count each target's grace period from its own signal"
+    );
+    eprintln!(
+        "  --kill-concurrency <N>                 Send SIGKILL to N processes at a time, pausing between"
+    );
+    eprintln!("  --shuffle                              Signal targets in a random order");
+    eprintln!(
+        "  --seed <N>                             Seed for --shuffle, to repeat an order (implies it)"
+    );
+    eprintln!(
+        "  --tree-order <ORDER>                   children-first (default), parents-first or simultaneous"
+    );
+    eprintln!(
+        "  --abort-on-kill-failure                Stop at the first failed SIGKILL and exit with code 5"
+    );
+    print_around_kill_options();
+    eprintln!(
+        "  --enrich <off|basic|full>              How much to read about each target from /proc (default: basic)"
+    );
+    eprintln!(
+        "  --liveness <signal0|proc|pidfd>        How to check whether a target is still running"
+    );
+}
+
+/// Options bounding and watching the waits between signals.
+fn print_wait_options() {
+    eprintln!(
+        "  --min-grace <DURATION>                 Never wait less than this before escalating"
+    );
+    eprintln!(
+        "  --max-grace <DURATION>                 Never wait more than this before escalating"
+    );
+    eprintln!(
+        "  --warn-long-exit <DURATION>            Warn about targets still running this long after the signal"
+    );
+    eprintln!(
+        "  --max-grace-overrun <DURATION>         Warn when a wait runs this much past its end"
+    );
+    eprintln!(
+        "  --adaptive-poll <fixed|curve>          Poll at a fixed interval, or more often near the deadline"
+    );
+    eprintln!(
+        "  --quorum <PERCENT>:<DURATION>          Once PERCENT of the targets have exited, give the rest DURATION"
+    );
+    eprintln!(
+        "  --grace-extend-if-progressing <DUR>    Give targets still shutting down up to DUR more (Linux)"
+    );
+    eprintln!(
+        "  --cpu-time-grace <DURATION>            Kill each target once it has used this much CPU time (Linux)"
+    );
+    eprintln!(
+        "  --load-aware                           Stretch the grace period while the CPU is under pressure (Linux)"
+    );
+}
+
+/// Options for what happens before the signals and after the kill.
+fn print_around_kill_options() {
+    eprintln!("  --exit-status                          Report how child processes exited");
+    eprintln!(
+        "  --wait-children                        Become a subreaper and wait for our own descendants"
+    );
+    eprintln!(
+        "  --reap                                 Reap our own defunct children after the kill"
+    );
+    eprintln!(
+        "  --reset-tty                            Restore the terminal a killed program left in raw mode"
+    );
+    eprintln!(
+        "  --then-exec <CMD>                      Once every target is gone, replace gracekill with CMD"
+    );
+    eprintln!(
+        "  --handoff <SOCKET>                     Leave the kill to a reaper, sending it the survivors' PIDs"
+    );
+    eprintln!(
+        "  --reap-timeout <DURATION>              Wait this long for killed children to be reaped (default: 5s)"
+    );
+    eprintln!(
+        "  --proc-timeout <DURATION>              Give up on /proc reads that take longer than this"
+    );
+    eprintln!(
+        "  --drain-idle <DURATION>                Before signalling, wait for targets to use no CPU this long (Linux)"
+    );
+    eprintln!(
+        "  --drain-max <DURATION>                 Give up waiting for --drain-idle after this (default: 60s)"
+    );
+    eprintln!(
+        "  --shutdown-url <URL>                   Request this http:// URL first, signalling only if needed"
+    );
+    eprintln!(
+        "  --shutdown-method <post|get>           How to request --shutdown-url (default: post)"
+    );
+    eprintln!(
+        "  --shutdown-wait <DURATION>             Wait this long for targets to exit after it (default: 10s)"
+    );
+    eprintln!(
+        "  --stdin-cmd <BYTES>                    Write BYTES, e.g. 'quit\\n', to each target's stdin pipe first (Linux)"
+    );
+    eprintln!(
+        "  --stdin-wait <DURATION>                Wait this long for targets to exit after it (default: 10s)"
+    );
+}
+
+/// Options for what gets logged and reported, and where.
+fn print_output_options() {
+    eprintln!("Output options:");
+    eprintln!(
+        "  --nagios                               Print a Nagios status line and exit with its code"
+    );
+    eprintln!(
+        "  --print-commands                       Print each signal sent as a kill command on stdout"
+    );
+    eprintln!(
+        "  --emit-script <PATH>                   Write the kill as a bash script to PATH instead of running it"
+    );
+    eprintln!(
+        "  --shell-vars                           Finish with GRACEKILL_* assignments for eval on stdout"
+    );
+    eprintln!(
+        "  --report <PATH>                        Write a CSV row per target to PATH, replacing it"
+    );
+    eprintln!("  --report-append                        Append to the --report file instead");
+    eprintln!(
+        "  --report-pids-fd <N>                   Write each signalled PID and its outcome to descriptor N"
+    );
+    eprintln!(
+        "  --trace-file <PATH>                    Write a timestamped trace of every event to PATH"
+    );
+    eprintln!(
+        "  --otlp-endpoint <URL>                  Send the run's spans to this OTLP/HTTP collector"
+    );
+    eprintln!(
+        "  --daemonize                            Carry on in the background, detached from the terminal"
+    );
+    eprintln!(
+        "  --background-escalate                  Return once signalled, waiting and killing in the background"
+    );
+    eprintln!(
+        "  --log-file <PATH>                      Append the log to PATH once in the background"
+    );
+    eprintln!(
+        "  --tail <PATH>                          Log the lines a target writes to PATH during the kill"
+    );
+    eprintln!(
+        "  --dump-before-kill <CMD>               Run '<CMD> <pid>' on each target before killing it"
+    );
+    eprintln!(
+        "  --dump-timeout <DURATION>              Stop --dump-before-kill commands after this (default: 30s)"
+    );
+    eprintln!(
+        "  --on-timeout <CMD>                     Run '<CMD> <pid>...' once with the survivors before killing them"
+    );
+    eprintln!(
+        "  --on-timeout-limit <DURATION>          Stop the --on-timeout command after this (default: 10s)"
+    );
+    eprintln!(
+        "  --output-pid-status-json               Print a JSON snapshot of every target on each poll"
+    );
+    eprintln!(
+        "  --snapshot                             Print what each target looked like before the kill, as JSON"
+    );
+    eprintln!(
+        "  --stats                                Log min/p50/p95/max/mean graceful exit latency"
+    );
+    eprintln!(
+        "  --sd-notify                            Extend systemd's stop timeout while waiting, via NOTIFY_SOCKET"
+    );
+    eprintln!(
+        "  --error-format <text|json>             Write errors as prose or as JSON objects on stderr"
+    );
+    eprintln!(
+        "  --epipe <ignore|abort>                 Once stdout is closed, carry on or stop (default: ignore)"
+    );
+    eprintln!(
+        "  --duration-format <short|clock|ms>     How durations are written in the logs (default: short)"
+    );
+    print_logging_options();
+    eprintln!(
+        "  --show-config                          Print the effective settings as JSON and exit"
+    );
+    eprintln!(
+        "  --proceed                              Go on with the kill after --show-config or --grace-table"
+    );
+    eprintln!(
+        "  --dry-run                              Show what would be signalled, signalling nothing"
+    );
+    eprintln!(
+        "  --grace-table                          Print each target's signal, grace period and kill time, then exit"
+    );
+    eprintln!(
+        "  --selftest                             Check that a kill works here, on a child of gracekill's own"
+    );
+    eprintln!(
+        "  --as-victim [options]                  Be a process that misbehaves on demand, for trying kills on"
+    );
+    eprintln!(
+        "  --simulate-timing                      With --dry-run, estimate when each target would exit"
+    );
+}
+
+/// Output options for how much gets logged.
+fn print_logging_options() {
+    eprintln!("  -v, --verbose                          Log every poll of the remaining processes");
+    eprintln!(
+        "  --coalesce-logs                        Log a line repeated within a second once, with a count"
+    );
+    eprintln!(
+        "  --summary-only                         Only log errors, warnings and the final summary"
+    );
+    eprintln!("  -q, --quiet                            Only log errors, with no RESULT line");
+    eprintln!(
+        "  --quiet-on-success                     Print nothing if every process exits gracefully"
+    );
+}
+
+/// Options choosing and guarding the targets.
+fn print_target_options() {
+    eprintln!("Target options:");
+    eprintln!(
+        "  --pid-signal <PID=SIGNAL>              Signal asking PID to exit, overriding --signal (repeatable)"
+    );
+    eprintln!(
+        "  --batch-file <PATH>                    Run one independent operation per line of PATH"
+    );
+    eprintln!(
+        "  --plan <PATH|->                        Run the targets and per-target settings in a JSON plan"
+    );
+    eprintln!(
+        "  --watch-pid-file <PATH>                Wait for the PID in PATH to exit without signalling it"
+    );
+    eprintln!(
+        "  --fifo <PATH>                          Run a kill for each batch-file line written to named pipe PATH"
+    );
+    eprintln!(
+        "  --discover <PATH>                      Take the target and its shutdown settings from sidecar PATH"
+    );
+    eprintln!(
+        "  --if-rss-above <SIZE>                  Only signal targets using more memory than SIZE, e.g. 2G"
+    );
+    eprintln!(
+        "  --if-cpu-above <DURATION>              Only signal targets that have used more CPU time than DURATION"
+    );
+    eprintln!(
+        "  --if-age-above <DURATION>              Only signal targets that have run longer than DURATION"
+    );
+    eprintln!(
+        "  --save-set <PATH>                      Record the targets in PATH instead of signalling them"
+    );
+    eprintln!(
+        "  --kill-new-since <PATH>                Only signal targets that aren't in the --save-set at PATH"
+    );
+    eprintln!(
+        "  --watch                                Keep killing --name processes as they appear, until SIGINT"
+    );
+    eprintln!(
+        "  --scan-interval <DURATION>             How often --watch looks for new processes (default: 1s)"
+    );
+    eprintln!(
+        "  --stream                               Kill PIDs read from stdin a chunk at a time, in bounded memory"
+    );
+    eprintln!("  --chunk-size <N>                       PIDs per --stream chunk (default: 1000)");
+    eprintln!("  --parallel                             Run batch operations concurrently");
+    eprintln!(
+        "  --session <SID>                        Target every process in session SID (Linux)"
+    );
+    eprintln!(
+        "  --name <NAME>                          Target every process with command name NAME (Linux)"
+    );
+    eprintln!(
+        "  --holding <PATH>                       Target every process with PATH open or mapped (Linux)"
+    );
+    eprintln!(
+        "  --port <PORT>                          Target every process with a TCP or UDP socket on PORT (Linux)"
+    );
+    eprintln!(
+        "  --netns <PID|PATH>                     Look up --port sockets in this network namespace (Linux)"
+    );
+    eprintln!(
+        "  --rescan                               Report processes the selectors find just before the signals"
+    );
+    eprintln!(
+        "  --container <NAME|ID>                  Target a container's main process and its descendants (Linux)"
+    );
+    eprintln!(
+        "  --container-socket <PATH>              Ask the container runtime on PATH (default: /var/run/docker.sock)"
+    );
+    eprintln!(
+        "  --pid-namespace <PID>                  Target PIDs are as seen in the PID namespace of PID (Linux)"
+    );
+    eprintln!(
+        "  --listen <PATH>                        Accept more PIDs on a Unix socket at PATH during the grace period"
+    );
+    eprintln!(
+        "  --tid <TID>                            Send the graceful signal to one thread (Linux)"
+    );
+    eprintln!(
+        "  --list-threads <PID>                   Show the state of each of PID's threads and exit (Linux)"
+    );
+    eprintln!(
+        "  --forbid-signal-to <PID|NAME>          Never send SIGKILL to matching targets (repeatable)"
+    );
+    eprintln!(
+        "  --protect-file <PATH>                  Never send SIGKILL to the PIDs and names listed in PATH"
+    );
+    eprintln!(
+        "  --kill-orphans-after-parent <PID>      Then kill the children target PID left to init (Linux)"
+    );
+    eprintln!(
+        "  --expect-cmd <PID=SUBSTRING>           Only signal PID while its command line contains SUBSTRING (Linux)"
+    );
+    eprintln!(
+        "  --expect-exit-file <PID=PATH>          Once PID has exited, read its exit code from PATH"
+    );
+    print_safety_options();
+}
+
+/// Target options for which targets are refused or skipped.
+fn print_safety_options() {
+    eprintln!(
+        "  --ignore-missing                       Treat targets that are already gone as exited"
+    );
+    eprintln!(
+        "  --skip-invalid                         Warn about targets that don't parse and go on without them"
+    );
+    eprintln!(
+        "  --skip-exiting                         Don't signal targets already exiting, just wait (Linux)"
+    );
+    eprintln!(
+        "  --strict-pids                          Refuse PIDs at or above the system's pid_max (Linux)"
+    );
+    eprintln!(
+        "  --force                                Signal our own ancestors or session anyway"
+    );
+    eprintln!(
+        "  --no-wait-lock                         Fail if another gracekill run holds a target's lock"
+    );
+    eprintln!(
+        "  --include-foreground-group             Signal targets in the terminal's foreground process group"
+    );
+    eprintln!("  --confirm-count <N>                    Ask before signalling more than N targets");
+    eprintln!(
+        "  --yes                                  Answer --confirm-count's question with yes"
+    );
+}
+
+/// The tradeoffs between `--liveness` strategies.
+fn print_liveness() {
+    eprintln!("Liveness checks:");
+    eprintln!("  signal0                                kill(pid, 0); portable, but sees zombies");
+    eprintln!(
+        "  proc                                   /proc/<pid> exists; needs no permission, but sees"
+    );
+    eprintln!("                                         zombies and PID reuse races (Linux)");
+    eprintln!(
+        "  pidfd                                  poll a pidfd; exact, zombies count as exited"
+    );
+    eprintln!("                                         (Linux 5.3+)");
+    eprintln!(
+        "  (default)                              signal0, one /proc scan per poll for 256+ targets"
+    );
+}
+
+pub fn parse_and_validate_pid(pid_str: &str) -> Result<u32, String> {
+    let pid = pid_str
+        .parse::<u32>()
+        .map_err(|_| format!("Invalid PID: '{pid_str}'"))?;
+    if pid == 0 {
+        return Err("PID 0 not allowed (affects process group)".to_string());
+    }
+    Ok(pid)
+}
+
+#[allow(clippy::struct_excessive_bools)] // one per command-line flag
+pub struct Options {
+    pub pids: Vec<u32>,
+    /// Process groups given as negative IDs after `--`.
+    pub groups: Vec<u32>,
+    /// Sessions given with `--session`.
+    pub sessions: Vec<u32>,
+    /// Command names given with `--name`.
+    pub names: Vec<String>,
+    /// Files given with `--holding`.
+    pub holding: Vec<String>,
+    /// Ports given with `--port`, and the `--netns` network namespace their
+    /// sockets are looked up in.
+    pub ports: Vec<u16>,
+    pub netns: Option<String>,
+    /// `--rescan` the selectors just before signalling.
+    pub rescan: bool,
+    /// Containers given with `--container`, and the `--container-socket`
+    /// their runtime is asked on.
+    pub containers: Vec<String>,
+    pub container_socket: Option<String>,
+    /// `--pid-namespace`: a process whose PID namespace target PIDs are in.
+    pub pid_namespace: Option<u32>,
+    /// `--listen` socket to accept more targets on.
+    pub listen: Option<String>,
+    pub grace_period: Duration,
+    /// `--by` time the grace period runs until, and what `--if-past` does
+    /// once it has passed.
+    pub deadline: Option<SystemTime>,
+    pub if_past: IfPast,
+    /// What `--if-over-budget` does with `--escalate` waits that don't fit
+    /// before the `--by` time.
+    pub over_budget: OverBudget,
+    pub signal: Signal,
+    /// Per-PID graceful signals from `PID/SIG` targets and `--pid-signal`.
+    pub pid_signals: Vec<(u32, Signal)>,
+    pub kill_signal: Signal,
+    /// `--stage` retries and jitter of the graceful signal.
+    pub retries: u32,
+    pub jitter: Duration,
+    /// Escalation stages between `signal` and `kill_signal`.
+    pub stages: Vec<Stage>,
+    /// Whether the signals and waits come from `--escalate` or `--stage`.
+    pub escalate: bool,
+    /// `--min-grace` floor and `--max-grace` ceiling on every wait.
+    pub min_grace: Option<Duration>,
+    pub max_grace: Option<Duration>,
+    /// `--warn-long-exit` threshold.
+    pub warn_long_exit: Option<Duration>,
+    /// `--max-grace-overrun` before timing is reported as degraded.
+    pub max_grace_overrun: Option<Duration>,
+    /// `--adaptive-poll` schedule.
+    pub poll_schedule: PollSchedule,
+    /// `--quorum` percentage of targets and grace period for the rest.
+    pub quorum: Option<(u8, Duration)>,
+    /// `--grace-extend-if-progressing` cap.
+    pub grace_extension: Option<Duration>,
+    /// `--cpu-time-grace` budget.
+    pub cpu_time_grace: Option<Duration>,
+    pub exit_non_zero_if_sigkill_required: bool,
+    /// Finish with a Nagios status line and exit code.
+    pub nagios: bool,
+    pub exit_status: bool,
+    pub abort_on_kill_failure: bool,
+    /// Signal targets that are ancestors of gracekill.
+    pub force: bool,
+    /// `--kill-compat`, or invoked as `gracekill-kill`.
+    pub kill_compat: bool,
+    pub double_tap: bool,
+    /// `--abort-with-core`: SIGABRT as the kill signal, with cores allowed.
+    pub abort_with_core: bool,
+    /// Signal targets in the terminal's foreground process group.
+    pub include_foreground: bool,
+    pub ignore_missing: bool,
+    /// `--no-wait-lock`: fail rather than wait for another run's locks.
+    pub no_wait_lock: bool,
+    /// `--skip-invalid`, and the targets it skipped, described as errors.
+    pub skip_invalid: bool,
+    pub skipped: Vec<String>,
+    /// Leave targets that are already exiting without the graceful signal.
+    pub skip_exiting: bool,
+    /// `--freeze` the targets' cgroups while gathering and signalling them.
+    pub freeze: bool,
+    /// `--load-aware`: stretch the grace period under CPU pressure.
+    pub load_aware: bool,
+    /// `--rate` limit on signals sent per second.
+    pub rate: Option<u32>,
+    /// `--per-wave-grace`: count each target's grace from its own signal.
+    pub per_wave_grace: bool,
+    /// `--kill-concurrency` limit on kill signals sent in one burst.
+    pub kill_concurrency: Option<usize>,
+    /// `--confirm-count`: how many targets can be signalled without asking,
+    /// and `--yes` to the question.
+    pub confirm_count: Option<usize>,
+    pub yes: bool,
+    pub print_commands: bool,
+    /// `--emit-script`: where to write the kill as a script instead.
+    pub emit_script: Option<String>,
+    pub follow_daemon: bool,
+    pub diagnose: bool,
+    /// `--require-handler`: what to do with targets that can't shut down
+    /// gracefully.
+    pub require_handler: Option<MissingHandler>,
+    /// Refuse target PIDs at or above `pid_max` rather than warning.
+    pub strict_pids: bool,
+    /// `--report` CSV file.
+    pub report: Option<String>,
+    pub report_append: bool,
+    /// `--report-pids-fd` descriptor for the signalled PIDs.
+    pub report_pids_fd: Option<i32>,
+    /// `--trace-file` event log.
+    pub trace_file: Option<String>,
+    /// `--otlp-endpoint` collector the spans are sent to.
+    pub otlp_endpoint: Option<String>,
+    /// `--daemonize`, and the `--log-file` it writes to.
+    pub daemonize: bool,
+    pub log_file: Option<String>,
+    /// `--background-escalate`: leave the wait and the kill signal to a
+    /// background process once the graceful signals are sent.
+    pub background_escalate: bool,
+    /// `--tail` log files to follow during the kill.
+    pub tail: Vec<String>,
+    pub error_format: ErrorFormat,
+    pub shell_vars: bool,
+    pub epipe: Epipe,
+    /// `--duration-format` of the durations in the logs.
+    pub duration_format: DurationFormat,
+    pub status_json: bool,
+    /// `--snapshot` of every target before the first signal.
+    pub snapshot: bool,
+    pub shuffle: bool,
+    pub seed: Option<u64>,
+    /// `--tree-order` between targets that are parent and child.
+    pub tree_order: TreeOrder,
+    /// `--escalate-privilege` helper, e.g. `sudo`.
+    pub privilege_helper: Option<String>,
+    pub quiet_on_success: bool,
+    pub coalesce_logs: bool,
+    /// `--show-config`, and `--proceed` to run after showing it, or after
+    /// `--grace-table`.
+    pub show_config: bool,
+    pub proceed: bool,
+    pub grace_table: bool,
+    pub batch_file: Option<String>,
+    /// `--plan` JSON file, or `-` for stdin.
+    pub plan: Option<String>,
+    pub parallel: bool,
+    pub watch_pid_file: Option<String>,
+    /// `--discover` sidecar file declaring a target and its settings.
+    pub discover: Option<String>,
+    /// `--fifo` named pipe to read kill commands from.
+    pub fifo: Option<String>,
+    /// `--watch` for processes with a `--name`, rescanning every
+    /// `--scan-interval`.
+    pub watch: bool,
+    pub stats: bool,
+    /// `--grace-from-env` variable targets may set their grace period in.
+    pub grace_from_env: Option<String>,
+    /// `--policy` rules, in the order given.
+    pub policies: Vec<Policy>,
+    /// `--if-rss-above`, `--if-cpu-above` and `--if-age-above`.
+    pub thresholds: Thresholds,
+    /// `--save-set` checkpoint to record the targets in.
+    pub save_set: Option<String>,
+    /// `--kill-new-since` checkpoint whose targets are left alone.
+    pub kill_new_since: Option<String>,
+    pub reset_tty: bool,
+    /// `--then-exec` command replacing gracekill after a clean kill.
+    pub then_exec: Option<String>,
+    /// `--sd-notify` systemd of progress through `NOTIFY_SOCKET`.
+    pub sd_notify: bool,
+    /// `--selftest`: kill a child of our own instead of any targets.
+    pub selftest: bool,
+    /// `--stream` PIDs from stdin, `--chunk-size` at a time.
+    pub stream: bool,
+    pub chunk_size: usize,
+    pub scan_interval: Duration,
+    pub log_level: Option<Level>,
+    pub tid: Option<u32>,
+    /// `--list-threads` process.
+    pub list_threads: Option<u32>,
+    pub forbid_kill: Vec<String>,
+    /// `--protect-file`, and the name patterns read from it.
+    pub protect_file: Option<String>,
+    pub protect_patterns: Vec<String>,
+    /// `--kill-orphans-after-parent` targets.
+    pub orphan_parents: Vec<u32>,
+    /// `--expect-cmd` substrings each PID's command line must contain.
+    pub expected_commands: Vec<(u32, String)>,
+    /// `--expect-exit-file` paths each PID writes its exit code to.
+    pub exit_files: Vec<(u32, String)>,
+    pub wait_children: bool,
+    /// `--reap` and the `--reap-timeout` bound on it.
+    pub reap: bool,
+    pub reap_timeout: Duration,
+    /// `--handoff` socket of the reaper to leave the kill to.
+    pub handoff: Option<String>,
+    pub proc_timeout: Option<Duration>,
+    /// `--drain-idle` window and the `--drain-max` cap on waiting for it.
+    pub drain_idle: Option<Duration>,
+    pub drain_max: Duration,
+    /// `--dump-before-kill` command and the `--dump-timeout` bound on it.
+    pub dump_command: Option<String>,
+    pub dump_timeout: Duration,
+    /// `--on-timeout` command and the `--on-timeout-limit` bound on it.
+    pub on_timeout: Option<String>,
+    pub on_timeout_limit: Duration,
+    /// `--shutdown-url` endpoint, the `--shutdown-method` to request it with
+    /// and the `--shutdown-wait` for targets to exit afterwards.
+    pub shutdown_url: Option<String>,
+    pub shutdown_method: Method,
+    pub shutdown_wait: Duration,
+    /// `--stdin-cmd` bytes and the `--stdin-wait` for targets to exit
+    /// after them.
+    pub stdin_cmd: Option<Vec<u8>>,
+    pub stdin_wait: Duration,
+    pub liveness: Option<Liveness>,
+    pub enrich: Enrich,
+    /// `--dry-run`, estimating how long the run would take with
+    /// `--simulate-timing`.
+    pub dry_run: bool,
+    pub simulate_timing: bool,
+    /// `--simulate`, with the scripted exit times of `--simulate-exit-after`.
+    pub simulate: bool,
+    pub simulated_exits: Vec<(u32, Duration)>,
+}
+
+impl Default for Options {
+    #[allow(clippy::too_many_lines)] // one per option
+    fn default() -> Self {
+        Self {
+            pids: Vec::new(),
+            groups: Vec::new(),
+            sessions: Vec::new(),
+            names: Vec::new(),
+            holding: Vec::new(),
+            ports: Vec::new(),
+            netns: None,
+            rescan: false,
+            containers: Vec::new(),
+            container_socket: None,
+            pid_namespace: None,
+            listen: None,
+            grace_period: gracekill::DEFAULT_GRACE,
+            quorum: None,
+            deadline: None,
+            if_past: IfPast::default(),
+            over_budget: OverBudget::default(),
+            signal: Signal::Term,
+            pid_signals: Vec::new(),
+            kill_signal: Signal::Kill,
+            retries: 0,
+            jitter: Duration::ZERO,
+            stages: Vec::new(),
+            escalate: false,
+            min_grace: None,
+            max_grace: None,
+            warn_long_exit: None,
+            max_grace_overrun: None,
+            poll_schedule: PollSchedule::Fixed,
+            grace_extension: None,
+            cpu_time_grace: None,
+            exit_non_zero_if_sigkill_required: false,
+            nagios: false,
+            exit_status: false,
+            abort_on_kill_failure: false,
+            force: false,
+            kill_compat: false,
+            double_tap: false,
+            abort_with_core: false,
+            include_foreground: false,
+            ignore_missing: false,
+            no_wait_lock: false,
+            skip_invalid: false,
+            load_aware: false,
+            freeze: false,
+            skipped: Vec::new(),
+            skip_exiting: false,
+            rate: None,
+            per_wave_grace: false,
+            kill_concurrency: None,
+            confirm_count: None,
+            yes: false,
+            print_commands: false,
+            emit_script: None,
+            follow_daemon: false,
+            diagnose: false,
+            require_handler: None,
+            strict_pids: false,
+            report: None,
+            report_append: false,
+            report_pids_fd: None,
+            trace_file: None,
+            otlp_endpoint: None,
+            daemonize: false,
+            log_file: None,
+            background_escalate: false,
+            tail: Vec::new(),
+            error_format: ErrorFormat::default(),
+            shell_vars: false,
+            epipe: Epipe::default(),
+            duration_format: DurationFormat::default(),
+            status_json: false,
+            snapshot: false,
+            shuffle: false,
+            seed: None,
+            tree_order: TreeOrder::default(),
+            privilege_helper: None,
+            quiet_on_success: false,
+            coalesce_logs: false,
+            show_config: false,
+            proceed: false,
+            grace_table: false,
+            batch_file: None,
+            plan: None,
+            parallel: false,
+            watch_pid_file: None,
+            discover: None,
+            fifo: None,
+            watch: false,
+            stats: false,
+            grace_from_env: None,
+            policies: Vec::new(),
+            thresholds: Thresholds::default(),
+            save_set: None,
+            kill_new_since: None,
+            reset_tty: false,
+            then_exec: None,
+            sd_notify: false,
+            selftest: false,
+            stream: false,
+            chunk_size: stream::DEFAULT_CHUNK_SIZE,
+            scan_interval: watch::DEFAULT_SCAN_INTERVAL,
+            log_level: None,
+            tid: None,
+            list_threads: None,
+            forbid_kill: Vec::new(),
+            protect_file: None,
+            protect_patterns: Vec::new(),
+            orphan_parents: Vec::new(),
+            expected_commands: Vec::new(),
+            exit_files: Vec::new(),
+            wait_children: false,
+            reap: false,
+            handoff: None,
+            reap_timeout: gracekill::children::DEFAULT_REAP_TIMEOUT,
+            proc_timeout: None,
+            drain_idle: None,
+            drain_max: crate::drain::DEFAULT_CAP,
+            dump_command: None,
+            dump_timeout: crate::dump::DEFAULT_TIMEOUT,
+            on_timeout: None,
+            on_timeout_limit: crate::on_timeout::DEFAULT_LIMIT,
+            shutdown_url: None,
+            shutdown_method: Method::default(),
+            shutdown_wait: crate::shutdown::DEFAULT_WAIT,
+            stdin_cmd: None,
+            stdin_wait: crate::stdin_cmd::DEFAULT_WAIT,
+            liveness: None,
+            enrich: Enrich::Basic,
+            dry_run: false,
+            simulate_timing: false,
+            simulate: false,
+            simulated_exits: Vec::new(),
+        }
+    }
+}
+
+impl Options {
+    /// Whether any targets were given, as PIDs, process groups, sessions,
+    /// containers, names or files held open.
+    pub fn has_targets(&self) -> bool {
+        !self.pids.is_empty()
+            || !self.groups.is_empty()
+            || !self.sessions.is_empty()
+            || !self.containers.is_empty()
+            || !self.names.is_empty()
+            || !self.holding.is_empty()
+            || !self.ports.is_empty()
+    }
+}
+
+/// Parses the command-line arguments, not including the program name. Errors
+/// name the offending argument by its 1-based position, as in `$1`; for
+/// tokens read from a response file, that is the position of the `@file`.
+pub fn parse_args(args: &[String]) -> Result<Options, String> {
+    parse_args_with(args, false)
+}
+
+/// Parses the arguments of gracekill invoked as `gracekill-kill`, which
+/// takes kill(1)-style arguments as with `--kill-compat`.
+pub fn parse_kill_args(args: &[String]) -> Result<Options, String> {
+    parse_args_with(args, true)
+}
+
+fn parse_args_with(args: &[String], kill_compat: bool) -> Result<Options, String> {
+    let mut position = 0;
+    let mut expanded = Vec::with_capacity(args.len());
+    for (arg, index) in args.iter().zip(1..) {
+        position = index;
+        expand_response_file(arg, index, &mut Vec::new(), &mut expanded)
+            .map_err(|e| at_argument(&e, position))?;
+    }
+    let kill_compat = kill_compat
+        || expanded
+            .iter()
+            .take_while(|(arg, _)| arg != "--")
+            .any(|(arg, _)| arg == "--kill-compat");
+    if kill_compat {
+        translate_kill_syntax(&mut expanded, &mut position)
+            .map_err(|e| at_argument(&e, position))?;
+    }
+    let mut options =
+        parse_args_at(&expanded, &mut position).map_err(|e| at_argument(&e, position))?;
+    options.kill_compat = kill_compat;
+    Ok(options)
+}
+
+/// Rewrites a kill(1)-style signal, `-TERM`, `-SIGTERM` or `-15` as the
+/// first argument or `-s 15`, into `--signal TERM`. Other arguments are
+/// left as they are, so every gracekill option still works alongside.
+/// Errors are at `position`.
+fn translate_kill_syntax(
+    args: &mut Vec<(String, usize)>,
+    position: &mut usize,
+) -> Result<(), String> {
+    let Some(first) = args.iter().position(|(arg, _)| arg != "--kill-compat") else {
+        return Ok(());
+    };
+    let (arg, index) = args[first].clone();
+    *position = index;
+    if arg == "-s" || arg == "--signal" {
+        if let Some((value, value_index)) = args.get_mut(first + 1)
+            && let Ok(number) = value.parse::<i32>()
+        {
+            *position = *value_index;
+            *value = signal_numbered(number, value)?.name().to_string();
+        }
+        return Ok(());
+    }
+    let Some(signal) = arg.strip_prefix('-').filter(|rest| !rest.starts_with('-')) else {
+        return Ok(());
+    };
+    let signal = match signal.parse::<i32>() {
+        Ok(number) => signal_numbered(number, &arg)?,
+        // Anything else that isn't a signal name is a gracekill option
+        Err(_) => match signal.parse::<Signal>() {
+            Ok(signal) => signal,
+            Err(_) => return Ok(()),
+        },
+    };
+    args.splice(
+        first..=first,
+        [
+            ("--signal".to_string(), index),
+            (signal.name().to_string(), index),
+        ],
+    );
+    Ok(())
+}
+
+fn signal_numbered(number: i32, arg: &str) -> Result<Signal, String> {
+    Signal::from_number(number).ok_or_else(|| {
+        let supported: Vec<String> = Signal::ALL
+            .iter()
+            .map(|signal| format!("{}={}", signal.number(), &signal.name()[3..]))
+            .collect();
+        format!(
+            "Unknown signal number: '{arg}' (supported: {})",
+            supported.join(", ")
+        )
+    })
+}
+
+/// Appends `arg` to `expanded` with its position, or if it is `@path`, the
+/// whitespace-separated tokens of the file at `path`, themselves expanded in
+/// turn. `including` holds the response files being read, to catch a file
+/// that includes itself.
+fn expand_response_file(
+    arg: &str,
+    index: usize,
+    including: &mut Vec<PathBuf>,
+    expanded: &mut Vec<(String, usize)>,
+) -> Result<(), String> {
+    let Some(path) = arg.strip_prefix('@').filter(|path| !path.is_empty()) else {
+        expanded.push((arg.to_string(), index));
+        return Ok(());
+    };
+    let error = |e: io::Error| format!("Cannot read response file '{path}': {e}");
+    let canonical = fs::canonicalize(path).map_err(error)?;
+    if including.contains(&canonical) {
+        return Err(format!("Response file includes itself: '{path}'"));
+    }
+    let contents = fs::read_to_string(&canonical).map_err(error)?;
+    including.push(canonical);
+    for token in contents.split_whitespace() {
+        expand_response_file(token, index, including, expanded)?;
+    }
+    including.pop();
+    Ok(())
+}
+
+/// Inserts the argument number into an error message:
+/// `Invalid PID: 'abc'` becomes `Invalid PID at argument 3: 'abc'`.
+fn at_argument(message: &str, position: usize) -> String {
+    if position == 0 {
+        return message.to_string();
+    }
+    match message.split_once(": ") {
+        Some((what, detail)) => format!("{what} at argument {position}: {detail}"),
+        None => format!("{message} at argument {position}"),
+    }
+}
+
+/// Does the parsing for [`parse_args`], keeping `position` at the argument
+/// being looked at.
+fn parse_args_at(args: &[(String, usize)], position: &mut usize) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut grace_given = false;
+    let mut ladder = None;
+    let mut blocks = Vec::new();
+    let mut class_graces = Vec::new();
+    // Set up front, so that it covers the targets before it too
+    options.skip_invalid = args
+        .iter()
+        .take_while(|(arg, _)| arg != "--")
+        .any(|(arg, _)| arg == "--skip-invalid");
+    let mut args = args.iter().map(|(arg, index)| (arg, *index));
+
+    while let Some((arg, index)) = args.next() {
+        *position = index;
+        if arg == "--" {
+            // Everything after the separator is a target, even if it starts with '-'
+            for (arg, index) in args.by_ref() {
+                *position = index;
+                parse_targets(arg, index, &mut options)?;
+            }
+            break;
+        }
+
+        // Long options may carry their value inline: --grace-seconds=10
+        let (name, inline_value) = match arg.split_once('=') {
+            Some((name, value)) if name.starts_with("--") => (name, Some(value)),
+            _ => (arg.as_str(), None),
+        };
+        let mut value_used = false;
+        let mut value = |option: &str| -> Result<String, String> {
+            value_used = true;
+            if let Some(value) = inline_value {
+                return Ok(value.to_string());
+            }
+            let (value, index) = args
+                .next()
+                .ok_or_else(|| format!("Missing value for {option}"))?;
+            *position = index;
+            Ok(value.clone())
+        };
+
+        match name {
+            "-g" | "--grace-seconds" => {
+                let value = value("grace-seconds")?;
+                let seconds = value
+                    .parse::<u64>()
+                    .map_err(|_| format!("Invalid grace-seconds value: '{value}'"))?;
+                options.grace_period = bounded(Duration::from_secs(seconds), &value)?;
+                grace_given = true;
+            }
+            "-s" | "--signal" => options.signal = value("signal")?.parse()?,
+            "--kill-signal" => options.kill_signal = value("kill-signal")?.parse()?,
+            "--escalate" => ladder = Some(parse_ladder(&value("escalate")?)?),
+            "--stage" => blocks.extend(stage::parse(&value("stage")?)?),
+            "--grace-per-signal-class" => {
+                class_graces.extend(parse_class_graces(&value("grace-per-signal-class")?)?);
+            }
+            "--escalate-privilege" => {
+                options.privilege_helper = Some(value("escalate-privilege")?);
+            }
+            "--batch-file" => options.batch_file = Some(value("batch-file")?),
+            "--plan" => options.plan = Some(value("plan")?),
+            "--watch-pid-file" => options.watch_pid_file = Some(value("watch-pid-file")?),
+            "--liveness" => options.liveness = Some(value("liveness")?.parse()?),
+            "--enrich" => options.enrich = value("enrich")?.parse()?,
+            "--shutdown-url" => options.shutdown_url = Some(value("shutdown-url")?),
+            "--shutdown-method" => options.shutdown_method = value("shutdown-method")?.parse()?,
+            "--require-handler" => {
+                options.require_handler = Some(value("require-handler")?.parse()?);
+            }
+            _ if parse_flag(name, &mut options) => {}
+            _ if parse_duration_option(name, &mut value, &mut options)? => {}
+            _ if parse_timing_option(name, &mut value, &mut options)? => {}
+            _ if parse_limit_option(name, &mut value, &mut options)? => {}
+            _ if parse_output_option(name, &mut value, &mut options)? => {}
+            _ if parse_target_option(name, &mut value, &mut options)? => {}
+            _ if name.starts_with('-') => return Err(format!("Unknown option: '{arg}'")),
+            _ => parse_targets(arg, index, &mut options)?,
+        }
+
+        if inline_value.is_some() && !value_used {
+            *position = index;
+            return Err(format!("Option '{name}' does not take a value"));
+        }
+    }
+
+    if options.deadline.is_some() && grace_given {
+        *position = 0;
+        return Err("--by cannot be combined with --grace-seconds".to_string());
+    }
+    let blocks = steps(ladder, blocks, &mut options).inspect_err(|_| *position = 0)?;
+    apply_graces(&mut options, blocks, &class_graces, grace_given);
+    if let (Some(min), Some(max)) = (options.min_grace, options.max_grace)
+        && min > max
+    {
+        *position = 0;
+        return Err(format!(
+            "--min-grace ({}) is greater than --max-grace ({})",
+            log::duration(min),
+            log::duration(max)
+        ));
+    }
+    Ok(options)
+}
+
+/// Sets the option for a switch that takes no value, returning whether `name`
+/// was one.
+fn parse_flag(name: &str, options: &mut Options) -> bool {
+    match name {
+        "--exit-non-zero-if-sigkill-required" => {
+            options.exit_non_zero_if_sigkill_required = true;
+        }
+        "--nagios" => options.nagios = true,
+        "--shell-vars" => options.shell_vars = true,
+        "--exit-status" => options.exit_status = true,
+        "--abort-on-kill-failure" => options.abort_on_kill_failure = true,
+        "--force" => options.force = true,
+        "--kill-compat" => options.kill_compat = true,
+        "--double-tap" => options.double_tap = true,
+        "--abort-with-core" => options.abort_with_core = true,
+        "--per-wave-grace" => options.per_wave_grace = true,
+        "--include-foreground-group" => options.include_foreground = true,
+        "--yes" => options.yes = true,
+        "--simulate" => options.simulate = true,
+        "--dry-run" => options.dry_run = true,
+        "--simulate-timing" => options.simulate_timing = true,
+        "--ignore-missing" => options.ignore_missing = true,
+        "--no-wait-lock" => options.no_wait_lock = true,
+        "--rescan" => options.rescan = true,
+        "--skip-invalid" => options.skip_invalid = true,
+        "--load-aware" => options.load_aware = true,
+        "--freeze" => options.freeze = true,
+        "--skip-exiting" => options.skip_exiting = true,
+        "--strict-pids" => options.strict_pids = true,
+        "--print-commands" => options.print_commands = true,
+        "--follow-daemon" => options.follow_daemon = true,
+        "--diagnose" => options.diagnose = true,
+        "--report-append" => options.report_append = true,
+        "--output-pid-status-json" => options.status_json = true,
+        "--snapshot" => options.snapshot = true,
+        "--shuffle" => options.shuffle = true,
+        "--parallel" => options.parallel = true,
+        "--wait-children" => options.wait_children = true,
+        "--reap" => options.reap = true,
+        "--watch" => options.watch = true,
+        "--stats" => options.stats = true,
+        "--stream" => options.stream = true,
+        "--reset-tty" => options.reset_tty = true,
+        "--sd-notify" => options.sd_notify = true,
+        "--selftest" => options.selftest = true,
+        "-v" | "--verbose" => options.log_level = Some(Level::Debug),
+        "--summary-only" => options.log_level = Some(Level::Summary),
+        "-q" | "--quiet" => options.log_level = Some(Level::Error),
+        "--quiet-on-success" => options.quiet_on_success = true,
+        "--coalesce-logs" => options.coalesce_logs = true,
+        "--show-config" => options.show_config = true,
+        "--proceed" => options.proceed = true,
+        "--grace-table" => options.grace_table = true,
+        "--daemonize" => options.daemonize = true,
+        "--background-escalate" => options.background_escalate = true,
+        _ => return false,
+    }
+    true
+}
+
+/// Sets the option for a switch naming where results are written, read with
+/// `value`, returning whether `name` was one.
+fn parse_output_option(
+    name: &str,
+    value: &mut impl FnMut(&str) -> Result<String, String>,
+    options: &mut Options,
+) -> Result<bool, String> {
+    match name {
+        "--report" => options.report = Some(value("report")?),
+        "--emit-script" => options.emit_script = Some(value("emit-script")?),
+        "--report-pids-fd" => {
+            let value = value("report-pids-fd")?;
+            let fd = value
+                .parse()
+                .ok()
+                .filter(|&fd: &i32| fd >= 0)
+                .ok_or_else(|| format!("Invalid file descriptor: '{value}'"))?;
+            options.report_pids_fd = Some(fd);
+        }
+        "--trace-file" => options.trace_file = Some(value("trace-file")?),
+        "--otlp-endpoint" => options.otlp_endpoint = Some(value("otlp-endpoint")?),
+        "--log-file" => options.log_file = Some(value("log-file")?),
+        "--handoff" => options.handoff = Some(value("handoff")?),
+        "--grace-from-env" => options.grace_from_env = Some(value("grace-from-env")?),
+        "--policy" => options.policies.push(Policy::parse(&value("policy")?)?),
+        "--dump-before-kill" => options.dump_command = Some(value("dump-before-kill")?),
+        "--on-timeout" => options.on_timeout = Some(value("on-timeout")?),
+        "--then-exec" => {
+            let command = value("then-exec")?;
+            then_exec::check(&command)?;
+            options.then_exec = Some(command);
+        }
+        "--tail" => options.tail.push(value("tail")?),
+        "--error-format" => options.error_format = value("error-format")?.parse()?,
+        "--epipe" => options.epipe = value("epipe")?.parse()?,
+        "--duration-format" => options.duration_format = value("duration-format")?.parse()?,
+        _ => return Ok(false),
+    }
+    Ok(true)
+}
+
+/// Sets the option for a switch that takes a positive count, read with
+/// `value`, returning whether `name` was one.
+fn parse_limit_option(
+    name: &str,
+    value: &mut impl FnMut(&str) -> Result<String, String>,
+    options: &mut Options,
+) -> Result<bool, String> {
+    let (option, what) = match name {
+        "--rate" => ("rate", "rate"),
+        "--kill-concurrency" => ("kill-concurrency", "kill concurrency"),
+        "--chunk-size" => ("chunk-size", "chunk size"),
+        "--confirm-count" => ("confirm-count", "confirm count"),
+        _ => return Ok(false),
+    };
+    let value = value(option)?;
+    let limit: u32 = value
+        .parse()
+        .ok()
+        .filter(|&limit| limit > 0)
+        .ok_or_else(|| format!("Invalid {what}: '{value}'"))?;
+    match name {
+        "--rate" => options.rate = Some(limit),
+        "--chunk-size" => options.chunk_size = limit as usize,
+        "--confirm-count" => options.confirm_count = Some(limit as usize),
+        _ => options.kill_concurrency = Some(limit as usize),
+    }
+    Ok(true)
+}
+
+/// Sets the option for a switch that takes a duration, read with `value`,
+/// returning whether `name` was one.
+fn parse_duration_option(
+    name: &str,
+    value: &mut impl FnMut(&str) -> Result<String, String>,
+    options: &mut Options,
+) -> Result<bool, String> {
+    let option = name.trim_start_matches('-');
+    let target = match name {
+        "--min-grace" => &mut options.min_grace,
+        "--max-grace" => &mut options.max_grace,
+        "--proc-timeout" => &mut options.proc_timeout,
+        "--warn-long-exit" => &mut options.warn_long_exit,
+        "--max-grace-overrun" => &mut options.max_grace_overrun,
+        "--grace-extend-if-progressing" => &mut options.grace_extension,
+        "--cpu-time-grace" => &mut options.cpu_time_grace,
+        "--drain-idle" => &mut options.drain_idle,
+        "--drain-max" => {
+            options.drain_max = parse_duration(&value(option)?)?;
+            return Ok(true);
+        }
+        "--reap-timeout" => {
+            options.reap_timeout = parse_duration(&value(option)?)?;
+            return Ok(true);
+        }
+        "--scan-interval" => {
+            options.scan_interval = parse_duration(&value(option)?)?;
+            return Ok(true);
+        }
+        "--dump-timeout" => {
+            options.dump_timeout = parse_duration(&value(option)?)?;
+            return Ok(true);
+        }
+        "--on-timeout-limit" => {
+            options.on_timeout_limit = parse_duration(&value(option)?)?;
+            return Ok(true);
+        }
+        "--shutdown-wait" => {
+            options.shutdown_wait = parse_duration(&value(option)?)?;
+            return Ok(true);
+        }
+        "--stdin-wait" => {
+            options.stdin_wait = parse_duration(&value(option)?)?;
+            return Ok(true);
+        }
+        "--if-cpu-above" => &mut options.thresholds.cpu,
+        "--if-age-above" => &mut options.thresholds.age,
+        _ => return Ok(false),
+    };
+    *target = Some(parse_duration(&value(option)?)?);
+    Ok(true)
+}
+
+/// Sets the option for a switch that decides when, and in what order, the
+/// signals go out, read with `value`, returning whether `name` was one.
+fn parse_timing_option(
+    name: &str,
+    value: &mut impl FnMut(&str) -> Result<String, String>,
+    options: &mut Options,
+) -> Result<bool, String> {
+    match name {
+        "--by" => options.deadline = Some(parse_deadline(&value("by")?)?),
+        "--if-past" => options.if_past = value("if-past")?.parse()?,
+        "--if-over-budget" => options.over_budget = value("if-over-budget")?.parse()?,
+        "--adaptive-poll" => options.poll_schedule = value("adaptive-poll")?.parse()?,
+        "--quorum" => options.quorum = Some(parse_quorum(&value("quorum")?)?),
+        "--seed" => {
+            let value = value("seed")?;
+            let seed = value
+                .parse()
+                .map_err(|_| format!("Invalid seed: '{value}'"))?;
+            options.seed = Some(seed);
+            options.shuffle = true;
+        }
+        _ => return Ok(false),
+    }
+    Ok(true)
+}
+
+/// Parses a `--quorum` of `PERCENT:DURATION`, the percent sign optional.
+fn parse_quorum(value: &str) -> Result<(u8, Duration), String> {
+    let invalid = || format!("Invalid quorum '{value}': expected PERCENT:DURATION, e.g. 90%:5s");
+    let (percent, stragglers) = value.split_once(':').ok_or_else(invalid)?;
+    let percent: u8 = percent
+        .strip_suffix('%')
+        .unwrap_or(percent)
+        .parse()
+        .map_err(|_| invalid())?;
+    if !(1..=100).contains(&percent) {
+        return Err(format!(
+            "Invalid quorum '{value}': the percentage must be 1 to 100"
+        ));
+    }
+    Ok((percent, parse_duration(stragglers)?))
+}
+
+/// Sets the option for a switch naming particular targets, read with
+/// `value`, returning whether `name` was one.
+fn parse_target_option(
+    name: &str,
+    value: &mut impl FnMut(&str) -> Result<String, String>,
+    options: &mut Options,
+) -> Result<bool, String> {
+    match name {
+        "--discover" => options.discover = Some(value("discover")?),
+        "--fifo" => options.fifo = Some(value("fifo")?),
+        "--save-set" => options.save_set = Some(value("save-set")?),
+        "--kill-new-since" => options.kill_new_since = Some(value("kill-new-since")?),
+        "--if-rss-above" => options.thresholds.rss = Some(parse_size(&value("if-rss-above")?)?),
+        "--tree-order" => options.tree_order = value("tree-order")?.parse()?,
+        "--holding" => options.holding.push(value("holding")?),
+        "--port" => options.ports.push(port::parse(&value("port")?)?),
+        "--netns" => options.netns = Some(value("netns")?),
+        "--stdin-cmd" => options.stdin_cmd = Some(stdin_cmd::parse_bytes(&value("stdin-cmd")?)?),
+        "--pid-signal" => {
+            let value = value("pid-signal")?;
+            let (pid, signal) = value
+                .split_once('=')
+                .ok_or_else(|| format!("Expected PID=SIGNAL, got '{value}'"))?;
+            options
+                .pid_signals
+                .push((parse_and_validate_pid(pid)?, signal.parse()?));
+        }
+        "--session" => {
+            let value = value("session")?;
+            let sid = parse_and_validate_pid(&value)
+                .map_err(|_| format!("Invalid session ID: '{value}'"))?;
+            options.sessions.push(sid);
+        }
+        "--container" => {
+            let name = value("container")?;
+            container::check_name(&name)?;
+            options.containers.push(name);
+        }
+        "--name" => {
+            let name = value("name")?;
+            if name.is_empty() || name.len() > MAX_COMM {
+                return Err(format!(
+                    "Invalid process name: '{name}' (command names are 1 to {MAX_COMM} bytes)"
+                ));
+            }
+            options.names.push(name);
+        }
+        "--container-socket" => options.container_socket = Some(value("container-socket")?),
+        "--pid-namespace" => {
+            options.pid_namespace = Some(parse_and_validate_pid(&value("pid-namespace")?)?);
+        }
+        "--listen" => options.listen = Some(value("listen")?),
+        "--list-threads" => {
+            options.list_threads = Some(parse_and_validate_pid(&value("list-threads")?)?);
+        }
+        "--tid" => {
+            let value = value("tid")?;
+            let tid =
+                parse_and_validate_pid(&value).map_err(|_| format!("Invalid TID: '{value}'"))?;
+            options.tid = Some(tid);
+        }
+        "--forbid-signal-to" => options.forbid_kill.push(value("forbid-signal-to")?),
+        "--protect-file" => options.protect_file = Some(value("protect-file")?),
+        "--kill-orphans-after-parent" => options.orphan_parents.push(parse_and_validate_pid(
+            &value("kill-orphans-after-parent")?,
+        )?),
+        "--expect-cmd" => {
+            let value = value("expect-cmd")?;
+            let (pid, substring) = value
+                .split_once('=')
+                .filter(|(_, substring)| !substring.is_empty())
+                .ok_or_else(|| format!("Expected PID=SUBSTRING, got '{value}'"))?;
+            options
+                .expected_commands
+                .push((parse_and_validate_pid(pid)?, substring.to_string()));
+        }
+        "--expect-exit-file" => {
+            let value = value("expect-exit-file")?;
+            let (pid, path) = value
+                .split_once('=')
+                .filter(|(_, path)| !path.is_empty())
+                .ok_or_else(|| format!("Expected PID=PATH, got '{value}'"))?;
+            options
+                .exit_files
+                .push((parse_and_validate_pid(pid)?, path.to_string()));
+        }
+        // Testing aid, deliberately left out of the usage text
+        "--simulate-exit-after" => {
+            let value = value("simulate-exit-after")?;
+            let (pid, after) = value
+                .split_once('=')
+                .ok_or_else(|| format!("Expected PID=DURATION, got '{value}'"))?;
+            options
+                .simulated_exits
+                .push((parse_and_validate_pid(pid)?, parse_duration(after)?));
+            options.simulate = true;
+        }
+        _ => return Ok(false),
+    }
+    Ok(true)
+}
+
+/// Parses an `--escalate` ladder such as `TERM:30s,QUIT:5s,KILL`: at least
+/// two signals, each but the last (the kill signal) with an optional wait.
+fn parse_ladder(value: &str) -> Result<Vec<(Signal, Option<Duration>)>, String> {
+    let stages = value
+        .split(',')
+        .map(|stage| match stage.trim().split_once(':') {
+            Some((signal, wait)) => Ok((signal.parse()?, Some(parse_duration(wait)?))),
+            None => Ok((stage.trim().parse()?, None)),
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    match stages.last() {
+        _ if stages.len() < 2 => Err(format!(
+            "Escalation ladder needs at least two signals: '{value}'"
+        )),
+        Some((signal, Some(_))) => Err(format!(
+            "The last signal of an escalation ladder is the kill and takes no duration: '{signal}'"
+        )),
+        _ => Ok(stages),
+    }
+}
+
+/// Parses `--grace-per-signal-class` defaults such as `TERM=30s,QUIT=5s`.
+fn parse_class_graces(value: &str) -> Result<Vec<(Signal, Duration)>, String> {
+    value
+        .split(',')
+        .map(|entry| {
+            let (signal, wait) = entry
+                .trim()
+                .split_once('=')
+                .ok_or_else(|| format!("Expected SIGNAL=DURATION, got '{entry}'"))?;
+            Ok((signal.parse()?, parse_duration(wait)?))
+        })
+        .collect()
+}
+
+/// The stages from `--escalate` or `--stage`, if either was given, taking
+/// the kill signal off the end of an `--escalate` ladder.
+fn steps(
+    ladder: Option<Vec<(Signal, Option<Duration>)>>,
+    blocks: Vec<Block>,
+    options: &mut Options,
+) -> Result<Option<Vec<Block>>, String> {
+    match ladder {
+        Some(_) if !blocks.is_empty() => {
+            Err("--stage cannot be combined with --escalate".to_string())
+        }
+        Some(mut ladder) => {
+            if let Some((kill_signal, _)) = ladder.pop() {
+                options.kill_signal = kill_signal;
+            }
+            Ok(Some(ladder.into_iter().map(Block::from).collect()))
+        }
+        None if blocks.is_empty() => Ok(None),
+        None if options.double_tap => {
+            Err("--stage cannot be combined with --double-tap, use retries=1 instead".to_string())
+        }
+        None => Ok(Some(blocks)),
+    }
+}
+
+/// Settles the wait after each signal. A duration written for a stage wins,
+/// then the default for its signal class, then `--grace-seconds`. Without
+/// `--escalate` or `--stage`, an explicit `--grace-seconds` counts as the
+/// graceful signal's own duration.
+fn apply_graces(
+    options: &mut Options,
+    ladder: Option<Vec<Block>>,
+    class_graces: &[(Signal, Duration)],
+    grace_given: bool,
+) {
+    let class_grace = |signal: Signal| {
+        class_graces
+            .iter()
+            .rev()
+            .find(|(class, _)| *class == signal)
+            .map(|&(_, wait)| wait)
+    };
+    let Some(ladder) = ladder else {
+        if !grace_given && let Some(wait) = class_grace(options.signal) {
+            options.grace_period = wait;
+        }
+        return;
+    };
+
+    let fallback = options.grace_period;
+    options.escalate = true;
+    let mut stages = ladder.into_iter().map(|block| Stage {
+        signal: block.signal,
+        grace: block
+            .grace
+            .or_else(|| class_grace(block.signal))
+            .unwrap_or(fallback),
+        retries: block.retries,
+        jitter: block.jitter,
+    });
+    if let Some(first) = stages.next() {
+        options.signal = first.signal;
+        options.grace_period = first.grace;
+        options.retries = first.retries;
+        options.jitter = first.jitter;
+    }
+    options.stages = stages.collect();
+}
+
+/// Parses a comma-separated list of targets. A negative value names a process
+/// group, which is only unambiguous after `--`. A PID may be followed by its
+/// own graceful signal, as in `1234/INT`. With `--skip-invalid`, a target
+/// that doesn't parse is set aside in [`Options::skipped`] instead,
+/// described as at argument `position`.
+fn parse_targets(arg: &str, position: usize, options: &mut Options) -> Result<(), String> {
+    for target in arg.split(',').map(str::trim) {
+        if let Err(e) = parse_target(target, options) {
+            if !options.skip_invalid {
+                return Err(e);
+            }
+            options.skipped.push(at_argument(&e, position));
+        }
+    }
+    Ok(())
+}
+
+fn parse_target(target: &str, options: &mut Options) -> Result<(), String> {
+    if let Some((pid, signal)) = target.split_once('/') {
+        let pid = parse_and_validate_pid(pid)?;
+        let signal = signal.parse()?;
+        options.pids.push(pid);
+        options.pid_signals.push((pid, signal));
+    } else if let Some(pgid) = target.strip_prefix('-') {
+        let pgid = parse_and_validate_pid(pgid)
+            .map_err(|_| format!("Invalid process group: '{target}'"))?;
+        options.groups.push(pgid);
+    } else {
+        options.pids.push(parse_and_validate_pid(target)?);
+    }
+    Ok(())
+}
@@ -0,0 +1,153 @@
+//! `--report`: a CSV audit trail of what happened to each target.
+//!
+//! Each row is `pid,command,signal_sent,outcome,grace_used_ms,timestamp`.
+//! `signal_sent` is the last signal delivered to the process, `grace_used_ms`
+//! how long after the first signal its outcome was settled, and `timestamp`
+//! when that happened, in Unix seconds with millisecond precision.
+
+use std::collections::HashMap;
+use std::fs::OpenOptions;
+use std::io::{self, BufWriter, Write};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use gracekill::{Outcome, Signal};
+
+use crate::commands::Commands;
+
+const HEADER: &str = "pid,command,signal_sent,outcome,grace_used_ms,timestamp";
+
+/// What happened to one target.
+pub struct Row {
+    pid: u32,
+    command: String,
+    signal: Option<Signal>,
+    outcome: Outcome,
+    grace_used: Duration,
+    timestamp: SystemTime,
+}
+
+impl Row {
+    /// A row for `pid`, which exited gracefully before it was sent a signal.
+    pub fn unsignalled(pid: u32) -> Row {
+        Row {
+            pid,
+            command: String::new(),
+            signal: None,
+            outcome: Outcome::Graceful,
+            grace_used: Duration::ZERO,
+            timestamp: SystemTime::now(),
+        }
+    }
+}
+
+type Resolved = Vec<(u32, Outcome, Duration, SystemTime)>;
+
+/// Collects the rows of a single run.
+pub struct Recorder {
+    commands: HashMap<u32, String>,
+    resolved: Arc<Mutex<Resolved>>,
+}
+
+impl Recorder {
+    /// Starts recording a run over `pids`, noting their command names while
+    /// they still exist unless `read_commands` is false.
+    pub fn new(pids: &[u32], read_commands: bool) -> Self {
+        #[cfg_attr(not(target_os = "linux"), allow(unused_mut))]
+        let mut commands = HashMap::new();
+        #[cfg(target_os = "linux")]
+        if read_commands {
+            for &pid in pids {
+                if let Some(comm) = gracekill::procfs::comm(pid) {
+                    commands.insert(pid, comm);
+                }
+            }
+        }
+        #[cfg(not(target_os = "linux"))]
+        let _ = (pids, read_commands);
+        Self {
+            commands,
+            resolved: Arc::default(),
+        }
+    }
+
+    /// A [`GraceKill::on_result`](gracekill::GraceKill::on_result) callback
+    /// timing each outcome from now.
+    pub fn callback(&self) -> impl FnMut(u32, &Outcome) + Send + 'static {
+        let start = Instant::now();
+        let resolved = Arc::clone(&self.resolved);
+        move |pid, outcome| {
+            resolved
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .push((pid, outcome.clone(), start.elapsed(), SystemTime::now()));
+        }
+    }
+
+    /// The recorded rows, with the signals `controller` delivered.
+    pub fn finish(self, controller: &Commands) -> Vec<Row> {
+        let resolved =
+            std::mem::take(&mut *self.resolved.lock().unwrap_or_else(PoisonError::into_inner));
+        resolved
+            .into_iter()
+            .map(|(pid, outcome, grace_used, timestamp)| Row {
+                pid,
+                command: self.commands.get(&pid).cloned().unwrap_or_default(),
+                signal: controller.last_signal(pid),
+                outcome,
+                grace_used,
+                timestamp,
+            })
+            .collect()
+    }
+}
+
+/// Writes `rows` to the CSV file at `path`, appending to it or replacing it.
+/// The header is only written when the file starts out empty.
+pub fn write(path: &str, append: bool, rows: &[Row]) -> io::Result<()> {
+    let file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(append)
+        .truncate(!append)
+        .open(path)?;
+    let is_new = file.metadata()?.len() == 0;
+    let mut writer = BufWriter::new(file);
+    if is_new {
+        writeln!(writer, "{HEADER}")?;
+    }
+    for row in rows {
+        let timestamp = row.timestamp.duration_since(UNIX_EPOCH).unwrap_or_default();
+        writeln!(
+            writer,
+            "{},{},{},{},{},{}.{:03}",
+            row.pid,
+            escape(&row.command),
+            row.signal.map(Signal::name).unwrap_or_default(),
+            escape(&outcome_name(&row.outcome)),
+            row.grace_used.as_millis(),
+            timestamp.as_secs(),
+            timestamp.subsec_millis()
+        )?;
+    }
+    writer.flush()
+}
+
+fn outcome_name(outcome: &Outcome) -> String {
+    match outcome {
+        Outcome::SignalFailed(e) => format!("signal failed: {e}"),
+        Outcome::Graceful => "graceful".to_string(),
+        Outcome::Killed => "killed".to_string(),
+        Outcome::KillFailed(e) => format!("kill failed: {e}"),
+        Outcome::Survived => "survived".to_string(),
+    }
+}
+
+/// Quotes a CSV field if it contains a delimiter, quote or line break.
+fn escape(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
@@ -0,0 +1,79 @@
+//! Batch files: one independent kill operation per line.
+//!
+//! Each non-blank line is a list of space-separated `key=value` settings:
+//!
+//! ```text
+//! # web tier gets a long drain, workers a quick interrupt
+//! pids=1234,1235 grace=30s
+//! pids=2001 grace=5s signal=INT kill_signal=KILL
+//! ```
+//!
+//! `pids` is required. `grace`, `signal` and `kill_signal` fall back to the
+//! command-line settings. Text after `#` is a comment.
+
+use std::time::Duration;
+
+use gracekill::Signal;
+
+use crate::args::parse_and_validate_pid;
+use crate::duration::parse_duration;
+
+/// A single line of a batch file, or a group of `--plan` targets.
+pub struct Operation {
+    /// Where the operation was described, e.g. `line 3`.
+    pub source: String,
+    pub pids: Vec<u32>,
+    pub grace: Option<Duration>,
+    pub signal: Option<Signal>,
+    pub kill_signal: Option<Signal>,
+}
+
+pub fn parse_batch(contents: &str) -> Result<Vec<Operation>, String> {
+    let mut operations = Vec::new();
+
+    for (index, line) in contents.lines().enumerate() {
+        let line_number = index + 1;
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let operation =
+            parse_operation(line_number, line).map_err(|e| format!("line {line_number}: {e}"))?;
+        operations.push(operation);
+    }
+
+    Ok(operations)
+}
+
+pub fn parse_operation(line_number: usize, line: &str) -> Result<Operation, String> {
+    let mut operation = Operation {
+        source: format!("line {line_number}"),
+        pids: Vec::new(),
+        grace: None,
+        signal: None,
+        kill_signal: None,
+    };
+
+    for setting in line.split_whitespace() {
+        let (key, value) = setting
+            .split_once('=')
+            .ok_or_else(|| format!("Expected key=value, got '{setting}'"))?;
+        match key {
+            "pids" => {
+                for pid_str in value.split(',') {
+                    operation.pids.push(parse_and_validate_pid(pid_str.trim())?);
+                }
+            }
+            "grace" => operation.grace = Some(parse_duration(value)?),
+            "signal" => operation.signal = Some(value.parse()?),
+            "kill_signal" => operation.kill_signal = Some(value.parse()?),
+            _ => return Err(format!("Unknown setting: '{key}'")),
+        }
+    }
+
+    if operation.pids.is_empty() {
+        return Err("No PIDs provided".to_string());
+    }
+    Ok(operation)
+}
@@ -0,0 +1,30 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Cancels a [`GraceKill::run`](crate::GraceKill::run) in progress from
+/// another thread, e.g. because the operation it was part of was aborted.
+/// Clones share the same state, so the run is given one clone and the
+/// caller keeps another.
+///
+/// Cancelling doesn't undo signals already sent: the run stops waiting at
+/// its next poll and returns, sending nothing further. See
+/// [`GraceKill::cancel_token`](crate::GraceKill::cancel_token).
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancels every run given this token, now or later.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    #[must_use]
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
@@ -0,0 +1,125 @@
+//! Housekeeping for gracekill's own child processes.
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use nix::errno::Errno;
+use nix::sys::wait::{WaitPidFlag, WaitStatus, waitpid};
+use nix::unistd::Pid;
+
+use crate::ExitStatus;
+use crate::log::log;
+
+/// Registers this process as a child subreaper with
+/// `prctl(PR_SET_CHILD_SUBREAPER)`, so descendants orphaned by their parent
+/// are reparented here instead of to init and can be waited on with
+/// [`wait_children`].
+///
+/// This only affects descendants of the calling process.
+///
+/// # Errors
+///
+/// Returns a description of the failure if the kernel rejects the request.
+#[cfg(target_os = "linux")]
+pub fn become_subreaper() -> Result<(), String> {
+    nix::sys::prctl::set_child_subreaper(true)
+        .map_err(|e| format!("Failed to become a child subreaper: {e}"))
+}
+
+/// How long [`reap`] waits for killed children to become reapable, unless
+/// told otherwise.
+pub const DEFAULT_REAP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The result of [`wait_children`] and [`reap`].
+#[derive(Clone, Debug, Default)]
+pub struct ChildrenWait {
+    /// Every child reaped, with how it terminated.
+    pub reaped: Vec<(u32, ExitStatus)>,
+    /// Whether children were still running when the timeout elapsed.
+    pub timed_out: bool,
+}
+
+/// Reaps children of this process as they exit until none are left or
+/// `timeout` elapses, checking every `poll_interval`.
+#[must_use]
+pub fn wait_children(timeout: Duration, poll_interval: Duration) -> ChildrenWait {
+    let start = Instant::now();
+    let mut wait = ChildrenWait::default();
+
+    loop {
+        match waitpid(None, Some(WaitPidFlag::WNOHANG)) {
+            Ok(WaitStatus::Exited(pid, code)) => {
+                reaped(&mut wait, pid.as_raw(), ExitStatus::Code(code));
+            }
+            Ok(WaitStatus::Signaled(pid, signal, _)) => {
+                reaped(&mut wait, pid.as_raw(), ExitStatus::Signaled(signal as i32));
+            }
+            Ok(WaitStatus::StillAlive) => {
+                if start.elapsed() >= timeout {
+                    wait.timed_out = true;
+                    return wait;
+                }
+                thread::sleep(poll_interval);
+            }
+            Ok(_) | Err(Errno::EINTR) => {}
+            // ECHILD: nothing left to wait for
+            Err(_) => return wait,
+        }
+    }
+}
+
+/// Reaps every defunct child of this process, and waits until those of
+/// `targets` that are children still running have exited and been reaped
+/// too, for at most `timeout`, checking every `poll_interval`.
+///
+/// Unlike [`wait_children`], children that aren't among `targets` are only
+/// reaped if they have already exited. Processes that aren't children of
+/// this one are never touched, as `waitpid` can't reach them.
+#[must_use]
+pub fn reap(targets: &[u32], timeout: Duration, poll_interval: Duration) -> ChildrenWait {
+    let start = Instant::now();
+    let mut wait = ChildrenWait::default();
+    let mut pending = targets.to_vec();
+
+    loop {
+        reap_defunct(&mut wait, None);
+        pending.retain(|&pid| {
+            i32::try_from(pid).is_ok_and(|pid| reap_defunct(&mut wait, Some(Pid::from_raw(pid))))
+        });
+        if pending.is_empty() {
+            return wait;
+        }
+        if start.elapsed() >= timeout {
+            wait.timed_out = true;
+            return wait;
+        }
+        thread::sleep(poll_interval);
+    }
+}
+
+/// Reaps `pid`, or every child with `None`, for as long as one has exited.
+/// Returns whether a matching child is still running.
+fn reap_defunct(wait: &mut ChildrenWait, pid: Option<Pid>) -> bool {
+    loop {
+        match waitpid(pid, Some(WaitPidFlag::WNOHANG)) {
+            Ok(WaitStatus::Exited(pid, code)) => {
+                reaped(wait, pid.as_raw(), ExitStatus::Code(code));
+            }
+            Ok(WaitStatus::Signaled(pid, signal, _)) => {
+                reaped(wait, pid.as_raw(), ExitStatus::Signaled(signal as i32));
+            }
+            Ok(WaitStatus::StillAlive) => return true,
+            Ok(_) | Err(Errno::EINTR) => {}
+            // ECHILD: no such child, or none left
+            Err(_) => return false,
+        }
+    }
+}
+
+fn reaped(wait: &mut ChildrenWait, pid: i32, status: ExitStatus) {
+    let Ok(pid) = u32::try_from(pid) else {
+        return;
+    };
+    log(&format!("Child process {pid} {status}"));
+    wait.reaped.push((pid, status));
+}
@@ -0,0 +1,278 @@
+//! Shell commands around signal delivery: `--print-commands` echoes each
+//! signal sent as an equivalent `kill` command, and `--escalate-privilege`
+//! retries signals refused with `EPERM` through a helper such as `sudo`.
+//! Liveness is checked as chosen with `--liveness`, and `--simulate` swaps
+//! the real processes for a [`Simulation`]. Before every signal, a target
+//! given `--expect-cmd` is checked to still run the expected command, and
+//! to be the same process it was at the first check.
+
+use std::collections::{HashMap, HashSet};
+use std::process::Command;
+
+use gracekill::log::{self, log};
+use gracekill::{ProcessController, Signal, SignalError, System, WaitResult};
+
+use crate::liveness::Liveness;
+use crate::simulate::Simulation;
+use crate::stdout;
+use crate::trace;
+
+/// Forwards to [`System`], printing and escalating as configured.
+pub struct Commands {
+    print: bool,
+    /// Command prefix, split on whitespace, e.g. `sudo -n`.
+    privilege_helper: Option<Vec<String>>,
+    /// The last signal delivered to each PID.
+    last_signal: HashMap<u32, Signal>,
+    /// An explicitly chosen liveness check, instead of [`System`]'s.
+    liveness: Option<Liveness>,
+    /// Simulated processes to drive instead of real ones.
+    simulation: Option<Simulation>,
+    /// `--expect-cmd` expectations, by PID.
+    expected: HashMap<u32, Expected>,
+}
+
+/// What `--expect-cmd` expects of a target.
+struct Expected {
+    /// A substring its command line must contain.
+    substring: String,
+    /// Its start time in clock ticks after boot, once first checked.
+    started: Option<u64>,
+}
+
+impl Commands {
+    pub fn new(print: bool, privilege_helper: Option<&str>, liveness: Option<Liveness>) -> Self {
+        Self {
+            print,
+            privilege_helper: privilege_helper
+                .map(|helper| helper.split_whitespace().map(String::from).collect()),
+            last_signal: HashMap::new(),
+            liveness,
+            simulation: None,
+            expected: HashMap::new(),
+        }
+    }
+
+    /// Refuses to signal each PID in `expected` unless its command line
+    /// contains the substring given with it.
+    pub fn expect_commands(mut self, expected: &[(u32, String)]) -> Self {
+        for (pid, substring) in expected {
+            self.expected.insert(
+                *pid,
+                Expected {
+                    substring: substring.clone(),
+                    started: None,
+                },
+            );
+        }
+        self
+    }
+
+    /// Drives `simulation` instead of real processes.
+    pub fn simulate(mut self, simulation: Simulation) -> Self {
+        self.simulation = Some(simulation);
+        self
+    }
+
+    /// The last signal delivered to `pid`, if any.
+    pub fn last_signal(&self, pid: u32) -> Option<Signal> {
+        self.last_signal.get(&pid).copied()
+    }
+
+    /// Delivers `signal` through [`System`], escalating privileges and
+    /// printing the command as configured.
+    fn deliver(&mut self, pid: u32, signal: Signal) -> Result<(), SignalError> {
+        let sent = match &mut self.simulation {
+            Some(simulation) => simulation.send_signal(pid, signal),
+            None => self
+                .check_expected(pid)
+                .and_then(|()| System.send_signal(pid, signal)),
+        };
+        let command_line = match sent {
+            Ok(()) => format!("kill -{} {pid}", kill_name(signal)),
+            // Only a permission problem is worth escalating, and only once
+            Err(SignalError::PermissionDenied) if let Some(helper) = &self.privilege_helper => {
+                run_privileged(helper, pid, signal).map_err(|e| {
+                    log::error(&format!("Privilege escalation failed: {e}"));
+                    SignalError::PermissionDenied
+                })?
+            }
+            Err(e) => return Err(e),
+        };
+        if self.print {
+            stdout::line(&command_line);
+        }
+        self.last_signal.insert(pid, signal);
+        Ok(())
+    }
+}
+
+/// Adds the trace events for delivering `signal` to `target`, counting it as
+/// an escalation if it differs from the `previous` signal delivered.
+fn trace_signal(
+    target: &str,
+    previous: Option<Signal>,
+    signal: Signal,
+    result: &Result<(), SignalError>,
+) {
+    if let Some(previous) = previous
+        && previous != signal
+        && result.is_ok()
+    {
+        trace::event("escalate", &format!("{target} from={previous} to={signal}"));
+    }
+    trace::event(
+        "signal",
+        &format!(
+            "{target} signal={signal} result={}",
+            trace::result_name(result)
+        ),
+    );
+}
+
+/// Runs `kill` through the privilege helper once, returning the command
+/// line on success.
+fn run_privileged(helper: &[String], pid: u32, signal: Signal) -> Result<String, String> {
+    let (program, helper_args) = helper.split_first().ok_or("empty privilege helper")?;
+    let kill_args = [
+        "kill".to_string(),
+        format!("-{}", kill_name(signal)),
+        pid.to_string(),
+    ];
+    let command_line = format!("{} {}", helper.join(" "), kill_args.join(" "));
+    log::warn(&format!(
+        "Permission denied sending {signal} to PID {pid}, retrying with '{command_line}'"
+    ));
+    let status = Command::new(program)
+        .args(helper_args)
+        .args(&kill_args)
+        .status()
+        .map_err(|e| format!("'{command_line}' could not be run: {e}"))?;
+    if status.success() {
+        log(&format!("'{command_line}' succeeded"));
+        Ok(command_line)
+    } else {
+        Err(format!("'{command_line}' failed ({status})"))
+    }
+}
+
+/// `SIGTERM` as `kill` spells it: `TERM`.
+impl Commands {
+    /// Checks that `pid` runs the command `--expect-cmd` expects of it, and
+    /// has the start time it had when first checked, so that a PID reused by
+    /// another process in the meantime is never signalled.
+    #[cfg(target_os = "linux")]
+    fn check_expected(&mut self, pid: u32) -> Result<(), SignalError> {
+        use gracekill::procfs;
+
+        let Some(expected) = self.expected.get_mut(&pid) else {
+            return Ok(());
+        };
+        // A target that is gone is reported as such by the signal itself
+        let Ok(stat) = procfs::stat(pid) else {
+            return Ok(());
+        };
+        // Zombies have no command line left to check, only a start time
+        let command = procfs::cmdline(pid).map(|cmdline| {
+            String::from_utf8_lossy(&cmdline)
+                .split('\0')
+                .filter(|arg| !arg.is_empty())
+                .collect::<Vec<_>>()
+                .join(" ")
+        });
+        let refusal = if *expected.started.get_or_insert(stat.starttime) != stat.starttime {
+            format!(
+                "PID {pid} has been taken by another process ({}) since it was first checked",
+                stat.comm
+            )
+        } else if let Some(command) = command
+            && !command.contains(&expected.substring)
+        {
+            format!(
+                "PID {pid} runs '{command}', which doesn't contain the expected '{}'",
+                expected.substring
+            )
+        } else {
+            return Ok(());
+        };
+        log::error(&format!("{refusal}, refusing to signal it"));
+        Err(SignalError::Other(
+            "command doesn't match --expect-cmd".to_string(),
+        ))
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn check_expected(&mut self, _pid: u32) -> Result<(), SignalError> {
+        Ok(())
+    }
+}
+
+/// `signal` as `kill -` takes it: `TERM` for SIGTERM.
+pub fn kill_name(signal: Signal) -> &'static str {
+    &signal.name()[3..]
+}
+
+impl ProcessController for Commands {
+    fn send_signal(&mut self, pid: u32, signal: Signal) -> Result<(), SignalError> {
+        let previous = self.last_signal(pid);
+        let result = self.deliver(pid, signal);
+        trace_signal(&format!("pid={pid}"), previous, signal, &result);
+        result
+    }
+
+    fn is_running(&mut self, pid: u32) -> bool {
+        if let Some(simulation) = &mut self.simulation {
+            return simulation.is_running(pid);
+        }
+        match self.liveness {
+            Some(liveness) => liveness.is_running(pid),
+            None => System.is_running(pid),
+        }
+    }
+
+    fn running_among(&mut self, pids: &[u32]) -> Option<HashSet<u32>> {
+        // An explicit choice applies to every check, however many targets
+        match self.liveness {
+            _ if self.simulation.is_some() => None,
+            Some(_) => None,
+            None => System.running_among(pids),
+        }
+    }
+
+    fn send_thread_signal(
+        &mut self,
+        pid: u32,
+        tid: u32,
+        signal: Signal,
+    ) -> Result<(), SignalError> {
+        let result = match &mut self.simulation {
+            Some(simulation) => simulation.send_thread_signal(pid, tid, signal),
+            None => self
+                .check_expected(pid)
+                .and_then(|()| System.send_thread_signal(pid, tid, signal)),
+        };
+        trace_signal(
+            &format!("pid={pid} tid={tid}"),
+            self.last_signal(pid),
+            signal,
+            &result,
+        );
+        result?;
+        if self.print {
+            // kill(1) can't target a single thread; tgkill(2) has no shell wrapper
+            stdout::line(&format!(
+                "kill -{} {pid}  # thread {tid} only",
+                kill_name(signal)
+            ));
+        }
+        self.last_signal.insert(pid, signal);
+        Ok(())
+    }
+
+    fn try_wait(&mut self, pid: u32) -> WaitResult {
+        if self.simulation.is_some() {
+            return WaitResult::NotChild;
+        }
+        System.try_wait(pid)
+    }
+}
@@ -0,0 +1,70 @@
+//! `--confirm-count`: ask before signalling more targets than expected, for
+//! selectors that match far more than was meant, such as a name pattern
+//! or a session. Once the targets are resolved, a set larger than the count
+//! is shown, as its size and the first few PIDs with their command names,
+//! and gracekill waits for a `y` on the terminal. `--yes` answers for
+//! scripts; without it, and without a terminal on stdin to ask on, the run
+//! is refused.
+
+use std::io::{self, BufRead, IsTerminal, Write};
+
+use gracekill::log;
+
+/// How many of the targets are shown by PID and command name.
+const SAMPLE: usize = 5;
+
+/// `pids` as `PID (name)`, the first [`SAMPLE`] of them, and how many more
+/// there are.
+fn sample(pids: &[u32]) -> String {
+    let mut shown: Vec<String> = pids
+        .iter()
+        .take(SAMPLE)
+        .map(|&pid| match gracekill::procfs::comm(pid) {
+            Some(name) => format!("{pid} ({name})"),
+            None => pid.to_string(),
+        })
+        .collect();
+    if pids.len() > SAMPLE {
+        shown.push(format!("and {} more", pids.len() - SAMPLE));
+    }
+    shown.join(", ")
+}
+
+/// Checks that signalling `pids` is confirmed if there are more than
+/// `limit` of them: by `yes`, or else by the user at the terminal.
+pub fn check(pids: &[u32], limit: usize, yes: bool) -> Result<(), String> {
+    if pids.len() <= limit {
+        return Ok(());
+    }
+    log::warn(&format!(
+        "About to signal {} process(es), more than --confirm-count {limit}: {}",
+        pids.len(),
+        sample(pids)
+    ));
+    if yes {
+        log::debug("Signalling them without asking, as --yes was given");
+        return Ok(());
+    }
+    let stdin = io::stdin();
+    if !stdin.is_terminal() {
+        return Err(format!(
+            "Not signalling {} process(es) without confirmation, and stdin is not a terminal to ask on. Pass --yes to signal them anyway",
+            pids.len()
+        ));
+    }
+    eprint!("Signal these {} process(es)? [y/N] ", pids.len());
+    let _ = io::stderr().flush();
+    let mut answer = String::new();
+    stdin
+        .lock()
+        .read_line(&mut answer)
+        .map_err(|e| format!("Cannot read the confirmation: {e}"))?;
+    if matches!(answer.trim().to_ascii_lowercase().as_str(), "y" | "yes") {
+        Ok(())
+    } else {
+        Err(format!(
+            "Not signalling {} process(es): not confirmed",
+            pids.len()
+        ))
+    }
+}
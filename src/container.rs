@@ -0,0 +1,145 @@
+//! `--container`: target a container by name or ID, asking the local
+//! container runtime for its main process and taking that process and
+//! every descendant of it through the graceful kill.
+//!
+//! The runtime is asked over the Docker Engine API on a Unix socket, with a
+//! single `GET /containers/<name>/json`: Docker's own socket, or any that
+//! speaks the same API, such as Podman's. containerd's native API is gRPC
+//! and isn't spoken. Like `--shutdown-url`, the client is minimal and sits
+//! behind a feature, `container`, which is on by default.
+
+/// The socket asked without `--container-socket` or a `unix://` `DOCKER_HOST`.
+#[cfg_attr(not(feature = "container"), allow(dead_code))]
+pub const DEFAULT_SOCKET: &str = "/var/run/docker.sock";
+
+/// The socket to ask: `socket` if given, else `DOCKER_HOST` if it names a
+/// Unix socket, else [`DEFAULT_SOCKET`].
+#[cfg_attr(not(feature = "container"), allow(dead_code))]
+pub fn socket_path(socket: Option<&str>) -> String {
+    if let Some(socket) = socket {
+        return socket.to_string();
+    }
+    std::env::var("DOCKER_HOST")
+        .ok()
+        .and_then(|host| host.strip_prefix("unix://").map(str::to_string))
+        .unwrap_or_else(|| DEFAULT_SOCKET.to_string())
+}
+
+/// Checks that `name` can be a container name or ID, which keeps it safe to
+/// put in a request path as it is.
+pub fn check_name(name: &str) -> Result<(), String> {
+    if !cfg!(feature = "container") {
+        return Err("--container needs gracekill built with the container feature".to_string());
+    }
+    let valid = !name.is_empty()
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'_' | b'.' | b'-'));
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("Invalid container name or ID: '{name}'"))
+    }
+}
+
+#[cfg(feature = "container")]
+pub use client::main_pid;
+
+#[cfg(feature = "container")]
+mod client {
+    use std::io::{Read, Write};
+    use std::os::unix::net::UnixStream;
+    use std::time::Duration;
+
+    use crate::json::{self, Value};
+
+    /// How long connecting to the socket and each read or write may take.
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// The host PID of the main process of container `name`, asked of the
+    /// runtime listening on `socket`.
+    pub fn main_pid(socket: &str, name: &str) -> Result<u32, String> {
+        let (status, body) = inspect(socket, name)?;
+        let document = json::parse(&body);
+        match status {
+            200 => {}
+            404 => return Err(format!("No such container: '{name}'")),
+            _ => {
+                let message = match document
+                    .as_ref()
+                    .ok()
+                    .and_then(|document| member(document, "message"))
+                {
+                    Some(Value::String(message)) => message.clone(),
+                    _ => body.trim().to_string(),
+                };
+                return Err(format!(
+                    "The container runtime answered {status} for '{name}': {message}"
+                ));
+            }
+        }
+        let document =
+            document.map_err(|e| format!("The container runtime sent invalid JSON: {e}"))?;
+        let state = member(&document, "State");
+        let running = state.and_then(|state| member(state, "Running"));
+        let pid = match state.and_then(|state| member(state, "Pid")) {
+            Some(Value::Number(pid)) => pid.parse::<u32>().ok(),
+            _ => None,
+        };
+        match (running, pid) {
+            (Some(Value::Bool(true)), Some(pid)) if pid > 0 => Ok(pid),
+            (Some(Value::Bool(_)), Some(_)) => Err(format!("Container '{name}' is not running")),
+            _ => Err(format!(
+                "The container runtime gave no State.Pid for '{name}'"
+            )),
+        }
+    }
+
+    /// Sends `GET /containers/<name>/json` and returns the status code and
+    /// body of the response.
+    fn inspect(socket: &str, name: &str) -> Result<(u16, String), String> {
+        let mut stream = UnixStream::connect(socket).map_err(|e| {
+            format!("Cannot connect to the container runtime at {socket}: {e}. Is it running? Use --container-socket to point at another socket")
+        })?;
+        stream
+            .set_read_timeout(Some(TIMEOUT))
+            .and_then(|()| stream.set_write_timeout(Some(TIMEOUT)))
+            .map_err(|e| e.to_string())?;
+        // HTTP/1.0, so that the body is neither chunked nor kept open
+        let request = format!(
+            "GET /containers/{name}/json HTTP/1.0\r\nHost: localhost\r\nUser-Agent: gracekill\r\n\r\n"
+        );
+        stream
+            .write_all(request.as_bytes())
+            .map_err(|e| format!("Cannot talk to the container runtime at {socket}: {e}"))?;
+        let mut response = String::new();
+        stream
+            .read_to_string(&mut response)
+            .map_err(|e| format!("Cannot read from the container runtime at {socket}: {e}"))?;
+        let (head, body) = response
+            .split_once("\r\n\r\n")
+            .ok_or_else(|| "The container runtime sent a malformed response".to_string())?;
+        // HTTP/1.0 200 OK
+        let status = head
+            .split(' ')
+            .nth(1)
+            .and_then(|code| code.parse().ok())
+            .ok_or_else(|| {
+                format!(
+                    "The container runtime sent no HTTP status: '{}'",
+                    head.lines().next().unwrap_or_default()
+                )
+            })?;
+        Ok((status, body.to_string()))
+    }
+
+    fn member<'a>(value: &'a Value, key: &str) -> Option<&'a Value> {
+        let Value::Object(members) = value else {
+            return None;
+        };
+        members
+            .iter()
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| value)
+    }
+}
@@ -0,0 +1,217 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use nix::sys::signal;
+use nix::sys::wait::{WaitPidFlag, WaitStatus, waitpid};
+use nix::unistd::Pid;
+
+use crate::{ExitStatus, Signal};
+
+/// Why a signal could not be delivered.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SignalError {
+    NotFound,
+    PermissionDenied,
+    PidTooLarge,
+    /// The kernel couldn't queue the signal (`EAGAIN`); retrying may succeed.
+    QueueFull,
+    Other(String),
+}
+
+impl fmt::Display for SignalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SignalError::NotFound => f.write_str("Process not found"),
+            SignalError::PermissionDenied => f.write_str("Permission denied"),
+            SignalError::PidTooLarge => f.write_str("PID too large for system"),
+            SignalError::QueueFull => f.write_str("Signal queue full, retry later"),
+            SignalError::Other(e) => write!(f, "Failed to send signal: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for SignalError {}
+
+/// The result of checking on a process with [`ProcessController::try_wait`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum WaitResult {
+    /// The process is not our child, so its exit status can't be collected.
+    NotChild,
+    /// The process is our child and has not exited yet.
+    Running,
+    /// The process was our child and has been reaped.
+    Exited(ExitStatus),
+}
+
+/// The operations gracekill performs on target processes.
+///
+/// [`System`] talks to the kernel; tests and embedders can provide their own
+/// implementation to script process behavior.
+pub trait ProcessController {
+    /// Delivers `signal` to `pid`.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`SignalError`] if the signal could not be delivered.
+    fn send_signal(&mut self, pid: u32, signal: Signal) -> Result<(), SignalError>;
+
+    /// Returns whether `pid` still exists.
+    fn is_running(&mut self, pid: u32) -> bool;
+
+    /// Checks many processes at once, returning a set that contains every
+    /// one of `pids` still running, or `None` to have each checked with
+    /// [`is_running`](Self::is_running) instead.
+    ///
+    /// The default implementation always returns `None`.
+    fn running_among(&mut self, _pids: &[u32]) -> Option<HashSet<u32>> {
+        None
+    }
+
+    /// Delivers `signal` to thread `tid` of process `pid` only.
+    ///
+    /// The default implementation reports thread-directed signals as
+    /// unsupported.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`SignalError`] if the signal could not be delivered.
+    fn send_thread_signal(
+        &mut self,
+        _pid: u32,
+        _tid: u32,
+        _signal: Signal,
+    ) -> Result<(), SignalError> {
+        Err(SignalError::Other(
+            "thread-directed signals are not supported".to_string(),
+        ))
+    }
+
+    /// Reaps `pid` without blocking if it is a child of this process that has
+    /// exited.
+    ///
+    /// The default implementation treats every process as someone else's
+    /// child.
+    fn try_wait(&mut self, _pid: u32) -> WaitResult {
+        WaitResult::NotChild
+    }
+}
+
+impl<T: ProcessController + ?Sized> ProcessController for &mut T {
+    fn send_signal(&mut self, pid: u32, signal: Signal) -> Result<(), SignalError> {
+        (**self).send_signal(pid, signal)
+    }
+
+    fn is_running(&mut self, pid: u32) -> bool {
+        (**self).is_running(pid)
+    }
+
+    fn send_thread_signal(
+        &mut self,
+        pid: u32,
+        tid: u32,
+        signal: Signal,
+    ) -> Result<(), SignalError> {
+        (**self).send_thread_signal(pid, tid, signal)
+    }
+
+    fn try_wait(&mut self, pid: u32) -> WaitResult {
+        (**self).try_wait(pid)
+    }
+}
+
+/// Number of processes at which [`System`] switches from one `kill(pid, 0)`
+/// per process to a single listing of `/proc` per poll. Per-PID checks cost a
+/// syscall each, while a scan costs roughly the same regardless of how many
+/// targets remain; see `benches/liveness.rs`.
+pub const PROC_SCAN_THRESHOLD: usize = 256;
+
+/// Controls real processes via `kill(2)`.
+///
+/// On Linux, liveness of large sets of processes (at least
+/// [`PROC_SCAN_THRESHOLD`]) is checked with a single scan of `/proc`.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct System;
+
+impl ProcessController for System {
+    fn send_signal(&mut self, pid: u32, signal: Signal) -> Result<(), SignalError> {
+        let nix_pid = Pid::from_raw(i32::try_from(pid).map_err(|_| SignalError::PidTooLarge)?);
+
+        match signal::kill(nix_pid, signal.to_nix()) {
+            Ok(()) => Ok(()),
+            Err(nix::errno::Errno::ESRCH) => Err(SignalError::NotFound),
+            Err(nix::errno::Errno::EPERM) => Err(SignalError::PermissionDenied),
+            Err(nix::errno::Errno::EAGAIN) => Err(SignalError::QueueFull),
+            Err(e) => Err(SignalError::Other(e.to_string())),
+        }
+    }
+
+    fn is_running(&mut self, pid: u32) -> bool {
+        let Ok(nix_pid) = i32::try_from(pid).map(Pid::from_raw) else {
+            return false; // PID too large, can't exist
+        };
+        // Send signal 0 to check if process exists. EPERM means it does, only
+        // that we may not signal it; only ESRCH means it is gone
+        match signal::kill(nix_pid, None) {
+            Ok(()) | Err(nix::errno::Errno::EPERM) => true,
+            Err(_) => false,
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    fn running_among(&mut self, pids: &[u32]) -> Option<HashSet<u32>> {
+        // /proc may belong to another PID namespace than ours (e.g. in a
+        // container with the host's /proc mounted), in which case its
+        // listing says nothing about the PIDs we signal.
+        let own_proc = std::fs::read_link("/proc/self")
+            .is_ok_and(|link| link.to_str() == Some(&std::process::id().to_string()));
+        if pids.len() < PROC_SCAN_THRESHOLD || !own_proc {
+            return None;
+        }
+        Some(crate::procfs::pids().ok()?.into_iter().collect())
+    }
+
+    #[cfg(target_os = "linux")]
+    fn send_thread_signal(
+        &mut self,
+        pid: u32,
+        tid: u32,
+        signal: Signal,
+    ) -> Result<(), SignalError> {
+        let process = i32::try_from(pid).map_err(|_| SignalError::PidTooLarge)?;
+        let thread = i32::try_from(tid).map_err(|_| SignalError::PidTooLarge)?;
+
+        // SAFETY: tgkill takes three integers and touches no memory of ours.
+        let result = unsafe {
+            libc::syscall(
+                libc::SYS_tgkill,
+                process,
+                thread,
+                signal.to_nix() as libc::c_int,
+            )
+        };
+        if result == 0 {
+            return Ok(());
+        }
+        match nix::errno::Errno::last() {
+            nix::errno::Errno::ESRCH => Err(SignalError::NotFound),
+            nix::errno::Errno::EPERM => Err(SignalError::PermissionDenied),
+            nix::errno::Errno::EAGAIN => Err(SignalError::QueueFull),
+            e => Err(SignalError::Other(e.to_string())),
+        }
+    }
+
+    fn try_wait(&mut self, pid: u32) -> WaitResult {
+        let Ok(nix_pid) = i32::try_from(pid).map(Pid::from_raw) else {
+            return WaitResult::NotChild;
+        };
+        match waitpid(nix_pid, Some(WaitPidFlag::WNOHANG)) {
+            Ok(WaitStatus::Exited(_, code)) => WaitResult::Exited(ExitStatus::Code(code)),
+            Ok(WaitStatus::Signaled(_, signal, _)) => {
+                WaitResult::Exited(ExitStatus::Signaled(signal as i32))
+            }
+            Ok(_) => WaitResult::Running,
+            // ECHILD: not one of ours
+            Err(_) => WaitResult::NotChild,
+        }
+    }
+}
@@ -0,0 +1,157 @@
+//! `--abort-with-core`: kill the targets still running after the grace
+//! period with SIGABRT, having made sure they may dump core, to get a core
+//! of a process that is stuck. A daemon is often started with a core size
+//! limit of 0, under which SIGABRT kills it without a core, so just before
+//! the signal each target's soft `RLIMIT_CORE` is raised with `prlimit`:
+//! to unlimited where allowed, else up to its hard limit.
+//!
+//! Changing another process's limits takes `CAP_SYS_RESOURCE`, or the same
+//! real, effective and saved user and group IDs as the target; without
+//! either, or with a hard limit of 0 and no `CAP_SYS_RESOURCE`, the target
+//! is sent SIGABRT all the same, with a warning that it may not dump core.
+//! The kernel can still refuse a core for other reasons, such as a
+//! `core_pattern` on a full or read-only file system, or a target that
+//! changed credentials at exec and isn't dumpable. The limit is left
+//! raised for a target that survives SIGABRT.
+
+use gracekill::log::{self, log};
+
+/// A limit as `ulimit -c` shows it.
+#[cfg(target_os = "linux")]
+fn describe(limit: libc::rlim_t) -> String {
+    if limit == libc::RLIM_INFINITY {
+        "unlimited".to_string()
+    } else {
+        format!("{limit} bytes")
+    }
+}
+
+/// Sets the core size limit of `pid` to `limit`.
+#[cfg(target_os = "linux")]
+fn set_limit(pid: u32, limit: libc::rlimit) -> std::io::Result<()> {
+    let pid = libc::pid_t::try_from(pid).map_err(|_| std::io::ErrorKind::InvalidInput)?;
+    // SAFETY: both pointers are to valid rlimit structs, or null
+    if unsafe {
+        libc::prlimit(
+            pid,
+            libc::RLIMIT_CORE,
+            &raw const limit,
+            std::ptr::null_mut(),
+        )
+    } == 0
+    {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error())
+    }
+}
+
+/// The core size limit of `pid`.
+#[cfg(target_os = "linux")]
+fn limit(pid: u32) -> std::io::Result<libc::rlimit> {
+    let pid = libc::pid_t::try_from(pid).map_err(|_| std::io::ErrorKind::InvalidInput)?;
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    // SAFETY: both pointers are to valid rlimit structs, or null
+    if unsafe { libc::prlimit(pid, libc::RLIMIT_CORE, std::ptr::null(), &raw mut limit) } == 0 {
+        Ok(limit)
+    } else {
+        Err(std::io::Error::last_os_error())
+    }
+}
+
+/// Raises the core size limit of `pid` as far as it can be, logging how
+/// it went.
+#[cfg(target_os = "linux")]
+fn allow_core(pid: u32) {
+    let old = match limit(pid) {
+        Ok(old) => old,
+        Err(e) => {
+            log::warn(&format!(
+                "Cannot read the core size limit of PID {pid}: {e}, it may not dump core"
+            ));
+            return;
+        }
+    };
+    if old.rlim_cur == libc::RLIM_INFINITY {
+        log::debug(&format!("PID {pid} already has no core size limit"));
+        return;
+    }
+    let unlimited = libc::rlimit {
+        rlim_cur: libc::RLIM_INFINITY,
+        rlim_max: libc::RLIM_INFINITY,
+    };
+    let to_hard = libc::rlimit {
+        rlim_cur: old.rlim_max,
+        rlim_max: old.rlim_max,
+    };
+    let raised = set_limit(pid, unlimited)
+        .map(|()| libc::RLIM_INFINITY)
+        .or_else(|e| match e.raw_os_error() {
+            Some(libc::EPERM) if old.rlim_max > old.rlim_cur => {
+                set_limit(pid, to_hard).map(|()| old.rlim_max)
+            }
+            _ => Err(e),
+        });
+    match raised {
+        Ok(new) => log(&format!(
+            "Raised the core size limit of PID {pid} from {} to {}",
+            describe(old.rlim_cur),
+            describe(new)
+        )),
+        Err(e) if old.rlim_cur == 0 => log::warn(&format!(
+            "Cannot raise the core size limit of PID {pid} from 0: {e}, it will not dump core"
+        )),
+        Err(e) => log::warn(&format!(
+            "Cannot raise the core size limit of PID {pid} above {}: {e}, its core may be cut short",
+            describe(old.rlim_cur)
+        )),
+    }
+}
+
+/// Logs where the kernel will put the cores, from `core_pattern`.
+#[cfg(target_os = "linux")]
+fn log_core_pattern() {
+    match std::fs::read_to_string("/proc/sys/kernel/core_pattern") {
+        Ok(pattern) if pattern.trim_end().starts_with('|') => log(&format!(
+            "Cores are piped to '{}'",
+            pattern.trim_end()[1..].trim_start()
+        )),
+        Ok(pattern) if pattern.starts_with('/') => {
+            log(&format!("Cores are written as '{}'", pattern.trim_end()));
+        }
+        Ok(pattern) => log(&format!(
+            "Cores are written as '{}', in each target's working directory",
+            pattern.trim_end()
+        )),
+        Err(e) => log::debug(&format!("Cannot read the core pattern: {e}")),
+    }
+}
+
+/// A callback for [`GraceKill::before_kill`](gracekill::GraceKill::before_kill)
+/// letting the targets about to be sent SIGABRT dump core.
+#[cfg(target_os = "linux")]
+pub fn hook() -> impl FnMut(&[u32]) + Send + 'static {
+    let mut logged = false;
+    move |pids| {
+        if !logged {
+            log_core_pattern();
+            logged = true;
+        }
+        for &pid in pids {
+            allow_core(pid);
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn hook() -> impl FnMut(&[u32]) + Send + 'static {
+    |pids| {
+        log::warn(&format!(
+            "Cannot raise the core size limit of {} process(es) on this platform, they only dump core if theirs allows",
+            pids.len()
+        ));
+    }
+}
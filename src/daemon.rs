@@ -0,0 +1,136 @@
+//! `--daemonize`: carry on in the background, detached from the terminal,
+//! so that a long drain started over SSH outlives the connection.
+//!
+//! gracekill forks once the targets are resolved, while the checks against
+//! its own session and ancestors still see the terminal it was started
+//! from. The parent prints the child's PID on stdout and exits; the child
+//! starts a new session, so the terminal closing sends it no SIGHUP, and
+//! writes everything it would have written to the terminal to
+//! `--log-file`, or nowhere without one. The working directory is kept, so
+//! a relative `--report` or `--trace-file` path means the same file.
+//!
+//! `--background-escalate` forks the same way, but later: just after the
+//! graceful signals are sent, so that the caller only waits for those,
+//! while the detached child waits out the grace period and sends the kill
+//! signal.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::os::fd::AsRawFd;
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use gracekill::log::{self, log};
+use nix::unistd::{ForkResult, fork, setsid};
+
+use crate::stdout;
+
+/// The files the background process reads and writes instead of the
+/// terminal: `/dev/null`, and `log_file` or `/dev/null`.
+fn background_files(log_file: Option<&str>) -> Result<(File, File), String> {
+    let output = match log_file {
+        Some(path) => OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| format!("Cannot open log file '{path}': {e}"))?,
+        None => OpenOptions::new()
+            .write(true)
+            .open("/dev/null")
+            .map_err(|e| format!("Cannot open /dev/null: {e}"))?,
+    };
+    let input = File::open("/dev/null").map_err(|e| format!("Cannot open /dev/null: {e}"))?;
+    Ok((input, output))
+}
+
+/// Starts a new session in the forked child and points its standard
+/// streams at `input` and `output`.
+fn detach(input: &File, output: &File) -> Result<(), String> {
+    setsid().map_err(|e| format!("Cannot start a new session: {e}"))?;
+    for (from, to) in [
+        (input.as_raw_fd(), libc::STDIN_FILENO),
+        (output.as_raw_fd(), libc::STDOUT_FILENO),
+        (output.as_raw_fd(), libc::STDERR_FILENO),
+    ] {
+        // SAFETY: both descriptors are open, and dup2 replaces `to` atomically
+        if unsafe { libc::dup2(from, to) } == -1 {
+            return Err(format!(
+                "Cannot redirect output to the log file: {}",
+                io::Error::last_os_error()
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Forks into the background, returning in the detached child. The parent
+/// exits here once it has printed the child's PID.
+pub fn daemonize(log_file: Option<&str>) -> Result<(), String> {
+    let (input, output) = background_files(log_file)?;
+    // Anything still buffered would otherwise be written by both processes
+    let _ = io::stdout().flush();
+
+    // SAFETY: the listener, tail and drain threads only start after this, so
+    // no other thread can be holding a lock the child would need
+    match unsafe { fork() } {
+        Ok(ForkResult::Parent { child }) => {
+            log(&format!(
+                "Continuing in the background as PID {child}, logging to {}",
+                log_file.unwrap_or("/dev/null")
+            ));
+            stdout::line(&child.to_string());
+            // Not finish(): the held log and the trace belong to the child now
+            process::exit(0);
+        }
+        Ok(ForkResult::Child) => {}
+        Err(e) => return Err(format!("Cannot fork into the background: {e}")),
+    }
+    detach(&input, &output)
+}
+
+/// Whether the wait has been handed off to a background process already,
+/// which later kills in the same run, such as the orphan cleanup, carry on
+/// in.
+static HANDED_OFF: AtomicBool = AtomicBool::new(false);
+
+/// A callback for [`GraceKill::after_signal`](gracekill::GraceKill::after_signal)
+/// for `--background-escalate`: once the graceful signals are out, forks,
+/// leaving the wait and the kill signal to the detached child, which logs
+/// to `log_file` as with `--daemonize`. The parent exits 0 once it has
+/// printed the child's PID. If there is nothing to wait for, or the fork
+/// fails, the kill carries on in the foreground.
+pub fn background_escalate(log_file: Option<String>) -> impl FnMut(&[u32]) + Send + 'static {
+    move |pids| {
+        if pids.is_empty() || HANDED_OFF.swap(true, Ordering::Relaxed) {
+            return;
+        }
+        let (input, output) = match background_files(log_file.as_deref()) {
+            Ok(files) => files,
+            Err(e) => {
+                log::warn(&format!("{e}, waiting in the foreground"));
+                return;
+            }
+        };
+        let _ = io::stdout().flush();
+        // SAFETY: --background-escalate can't be combined with --listen or
+        // --tail, whose threads are the only others running during a kill
+        match unsafe { fork() } {
+            Ok(ForkResult::Parent { child }) => {
+                log(&format!(
+                    "Signalled {} process(es), handing the wait and the kill off to background PID {child}, logging to {}",
+                    pids.len(),
+                    log_file.as_deref().unwrap_or("/dev/null")
+                ));
+                stdout::line(&child.to_string());
+                process::exit(0);
+            }
+            Ok(ForkResult::Child) => {
+                // Nowhere left to report a failure to
+                let _ = detach(&input, &output);
+            }
+            Err(e) => log::warn(&format!(
+                "Cannot fork into the background: {e}, waiting in the foreground"
+            )),
+        }
+    }
+}
@@ -0,0 +1,244 @@
+//! `--by`: a grace period given as the wall-clock time by which every
+//! target must be gone, e.g. `--by 02:00` or `--by 2026-03-01T02:00:00Z`.
+//! With `--escalate`, the time instead bounds the whole ladder.
+
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const DAY: i64 = 24 * 60 * 60;
+
+/// What to do with a `--by` time that has already passed.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum IfPast {
+    /// Escalate straight away, as with a zero grace period.
+    #[default]
+    Kill,
+    /// Refuse to run.
+    Error,
+}
+
+impl FromStr for IfPast {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "kill" => Ok(IfPast::Kill),
+            "error" => Ok(IfPast::Error),
+            _ => Err(format!(
+                "Unknown --if-past action: '{s}' (supported: kill, error)"
+            )),
+        }
+    }
+}
+
+/// What to do with `--escalate` waits that add up to more than the time
+/// left until `--by`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum OverBudget {
+    /// Refuse to run.
+    #[default]
+    Error,
+    /// Shorten the last wait so that the kill goes out at the `--by` time.
+    Clamp,
+}
+
+impl FromStr for OverBudget {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "error" => Ok(OverBudget::Error),
+            "clamp" => Ok(OverBudget::Clamp),
+            _ => Err(format!(
+                "Unknown --if-over-budget action: '{s}' (supported: error, clamp)"
+            )),
+        }
+    }
+}
+
+/// Parses a `--by` time: an RFC 3339 timestamp such as
+/// `2026-03-01T02:00:00+01:00`, or `HH:MM` in local time, taken as its next
+/// occurrence, so that `02:00` is later tonight at 23:00 and tomorrow night
+/// at 02:05. Only an RFC 3339 time can be in the past.
+pub fn parse_deadline(value: &str) -> Result<SystemTime, String> {
+    let seconds = if value.len() <= 5 {
+        next_local(value)?
+    } else {
+        parse_rfc3339(value)?
+    };
+    let since_epoch = Duration::from_secs(seconds.unsigned_abs());
+    if seconds >= 0 {
+        UNIX_EPOCH.checked_add(since_epoch)
+    } else {
+        UNIX_EPOCH.checked_sub(since_epoch)
+    }
+    .ok_or_else(|| format!("Invalid --by time: '{value}' (out of range)"))
+}
+
+/// How long until `deadline`, or `None` if it has passed.
+pub fn until(deadline: SystemTime) -> Option<Duration> {
+    deadline.duration_since(SystemTime::now()).ok()
+}
+
+/// `time` as `HH:MM:SS` in local time, or `?` if it can't be worked out.
+pub fn local_clock(time: SystemTime) -> String {
+    let Ok(seconds) = libc::time_t::try_from(
+        time.duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_secs()),
+    ) else {
+        return "?".to_string();
+    };
+    // SAFETY: localtime_r only reads seconds and writes the tm we own
+    unsafe {
+        let mut tm: libc::tm = std::mem::zeroed();
+        if libc::localtime_r(&raw const seconds, &raw mut tm).is_null() {
+            return "?".to_string();
+        }
+        format!("{:02}:{:02}:{:02}", tm.tm_hour, tm.tm_min, tm.tm_sec)
+    }
+}
+
+/// Parses `HH:MM` into the hour and minute.
+fn parse_clock(value: &str) -> Option<(i64, i64)> {
+    let (hour, minute) = value.split_once(':')?;
+    let field = |digits: &str, max: i64| {
+        Some(digits)
+            .filter(|digits| digits.len() == 2 && digits.bytes().all(|b| b.is_ascii_digit()))
+            .and_then(|digits| digits.parse::<i64>().ok())
+            .filter(|&n| n <= max)
+    };
+    Some((field(hour, 23)?, field(minute, 59)?))
+}
+
+/// The Unix time of the next occurrence of local `HH:MM`, later today or
+/// else tomorrow.
+fn next_local(value: &str) -> Result<i64, String> {
+    let (hour, minute) = parse_clock(value)
+        .ok_or_else(|| format!("Invalid --by time: '{value}' (expected HH:MM or RFC 3339)"))?;
+    let now = libc::time_t::try_from(
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_secs()),
+    )
+    .map_err(|_| "The system clock is out of range".to_string())?;
+    // SAFETY: localtime_r only reads now and writes the tm we own
+    let now_tm = unsafe {
+        let mut tm: libc::tm = std::mem::zeroed();
+        if libc::localtime_r(&raw const now, &raw mut tm).is_null() {
+            return Err("Cannot work out the local time".to_string());
+        }
+        tm
+    };
+    // HH:MM that many days from today, with mktime working out the date
+    // and whether it falls in summer time
+    let on_day = |days: i32| {
+        let mut tm = libc::tm {
+            tm_mday: now_tm.tm_mday + days,
+            tm_hour: i32::try_from(hour).unwrap_or(0),
+            tm_min: i32::try_from(minute).unwrap_or(0),
+            tm_sec: 0,
+            tm_isdst: -1,
+            ..now_tm
+        };
+        // SAFETY: mktime only touches the tm we own
+        match unsafe { libc::mktime(&raw mut tm) } {
+            -1 => Err(format!("Cannot work out the local time of '{value}'")),
+            #[allow(clippy::useless_conversion)] // time_t is narrower on some targets
+            time => Ok(i64::from(time)),
+        }
+    };
+    #[allow(clippy::useless_conversion)] // time_t is narrower on some targets
+    let now = i64::from(now);
+    let today = on_day(0)?;
+    if today > now { Ok(today) } else { on_day(1) }
+}
+
+/// Parses an RFC 3339 timestamp into Unix time, ignoring fractions of a
+/// second.
+fn parse_rfc3339(value: &str) -> Result<i64, String> {
+    let invalid = |why: &str| {
+        format!("Invalid --by time: '{value}' ({why}, expected e.g. 2026-03-01T02:00:00Z or HH:MM)")
+    };
+    let (date, rest) = value
+        .split_once(['T', 't', ' '])
+        .ok_or_else(|| invalid("no 'T' between date and time"))?;
+    let number = |digits: &str, what: &str| {
+        Some(digits)
+            .filter(|digits| !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()))
+            .and_then(|digits| digits.parse::<i64>().ok())
+            .ok_or_else(|| invalid(&format!("bad {what}")))
+    };
+
+    let mut fields = date.split('-');
+    let (Some(year), Some(month), Some(day), None) =
+        (fields.next(), fields.next(), fields.next(), fields.next())
+    else {
+        return Err(invalid("the date is not YYYY-MM-DD"));
+    };
+    if year.len() != 4 {
+        return Err(invalid("the year is not 4 digits"));
+    }
+    let (year, month, day) = (
+        number(year, "year")?,
+        number(month, "month")?,
+        number(day, "day")?,
+    );
+    if !(1..=12).contains(&month) || day < 1 || day > days_in_month(year, month) {
+        return Err(invalid("no such date"));
+    }
+
+    let offset_at = rest
+        .find(['Z', 'z', '+', '-'])
+        .ok_or_else(|| invalid("no time zone, add Z or an offset such as +01:00"))?;
+    let (time, zone) = rest.split_at(offset_at);
+    let time = time.split_once('.').map_or(time, |(whole, _)| whole);
+    let mut fields = time.split(':');
+    let (Some(hour), Some(minute), Some(second), None) =
+        (fields.next(), fields.next(), fields.next(), fields.next())
+    else {
+        return Err(invalid("the time is not HH:MM:SS"));
+    };
+    let (hour, minute, second) = (
+        number(hour, "hour")?,
+        number(minute, "minute")?,
+        number(second, "second")?,
+    );
+    if hour > 23 || minute > 59 || second > 60 {
+        return Err(invalid("no such time"));
+    }
+    let offset = match zone {
+        "Z" | "z" => 0,
+        _ => {
+            let (hours, minutes) =
+                parse_clock(&zone[1..]).ok_or_else(|| invalid("bad time zone offset"))?;
+            let offset = hours * 3600 + minutes * 60;
+            if zone.starts_with('-') {
+                -offset
+            } else {
+                offset
+            }
+        }
+    };
+
+    Ok(days_from_civil(year, month, day) * DAY + hour * 3600 + minute * 60 + second - offset)
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Days from 1970-01-01 to the given date, after Howard Hinnant's
+/// `days_from_civil`.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
@@ -0,0 +1,163 @@
+//! `--discover`: take the target and how to shut it down from a sidecar
+//! file the application writes next to its PID file, so that it declares
+//! its own shutdown contract:
+//!
+//! ```text
+//! # /run/web/shutdown.conf
+//! pid_file = web.pid
+//! signal = INT
+//! grace = 30s
+//! url = http://127.0.0.1:8080/quitquitquit
+//! ```
+//!
+//! Each non-blank line is `key = value`; lines starting with `#` are
+//! comments. The keys are:
+//!
+//! - `pid_file`: the PID file, relative to the sidecar's directory, or
+//! - `pid`: the PID itself; exactly one of the two is required;
+//! - `signal`, `kill_signal`: as `--signal` and `--kill-signal`;
+//! - `grace`: as `-g`, a duration or a number of seconds;
+//! - `url`, `url_method`, `url_wait`: as `--shutdown-url`,
+//!   `--shutdown-method` and `--shutdown-wait`.
+//!
+//! What the sidecar declares replaces the command-line setting; what it
+//! leaves out keeps it.
+
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use gracekill::Signal;
+use gracekill::log::{self, log};
+
+use crate::args::{Options, parse_and_validate_pid};
+use crate::duration::parse_duration;
+use crate::shutdown::Method;
+
+const KEYS: &str = "pid, pid_file, signal, kill_signal, grace, url, url_method, url_wait";
+
+/// The settings in a sidecar file.
+#[derive(Debug, Default)]
+struct Sidecar {
+    pid: Option<u32>,
+    pid_file: Option<String>,
+    signal: Option<Signal>,
+    kill_signal: Option<Signal>,
+    grace: Option<Duration>,
+    url: Option<String>,
+    url_method: Option<Method>,
+    url_wait: Option<Duration>,
+}
+
+/// Stores `value` in `slot`, refusing a key given twice.
+fn set<T>(slot: &mut Option<T>, value: T, key: &str) -> Result<(), String> {
+    if slot.is_some() {
+        return Err(format!("'{key}' is given more than once"));
+    }
+    *slot = Some(value);
+    Ok(())
+}
+
+fn parse(contents: &str) -> Result<Sidecar, String> {
+    let mut sidecar = Sidecar::default();
+    for (index, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        parse_line(&mut sidecar, line).map_err(|e| format!("line {}: {e}", index + 1))?;
+    }
+    if sidecar.pid.is_some() == sidecar.pid_file.is_some() {
+        return Err("exactly one of 'pid' and 'pid_file' is required".to_string());
+    }
+    Ok(sidecar)
+}
+
+fn parse_line(sidecar: &mut Sidecar, line: &str) -> Result<(), String> {
+    let (key, value) = line
+        .split_once('=')
+        .ok_or_else(|| format!("Expected key = value, got '{line}'"))?;
+    let (key, value) = (key.trim(), value.trim());
+    if value.is_empty() {
+        return Err(format!("'{key}' has no value"));
+    }
+    match key {
+        "pid" => set(&mut sidecar.pid, parse_and_validate_pid(value)?, key),
+        "pid_file" => set(&mut sidecar.pid_file, value.to_string(), key),
+        "signal" => set(&mut sidecar.signal, value.parse()?, key),
+        "kill_signal" => set(&mut sidecar.kill_signal, value.parse()?, key),
+        "grace" => set(&mut sidecar.grace, parse_duration(value)?, key),
+        "url" => set(&mut sidecar.url, value.to_string(), key),
+        "url_method" => set(&mut sidecar.url_method, value.parse()?, key),
+        "url_wait" => set(&mut sidecar.url_wait, parse_duration(value)?, key),
+        _ => Err(format!("Unknown key: '{key}' (supported: {KEYS})")),
+    }
+}
+
+/// Reads the PID from `pid_file`, relative to the directory of `sidecar`.
+fn read_pid_file(sidecar: &Path, pid_file: &str) -> Result<u32, String> {
+    let path = sidecar
+        .parent()
+        .unwrap_or_else(|| Path::new("."))
+        .join(pid_file);
+    let contents = fs::read_to_string(&path)
+        .map_err(|e| format!("Cannot read PID file '{}': {e}", path.display()))?;
+    parse_and_validate_pid(contents.trim())
+        .map_err(|e| format!("{e} in PID file '{}'", path.display()))
+}
+
+/// Reads the sidecar at `path`, adding its target to the PIDs and its
+/// settings to `options`.
+pub fn apply(path: &str, options: &mut Options) -> Result<(), String> {
+    let invalid = |e: String| format!("Invalid sidecar '{path}': {e}");
+    let contents =
+        fs::read_to_string(path).map_err(|e| format!("Cannot read sidecar '{path}': {e}"))?;
+    let sidecar = parse(&contents).map_err(invalid)?;
+    let pid = match (&sidecar.pid_file, sidecar.pid) {
+        (Some(pid_file), _) => read_pid_file(Path::new(path), pid_file).map_err(invalid)?,
+        (None, pid) => pid.unwrap_or_default(),
+    };
+
+    let mut declared = Vec::new();
+    if let Some(signal) = sidecar.signal {
+        declared.push(format!("signal {signal}"));
+        options.signal = signal;
+    }
+    if let Some(grace) = sidecar.grace {
+        declared.push(format!("{} grace period", log::duration(grace)));
+        options.grace_period = grace;
+    }
+    if let Some(kill_signal) = sidecar.kill_signal {
+        declared.push(format!("kill signal {kill_signal}"));
+        options.kill_signal = kill_signal;
+    }
+    if let Some(url) = sidecar.url {
+        declared.push(format!("shutdown URL {url}"));
+        options.shutdown_url = Some(url);
+    }
+    if let Some(method) = sidecar.url_method {
+        options.shutdown_method = method;
+    }
+    if let Some(wait) = sidecar.url_wait {
+        options.shutdown_wait = wait;
+    }
+    if options.shutdown_url.is_none()
+        && (sidecar.url_method.is_some() || sidecar.url_wait.is_some())
+    {
+        return Err(invalid(
+            "'url_method' and 'url_wait' only apply with a 'url'".to_string(),
+        ));
+    }
+    log(&format!(
+        "Sidecar '{path}' declares PID {pid}{}",
+        if declared.is_empty() {
+            String::new()
+        } else {
+            format!(" with {}", declared.join(", "))
+        }
+    ));
+    if !options.pids.contains(&pid) {
+        options.pids.push(pid);
+    }
+    Ok(())
+}
@@ -0,0 +1,108 @@
+//! `--drain-idle`: before signalling, wait for the targets to go quiet.
+//!
+//! There's no general way to ask a process whether it is still serving
+//! requests, so CPU time stands in for it: a target counts as idle once it
+//! has used next to no CPU (see [`IDLE_CPU_PERCENT`]) for the whole drain
+//! window, judged from the `utime` and `stime` fields of `/proc/<pid>/stat`.
+//! Signalling goes ahead once every target is idle, or once the cap runs out.
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use gracekill::log::{self, log};
+
+/// How long to wait for targets to go idle without `--drain-max`.
+pub const DEFAULT_CAP: Duration = Duration::from_mins(1);
+
+/// CPU use, as a percentage of one core, below which a target counts as
+/// idle. One clock tick is always allowed, so a process woken by a timer now
+/// and then still drains.
+pub const IDLE_CPU_PERCENT: u64 = 1;
+
+/// How often CPU time is sampled while draining.
+#[cfg(target_os = "linux")]
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(250);
+
+/// The start of a target's current quiet window: when it began and the CPU
+/// time, in clock ticks, used up to then.
+#[cfg(target_os = "linux")]
+struct Window {
+    pid: u32,
+    since: Instant,
+    ticks: u64,
+}
+
+/// Waits until every one of `pids` has been idle for `idle`, or until `cap`
+/// has passed, whichever comes first.
+#[cfg(target_os = "linux")]
+pub fn wait_idle(pids: &[u32], idle: Duration, cap: Duration) {
+    let ticks_per_second = gracekill::procfs::ticks_per_second();
+    let start = Instant::now();
+    let mut windows: Vec<Window> = pids
+        .iter()
+        .filter_map(|&pid| {
+            let ticks = cpu_ticks(pid)?;
+            Some(Window {
+                pid,
+                since: start,
+                ticks,
+            })
+        })
+        .collect();
+    log(&format!(
+        "Draining: waiting up to {} for {} process(es) to be idle for {}",
+        log::duration(cap),
+        windows.len(),
+        log::duration(idle)
+    ));
+
+    loop {
+        windows.retain_mut(|window| {
+            let Some(ticks) = cpu_ticks(window.pid) else {
+                log::debug(&format!("Process {} exited while draining", window.pid));
+                return false;
+            };
+            let elapsed_ms = u64::try_from(window.since.elapsed().as_millis()).unwrap_or(u64::MAX);
+            let allowance =
+                (elapsed_ms.saturating_mul(ticks_per_second * IDLE_CPU_PERCENT) / 100_000).max(1);
+            if ticks - window.ticks > allowance {
+                window.since = Instant::now();
+                window.ticks = ticks;
+            }
+            true
+        });
+        let busy = windows
+            .iter()
+            .filter(|window| window.since.elapsed() < idle)
+            .count();
+        if busy == 0 {
+            log(&format!(
+                "All processes idle for {} after {}, signalling",
+                log::duration(idle),
+                log::duration(start.elapsed())
+            ));
+            return;
+        }
+        if start.elapsed() >= cap {
+            log::warn(&format!(
+                "{busy} process(es) still busy after draining for {}, signalling anyway",
+                log::duration(cap)
+            ));
+            return;
+        }
+        log::debug(&format!("{busy} process(es) still busy"));
+        thread::sleep(SAMPLE_INTERVAL.min(cap.saturating_sub(start.elapsed())));
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn wait_idle(_pids: &[u32], _idle: Duration, _cap: Duration) {
+    log::warn("--drain-idle is only supported on Linux, signalling immediately");
+}
+
+/// Total CPU time `pid` has used, in clock ticks.
+#[cfg(target_os = "linux")]
+fn cpu_ticks(pid: u32) -> Option<u64> {
+    let stat = gracekill::procfs::stat(pid).ok()?;
+    Some(stat.utime + stat.stime)
+}
@@ -0,0 +1,130 @@
+//! What changed among the targets between choosing them and signalling
+//! them, for runs that choose them by `--name`, `--session`, `--holding`,
+//! `--port` or process group on a system where processes come and go.
+//!
+//! A target that exited in between is noticed when its graceful signal
+//! finds it gone, and logged as vanished before signal. With `--rescan`,
+//! the selectors are run again just before the signals go out, and the
+//! processes they find that weren't chosen the first time are logged as
+//! having appeared since; they are not signalled, not having been part of
+//! what was chosen. A line after the summary counts both:
+//!
+//! ```text
+//! Drift since selection: 1 vanished before signal (1234), 2 appeared after selection (5678, 5679)
+//! ```
+
+use std::sync::{Mutex, PoisonError};
+
+use gracekill::KillReport;
+use gracekill::log;
+
+use crate::args::Options;
+
+/// Every process the selectors found when the targets were chosen, before
+/// any was filtered out.
+static SELECTED: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+
+/// The processes `--rescan` found that weren't selected.
+static APPEARED: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+
+/// Records processes a selector found while the targets are chosen.
+pub fn note_selected(pids: &[u32]) {
+    let mut selected = SELECTED.lock().unwrap_or_else(PoisonError::into_inner);
+    selected.extend_from_slice(pids);
+}
+
+/// Whether `options` chose any targets with a selector `--rescan` can run
+/// again.
+pub fn has_selectors(options: &Options) -> bool {
+    !options.sessions.is_empty()
+        || !options.groups.is_empty()
+        || !options.names.is_empty()
+        || !options.holding.is_empty()
+        || !options.ports.is_empty()
+}
+
+/// What the selectors of `options` find now.
+#[cfg(target_os = "linux")]
+fn scan(options: &Options) -> Vec<u32> {
+    use gracekill::procfs;
+
+    let mut found = Vec::new();
+    for &sid in &options.sessions {
+        found.extend(procfs::processes_in_session(sid).unwrap_or_default());
+    }
+    for &pgid in &options.groups {
+        found.extend(procfs::processes_in_group(pgid).unwrap_or_default());
+    }
+    if !options.names.is_empty() {
+        found.extend(crate::processes_named(&options.names).unwrap_or_default());
+    }
+    for path in &options.holding {
+        if let Ok((holders, _)) = crate::processes_holding(path) {
+            found.extend(holders);
+        }
+    }
+    for &port in &options.ports {
+        if let Ok((holders, _)) = crate::port::processes_on(port, options.netns.as_deref()) {
+            found.extend(holders);
+        }
+    }
+    found
+}
+
+#[cfg(not(target_os = "linux"))]
+fn scan(_options: &Options) -> Vec<u32> {
+    Vec::new()
+}
+
+/// For `--rescan`, runs the selectors of `options` again and logs the
+/// processes they find that weren't selected, for the summary.
+pub fn rescan(options: &Options) {
+    let own = std::process::id();
+    let selected = SELECTED.lock().unwrap_or_else(PoisonError::into_inner);
+    let mut appeared: Vec<u32> = Vec::new();
+    for pid in scan(options) {
+        if pid != own && !selected.contains(&pid) && !appeared.contains(&pid) {
+            appeared.push(pid);
+        }
+    }
+    if appeared.is_empty() {
+        log::debug("Rescan found no processes appeared since selection");
+        return;
+    }
+    log::warn(&format!(
+        "{} process(es) appeared since the targets were selected, not signalling them: {}",
+        appeared.len(),
+        pid_list(&appeared)
+    ));
+    *APPEARED.lock().unwrap_or_else(PoisonError::into_inner) = appeared;
+}
+
+fn pid_list(pids: &[u32]) -> String {
+    pids.iter()
+        .map(u32::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Logs the drift line after the summary, if anything drifted.
+pub fn summarize(report: &KillReport) {
+    let appeared = APPEARED.lock().unwrap_or_else(PoisonError::into_inner);
+    let mut parts = Vec::new();
+    if !report.vanished.is_empty() {
+        parts.push(format!(
+            "{} vanished before signal ({})",
+            report.vanished.len(),
+            pid_list(&report.vanished)
+        ));
+    }
+    if !appeared.is_empty() {
+        parts.push(format!(
+            "{} appeared after selection ({})",
+            appeared.len(),
+            pid_list(&appeared)
+        ));
+    }
+    if !parts.is_empty() {
+        log::summary(&format!("Drift since selection: {}", parts.join(", ")));
+    }
+}
@@ -0,0 +1,179 @@
+//! `--dry-run`: show which targets a run would signal, and with what,
+//! without signalling anything. With `--simulate-timing` it also estimates
+//! when each target would exit or be killed, and how long the whole run
+//! would take, to help plan a maintenance window.
+//!
+//! The estimate is a fixed model of how each target handles the signal,
+//! read from `/proc/<pid>/status`, not a measurement:
+//!
+//! - a target that neither catches, blocks nor ignores the signal is taken
+//!   to exit as soon as it is sent;
+//! - one with a handler is taken to need [`HANDLER_ESTIMATE`] to shut down,
+//!   and to be killed if the grace period runs out first;
+//! - one that blocks or ignores the signal, or whose handling can't be
+//!   read, is taken to run until it is killed.
+//!
+//! The grace period starts once every signal is sent, which `--rate`
+//! spreads out. Only the first signal is modelled: escalation stages only
+//! add to the time before the kill.
+
+use std::time::Duration;
+
+use gracekill::log::{self, log};
+use gracekill::{ProcessController, Signal};
+
+use crate::args::Options;
+
+/// How long a target with a handler for the signal is taken to need to
+/// shut down.
+pub const HANDLER_ESTIMATE: Duration = Duration::from_secs(2);
+
+/// How a target handles the signal it would be sent.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Handling {
+    Default,
+    Caught,
+    Blocked,
+    Ignored,
+    Unknown,
+}
+
+/// Logs what the run described by `options` would do.
+pub fn run(options: &Options) {
+    let mut targets = Vec::new();
+    for &pid in &options.pids {
+        if !gracekill::System.is_running(pid) {
+            log(&format!(
+                "PID {pid} is not running, nothing would be sent to it"
+            ));
+            continue;
+        }
+        let signal = signal_for(pid, options);
+        log(&format!("Would send {signal} to {}", describe(pid)));
+        targets.push((pid, signal));
+    }
+    let kill_after = options
+        .stages
+        .iter()
+        .fold(options.grace_period, |sum, stage| {
+            sum.saturating_add(stage.grace)
+        });
+    log(&format!(
+        "Dry run: would send {} process(es) their signal, then {} to any still running after {}",
+        targets.len(),
+        options.kill_signal,
+        log::duration(kill_after)
+    ));
+    if options.simulate_timing {
+        estimate(&targets, kill_after, options);
+    }
+}
+
+/// Logs the estimated timeline of the run and its total duration.
+fn estimate(targets: &[(u32, Signal)], kill_after: Duration, options: &Options) {
+    let send_at = |index: usize| match options.rate {
+        Some(rate) if rate > 0 => {
+            Duration::from_secs(u64::try_from(index).unwrap_or(u64::MAX)) / rate
+        }
+        _ => Duration::ZERO,
+    };
+    let kill_at = send_at(targets.len().saturating_sub(1)).saturating_add(kill_after);
+
+    let mut events: Vec<(Duration, String, bool)> = targets
+        .iter()
+        .enumerate()
+        .map(|(index, &(pid, signal))| {
+            let sent = send_at(index);
+            let target = describe(pid);
+            match handling(pid, signal) {
+                Handling::Default => (
+                    sent,
+                    format!("{target} exits on {signal}, which it doesn't handle"),
+                    false,
+                ),
+                Handling::Caught if sent.saturating_add(HANDLER_ESTIMATE) <= kill_at => (
+                    sent.saturating_add(HANDLER_ESTIMATE),
+                    format!("{target} exits, having handled {signal}"),
+                    false,
+                ),
+                Handling::Caught => (
+                    kill_at,
+                    format!(
+                        "{target} is sent {}, still handling {signal}",
+                        options.kill_signal
+                    ),
+                    true,
+                ),
+                handling => {
+                    let reason = match handling {
+                        Handling::Blocked => format!("as it blocks {signal}"),
+                        Handling::Ignored => format!("as it ignores {signal}"),
+                        _ => format!("as how it handles {signal} can't be read"),
+                    };
+                    (
+                        kill_at,
+                        format!("{target} is sent {} {reason}", options.kill_signal),
+                        true,
+                    )
+                }
+            }
+        })
+        .collect();
+    events.sort_by_key(|&(at, _, _)| at);
+
+    log("Estimated timeline, from a model of how each target handles its signal:");
+    for (at, event, _) in &events {
+        log(&format!("  +{}: {event}", log::duration(*at)));
+    }
+    let killed = events.iter().filter(|(_, _, killed)| *killed).count();
+    let total = events
+        .iter()
+        .map(|(at, _, _)| *at)
+        .max()
+        .unwrap_or_default();
+    log(&format!(
+        "Estimated total duration: {} ({} exiting gracefully, {killed} killed). This is an estimate, not a measurement",
+        log::duration(total),
+        events.len() - killed
+    ));
+}
+
+fn signal_for(pid: u32, options: &Options) -> Signal {
+    options
+        .pid_signals
+        .iter()
+        .find(|&&(target, _)| target == pid)
+        .map_or(options.signal, |&(_, signal)| signal)
+}
+
+/// `PID 1234 (nginx)`, or just `PID 1234` if its name can't be read.
+fn describe(pid: u32) -> String {
+    #[cfg(target_os = "linux")]
+    if let Some(comm) = gracekill::procfs::comm(pid) {
+        return format!("PID {pid} ({comm})");
+    }
+    format!("PID {pid}")
+}
+
+#[cfg(target_os = "linux")]
+fn handling(pid: u32, signal: Signal) -> Handling {
+    use gracekill::procfs::{self, SignalMasks};
+
+    let Ok(masks) = procfs::signal_masks(pid) else {
+        return Handling::Unknown;
+    };
+    if SignalMasks::contains(masks.ignored, signal) {
+        Handling::Ignored
+    } else if SignalMasks::contains(masks.blocked, signal) {
+        Handling::Blocked
+    } else if SignalMasks::contains(masks.caught, signal) {
+        Handling::Caught
+    } else {
+        Handling::Default
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn handling(_pid: u32, _signal: Signal) -> Handling {
+    Handling::Unknown
+}
@@ -0,0 +1,107 @@
+//! `--dump-before-kill`: capture diagnostics from the targets that outlive
+//! the grace period, just before they are killed.
+//!
+//! The command is split on whitespace and run with the PID appended, as in
+//! `gcore` or `py-spy dump --pid`, once per survivor and all at the same
+//! time. Its stdout and stderr go to `gracekill-dump-<pid>.txt` in the
+//! working directory. A command still running after `--dump-timeout` is
+//! killed so that the kill itself is only ever delayed that long; how long
+//! it has left is in its `GRACEKILL_REMAINING_MS`, in milliseconds.
+
+use std::fs::File;
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use gracekill::log::{self, log};
+
+use crate::on_timeout;
+
+/// How long dump commands may run without `--dump-timeout`.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How often running dump commands are checked for completion.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// A callback for [`GraceKill::before_kill`](gracekill::GraceKill::before_kill)
+/// running `command` against each target about to be killed.
+pub fn dumper(command: &str, timeout: Duration) -> impl FnMut(&[u32]) + Send + 'static {
+    let command: Vec<String> = command.split_whitespace().map(String::from).collect();
+    move |pids| dump(&command, pids, timeout)
+}
+
+/// The file `pid`'s dump is written to.
+fn output_path(pid: u32) -> String {
+    format!("gracekill-dump-{pid}.txt")
+}
+
+fn dump(command: &[String], pids: &[u32], timeout: Duration) {
+    let Some((program, args)) = command.split_first() else {
+        return;
+    };
+    let deadline = Instant::now() + timeout;
+    let mut running: Vec<(u32, Child)> = pids
+        .iter()
+        .filter_map(|&pid| match spawn(program, args, pid, deadline) {
+            Ok(child) => Some((pid, child)),
+            Err(e) => {
+                log::warn(&format!("Failed to dump PID {pid}: {e}"));
+                None
+            }
+        })
+        .collect();
+    if !running.is_empty() {
+        log(&format!(
+            "Dumping {} process(es) with '{}' before killing them",
+            running.len(),
+            command.join(" ")
+        ));
+    }
+    while !running.is_empty() {
+        running.retain_mut(|(pid, child)| match child.try_wait() {
+            Ok(Some(status)) if status.success() => {
+                log(&format!("Dumped PID {pid} to {}", output_path(*pid)));
+                false
+            }
+            Ok(Some(status)) => {
+                log::warn(&format!(
+                    "Dump command for PID {pid} failed ({status}), see {}",
+                    output_path(*pid)
+                ));
+                false
+            }
+            Ok(None) => true,
+            Err(e) => {
+                log::warn(&format!("Failed to wait for the dump of PID {pid}: {e}"));
+                false
+            }
+        });
+        if Instant::now() >= deadline {
+            for (pid, child) in &mut running {
+                log::warn(&format!(
+                    "Dump command for PID {pid} still running after {}, stopping it",
+                    log::duration(timeout)
+                ));
+                let _ = child.kill();
+                let _ = child.wait();
+            }
+            return;
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+fn spawn(program: &str, args: &[String], pid: u32, deadline: Instant) -> Result<Child, String> {
+    let path = output_path(pid);
+    let output = File::create(&path).map_err(|e| format!("cannot create {path}: {e}"))?;
+    let errors = output.try_clone().map_err(|e| e.to_string())?;
+    Command::new(program)
+        .args(args)
+        .arg(pid.to_string())
+        .env("GRACEKILL_REMAINING_MS", on_timeout::remaining_ms(deadline))
+        .stdin(Stdio::null())
+        .stdout(output)
+        .stderr(errors)
+        .spawn()
+        .map_err(|e| format!("'{program}' could not be run: {e}"))
+}
@@ -0,0 +1,42 @@
+use std::time::Duration;
+
+/// The longest duration accepted, ten thousand years, so that the waits of
+/// a kill, however many there are, add up without overflowing.
+pub const MAX_DURATION: Duration = Duration::from_hours(10_000 * 365 * 24);
+
+/// `duration`, written as `value`, unless it is longer than
+/// [`MAX_DURATION`].
+pub fn bounded(duration: Duration, value: &str) -> Result<Duration, String> {
+    if duration > MAX_DURATION {
+        return Err(format!(
+            "Invalid duration: '{value}' (at most ten thousand years)"
+        ));
+    }
+    Ok(duration)
+}
+
+/// Parses a duration such as `10`, `10s`, `500ms`, `2m` or `1h`. A bare
+/// number is taken as seconds. At most [`MAX_DURATION`].
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+    let invalid = || format!("Invalid duration: '{value}'");
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number = number.parse::<u64>().map_err(|_| invalid())?;
+
+    let duration = match unit {
+        "" | "s" => Ok(Duration::from_secs(number)),
+        "ms" => Ok(Duration::from_millis(number)),
+        "m" => number
+            .checked_mul(60)
+            .map(Duration::from_secs)
+            .ok_or_else(invalid),
+        "h" => number
+            .checked_mul(3600)
+            .map(Duration::from_secs)
+            .ok_or_else(invalid),
+        _ => Err(invalid()),
+    }?;
+    bounded(duration, value)
+}
@@ -0,0 +1,245 @@
+//! `--emit-script`: write the kill as a standalone bash script instead of
+//! running it, for change management to review and archive. The script
+//! holds the targets as they were resolved and every signal and wait in
+//! order: the graceful signal, each escalation stage with its retries and
+//! jitter, and the kill signal for whatever is left, sparing the targets
+//! protected from it. It needs nothing but bash and `kill`, and exits 4,
+//! as gracekill would, if a target is still there a second after the kill.
+//!
+//! Only signals and waits are written, so features that do more than that
+//! during a run, such as `--drain-idle` or `--on-timeout`, have no part in
+//! it; the header lists the full command line it was made from.
+
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+use std::time::Duration;
+
+use gracekill::log::{self, log};
+use gracekill::{MAX_RETRIES, Signal};
+
+use crate::args::Options;
+use crate::commands::kill_name;
+use crate::shell_vars::quote;
+
+/// Polls `kill -0` for the targets still running, counting zombies as gone,
+/// and waits up to a number of milliseconds for them to exit.
+const HELPERS: &str = r#"# alive PID...: prints the PIDs still running.
+alive() {
+    local pid stat
+    for pid in "$@"; do
+        kill -0 "$pid" 2>/dev/null || continue
+        # A zombie has exited and only waits to be reaped (Linux)
+        stat=$(cat "/proc/$pid/stat" 2>/dev/null)
+        case ${stat##*) } in
+            Z*) continue ;;
+        esac
+        echo "$pid"
+    done
+}
+
+# wait_for MILLISECONDS PID...: waits up to MILLISECONDS for the PIDs to
+# exit, checking every 100ms.
+wait_for() {
+    local left=$1
+    shift
+    while [ "$left" -gt 0 ] && [ -n "$(alive "$@")" ]; do
+        sleep 0.1
+        left=$((left - 100))
+    done
+}
+"#;
+
+/// Whole milliseconds, as the script's waits count them.
+fn millis(duration: Duration) -> u128 {
+    duration.as_millis()
+}
+
+/// A wait of `grace` plus up to `jitter`, as a bash arithmetic expression.
+fn wait_expression(grace: Duration, jitter: Duration) -> String {
+    if jitter.is_zero() {
+        millis(grace).to_string()
+    } else {
+        // $RANDOM is 0 to 32767
+        format!(
+            "$(({} + RANDOM * {} / 32767))",
+            millis(grace),
+            millis(jitter)
+        )
+    }
+}
+
+/// The waits of a stage of `grace` with `retries`, up to [`MAX_RETRIES`] as
+/// for a kill, each followed by the signal again, and its jitter on the
+/// last.
+fn write_wait(
+    script: &mut String,
+    (grace, retries, jitter): (Duration, u32, Duration),
+    signal: Signal,
+    targets: &str,
+) {
+    let retries = retries.min(MAX_RETRIES);
+    let slice = grace / (retries + 1);
+    for retry in 1..=retries {
+        let _ = writeln!(script, "wait_for {} {targets}", millis(slice));
+        let _ = writeln!(
+            script,
+            "# Again ({retry}/{retries}) to the targets still running\nremaining=$(alive {targets})"
+        );
+        let _ = writeln!(
+            script,
+            "[ -n \"$remaining\" ] && kill -{} $remaining 2>/dev/null",
+            kill_name(signal)
+        );
+    }
+    let last = grace.saturating_sub(slice.saturating_mul(retries));
+    let _ = writeln!(
+        script,
+        "wait_for {} {targets}",
+        wait_expression(last, jitter)
+    );
+}
+
+/// A wait as the comments describe it, e.g. `up to 25s plus up to 1s of
+/// jitter for the targets to exit, sending the signal again 2 time(s) on
+/// the way`.
+fn describe_wait(grace: Duration, retries: u32, jitter: Duration) -> String {
+    let mut wait = format!("up to {}", log::duration(grace));
+    if !jitter.is_zero() {
+        let _ = write!(wait, " plus up to {} of jitter", log::duration(jitter));
+    }
+    wait.push_str(" for the targets to exit");
+    let retries = retries.min(MAX_RETRIES);
+    if retries > 0 {
+        let _ = write!(
+            wait,
+            ", sending the signal again {retries} time(s) on the way"
+        );
+    }
+    wait
+}
+
+/// The script for the kill `options` describe, sparing `protected` the kill
+/// signal.
+fn script(options: &Options, protected: &[u32]) -> String {
+    let targets = options
+        .pids
+        .iter()
+        .map(u32::to_string)
+        .collect::<Vec<_>>()
+        .join(" ");
+    let command = env::args()
+        .map(|arg| quote(&arg))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let mut script = String::new();
+    let _ = writeln!(script, "#!/usr/bin/env bash");
+    let _ = writeln!(
+        script,
+        "# Generated by gracekill {} without signalling anything. It replays the",
+        env!("CARGO_PKG_VERSION")
+    );
+    let _ = writeln!(script, "# signals and waits of:\n#\n#   {command}\n#");
+    let _ = writeln!(script, "# for these targets, as they were resolved:\n#");
+    for &pid in &options.pids {
+        let name = gracekill::procfs::comm(pid).unwrap_or_else(|| "?".to_string());
+        let _ = writeln!(script, "#   {pid:<8} {name}");
+    }
+    let _ = writeln!(
+        script,
+        "#\n# PIDs are reused once a process exits: check that they still belong to\n# the same processes before running it.\n\nset -u\n\n{HELPERS}\ntargets={}\n",
+        quote(&targets)
+    );
+
+    // The graceful signal, per target where one has its own
+    let retries = options.retries.max(u32::from(options.double_tap));
+    let _ = writeln!(
+        script,
+        "# {}, then {}",
+        options.signal,
+        describe_wait(options.grace_period, retries, options.jitter)
+    );
+    for &pid in &options.pids {
+        let signal = options
+            .pid_signals
+            .iter()
+            .find(|&&(target, _)| target == pid)
+            .map_or(options.signal, |&(_, signal)| signal);
+        let _ = writeln!(script, "kill -{} {pid} 2>/dev/null", kill_name(signal));
+    }
+    write_wait(
+        &mut script,
+        (options.grace_period, retries, options.jitter),
+        options.signal,
+        "$targets",
+    );
+
+    for stage in &options.stages {
+        let _ = writeln!(
+            script,
+            "\n# Escalate: {} to the targets still running, then {}\nremaining=$(alive $targets)\nif [ -n \"$remaining\" ]; then\n    kill -{} $remaining 2>/dev/null",
+            stage.signal,
+            describe_wait(stage.grace, stage.retries, stage.jitter),
+            kill_name(stage.signal)
+        );
+        let mut wait = String::new();
+        write_wait(
+            &mut wait,
+            (stage.grace, stage.retries, stage.jitter),
+            stage.signal,
+            "$remaining",
+        );
+        for line in wait.lines() {
+            let _ = writeln!(script, "    {line}");
+        }
+        let _ = writeln!(script, "fi");
+    }
+
+    let killable = options
+        .pids
+        .iter()
+        .filter(|pid| !protected.contains(pid))
+        .map(u32::to_string)
+        .collect::<Vec<_>>()
+        .join(" ");
+    let _ = writeln!(script, "\n# {} to whatever is left", options.kill_signal);
+    if !protected.is_empty() {
+        let protected = protected
+            .iter()
+            .map(u32::to_string)
+            .collect::<Vec<_>>()
+            .join(" ");
+        let _ = writeln!(
+            script,
+            "# Never to the protected PID(s) {protected}, which are left running"
+        );
+    }
+    let _ = writeln!(
+        script,
+        "remaining=$(alive {killable})\n[ -n \"$remaining\" ] && kill -{} $remaining 2>/dev/null\n",
+        kill_name(options.kill_signal)
+    );
+    let _ = writeln!(
+        script,
+        "# Still there a second later: stuck, protected or failed to kill\nif [ -n \"$remaining\" ]; then\n    sleep 1\nfi\nleft=$(alive $targets)\nif [ -n \"$left\" ]; then\n    echo \"Still running:\" $left >&2\n    exit 4\nfi"
+    );
+    script
+}
+
+/// Writes the script for `options` to `path`, executable, sparing
+/// `protected` the kill signal.
+pub fn write(path: &str, options: &Options, protected: &[u32]) -> Result<(), String> {
+    fs::write(path, script(options, protected))
+        .map_err(|e| format!("Cannot write script '{path}': {e}"))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o755))
+            .map_err(|e| format!("Cannot make script '{path}' executable: {e}"))?;
+    }
+    log(&format!(
+        "Wrote the kill of {} process(es) to '{path}' without signalling anything",
+        options.pids.len()
+    ));
+    Ok(())
+}
@@ -0,0 +1,70 @@
+//! `--enrich`: how much gracekill reads about each target from `/proc` on
+//! its own, as opposed to what options such as `--follow-daemon` or
+//! `--diagnose` need.
+//!
+//! At `off` liveness is only ever checked with `kill(pid, 0)`: no command
+//! names for `--report`, no zombie check when confirming kills and no
+//! single-scan liveness for large batches. `basic`, the default, does all of
+//! those. `full` also logs each target's state, owner, start time and
+//! command line before signalling it. See `benches/enrich.rs` for the cost
+//! of each level.
+
+use std::str::FromStr;
+
+/// The `--enrich` level.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Enrich {
+    Off,
+    #[default]
+    Basic,
+    Full,
+}
+
+impl FromStr for Enrich {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "off" => Ok(Enrich::Off),
+            "basic" => Ok(Enrich::Basic),
+            "full" => Ok(Enrich::Full),
+            _ => Err(format!(
+                "Unknown enrichment level: '{s}' (supported: off, basic, full)"
+            )),
+        }
+    }
+}
+
+/// Logs what `/proc` says about each of `pids`, for `--enrich full`.
+#[cfg(target_os = "linux")]
+pub fn describe(pids: &[u32]) {
+    use gracekill::log::log;
+    use gracekill::procfs;
+
+    let ticks_per_second = procfs::ticks_per_second();
+    for &pid in pids {
+        let Ok(stat) = procfs::stat(pid) else {
+            continue;
+        };
+        let owner = procfs::uid(pid).map_or_else(|_| "?".to_string(), |uid| uid.to_string());
+        let cmdline = procfs::cmdline(pid).map_or_else(String::new, |cmdline| {
+            String::from_utf8_lossy(&cmdline)
+                .split('\0')
+                .filter(|arg| !arg.is_empty())
+                .collect::<Vec<_>>()
+                .join(" ")
+        });
+        log(&format!(
+            "PID {pid} ({}): state {}, uid {owner}, started {}.{:02}s after boot, cmdline '{cmdline}'",
+            stat.comm,
+            stat.state,
+            stat.starttime / ticks_per_second,
+            stat.starttime % ticks_per_second * 100 / ticks_per_second
+        ));
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn describe(_pids: &[u32]) {
+    gracekill::log::warn("--enrich full is only supported on Linux");
+}
@@ -0,0 +1,130 @@
+//! `--error-format json`: errors as one JSON object per line on stderr, for
+//! wrappers that branch on the kind of failure:
+//!
+//! ```text
+//! {"error":"Invalid PID at argument 1: 'abc'","kind":"parse"}
+//! {"error":"Failed to send the graceful signal to PID 1234: Permission denied","kind":"permission","pid":1234}
+//! ```
+//!
+//! `kind` is one of [`Kind`]'s names and is stable across releases; the
+//! `error` text is for people and may change. Fatal errors replace the
+//! `Error:` line. Signals that fail are reported as they happen, and are
+//! still logged as usual.
+
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use gracekill::{Outcome, SignalError};
+
+use crate::json;
+
+/// Whether `--error-format json` is in effect.
+static JSON: AtomicBool = AtomicBool::new(false);
+
+/// The `--error-format`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ErrorFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl FromStr for ErrorFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "text" => Ok(ErrorFormat::Text),
+            "json" => Ok(ErrorFormat::Json),
+            _ => Err(format!(
+                "Unknown error format: '{s}' (supported: text, json)"
+            )),
+        }
+    }
+}
+
+/// What went wrong, as reported in `kind`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Kind {
+    /// The command line could not be parsed.
+    Parse,
+    /// The command line parsed but can't be carried out as given.
+    Usage,
+    /// A signal was refused for lack of permission.
+    Permission,
+    /// A target didn't exist.
+    NotFound,
+    /// A signal failed for another reason.
+    Signal,
+}
+
+impl Kind {
+    fn name(self) -> &'static str {
+        match self {
+            Kind::Parse => "parse",
+            Kind::Usage => "usage",
+            Kind::Permission => "permission",
+            Kind::NotFound => "not_found",
+            Kind::Signal => "signal",
+        }
+    }
+
+    fn of(error: &SignalError) -> Kind {
+        match error {
+            SignalError::NotFound => Kind::NotFound,
+            SignalError::PermissionDenied => Kind::Permission,
+            _ => Kind::Signal,
+        }
+    }
+}
+
+/// Switches error output to `format`.
+pub fn set(format: ErrorFormat) {
+    JSON.store(format == ErrorFormat::Json, Ordering::Relaxed);
+}
+
+/// Whether `args` ask for `--error-format json`, for errors found before
+/// they could be parsed.
+pub fn requested_in(args: &[String]) -> bool {
+    args.iter().enumerate().any(|(index, arg)| {
+        let value = match arg.strip_prefix("--error-format") {
+            Some("") => args.get(index + 1).map(String::as_str),
+            Some(inline) => inline.strip_prefix('='),
+            None => None,
+        };
+        value.is_some_and(|value| value.eq_ignore_ascii_case("json"))
+    })
+}
+
+/// Prints a fatal error.
+pub fn report(kind: Kind, message: &str) {
+    if JSON.load(Ordering::Relaxed) {
+        eprintln!(
+            "{{\"error\":{},\"kind\":\"{}\"}}",
+            json::quote(message),
+            kind.name()
+        );
+    } else {
+        eprintln!("Error: {message}");
+    }
+}
+
+/// A [`GraceKill::on_result`](gracekill::GraceKill::on_result) callback
+/// reporting each signal that fails, when errors are JSON.
+pub fn signal_failures() -> Option<impl FnMut(u32, &Outcome) + Send + 'static> {
+    JSON.load(Ordering::Relaxed)
+        .then_some(|pid, outcome: &Outcome| {
+            let (stage, error) = match outcome {
+                Outcome::SignalFailed(error) => ("graceful", error),
+                Outcome::KillFailed(error) => ("kill", error),
+                _ => return,
+            };
+            eprintln!(
+                "{{\"error\":{},\"kind\":\"{}\",\"pid\":{pid}}}",
+                json::quote(&format!(
+                    "Failed to send the {stage} signal to PID {pid}: {error}"
+                )),
+                Kind::of(error).name()
+            );
+        })
+}
@@ -0,0 +1,92 @@
+//! `--expect-exit-file PID=PATH`: confirm a clean shutdown with the exit
+//! code a service writes to a file as it exits, for services that aren't
+//! gracekill's children and so can't be waited for.
+//!
+//! Once the target has exited, PATH is read: `0` is a clean shutdown, any
+//! other code an unclean one, and the code is recorded as the target's exit
+//! status in the report. A file that is missing, doesn't hold a number, or
+//! hasn't changed since before the first signal (left over from an earlier
+//! run) leaves the shutdown unknown, which is logged but doesn't fail the
+//! run.
+
+use std::fs;
+use std::time::SystemTime;
+
+use gracekill::log::{self, log};
+use gracekill::{ExitStatus, KillReport, Outcome};
+
+/// The exit code files of the targets, with when each was last modified
+/// before the first signal.
+pub struct ExitFiles {
+    files: Vec<(u32, String, Option<SystemTime>)>,
+}
+
+fn modified(path: &str) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+impl ExitFiles {
+    /// Notes the state of each `(pid, path)` file before anything is
+    /// signalled.
+    pub fn note(files: &[(u32, String)]) -> Self {
+        ExitFiles {
+            files: files
+                .iter()
+                .map(|(pid, path)| (*pid, path.clone(), modified(path)))
+                .collect(),
+        }
+    }
+
+    /// Reads the exit code file of every target that has exited, logging
+    /// what it says and recording the code in `report`.
+    pub fn check(&self, report: &mut KillReport) {
+        for (pid, path, before) in &self.files {
+            let pid = *pid;
+            if !matches!(
+                report.outcome(pid),
+                Some(Outcome::Graceful | Outcome::Killed)
+            ) {
+                log::debug(&format!(
+                    "PID {pid} didn't exit, not reading its exit code from {path}"
+                ));
+                continue;
+            }
+            let contents = match fs::read_to_string(path) {
+                Ok(contents) => contents,
+                Err(e) => {
+                    log::warn(&format!(
+                        "PID {pid} left no exit code in {path} ({e}), its shutdown is unknown"
+                    ));
+                    continue;
+                }
+            };
+            if before.is_some() && modified(path) == *before {
+                log::warn(&format!(
+                    "{path} hasn't changed since before PID {pid} was signalled, its shutdown is unknown"
+                ));
+                continue;
+            }
+            let Ok(code) = contents.trim().parse::<i32>() else {
+                log::warn(&format!(
+                    "PID {pid} wrote '{}' to {path}, which isn't an exit code, its shutdown is unknown",
+                    contents.trim()
+                ));
+                continue;
+            };
+            if code == 0 {
+                log(&format!(
+                    "PID {pid} reported exit code 0 in {path}: a clean shutdown"
+                ));
+            } else {
+                log::warn(&format!(
+                    "PID {pid} reported exit code {code} in {path}: an unclean shutdown"
+                ));
+            }
+            // What the service itself reports wins over how it was reaped
+            report.exit_statuses.retain(|&(p, _)| p != pid);
+            report.exit_statuses.push((pid, ExitStatus::Code(code)));
+        }
+    }
+}
@@ -0,0 +1,187 @@
+//! `--fifo`: take kill commands from a named pipe for as long as gracekill
+//! runs, for drain controllers on the same host that want something
+//! lighter than `--listen`:
+//!
+//! ```text
+//! mkfifo /run/gracekill.fifo
+//! gracekill --fifo /run/gracekill.fifo &
+//! echo 'pids=1234,1235 grace=30s' > /run/gracekill.fifo
+//! echo 'pids=2001 signal=INT' > /run/gracekill.fifo
+//! ```
+//!
+//! Each line is a command in the batch file format, and starts a kill of
+//! its own straight away, alongside any still under way. A writer closing
+//! the pipe doesn't end anything: it is opened again for the next one.
+//! SIGINT or SIGTERM stops gracekill reading; the kills under way are seen
+//! through first.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read};
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::{FileTypeExt, OpenOptionsExt};
+use std::thread::{self, ScopedJoinHandle};
+
+use gracekill::KillReport;
+use gracekill::log::{self, log};
+
+use crate::batch::{Operation, parse_operation};
+use crate::watch;
+
+/// Opens the pipe without waiting for a writer. Reading it never blocks.
+fn open(path: &str) -> Result<File, String> {
+    OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_NONBLOCK)
+        .open(path)
+        .map_err(|e| format!("Cannot open FIFO '{path}': {e}"))
+}
+
+/// Waits up to [`watch::STOP_CHECK`] for `file` to have something to
+/// read, or for its writers to have gone.
+fn ready(file: &File) -> bool {
+    let mut pollfd = libc::pollfd {
+        fd: file.as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    let timeout = libc::c_int::try_from(watch::STOP_CHECK.as_millis()).unwrap_or(100);
+    // SAFETY: pollfd is a valid pollfd for an fd `file` keeps open.
+    // Interrupted by a signal, it returns -1 and the caller checks again.
+    unsafe { libc::poll(&raw mut pollfd, 1, timeout) > 0 }
+}
+
+/// The lines read so far and the commands they made.
+struct Commands<'a> {
+    path: &'a str,
+    received: usize,
+    partial: Vec<u8>,
+}
+
+impl Commands<'_> {
+    /// Takes `bytes` read from the pipe, returning the commands completed.
+    fn take(&mut self, bytes: &[u8]) -> Vec<Operation> {
+        self.partial.extend_from_slice(bytes);
+        let mut operations = Vec::new();
+        while let Some(end) = self.partial.iter().position(|&byte| byte == b'\n') {
+            let line: Vec<u8> = self.partial.drain(..=end).collect();
+            operations.extend(self.parse(&line));
+        }
+        operations
+    }
+
+    /// Takes what is left once a writer has gone, a last command without
+    /// its newline.
+    fn flush(&mut self) -> Option<Operation> {
+        let line = std::mem::take(&mut self.partial);
+        self.parse(&line)
+    }
+
+    fn parse(&mut self, line: &[u8]) -> Option<Operation> {
+        let line = String::from_utf8_lossy(line);
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            return None;
+        }
+        self.received += 1;
+        let number = self.received;
+        match parse_operation(number, line) {
+            Ok(mut operation) => {
+                operation.source = format!("command {number} on '{}'", self.path);
+                Some(operation)
+            }
+            Err(e) => {
+                log::warn(&format!(
+                    "Ignoring command {number} on '{}': {e}",
+                    self.path
+                ));
+                None
+            }
+        }
+    }
+}
+
+/// Reads commands from the FIFO at `path` and kills each with `kill`,
+/// until SIGINT or SIGTERM. Returns the report of each kill.
+pub fn run(
+    path: &str,
+    kill: impl Fn(&Operation) -> KillReport + Sync,
+) -> Result<Vec<KillReport>, String> {
+    let metadata = fs::metadata(path).map_err(|e| format!("Cannot open FIFO '{path}': {e}"))?;
+    if !metadata.file_type().is_fifo() {
+        return Err(format!(
+            "'{path}' is not a FIFO, create it with mkfifo first"
+        ));
+    }
+    watch::catch_stop_signals("--fifo")?;
+    let mut file = open(path)?;
+    log(&format!("Reading commands from FIFO '{path}'"));
+
+    let mut commands = Commands {
+        path,
+        received: 0,
+        partial: Vec::new(),
+    };
+    let kill = &kill;
+    thread::scope(|scope| {
+        let mut running: Vec<ScopedJoinHandle<KillReport>> = Vec::new();
+        let mut reports = Vec::new();
+        let mut buffer = [0; 4096];
+        while !watch::stopped() {
+            collect(&mut running, &mut reports, false);
+            if !ready(&file) {
+                continue;
+            }
+            match file.read(&mut buffer) {
+                Ok(0) => {
+                    // Every writer has gone; reopened, the pipe waits for
+                    // the next rather than reporting the last one gone
+                    if let Some(operation) = commands.flush() {
+                        running.push(scope.spawn(move || kill(&operation)));
+                    }
+                    log::debug(&format!("Writer closed FIFO '{path}', reopening it"));
+                    file = match open(path) {
+                        Ok(file) => file,
+                        Err(e) => {
+                            log::error(&e);
+                            break;
+                        }
+                    };
+                }
+                Ok(read) => {
+                    for operation in commands.take(&buffer[..read]) {
+                        running.push(scope.spawn(move || kill(&operation)));
+                    }
+                }
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted
+                    ) => {}
+                Err(e) => {
+                    log::error(&format!("Cannot read FIFO '{path}': {e}"));
+                    break;
+                }
+            }
+        }
+        log::debug("Stopped reading the FIFO");
+        collect(&mut running, &mut reports, true);
+        Ok(reports)
+    })
+}
+
+/// Moves the reports of the kills in `running` that are over, or of all of
+/// them once over with `wait`, to `reports`.
+fn collect(
+    running: &mut Vec<ScopedJoinHandle<'_, KillReport>>,
+    reports: &mut Vec<KillReport>,
+    wait: bool,
+) {
+    let mut index = 0;
+    while index < running.len() {
+        if wait || running[index].is_finished() {
+            reports.push(running.swap_remove(index).join().unwrap_or_default());
+        } else {
+            index += 1;
+        }
+    }
+}
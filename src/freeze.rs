@@ -0,0 +1,166 @@
+//! `--freeze`: pause the targets with the cgroup v2 freezer while their
+//! process trees are gathered and signalled, so that no target can fork a
+//! child out of reach of the kill.
+//!
+//! A frozen process can't act on a signal, so the cgroups are only frozen
+//! while the tree has to hold still:
+//!
+//! 1. every target's cgroup is frozen, and gracekill waits until the kernel
+//!    reports each one frozen;
+//! 2. every descendant of a target becomes a target too;
+//! 3. the graceful signal is sent, and stays pending;
+//! 4. the cgroups are thawed, so that targets can handle it during the
+//!    grace period;
+//! 5. just before the kill signal, which frozen processes still obey, the
+//!    cgroups are frozen again, and children that targets forked during
+//!    the grace period are sent the kill signal too;
+//! 6. the cgroups are thawed once the kill is over.
+//!
+//! Every target must be in a cgroup v2 cgroup other than the root one,
+//! which can't be frozen, and gracekill must not be in one of those cgroups
+//! itself.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use gracekill::log::{self, log};
+
+/// How long to wait for the kernel to report a cgroup frozen.
+const FREEZE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The cgroups of the targets.
+pub struct Cgroups(Vec<PathBuf>);
+
+/// Finds the cgroup of every one of `pids`, checking that each can be
+/// frozen without freezing gracekill.
+pub fn cgroups(pids: &[u32]) -> Result<Cgroups, String> {
+    let mount = cgroup2_mount().ok_or_else(|| {
+        "--freeze needs the cgroup v2 hierarchy, which is not mounted".to_string()
+    })?;
+    let own = cgroup_path(std::process::id())?;
+    let mut cgroups = Vec::new();
+    for &pid in pids {
+        let path = cgroup_path(pid)?;
+        if path == "/" {
+            return Err(format!(
+                "PID {pid} is in the root cgroup, which can't be frozen. --freeze needs targets in a cgroup v2 cgroup of their own"
+            ));
+        }
+        if own == path || own.starts_with(&format!("{path}/")) {
+            return Err(format!(
+                "gracekill is in the cgroup of PID {pid}, {path}, and freezing it would freeze gracekill too"
+            ));
+        }
+        let cgroup = mount.join(path.trim_start_matches('/'));
+        if !cgroup.join("cgroup.freeze").exists() {
+            return Err(format!(
+                "PID {pid} is not in a freezable cgroup: {} has no cgroup.freeze",
+                cgroup.display()
+            ));
+        }
+        if !cgroups.contains(&cgroup) {
+            cgroups.push(cgroup);
+        }
+    }
+    Ok(Cgroups(cgroups))
+}
+
+impl Cgroups {
+    /// How many cgroups there are.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Freezes every cgroup and waits until the kernel reports them frozen,
+    /// for at most [`FREEZE_TIMEOUT`].
+    pub fn freeze(&self) -> Result<(), String> {
+        for cgroup in &self.0 {
+            write_freeze(cgroup, "1")?;
+        }
+        let deadline = Instant::now() + FREEZE_TIMEOUT;
+        let mut pending: Vec<&PathBuf> = self.0.iter().collect();
+        while !pending.is_empty() {
+            pending.retain(|cgroup| !is_frozen(cgroup));
+            if Instant::now() >= deadline {
+                for cgroup in pending {
+                    log::warn(&format!(
+                        "{} is not frozen yet after {}, carrying on",
+                        cgroup.display(),
+                        log::duration(FREEZE_TIMEOUT)
+                    ));
+                }
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        Ok(())
+    }
+
+    /// Thaws every cgroup, warning about any that can't be.
+    pub fn thaw(&self) {
+        for cgroup in &self.0 {
+            if let Err(e) = write_freeze(cgroup, "0") {
+                log::warn(&e);
+            }
+        }
+        log::debug(&format!("Thawed {} cgroup(s)", self.0.len()));
+    }
+}
+
+/// Freezes the cgroups of `pids`, and returns them with every descendant of
+/// theirs, which can no longer change until the cgroups are thawed.
+pub fn freeze_tree(cgroups: &Cgroups, pids: &[u32]) -> Result<Vec<u32>, String> {
+    cgroups.freeze()?;
+    let mut tree = pids.to_vec();
+    for &pid in pids {
+        for descendant in gracekill::procfs::descendants(pid) {
+            if !tree.contains(&descendant) {
+                tree.push(descendant);
+            }
+        }
+    }
+    log(&format!(
+        "Froze {} cgroup(s), adding {} descendant(s) of the targets",
+        cgroups.len(),
+        tree.len() - pids.len()
+    ));
+    Ok(tree)
+}
+
+fn write_freeze(cgroup: &Path, value: &str) -> Result<(), String> {
+    fs::write(cgroup.join("cgroup.freeze"), value).map_err(|e| {
+        let action = if value == "1" { "freeze" } else { "thaw" };
+        format!("Cannot {action} {}: {e}", cgroup.display())
+    })
+}
+
+fn is_frozen(cgroup: &Path) -> bool {
+    fs::read_to_string(cgroup.join("cgroup.events"))
+        .is_ok_and(|events| events.lines().any(|line| line == "frozen 1"))
+}
+
+/// The cgroup v2 path of `pid`, e.g. `/system.slice/nginx.service`, from
+/// the `0::` line of `/proc/<pid>/cgroup`.
+fn cgroup_path(pid: u32) -> Result<String, String> {
+    let contents = fs::read_to_string(format!("/proc/{pid}/cgroup"))
+        .map_err(|e| format!("Cannot read the cgroup of PID {pid}: {e}"))?;
+    contents
+        .lines()
+        .find_map(|line| line.strip_prefix("0::"))
+        .map(str::to_string)
+        .ok_or_else(|| format!("PID {pid} is in no cgroup v2 cgroup, which --freeze needs"))
+}
+
+/// Where the cgroup v2 hierarchy is mounted, from `/proc/self/mountinfo`.
+fn cgroup2_mount() -> Option<PathBuf> {
+    let mountinfo = fs::read_to_string("/proc/self/mountinfo").ok()?;
+    mountinfo.lines().find_map(|line| {
+        let (mount, filesystem) = line.split_once(" - ")?;
+        if filesystem.split_whitespace().next()? != "cgroup2" {
+            return None;
+        }
+        mount.split_whitespace().nth(4).map(PathBuf::from)
+    })
+}
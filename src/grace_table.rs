@@ -0,0 +1,144 @@
+//! `--grace-table`: once the targets are resolved, and before anything is
+//! signalled, print one row per target with its command, the signal it
+//! would be sent, its grace period and when it would be killed if still
+//! running. The rows are read from the kills that would run, so a
+//! `--policy` or `--grace-from-env` target shows its own grace period,
+//! within `--min-grace` and `--max-grace`, and the targets come in the
+//! order `--shuffle` and `--tree-order` would signal them. The run then
+//! stops, unless `--proceed` is given too.
+//!
+//! ```text
+//! PID   COMMAND  SIGNAL   GRACE  KILL AT
+//! 1234  nginx    SIGQUIT  60s    14:03:25 (+60s)
+//! 1240  worker   SIGTERM  25s    14:02:50 (+25s)
+//! ```
+//!
+//! The grace period includes any escalation stages, and the kill time the
+//! spread of `--rate` and the most `--jitter` could add.
+
+use std::time::{Duration, SystemTime};
+
+use gracekill::log;
+use gracekill::{GraceKill, ProcessController, Stage};
+
+use crate::args::Options;
+use crate::batch::Operation;
+use crate::commands::Commands;
+use crate::deadline::local_clock;
+use crate::stdout;
+
+/// One target's row: its PID, command, signal, grace period and kill time.
+type Row = [String; 5];
+
+/// The kills that would run, each with its targets in the order they would
+/// be signalled: one per operation if `operations` group the targets, or
+/// else the one of the command line.
+fn kills(
+    options: &Options,
+    operations: Option<&[Operation]>,
+) -> Vec<(Vec<u32>, GraceKill<Commands>)> {
+    let Some(operations) = operations else {
+        let (pids, _) = crate::signal_order(&options.pids, options);
+        return vec![(pids, crate::kill_for(&options.pids, options))];
+    };
+    operations
+        .iter()
+        .map(|operation| {
+            let grace = crate::operation_grace(operation, options);
+            let (pids, _) = crate::signal_order(&operation.pids, options);
+            (pids, crate::operation_kill(operation, grace, options))
+        })
+        .collect()
+}
+
+/// The rows for every target, in the order they would be signalled.
+fn rows(options: &Options, operations: Option<&[Operation]>) -> Vec<Row> {
+    let now = SystemTime::now();
+    let kills = kills(options, operations);
+    let targets: Vec<(u32, &GraceKill<Commands>)> = kills
+        .iter()
+        .flat_map(|(pids, kill)| pids.iter().map(move |&pid| (pid, kill)))
+        .collect();
+    let send_at = |index: usize| match options.rate {
+        Some(rate) if rate > 0 => {
+            Duration::from_secs(u64::try_from(index).unwrap_or(u64::MAX)) / rate
+        }
+        _ => Duration::ZERO,
+    };
+    // The grace period starts once every signal is sent, or with
+    // --per-wave-grace, once each target's is
+    let last_sent = send_at(targets.len().saturating_sub(1));
+    targets
+        .iter()
+        .enumerate()
+        .map(|(index, &(pid, kill))| {
+            let stages = kill.stages();
+            let total = |wait: fn(&Stage) -> Duration| {
+                stages
+                    .iter()
+                    .map(wait)
+                    .fold(Duration::ZERO, Duration::saturating_add)
+            };
+            let (grace, jitter) = (total(|stage| stage.grace), total(|stage| stage.jitter));
+            let command = gracekill::procfs::comm(pid).unwrap_or_else(|| "?".to_string());
+            let grace_cell = match options.cpu_time_grace {
+                Some(budget) => format!("{} CPU", log::duration(budget)),
+                None => log::duration(grace),
+            };
+            let kill_at = if !gracekill::System.is_running(pid) {
+                "not running".to_string()
+            } else if !kill.kills(pid) {
+                "never (protected)".to_string()
+            } else if options.cpu_time_grace.is_some() {
+                // Depends on how fast the target uses CPU time
+                "-".to_string()
+            } else {
+                let sent = if options.per_wave_grace {
+                    send_at(index)
+                } else {
+                    last_sent
+                };
+                let after = sent.saturating_add(grace).saturating_add(jitter);
+                let by = if jitter.is_zero() { "" } else { "by " };
+                match now.checked_add(after) {
+                    Some(at) => format!("{by}{} (+{})", local_clock(at), log::duration(after)),
+                    None => format!("+{}", log::duration(after)),
+                }
+            };
+            [
+                pid.to_string(),
+                command,
+                kill.signal_of(pid).to_string(),
+                grace_cell,
+                kill_at,
+            ]
+        })
+        .collect()
+}
+
+/// Prints the table for the kill `options` describe, with the targets
+/// grouped into `operations` if they have grace periods of their own.
+pub fn print(options: &Options, operations: Option<&[Operation]>) {
+    let header = ["PID", "COMMAND", "SIGNAL", "GRACE", "KILL AT"].map(str::to_string);
+    let rows = rows(options, operations);
+    let mut widths = header.clone().map(|cell| cell.len());
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+    for row in std::iter::once(&header).chain(&rows) {
+        let line = row
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{cell:<width$}"))
+            .collect::<Vec<_>>()
+            .join("  ");
+        stdout::line(line.trim_end());
+    }
+    log::debug(&format!(
+        "Printed the grace table for {} process(es), {} signalling them",
+        rows.len(),
+        if options.proceed { "now" } else { "not" }
+    ));
+}
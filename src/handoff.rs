@@ -0,0 +1,61 @@
+//! `--handoff`: leave the kill signal to a separate, long-lived reaper, so
+//! that gracekill only does the graceful part.
+//!
+//! The reaper listens on a Unix stream socket and speaks a one-way line
+//! protocol:
+//!
+//! 1. gracekill connects when it starts, before any signal is sent, so that
+//!    a reaper that isn't listening is found while nothing has been touched;
+//! 2. once the grace period and any escalation stages are over, it writes
+//!    the PID of every target still running, in decimal, one per line, each
+//!    ended by `\n`;
+//! 3. it closes the connection, and exits.
+//!
+//! A connection closed without a line means every target exited. The
+//! reaper sends nothing back; enforcing the kill from there is up to it.
+//! Targets protected with `--forbid-signal-to` or `--protect-file` are
+//! never handed off.
+
+use std::fmt::Write as _;
+use std::io::Write;
+use std::os::unix::net::UnixStream;
+use std::time::Duration;
+
+/// How long writing the survivors may take.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A connection to the reaper.
+pub struct Handoff {
+    socket: String,
+    stream: UnixStream,
+}
+
+/// Connects to the reaper listening on `socket`.
+pub fn connect(socket: &str) -> Result<Handoff, String> {
+    let stream = UnixStream::connect(socket)
+        .map_err(|e| format!("Cannot connect to the reaper at {socket} for --handoff: {e}"))?;
+    stream
+        .set_write_timeout(Some(TIMEOUT))
+        .map_err(|e| e.to_string())?;
+    Ok(Handoff {
+        socket: socket.to_string(),
+        stream,
+    })
+}
+
+impl Handoff {
+    /// Hands `pids` off to the reaper and closes the connection.
+    pub fn send(mut self, pids: &[u32]) -> Result<(), String> {
+        let lines: String = pids.iter().fold(String::new(), |mut lines, pid| {
+            let _ = writeln!(lines, "{pid}");
+            lines
+        });
+        self.stream
+            .write_all(lines.as_bytes())
+            .map_err(|e| format!("Cannot hand off to the reaper at {}: {e}", self.socket))
+    }
+
+    pub fn socket(&self) -> &str {
+        &self.socket
+    }
+}
@@ -0,0 +1,2250 @@
+//! Gracefully terminate processes: send a polite signal, wait for a grace
+//! period, then escalate to a forceful one for anything still running.
+//!
+//! ```no_run
+//! use std::time::Duration;
+//! use gracekill::GraceKill;
+//!
+//! let report = GraceKill::new()
+//!     .grace(Duration::from_secs(10))
+//!     .run(&[1234, 5678]);
+//! println!("{} exited gracefully, {} killed", report.graceful(), report.killed());
+//! ```
+
+#![warn(clippy::all, clippy::pedantic, clippy::cargo)]
+
+mod cancel;
+pub mod children;
+mod controller;
+pub mod log;
+#[cfg(target_os = "linux")]
+pub mod procfs;
+mod report;
+mod signal;
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::mpsc::Receiver;
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub use cancel::CancelToken;
+pub use controller::{PROC_SCAN_THRESHOLD, ProcessController, SignalError, System, WaitResult};
+pub use report::{ExitStatus, KillReport, Outcome, Snapshot, Survival, Tally};
+pub use signal::Signal;
+
+use log::log;
+
+/// Grace period used when none is configured.
+pub const DEFAULT_GRACE: Duration = Duration::from_secs(25);
+
+/// How often target processes are checked during the grace period by default.
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// With [`PollSchedule::Curve`], the longest wait between polls, in poll
+/// intervals.
+pub const CURVE_SLOWEST: u32 = 5;
+
+/// With [`PollSchedule::Curve`], the wait before each poll is this fraction
+/// of the time left until the deadline, within its bounds.
+const CURVE_FRACTION: u32 = 8;
+
+/// The most times a signal is sent again during one wait, however many
+/// [retries](GraceKill::retries) are asked for: polls are rarely more
+/// frequent than that anyway.
+pub const MAX_RETRIES: u32 = 100;
+
+/// How many times to retry a signal the kernel couldn't queue (`EAGAIN`).
+const QUEUE_FULL_RETRIES: u32 = 3;
+
+/// Delay between retries of a signal the kernel couldn't queue.
+const QUEUE_FULL_BACKOFF: Duration = Duration::from_millis(10);
+
+/// How long [`GraceKill::kill_concurrency`] pauses between bursts of kill
+/// signals.
+pub const KILL_BURST_PAUSE: Duration = Duration::from_millis(100);
+
+/// Upper bound on waiting for a killed child to be reaped.
+const REAP_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How long after a signal [`GraceKill::diagnose`] waits before checking
+/// whether targets are ignoring it.
+const DIAGNOSE_WINDOW: Duration = Duration::from_millis(500);
+
+/// How long a target may go without progress before
+/// [`GraceKill::extend_if_progressing`] stops waiting for it.
+#[cfg(target_os = "linux")]
+const PROGRESS_WINDOW: Duration = Duration::from_secs(1);
+
+/// How long to keep checking that killed processes are really gone. A
+/// process in uninterruptible sleep doesn't die until it wakes up.
+const VERIFY_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How long to keep checking for killed processes still writing a core
+/// dump, which for a big process can take much longer than
+/// [`VERIFY_TIMEOUT`].
+const CORE_DUMP_TIMEOUT: Duration = Duration::from_mins(1);
+
+/// Whether `pid` has exited but not been reaped yet.
+#[cfg(target_os = "linux")]
+fn is_zombie(pid: u32) -> bool {
+    procfs::stat(pid).is_ok_and(|stat| stat.state == 'Z')
+}
+
+#[cfg(not(target_os = "linux"))]
+fn is_zombie(_pid: u32) -> bool {
+    false
+}
+
+/// The start time of each of `pids` that can be read, to tell them from a
+/// later process given the same PID.
+#[cfg(target_os = "linux")]
+fn start_times(pids: &[u32]) -> HashMap<u32, u64> {
+    pids.iter()
+        .filter_map(|&pid| procfs::stat(pid).ok().map(|stat| (pid, stat.starttime)))
+        .collect()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn start_times(_pids: &[u32]) -> HashMap<u32, u64> {
+    HashMap::new()
+}
+
+/// Whether `pid` now belongs to another process than the one that started
+/// at `started`.
+#[cfg(target_os = "linux")]
+fn is_reused(pid: u32, started: Option<&u64>) -> bool {
+    started.is_some_and(|&started| procfs::stat(pid).is_ok_and(|stat| stat.starttime != started))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn is_reused(_pid: u32, _started: Option<&u64>) -> bool {
+    false
+}
+
+/// Whether `pid` is in the middle of dumping core.
+#[cfg(target_os = "linux")]
+fn is_dumping_core(pid: u32) -> bool {
+    procfs::stat(pid).is_ok_and(|stat| stat.dumping_core())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn is_dumping_core(_pid: u32) -> bool {
+    false
+}
+
+/// Why `pid` is already on its way out, if it is, and whether it has
+/// exited already, leaving only a zombie or a dead process behind.
+#[cfg(target_os = "linux")]
+fn exiting(pid: u32) -> Option<(&'static str, bool)> {
+    let stat = procfs::stat(pid).ok()?;
+    let reason = stat.exiting()?;
+    Some((reason, matches!(stat.state, 'Z' | 'X' | 'x')))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn exiting(_pid: u32) -> Option<(&'static str, bool)> {
+    None
+}
+
+/// A [`Snapshot`] of `pid`, or `None` if it exited before every part of it
+/// could be read. The stat file is read again last, so that a PID reused
+/// in the meantime isn't taken for the same process.
+#[cfg(target_os = "linux")]
+fn snapshot(pid: u32) -> Option<Snapshot> {
+    let stat = procfs::stat(pid).ok()?;
+    let uid = procfs::uid(pid).ok()?;
+    let command = procfs::cmdline(pid).map_or_else(
+        || format!("[{}]", stat.comm),
+        |cmdline| {
+            String::from_utf8_lossy(&cmdline)
+                .split('\0')
+                .filter(|arg| !arg.is_empty())
+                .collect::<Vec<_>>()
+                .join(" ")
+        },
+    );
+    let rss = procfs::rss(pid).ok()?;
+    if procfs::stat(pid).ok()?.starttime != stat.starttime {
+        return None;
+    }
+    let ticks_per_second = procfs::ticks_per_second();
+    Some(Snapshot {
+        pid,
+        command,
+        uid,
+        state: stat.state,
+        start_time: Duration::from_secs(stat.starttime / ticks_per_second)
+            + Duration::from_nanos(
+                stat.starttime % ticks_per_second * 1_000_000_000 / ticks_per_second,
+            ),
+        rss,
+    })
+}
+
+/// A [`Snapshot`] of every one of `pids` that is still there.
+fn take_snapshots(pids: &[u32]) -> Vec<Snapshot> {
+    pids.iter()
+        .filter_map(|&pid| {
+            let snapshot = snapshot(pid);
+            if snapshot.is_none() {
+                log::debug(&format!("PID {pid} is gone, no snapshot taken"));
+            }
+            snapshot
+        })
+        .collect()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn snapshot(_pid: u32) -> Option<Snapshot> {
+    None
+}
+
+/// One step of a kill: a signal, how long to wait for the targets to exit
+/// after it, how many times to send it again in the meantime, and how much
+/// random time may be added to the wait.
+///
+/// See [`GraceKill::stage`] for the graceful signal and
+/// [`GraceKill::escalate`] for the stages after it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Stage {
+    pub signal: Signal,
+    /// How long to wait after the signal before moving on.
+    pub grace: Duration,
+    /// How many more times to send the signal to targets still running,
+    /// evenly spread over the wait.
+    pub retries: u32,
+    /// The most random time added to the wait, so that many runs started at
+    /// once don't all escalate in the same instant.
+    pub jitter: Duration,
+}
+
+impl Stage {
+    /// A stage sending `signal` once and waiting exactly `grace`.
+    #[must_use]
+    pub fn new(signal: Signal, grace: Duration) -> Self {
+        Self {
+            signal,
+            grace,
+            retries: 0,
+            jitter: Duration::ZERO,
+        }
+    }
+}
+
+impl From<(Signal, Duration)> for Stage {
+    fn from((signal, grace): (Signal, Duration)) -> Self {
+        Self::new(signal, grace)
+    }
+}
+
+/// `wait` plus a random part of `jitter`.
+fn jittered(wait: Duration, jitter: Duration) -> Duration {
+    if jitter.is_zero() {
+        return wait;
+    }
+    let random = std::collections::hash_map::RandomState::new()
+        .build_hasher()
+        .finish();
+    let nanos = u64::try_from(jitter.as_nanos()).unwrap_or(u64::MAX);
+    let extra = Duration::from_nanos(random % nanos.saturating_add(1));
+    log::debug(&format!(
+        "Adding {} of jitter to a {} wait",
+        log::duration(extra),
+        log::duration(wait)
+    ));
+    wait.saturating_add(extra)
+}
+
+/// What [`GraceKill::require_handler`] does with a target that has no
+/// handler installed for its graceful signal.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MissingHandler {
+    /// Log a warning and send the signal anyway.
+    Warn,
+    /// Log a warning and send the kill signal straight away instead.
+    Escalate,
+}
+
+impl std::str::FromStr for MissingHandler {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "warn" => Ok(MissingHandler::Warn),
+            "escalate" => Ok(MissingHandler::Escalate),
+            _ => Err(format!(
+                "Unknown --require-handler action: '{s}' (supported: warn, escalate)"
+            )),
+        }
+    }
+}
+
+/// How the wait between polls is chosen during a grace period, set with
+/// [`GraceKill::poll_schedule`].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum PollSchedule {
+    /// Every [`poll_interval`](GraceKill::poll_interval).
+    #[default]
+    Fixed,
+    /// Widely spaced while the deadline is far off, up to
+    /// [`CURVE_SLOWEST`] poll intervals apart, and ever closer as it nears,
+    /// down to a tenth of one, with the last poll landing on the deadline.
+    Curve,
+}
+
+impl std::str::FromStr for PollSchedule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "fixed" => Ok(PollSchedule::Fixed),
+            "curve" => Ok(PollSchedule::Curve),
+            _ => Err(format!(
+                "Unknown --adaptive-poll schedule: '{s}' (supported: fixed, curve)"
+            )),
+        }
+    }
+}
+
+impl PollSchedule {
+    /// The name it is given on the command line, e.g. `curve`.
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            PollSchedule::Fixed => "fixed",
+            PollSchedule::Curve => "curve",
+        }
+    }
+}
+
+/// What [`GraceKill::extend_if_progressing`] watches to tell whether a
+/// target is shutting down.
+#[cfg(target_os = "linux")]
+#[derive(Copy, Clone)]
+struct Progress {
+    state: char,
+    open_fds: Option<usize>,
+    threads: u64,
+}
+
+#[cfg(target_os = "linux")]
+impl Progress {
+    fn sample(pid: u32) -> Option<Progress> {
+        let stat = procfs::stat(pid).ok()?;
+        Some(Progress {
+            state: stat.state,
+            open_fds: procfs::open_fds(pid).ok(),
+            threads: stat.num_threads,
+        })
+    }
+
+    /// Whether this sample shows progress towards exiting since `before`.
+    fn progressed_since(self, before: &Progress) -> bool {
+        matches!(self.state, 'X' | 'Z')
+            || self
+                .open_fds
+                .zip(before.open_fds)
+                .is_some_and(|(now, then)| now < then)
+            || self.threads < before.threads
+    }
+
+    /// What changed since `before`, e.g. `open files 40 -> 31`.
+    fn describe(self, before: &Progress) -> String {
+        if matches!(self.state, 'X' | 'Z') {
+            return format!("state {}", self.state);
+        }
+        let mut changes = Vec::new();
+        if let (Some(now), Some(then)) = (self.open_fds, before.open_fds)
+            && now < then
+        {
+            changes.push(format!("open files {then} -> {now}"));
+        }
+        if self.threads < before.threads {
+            changes.push(format!("threads {} -> {}", before.threads, self.threads));
+        }
+        changes.join(", ")
+    }
+}
+
+/// Configures and runs a graceful kill.
+///
+/// A run sends [`signal`](Self::signal) to every target, waits up to
+/// [`grace`](Self::grace) for them to exit, works through any
+/// [`escalate`](Self::escalate) stages, then sends
+/// [`kill_signal`](Self::kill_signal) to whatever is left unless
+/// [`no_kill`](Self::no_kill) is set or the process is
+/// [protected](Self::protect).
+///
+/// Processes are driven through a [`ProcessController`], which defaults to
+/// [`System`]. Supplying a different controller makes the behavior scriptable:
+///
+/// ```
+/// use std::time::Duration;
+/// use gracekill::{GraceKill, Outcome, ProcessController, Signal, SignalError};
+///
+/// /// Exits on SIGTERM unless its PID is odd.
+/// struct Mock {
+///     alive: Vec<u32>,
+/// }
+///
+/// impl ProcessController for Mock {
+///     fn send_signal(&mut self, pid: u32, signal: Signal) -> Result<(), SignalError> {
+///         if !self.alive.contains(&pid) {
+///             return Err(SignalError::NotFound);
+///         }
+///         if signal == Signal::Kill || pid % 2 == 0 {
+///             self.alive.retain(|&p| p != pid);
+///         }
+///         Ok(())
+///     }
+///
+///     fn is_running(&mut self, pid: u32) -> bool {
+///         self.alive.contains(&pid)
+///     }
+/// }
+///
+/// let mut mock = Mock { alive: vec![10, 11] };
+/// let report = GraceKill::with_controller(&mut mock)
+///     .grace(Duration::from_millis(50))
+///     .poll_interval(Duration::from_millis(10))
+///     .run(&[10, 11, 12]);
+///
+/// assert_eq!(report.outcome(10), Some(&Outcome::Graceful));
+/// assert_eq!(report.outcome(11), Some(&Outcome::Killed));
+/// assert_eq!(report.outcome(12), Some(&Outcome::SignalFailed(SignalError::NotFound)));
+/// assert!(mock.alive.is_empty());
+/// ```
+#[allow(clippy::struct_excessive_bools)] // one per builder switch
+pub struct GraceKill<C = System> {
+    controller: C,
+    grace: Duration,
+    /// The most random time added to `grace`.
+    jitter: Duration,
+    signal: Signal,
+    /// Per-target overrides of `signal`.
+    signals: HashMap<u32, Signal>,
+    /// The last signal delivered to each process during a run.
+    last_sent: HashMap<u32, Signal>,
+    kill_signal: Signal,
+    /// Intermediate signals, each with its own wait, tried before the kill.
+    stages: Vec<Stage>,
+    poll_interval: Duration,
+    poll_schedule: PollSchedule,
+    no_kill: bool,
+    exit_status: bool,
+    tid: Option<u32>,
+    protected: HashSet<u32>,
+    on_result: Vec<ResultCallback>,
+    on_poll: Vec<PollCallback>,
+    after_signal: Vec<TargetsCallback>,
+    before_kill: Vec<TargetsCallback>,
+    /// When the first signal of the current run was sent.
+    started: Instant,
+    follow_daemons: bool,
+    abort_on_kill_failure: bool,
+    ignore_missing: bool,
+    /// Targets signalled after a parent or child of theirs.
+    exit_with_relatives: HashSet<u32>,
+    require_handler: Option<MissingHandler>,
+    /// How many times to re-send the graceful signal during the grace
+    /// period, and when each target was first sent it.
+    retries: u32,
+    first_sent: HashMap<u32, Instant>,
+    check_zombies: bool,
+    skip_exiting: bool,
+    snapshot: bool,
+    /// Most extra time given to targets still shutting down at the deadline.
+    progress_extension: Option<Duration>,
+    /// CPU time each target may use after the graceful signal, and each
+    /// target's start time and CPU time in clock ticks when it was sent.
+    cpu_grace: Option<Duration>,
+    cpu_at_signal: HashMap<u32, (u64, u64)>,
+    /// Least time between two signals, and when the last one was sent.
+    send_interval: Option<Duration>,
+    /// Count each target's grace period from its own graceful signal.
+    per_wave_grace: bool,
+    last_send: Option<Instant>,
+    /// Percentage of the targets that must exit gracefully before the rest
+    /// are given the shorter grace period.
+    quorum: Option<(u8, Duration)>,
+    /// Most kill signals sent in one burst, and how many the current burst
+    /// has sent.
+    kill_concurrency: Option<usize>,
+    kills_in_burst: usize,
+    /// Time after the first signal past which a target is logged as slow,
+    /// and the targets logged so far.
+    slow_exit: Option<Duration>,
+    warned_slow: HashSet<u32>,
+    /// Most a wait may run past its end before a warning is logged.
+    max_grace_overrun: Option<Duration>,
+    /// Further targets arriving during the grace period.
+    incoming: Option<Receiver<u32>>,
+    cancel: Option<CancelToken>,
+    /// Command line and start time of each target, for following daemons.
+    launchers: HashMap<u32, (Vec<u8>, u64)>,
+    diagnose: bool,
+    /// State and total CPU time of each target just before the last signal.
+    before_signal: HashMap<u32, (char, u64)>,
+}
+
+/// Called with each target's outcome as soon as it is known.
+type ResultCallback = Box<dyn FnMut(u32, &Outcome) + Send>;
+
+/// Called after every poll with the time since the first signal, the PIDs
+/// still being waited for and the outcomes so far.
+type PollCallback = Box<dyn FnMut(Duration, &[u32], &KillReport) + Send>;
+
+/// Called with the targets just sent the graceful signal, or about to be
+/// sent the kill signal.
+type TargetsCallback = Box<dyn FnMut(&[u32]) + Send>;
+
+impl<C: fmt::Debug> fmt::Debug for GraceKill<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GraceKill")
+            .field("controller", &self.controller)
+            .field("grace", &self.grace)
+            .field("jitter", &self.jitter)
+            .field("signal", &self.signal)
+            .field("signals", &self.signals)
+            .field("kill_signal", &self.kill_signal)
+            .field("stages", &self.stages)
+            .field("poll_interval", &self.poll_interval)
+            .field("poll_schedule", &self.poll_schedule)
+            .field("no_kill", &self.no_kill)
+            .field("exit_status", &self.exit_status)
+            .field("tid", &self.tid)
+            .field("protected", &self.protected)
+            .field("on_result", &self.on_result.len())
+            .field("on_poll", &self.on_poll.len())
+            .field("after_signal", &self.after_signal.len())
+            .field("before_kill", &self.before_kill.len())
+            .field("follow_daemons", &self.follow_daemons)
+            .field("abort_on_kill_failure", &self.abort_on_kill_failure)
+            .field("ignore_missing", &self.ignore_missing)
+            .field("exit_with_relatives", &self.exit_with_relatives)
+            .field("require_handler", &self.require_handler)
+            .field("retries", &self.retries)
+            .field("check_zombies", &self.check_zombies)
+            .field("skip_exiting", &self.skip_exiting)
+            .field("snapshot", &self.snapshot)
+            .field("progress_extension", &self.progress_extension)
+            .field("cpu_grace", &self.cpu_grace)
+            .field("send_interval", &self.send_interval)
+            .field("per_wave_grace", &self.per_wave_grace)
+            .field("quorum", &self.quorum)
+            .field("kill_concurrency", &self.kill_concurrency)
+            .field("slow_exit", &self.slow_exit)
+            .field("max_grace_overrun", &self.max_grace_overrun)
+            .field("incoming", &self.incoming.is_some())
+            .field("cancel", &self.cancel)
+            .field("diagnose", &self.diagnose)
+            .finish_non_exhaustive()
+    }
+}
+
+impl GraceKill<System> {
+    /// A graceful kill of real processes with the default settings:
+    /// SIGTERM, a 25 second grace period, then SIGKILL.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::with_controller(System)
+    }
+}
+
+impl Default for GraceKill<System> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C: ProcessController> GraceKill<C> {
+    /// A graceful kill with the default settings that drives processes
+    /// through `controller`.
+    pub fn with_controller(controller: C) -> Self {
+        Self {
+            controller,
+            grace: DEFAULT_GRACE,
+            jitter: Duration::ZERO,
+            signal: Signal::Term,
+            signals: HashMap::new(),
+            last_sent: HashMap::new(),
+            kill_signal: Signal::Kill,
+            stages: Vec::new(),
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            poll_schedule: PollSchedule::Fixed,
+            no_kill: false,
+            exit_status: false,
+            tid: None,
+            protected: HashSet::new(),
+            on_result: Vec::new(),
+            on_poll: Vec::new(),
+            after_signal: Vec::new(),
+            before_kill: Vec::new(),
+            started: Instant::now(),
+            follow_daemons: false,
+            abort_on_kill_failure: false,
+            ignore_missing: false,
+            exit_with_relatives: HashSet::new(),
+            require_handler: None,
+            retries: 0,
+            first_sent: HashMap::new(),
+            check_zombies: true,
+            skip_exiting: false,
+            snapshot: false,
+            progress_extension: None,
+            cpu_grace: None,
+            cpu_at_signal: HashMap::new(),
+            send_interval: None,
+            per_wave_grace: false,
+            last_send: None,
+            quorum: None,
+            kill_concurrency: None,
+            kills_in_burst: 0,
+            slow_exit: None,
+            warned_slow: HashSet::new(),
+            max_grace_overrun: None,
+            incoming: None,
+            cancel: None,
+            launchers: HashMap::new(),
+            diagnose: false,
+            before_signal: HashMap::new(),
+        }
+    }
+
+    /// The controller processes are driven through, e.g. to inspect what a
+    /// custom controller recorded during a run.
+    pub fn controller(&self) -> &C {
+        &self.controller
+    }
+
+    /// The graceful signal `pid` would be sent: its own from
+    /// [`signal_for`](Self::signal_for), or else the one set with
+    /// [`signal`](Self::signal).
+    #[must_use]
+    pub fn signal_of(&self, pid: u32) -> Signal {
+        self.signals.get(&pid).copied().unwrap_or(self.signal)
+    }
+
+    /// Every signal sent before the kill signal and the wait after it, in
+    /// order: the graceful signal, as set with [`stage`](Self::stage), then
+    /// each of the [`escalate`](Self::escalate) stages.
+    #[must_use]
+    pub fn stages(&self) -> Vec<Stage> {
+        let graceful = Stage {
+            signal: self.signal,
+            grace: self.grace,
+            retries: self.retries,
+            jitter: self.jitter,
+        };
+        std::iter::once(graceful)
+            .chain(self.stages.iter().copied())
+            .collect()
+    }
+
+    /// Whether `pid` would be sent the kill signal if it outlived every
+    /// stage: not with [`no_kill`](Self::no_kill), nor once
+    /// [`protect`](Self::protect)ed.
+    #[must_use]
+    pub fn kills(&self, pid: u32) -> bool {
+        !self.no_kill && !self.protected.contains(&pid)
+    }
+
+    /// How long to wait after the graceful signal before escalating.
+    #[must_use]
+    pub fn grace(mut self, grace: Duration) -> Self {
+        self.grace = grace;
+        self
+    }
+
+    /// The signal sent first, asking processes to exit.
+    #[must_use]
+    pub fn signal(mut self, signal: Signal) -> Self {
+        self.signal = signal;
+        self
+    }
+
+    /// Adds up to `jitter` of random time to the grace period.
+    #[must_use]
+    pub fn jitter(mut self, jitter: Duration) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Sets the graceful signal, the grace period, its
+    /// [`retries`](Self::retries) and its [`jitter`](Self::jitter) from
+    /// `stage`, the first of a list whose others go to
+    /// [`escalate`](Self::escalate).
+    #[must_use]
+    pub fn stage(mut self, stage: Stage) -> Self {
+        self.signal = stage.signal;
+        self.grace = stage.grace;
+        self.retries = stage.retries.min(MAX_RETRIES);
+        self.jitter = stage.jitter;
+        self
+    }
+
+    /// Sends `signal` instead of the one set with [`signal`](Self::signal) as
+    /// the graceful signal to `pid`, for batches of processes that each expect
+    /// a different shutdown signal. Escalation stages and the kill signal are
+    /// unaffected.
+    #[must_use]
+    pub fn signal_for(mut self, pid: u32, signal: Signal) -> Self {
+        self.signals.insert(pid, signal);
+        self
+    }
+
+    /// The signal sent to processes still running after the grace period.
+    #[must_use]
+    pub fn kill_signal(mut self, signal: Signal) -> Self {
+        self.kill_signal = signal;
+        self
+    }
+
+    /// Adds escalation stages between the grace period and the kill signal.
+    /// Each stage's signal is sent to processes still running when the
+    /// previous wait ran out, followed by a wait of its own duration.
+    /// Processes exiting during a stage still count as
+    /// [`Outcome::Graceful`]. A stage is a [`Stage`], or a `(Signal,
+    /// Duration)` pair for one without retries or jitter.
+    #[must_use]
+    pub fn escalate<S: Into<Stage>>(mut self, stages: impl IntoIterator<Item = S>) -> Self {
+        self.stages.extend(stages.into_iter().map(Into::into));
+        self
+    }
+
+    /// How often to check whether targets have exited.
+    #[must_use]
+    pub fn poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval = interval;
+        self
+    }
+
+    /// How the waits between polls are spread over the grace period and
+    /// each escalation stage's wait. [`PollSchedule::Fixed`] by default;
+    /// [`PollSchedule::Curve`] polls less often early on, noticing graceful
+    /// exits later, and more often near the deadline, so the next signal
+    /// goes out close to on time. Waits for
+    /// [`per_wave_grace`](Self::per_wave_grace) are always polled at the
+    /// fixed interval.
+    #[must_use]
+    pub fn poll_schedule(mut self, schedule: PollSchedule) -> Self {
+        self.poll_schedule = schedule;
+        self
+    }
+
+    /// When set, processes still running after the grace period are left
+    /// alone and reported as [`Outcome::Survived`].
+    ///
+    /// A zero grace period would report every target as surviving before it
+    /// has had a chance to act on the signal, so with no escalation stages
+    /// it is stretched to a single poll, with a warning that the combination
+    /// is likely a mistake.
+    #[must_use]
+    pub fn no_kill(mut self, no_kill: bool) -> Self {
+        self.no_kill = no_kill;
+        self
+    }
+
+    /// When set, targets that are children of this process are reaped with
+    /// `waitpid` and their exit code or terminating signal is logged and
+    /// recorded in [`KillReport::exit_statuses`].
+    ///
+    /// This consumes the child's exit status, so leave it off if something
+    /// else in the process intends to wait on the targets.
+    #[must_use]
+    pub fn exit_status(mut self, exit_status: bool) -> Self {
+        self.exit_status = exit_status;
+        self
+    }
+
+    /// Delivers the graceful signal to thread `tid` of the target with
+    /// `tgkill(2)` instead of to the process as a whole, for processes that
+    /// handle shutdown on a particular thread. Meant for a single target; the
+    /// kernel rejects targets that `tid` does not belong to. The kill signal
+    /// is still sent to the whole process.
+    #[must_use]
+    pub fn thread(mut self, tid: u32) -> Self {
+        self.tid = Some(tid);
+        self
+    }
+
+    /// Marks `pids` as protected: they receive the graceful signal like any
+    /// other target, but are never sent the kill signal. A protected process
+    /// still running after the grace period is logged as a policy refusal and
+    /// reported as [`Outcome::Survived`].
+    #[must_use]
+    pub fn protect(mut self, pids: impl IntoIterator<Item = u32>) -> Self {
+        self.protected.extend(pids);
+        self
+    }
+
+    /// Calls `callback` with each target's PID and outcome as soon as it is
+    /// resolved, in the order they resolve, rather than only in the final
+    /// [`KillReport`]. The callback runs synchronously on the thread calling
+    /// [`run`](Self::run), between polls, so it should return quickly. Each
+    /// call adds a callback; they are called in the order they were added.
+    #[must_use]
+    pub fn on_result(mut self, callback: impl FnMut(u32, &Outcome) + Send + 'static) -> Self {
+        self.on_result.push(Box::new(callback));
+        self
+    }
+
+    /// Calls `callback` after every poll with the time elapsed since the
+    /// first signal, the PIDs still running and the report so far, and once
+    /// more at the end of the run with no PIDs left. Like
+    /// [`on_result`](Self::on_result), it runs on the thread calling
+    /// [`run`](Self::run), and each call adds a callback.
+    #[must_use]
+    pub fn on_poll(
+        mut self,
+        callback: impl FnMut(Duration, &[u32], &KillReport) + Send + 'static,
+    ) -> Self {
+        self.on_poll.push(Box::new(callback));
+        self
+    }
+
+    /// Calls `callback` with the targets the graceful signal reached, once it
+    /// has been sent to every target and before the grace period starts,
+    /// e.g. to let targets paused for the signal run again. It is called even
+    /// if no target was reached. Like [`on_result`](Self::on_result), it runs
+    /// on the thread calling [`run`](Self::run), and each call adds a
+    /// callback.
+    #[must_use]
+    pub fn after_signal(mut self, callback: impl FnMut(&[u32]) + Send + 'static) -> Self {
+        self.after_signal.push(Box::new(callback));
+        self
+    }
+
+    /// Calls `callback` with the targets still running at the end of the
+    /// grace period, just before they are sent the kill signal, e.g. to
+    /// capture diagnostics from them. The kill waits for the callback to
+    /// return. Like [`on_result`](Self::on_result), it runs on the thread
+    /// calling [`run`](Self::run), and each call adds a callback.
+    #[must_use]
+    pub fn before_kill(mut self, callback: impl FnMut(&[u32]) + Send + 'static) -> Self {
+        self.before_kill.push(Box::new(callback));
+        self
+    }
+
+    /// When set, a target that exits during a wait is checked for a
+    /// double-fork daemon it left behind: a process reparented to init,
+    /// started after the target and running the same command line. If one is
+    /// found it is sent the current signal and followed in the target's
+    /// place. This is a heuristic, and only works on Linux.
+    #[must_use]
+    pub fn follow_daemons(mut self, follow: bool) -> Self {
+        self.follow_daemons = follow;
+        self
+    }
+
+    /// When set, each target's state is sampled from `/proc` just before a
+    /// signal and again shortly after. A target that hasn't changed state or
+    /// used any CPU in between and whose `SigIgn` mask shows the signal as
+    /// ignored is logged as appearing to ignore it. Only works on Linux.
+    #[must_use]
+    pub fn diagnose(mut self, diagnose: bool) -> Self {
+        self.diagnose = diagnose;
+        self
+    }
+
+    /// When set, the kill stage stops at the first process the kill signal
+    /// can't be delivered to: processes not yet sent it are left running and
+    /// reported as [`Outcome::Survived`], and [`KillReport::aborted`] is set.
+    /// Processes already killed are still reported as such.
+    #[must_use]
+    pub fn abort_on_kill_failure(mut self, abort: bool) -> Self {
+        self.abort_on_kill_failure = abort;
+        self
+    }
+
+    /// When set, targets that no longer exist when the graceful signal is
+    /// sent are taken to have exited already: they are logged at debug level
+    /// only and reported as [`Outcome::Graceful`] rather than
+    /// [`Outcome::SignalFailed`]. Other delivery failures are unaffected.
+    #[must_use]
+    pub fn ignore_missing(mut self, ignore: bool) -> Self {
+        self.ignore_missing = ignore;
+        self
+    }
+
+    /// Marks `pids` as targets signalled after a parent or child of theirs
+    /// among the targets, which may take them down before their own signal
+    /// arrives. Any of them that no longer exists by then is taken to have
+    /// exited along with it, as with [`ignore_missing`](Self::ignore_missing).
+    #[must_use]
+    pub fn exit_with_relatives(mut self, pids: impl IntoIterator<Item = u32>) -> Self {
+        self.exit_with_relatives = pids.into_iter().collect();
+        self
+    }
+
+    /// Holds back the kill signal for targets that are visibly shutting down
+    /// when the grace period ends, for at most `cap` in total. A target
+    /// counts as shutting down while it is dying or a zombie, or while its
+    /// number of open files or threads keeps falling, at least once every
+    /// second; once it stops, or the cap runs out, it is sent the kill
+    /// signal. Only works on Linux.
+    #[must_use]
+    pub fn extend_if_progressing(mut self, cap: Duration) -> Self {
+        self.progress_extension = Some(cap);
+        self
+    }
+
+    /// Also measures the grace period in CPU time: each target is sent the
+    /// kill signal as soon as it has used `budget` of CPU time since the
+    /// graceful signal, however little wall-clock time that took. A target
+    /// that is blocked or asleep, or a machine that is suspended, uses none,
+    /// so a target only doing so waits out the wall-clock
+    /// [`grace`](Self::grace) instead, which still bounds the wait. A PID
+    /// found to belong to a process started since counts as having exited.
+    /// Escalation stages still wait in wall-clock time. Only works on Linux.
+    #[must_use]
+    pub fn cpu_time_grace(mut self, budget: Duration) -> Self {
+        self.cpu_grace = Some(budget);
+        self
+    }
+
+    /// Whether a killed process that has exited but not been reaped yet (a
+    /// zombie) counts as gone when confirming the kill, found by reading
+    /// `/proc/<pid>/stat`. On by default. Turning it off saves that read for
+    /// every killed process, at the cost of reporting zombies whose parent
+    /// doesn't reap them promptly in [`KillReport::lingering`]. Only has an
+    /// effect on Linux.
+    #[must_use]
+    pub fn check_zombies(mut self, check: bool) -> Self {
+        self.check_zombies = check;
+        self
+    }
+
+    /// When set, targets that are already exiting when the kill starts are
+    /// not sent the graceful signal, so as not to interfere with a shutdown
+    /// in progress. Such a target is waited for as usual, and one that has
+    /// already exited but not been reaped (a zombie) counts as exited
+    /// gracefully straight away. Only has an effect on Linux, where the
+    /// state is read from `/proc/<pid>/stat`.
+    #[must_use]
+    pub fn skip_exiting(mut self, skip: bool) -> Self {
+        self.skip_exiting = skip;
+        self
+    }
+
+    /// When set, a [`Snapshot`] of every target is taken just before the
+    /// first signal and kept in [`KillReport::snapshots`], as a record of
+    /// what the processes looked like before the kill. Targets that exit
+    /// while being read are left out. Only has an effect on Linux, where it
+    /// is read from `/proc`.
+    #[must_use]
+    pub fn snapshot(mut self, snapshot: bool) -> Self {
+        self.snapshot = snapshot;
+        self
+    }
+
+    /// When set, targets still running halfway through the grace period are
+    /// sent their graceful signal a second time, for programs that only
+    /// start shutting down once the signal is repeated. The kill signal still
+    /// follows at the end of the grace period. Escalation stages are not
+    /// repeated. The same as one of [`retries`](Self::retries).
+    #[must_use]
+    pub fn double_tap(mut self, double_tap: bool) -> Self {
+        self.retries = u32::from(double_tap);
+        self
+    }
+
+    /// Sends the graceful signal `retries` more times to the targets still
+    /// running, evenly spread over the grace period: with 2, a third and two
+    /// thirds of the way through. At most [`MAX_RETRIES`].
+    #[must_use]
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.retries = retries.min(MAX_RETRIES);
+        self
+    }
+
+    /// Checks the `SigCgt` mask of every target before sending it the
+    /// graceful signal. A target with no handler installed for the signal
+    /// can't shut down gracefully: the signal's default action ends it at
+    /// once. Such targets are logged, and either signalled anyway or, with
+    /// [`MissingHandler::Escalate`], sent the kill signal without waiting out
+    /// the grace period (unless [`no_kill`](Self::no_kill) is set). Only
+    /// works on Linux; elsewhere every target is signalled as usual.
+    #[must_use]
+    pub fn require_handler(mut self, policy: MissingHandler) -> Self {
+        self.require_handler = Some(policy);
+        self
+    }
+
+    /// Sends at most `per_second` signals per second, spacing them evenly,
+    /// to avoid a load spike when signalling very many processes. The grace
+    /// period starts once the graceful signal has been sent to every target,
+    /// so a low rate delays escalation rather than shortening the wait; a
+    /// warning is logged if sending takes longer than the grace period
+    /// itself. Zero removes the limit.
+    #[must_use]
+    pub fn max_send_rate(mut self, per_second: u32) -> Self {
+        self.send_interval = (per_second > 0).then(|| Duration::from_secs(1) / per_second);
+        self
+    }
+
+    /// Counts each target's grace period from when it was sent the graceful
+    /// signal rather than from when every target had been, so that targets
+    /// signalled in waves, under [`max_send_rate`](Self::max_send_rate) or
+    /// arriving through [`add_targets_from`](Self::add_targets_from), each
+    /// get the whole grace period and no more. Each target still running at
+    /// the end of its own grace period is killed then, without waiting for
+    /// the rest. Has no effect with [`escalate`](Self::escalate) stages or
+    /// [`extend_if_progressing`](Self::extend_if_progressing).
+    #[must_use]
+    pub fn per_wave_grace(mut self, per_wave: bool) -> Self {
+        self.per_wave_grace = per_wave;
+        self
+    }
+
+    /// Keys the grace period to the targets as a group: once `percent` of
+    /// them have exited gracefully, those left are given `stragglers` more,
+    /// if that ends sooner than the grace period would. Meant for members of
+    /// a service that shut down together, where the last few still running
+    /// once most are gone are likely stuck. Targets that exited before their
+    /// signal count as having exited. Has no effect with
+    /// [`per_wave_grace`](Self::per_wave_grace), and escalation stages wait
+    /// as usual.
+    ///
+    /// # Panics
+    ///
+    /// If `percent` is zero or over 100.
+    #[must_use]
+    pub fn quorum(mut self, percent: u8, stragglers: Duration) -> Self {
+        assert!(
+            (1..=100).contains(&percent),
+            "quorum must be 1 to 100 percent"
+        );
+        self.quorum = Some((percent, stragglers));
+        self
+    }
+
+    /// Sends the kill signal to at most `limit` processes at a time, pausing
+    /// for [`KILL_BURST_PAUSE`] after each burst so that the kernel can tear
+    /// them down before the next. Killing hundreds of processes at once can
+    /// otherwise cause a burst of CPU and IO load. Applies on top of
+    /// [`max_send_rate`](Self::max_send_rate). Zero removes the limit.
+    #[must_use]
+    pub fn kill_concurrency(mut self, limit: usize) -> Self {
+        self.kill_concurrency = (limit > 0).then_some(limit);
+        self
+    }
+
+    /// Logs a warning for every target still running `threshold` after the
+    /// first signal, once per target, to flag processes heading for the kill
+    /// signal before they get there. Has no effect on when signals are sent.
+    #[must_use]
+    pub fn warn_slow_exit(mut self, threshold: Duration) -> Self {
+        self.slow_exit = Some(threshold);
+        self
+    }
+
+    /// Logs a warning whenever the grace period, or an escalation stage's
+    /// wait, runs more than `limit` past its end before the next signal is
+    /// sent, as it does when polls are slow to come round: a coarse poll
+    /// interval, `/proc` reads that hang or an overloaded scheduler. The
+    /// largest overrun is reported in [`KillReport::grace_overrun`] either
+    /// way. Has no effect on when signals are sent.
+    #[must_use]
+    pub fn max_grace_overrun(mut self, limit: Duration) -> Self {
+        self.max_grace_overrun = Some(limit);
+        self
+    }
+
+    /// Accepts further targets from `incoming` while waiting out the grace
+    /// period. Each PID received is sent the graceful signal at the next poll
+    /// and joins the targets already being waited for, sharing what is left
+    /// of their grace period. The grace period is then waited out in full
+    /// even once every target has exited, so that targets can keep arriving
+    /// until it ends, and [`run`](Self::run) may be given no PIDs at all.
+    #[must_use]
+    pub fn add_targets_from(mut self, incoming: Receiver<u32>) -> Self {
+        self.incoming = Some(incoming);
+        self
+    }
+
+    /// Lets `token` cancel the run from another thread. Once it is
+    /// cancelled the run stops waiting at its next poll and returns, with
+    /// [`KillReport::cancelled`] set and the targets still running reported
+    /// as [`Outcome::Survived`]. Signals already sent aren't undone, but
+    /// nothing further is sent: no escalation stage and no kill signal.
+    /// Cancelled before the run starts, it signals nothing.
+    #[must_use]
+    pub fn cancel_token(mut self, token: CancelToken) -> Self {
+        self.cancel = Some(token);
+        self
+    }
+
+    fn cancelled(&self) -> bool {
+        self.cancel.as_ref().is_some_and(CancelToken::is_cancelled)
+    }
+
+    /// Signals `pids`, waits for them to exit, and escalates as configured.
+    pub fn run(&mut self, pids: &[u32]) -> KillReport {
+        let mut report = KillReport::default();
+        if self.cancelled() {
+            log("Cancelled before any signal was sent");
+            report.cancelled = true;
+            return report;
+        }
+        self.warned_slow.clear();
+        self.first_sent.clear();
+        self.last_sent.clear();
+        if let Some(interval) = self.send_interval {
+            let sending = interval * u32::try_from(pids.len()).unwrap_or(u32::MAX);
+            if sending > self.grace {
+                log::warn(&format!(
+                    "Signalling {} process(es) at the send rate limit takes {}, longer than the {} grace period",
+                    pids.len(),
+                    log::duration(sending),
+                    log::duration(self.grace)
+                ));
+            }
+        }
+        if self.snapshot {
+            report.snapshots = take_snapshots(pids);
+        }
+        self.remember_launchers(pids);
+        self.sample_before_signal(pids);
+        let active_pids = match self.tid {
+            Some(tid) => self.send_to_thread(pids, tid, &mut report),
+            None => self.send_graceful(pids, &mut report),
+        };
+        for callback in &mut self.after_signal {
+            callback(&active_pids);
+        }
+        self.cpu_at_signal.clear();
+
+        if active_pids.is_empty() && self.incoming.is_none() {
+            log("No processes to wait for");
+            return report;
+        }
+
+        // Wait for processes to exit gracefully
+        let start = Instant::now();
+        self.started = start;
+        let mut remaining = active_pids;
+        let mut grace = jittered(self.grace, self.jitter);
+        if self.no_kill && grace.is_zero() && self.stages.is_empty() {
+            log::warn(
+                "Zero grace period without a kill signal: processes get one poll to exit before being reported as survivors",
+            );
+            grace = self.poll_interval;
+        }
+        self.wait(
+            &mut remaining,
+            None,
+            start,
+            grace,
+            self.retries,
+            &mut report,
+        );
+        self.run_stages(&mut remaining, start, &mut report);
+
+        if remaining.is_empty() && report.graceful() < report.signalled() {
+            log(&format!(
+                "No processes left to wait for after {}",
+                log::duration(start.elapsed())
+            ));
+        } else if remaining.is_empty() {
+            log(&format!(
+                "All processes exited gracefully after {}",
+                log::duration(start.elapsed())
+            ));
+        } else if self.cancelled() {
+            self.leave_cancelled(remaining, start, &mut report);
+        } else if self.no_kill {
+            self.leave_survivors(remaining, start, &mut report);
+        } else {
+            let remaining = self.extend_for_progress(remaining, start, &mut report);
+            if self.cancelled() {
+                self.leave_cancelled(remaining, start, &mut report);
+            } else {
+                self.kill_after_grace(remaining, start, &mut report);
+            }
+        }
+
+        report.elapsed = start.elapsed();
+        if !self.stages.is_empty() {
+            report.stages = self.stage_exits(&report);
+        }
+        for callback in &mut self.on_poll {
+            callback(report.elapsed, &[], &report);
+        }
+        report
+            .outcomes
+            .sort_by_key(|(pid, _)| pids.iter().position(|p| p == pid).unwrap_or(usize::MAX));
+        if self.exit_status {
+            for (pid, outcome) in &report.outcomes {
+                if !matches!(outcome, Outcome::SignalFailed(_))
+                    && report.exit_status(*pid).is_none()
+                {
+                    log(&format!(
+                        "Process {pid}: exit status unavailable (not a child of gracekill)"
+                    ));
+                }
+            }
+        }
+        report
+    }
+
+    /// Sends each escalation stage's signal in turn to the processes still in
+    /// `remaining`, and waits out its duration, until none are left.
+    fn run_stages(&mut self, remaining: &mut Vec<u32>, start: Instant, report: &mut KillReport) {
+        for stage in self.stages.clone() {
+            if remaining.is_empty() || self.cancelled() {
+                break;
+            }
+            log(&format!(
+                "{} process(es) still running after {} elapsed, sending {}",
+                remaining.len(),
+                log::duration(start.elapsed()),
+                stage.signal
+            ));
+            self.sample_before_signal(remaining);
+            *remaining = self.send_to_stage(remaining, stage.signal, report);
+            let wait = jittered(stage.grace, stage.jitter);
+            self.wait(
+                remaining,
+                Some(stage.signal),
+                Instant::now(),
+                wait,
+                stage.retries,
+                report,
+            );
+        }
+    }
+
+    /// Captures the command line and start time of every target for
+    /// [`follow_daemons`](Self::follow_daemons), before signalling them while
+    /// they still exist.
+    fn remember_launchers(&mut self, pids: &[u32]) {
+        self.launchers.clear();
+        #[cfg(target_os = "linux")]
+        if self.follow_daemons {
+            for &pid in pids {
+                if let (Some(cmdline), Ok(stat)) = (procfs::cmdline(pid), procfs::stat(pid)) {
+                    self.launchers.insert(pid, (cmdline, stat.starttime));
+                }
+            }
+        }
+    }
+
+    /// Records the state of every target for [`diagnose`](Self::diagnose)
+    /// before a signal is sent.
+    fn sample_before_signal(&mut self, pids: &[u32]) {
+        self.before_signal.clear();
+        #[cfg(target_os = "linux")]
+        if self.diagnose {
+            for &pid in pids {
+                if let Ok(stat) = procfs::stat(pid) {
+                    self.before_signal
+                        .insert(pid, (stat.state, stat.utime + stat.stime));
+                }
+            }
+        }
+    }
+
+    /// Logs every target in `pids` that appears to be ignoring `signal`, or
+    /// its graceful signal if `None`: unchanged since
+    /// [`sample_before_signal`](Self::sample_before_signal) and with the
+    /// signal in its `SigIgn` mask.
+    #[cfg_attr(not(target_os = "linux"), allow(clippy::unused_self))]
+    fn diagnose_ignored(&self, pids: &[u32], signal: Option<Signal>) {
+        #[cfg(target_os = "linux")]
+        for &pid in pids {
+            let signal = signal.unwrap_or_else(|| self.signal_of(pid));
+            let (Some(&before), Ok(after), Ok(masks)) = (
+                self.before_signal.get(&pid),
+                procfs::stat(pid),
+                procfs::signal_masks(pid),
+            ) else {
+                continue;
+            };
+            let unchanged = before == (after.state, after.utime + after.stime);
+            if unchanged && procfs::SignalMasks::contains(masks.ignored, signal) {
+                log::warn(&format!("PID {pid} appears to be ignoring {signal}"));
+            } else if procfs::SignalMasks::contains(masks.caught, signal) {
+                log::debug(&format!(
+                    "PID {pid} has a handler for {signal} and is still running"
+                ));
+            } else if procfs::SignalMasks::contains(masks.blocked, signal) {
+                log::debug(&format!("PID {pid} has {signal} blocked"));
+            }
+        }
+    }
+
+    /// The daemon a double-forking target `pid` left behind, if following
+    /// daemons and one can be found. The daemon is remembered in turn, so a
+    /// chain of forks is followed to its end.
+    #[cfg(target_os = "linux")]
+    fn find_daemon(&mut self, pid: u32) -> Option<u32> {
+        let (cmdline, since) = self.launchers.get(&pid)?.clone();
+        let known: Vec<u32> = self.launchers.keys().copied().collect();
+        let daemon = procfs::reparented_daemon(&cmdline, since, &known).ok()??;
+        self.launchers.insert(daemon, (cmdline, since));
+        Some(daemon)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn find_daemon(&mut self, _pid: u32) -> Option<u32> {
+        None
+    }
+
+    /// Sends the graceful signal to every target received from
+    /// [`add_targets_from`](Self::add_targets_from) since the last poll and
+    /// adds the ones it reached to `remaining`. PIDs already targeted are
+    /// ignored.
+    fn take_incoming(&mut self, remaining: &mut Vec<u32>, report: &mut KillReport) {
+        let Some(incoming) = &self.incoming else {
+            return;
+        };
+        let mut new = Vec::new();
+        for pid in incoming.try_iter() {
+            if !remaining.contains(&pid) && !new.contains(&pid) && report.outcome(pid).is_none() {
+                new.push(pid);
+            }
+        }
+        if !new.is_empty() {
+            remaining.extend(self.send_graceful(&new, report));
+        }
+    }
+
+    /// Leaves the targets still running in `remaining` alone once the run
+    /// has been cancelled, reporting them as survivors.
+    fn leave_cancelled(&mut self, remaining: Vec<u32>, start: Instant, report: &mut KillReport) {
+        if remaining.is_empty() {
+            return;
+        }
+        log(&format!(
+            "Cancelled after {} with {} process(es) still running, leaving them running",
+            log::duration(start.elapsed()),
+            remaining.len()
+        ));
+        for pid in remaining {
+            self.record(report, pid, Outcome::Survived);
+        }
+        report.cancelled = true;
+    }
+
+    /// Leaves the targets still running in `remaining` at the end of the
+    /// grace period that began at `start` alone, for
+    /// [`no_kill`](Self::no_kill).
+    fn leave_survivors(&mut self, remaining: Vec<u32>, start: Instant, report: &mut KillReport) {
+        self.classify_survivors(&remaining, report);
+        log(&format!(
+            "{} process(es) still running after grace period ({} elapsed), leaving them running",
+            remaining.len(),
+            log::duration(start.elapsed())
+        ));
+        for pid in remaining {
+            self.record(report, pid, Outcome::Survived);
+        }
+    }
+
+    /// Records and logs why each of `pids` outlived the last signal it was
+    /// sent, into [`KillReport::survivals`], before it is escalated.
+    #[cfg_attr(not(target_os = "linux"), allow(clippy::unused_self))]
+    fn classify_survivors(&self, pids: &[u32], report: &mut KillReport) {
+        #[cfg(target_os = "linux")]
+        for &pid in pids {
+            let signal = self
+                .last_sent
+                .get(&pid)
+                .copied()
+                .unwrap_or_else(|| self.signal_of(pid));
+            let (Ok(stat), Ok(masks)) = (procfs::stat(pid), procfs::signal_masks(pid)) else {
+                continue;
+            };
+            let survival = procfs::classify_survivor(&stat, &masks, signal);
+            log(&format!("PID {pid} outlived {signal}: {survival}"));
+            report.survivals.push((pid, survival));
+        }
+    }
+
+    /// Sends the kill signal to `remaining` once the grace period that began
+    /// at `start` is over.
+    fn kill_after_grace(&mut self, remaining: Vec<u32>, start: Instant, report: &mut KillReport) {
+        if remaining.is_empty() {
+            return;
+        }
+        self.classify_survivors(&remaining, report);
+        log(&format!(
+            "{} process(es) still running after grace period ({} elapsed), sending {}",
+            remaining.len(),
+            log::duration(start.elapsed()),
+            self.kill_signal
+        ));
+        self.kill(remaining, report);
+    }
+
+    /// Keeps waiting for the targets in `remaining` that are visibly shutting
+    /// down, for [`extend_if_progressing`](Self::extend_if_progressing),
+    /// killing each as soon as it stops making progress. Returns the targets
+    /// still running when the extension runs out.
+    #[cfg(target_os = "linux")]
+    fn extend_for_progress(
+        &mut self,
+        mut remaining: Vec<u32>,
+        start: Instant,
+        report: &mut KillReport,
+    ) -> Vec<u32> {
+        let Some(cap) = self.progress_extension else {
+            return remaining;
+        };
+        let deadline = Instant::now() + cap;
+        // Each target's state when it last made progress, and when that was
+        let mut previous: HashMap<u32, (Progress, Instant)> = remaining
+            .iter()
+            .filter_map(|&pid| Some((pid, (Progress::sample(pid)?, Instant::now()))))
+            .collect();
+        let mut extended = HashSet::new();
+        while !remaining.is_empty() && !self.cancelled() {
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                log::warn(&format!(
+                    "{} process(es) still shutting down after the {} extension",
+                    remaining.len(),
+                    log::duration(cap)
+                ));
+                break;
+            }
+            thread::sleep(self.poll_interval.min(left));
+            remaining.retain(|&pid| {
+                if self.probe(pid, None, report) {
+                    return true;
+                }
+                log(&format!(
+                    "Process {pid} exited gracefully during the grace extension"
+                ));
+                self.record(report, pid, Outcome::Graceful);
+                false
+            });
+            let mut stalled = Vec::new();
+            remaining.retain(|&pid| {
+                let (Some(&(before, since)), Some(now)) =
+                    (previous.get(&pid), Progress::sample(pid))
+                else {
+                    stalled.push(pid);
+                    return false;
+                };
+                if !now.progressed_since(&before) {
+                    if since.elapsed() >= PROGRESS_WINDOW {
+                        stalled.push(pid);
+                        return false;
+                    }
+                    return true;
+                }
+                if extended.insert(pid) {
+                    log(&format!(
+                        "PID {pid} is shutting down ({}), holding back {} for up to {}",
+                        now.describe(&before),
+                        self.kill_signal,
+                        log::duration(cap)
+                    ));
+                }
+                previous.insert(pid, (now, Instant::now()));
+                true
+            });
+            self.kill_after_grace(stalled, start, report);
+        }
+        remaining
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn extend_for_progress(
+        &mut self,
+        remaining: Vec<u32>,
+        _start: Instant,
+        _report: &mut KillReport,
+    ) -> Vec<u32> {
+        remaining
+    }
+
+    /// Sends every target in `remaining` the stage's `signal` again, or its
+    /// graceful signal for `None`, for the `retry`th of `retries`. A target
+    /// that has exited in the meantime is left for the next poll to record.
+    fn send_again(
+        &mut self,
+        remaining: &[u32],
+        signal: Option<Signal>,
+        since: Instant,
+        (retry, retries): (u32, u32),
+    ) {
+        let count = if retries > 1 {
+            format!(" ({retry}/{retries})")
+        } else {
+            String::new()
+        };
+        for &pid in remaining {
+            let (signal, tid, first) = match signal {
+                Some(signal) => (signal, None, since),
+                None => (
+                    self.signal_of(pid),
+                    self.tid,
+                    self.first_sent.get(&pid).copied().unwrap_or(since),
+                ),
+            };
+            match self.deliver(pid, tid, signal) {
+                Ok(()) => log(&format!(
+                    "Sent {signal} to PID {pid} again{count}, {} after the first",
+                    log::duration(first.elapsed())
+                )),
+                Err(SignalError::NotFound) => {}
+                Err(e) => log::warn(&format!("Failed to send {signal} to PID {pid} again: {e}")),
+            }
+        }
+    }
+
+    /// Warns about the targets in `remaining` that have been running longer
+    /// than the [`warn_slow_exit`](Self::warn_slow_exit) threshold.
+    fn warn_slow(&mut self, remaining: &[u32]) {
+        let Some(threshold) = self.slow_exit else {
+            return;
+        };
+        let elapsed = self.started.elapsed();
+        if elapsed < threshold {
+            return;
+        }
+        for &pid in remaining {
+            if self.warned_slow.insert(pid) {
+                log::warn(&format!(
+                    "PID {pid} is slow to exit: still running {} after the first signal",
+                    log::duration(elapsed)
+                ));
+            }
+        }
+    }
+
+    /// Records the outcome of `pid`, reporting it to the result callback.
+    fn record(&mut self, report: &mut KillReport, pid: u32, outcome: Outcome) {
+        for callback in &mut self.on_result {
+            callback(pid, &outcome);
+        }
+        if outcome == Outcome::Graceful
+            && let Some(&signal) = self.last_sent.get(&pid)
+        {
+            report.exit_signals.push((pid, signal));
+        }
+        report.outcomes.push((pid, outcome));
+    }
+
+    /// How many processes exited on each graceful signal and then on each
+    /// escalation stage's signal.
+    fn stage_exits(&self, report: &KillReport) -> Vec<(Signal, usize)> {
+        let mut ladder = vec![self.signal];
+        // Targets given a graceful signal of their own
+        for &(_, signal) in &report.exit_signals {
+            if !ladder.contains(&signal) && !self.stages.iter().any(|stage| stage.signal == signal)
+            {
+                ladder.push(signal);
+            }
+        }
+        for stage in &self.stages {
+            if !ladder.contains(&stage.signal) {
+                ladder.push(stage.signal);
+            }
+        }
+        ladder
+            .into_iter()
+            .map(|signal| {
+                let exited = report
+                    .exit_signals
+                    .iter()
+                    .filter(|&&(_, s)| s == signal)
+                    .count();
+                (signal, exited)
+            })
+            .collect()
+    }
+
+    /// How long to sleep before the next poll with `left` until the
+    /// deadline, following the [`poll_schedule`](Self::poll_schedule).
+    fn poll_wait(&self, left: Duration) -> Duration {
+        match self.poll_schedule {
+            PollSchedule::Fixed => self.poll_interval,
+            PollSchedule::Curve => (left / CURVE_FRACTION)
+                .min(self.poll_interval * CURVE_SLOWEST)
+                .max(self.poll_interval / 10)
+                .max(Duration::from_millis(1))
+                .min(left),
+        }
+    }
+
+    /// Polls `remaining` until every process has exited or `wait` has passed
+    /// since `since`, recording the ones that exit as graceful. Daemons
+    /// followed from exiting targets are sent `signal`, or the target's
+    /// graceful signal if `None`, and join `remaining`. The signal is sent
+    /// again `retries` times on the way, up to [`MAX_RETRIES`].
+    fn wait(
+        &mut self,
+        remaining: &mut Vec<u32>,
+        signal: Option<Signal>,
+        since: Instant,
+        mut wait: Duration,
+        retries: u32,
+        report: &mut KillReport,
+    ) {
+        let mut diagnosed = !self.diagnose;
+        let retries = retries.min(MAX_RETRIES);
+        let mut sent_again = 0;
+        let listening = signal.is_none() && self.incoming.is_some();
+        let per_wave = signal.is_none()
+            && self.per_wave_grace
+            && self.stages.is_empty()
+            && self.progress_extension.is_none();
+        // Per wave, each target is killed once its own grace period is over,
+        // so the wait lasts as long as any is left
+        while if per_wave {
+            !remaining.is_empty() || (listening && since.elapsed() < wait)
+        } else {
+            (listening || !remaining.is_empty()) && since.elapsed() < wait
+        } {
+            thread::sleep(if per_wave {
+                self.poll_interval
+            } else {
+                self.poll_wait(wait.saturating_sub(since.elapsed()))
+            });
+            let cancelled = self.cancelled();
+            if !diagnosed && since.elapsed() >= DIAGNOSE_WINDOW {
+                self.diagnose_ignored(remaining, signal);
+                diagnosed = true;
+            }
+            let running = self.controller.running_among(remaining);
+            let mut followed = Vec::new();
+            remaining.retain(|&pid| {
+                if self.probe(pid, running.as_ref(), report) {
+                    return true;
+                }
+                log(&format!("Process {pid} exited gracefully"));
+                self.record(report, pid, Outcome::Graceful);
+                if let Some(daemon) = self.find_daemon(pid) {
+                    log(&format!(
+                        "Process {pid} left daemon PID {daemon} behind, following it"
+                    ));
+                    let signal = signal.unwrap_or_else(|| self.signal_of(pid));
+                    self.signals.insert(daemon, signal);
+                    match self.deliver(daemon, None, signal) {
+                        Ok(()) => {
+                            log(&format!("Sent {signal} to PID {daemon}"));
+                            followed.push(daemon);
+                        }
+                        Err(e) => {
+                            log::error(&format!("Failed to send {signal} to PID {daemon}: {e}"));
+                            self.record(report, daemon, Outcome::SignalFailed(e));
+                        }
+                    }
+                }
+                false
+            });
+            remaining.extend(followed);
+            if cancelled {
+                // This poll's exits are recorded, but nothing more is sent
+                break;
+            }
+            if signal.is_none() {
+                self.kill_over_cpu_budget(remaining, report);
+            }
+            if signal.is_none() && !per_wave {
+                self.check_quorum(remaining, since, &mut wait, report);
+            }
+            if per_wave {
+                self.end_own_grace(remaining, since, wait, report);
+            }
+            if sent_again < retries
+                && since.elapsed() >= (wait / (retries + 1)).saturating_mul(sent_again + 1)
+            {
+                sent_again += 1;
+                self.send_again(remaining, signal, since, (sent_again, retries));
+            }
+            if listening && since.elapsed() < wait {
+                self.take_incoming(remaining, report);
+            }
+            self.warn_slow(remaining);
+            for callback in &mut self.on_poll {
+                callback(self.started.elapsed(), remaining, report);
+            }
+            if !remaining.is_empty() {
+                log::debug(&format!(
+                    "{} process(es) still running after {}",
+                    remaining.len(),
+                    log::duration(since.elapsed())
+                ));
+            }
+        }
+        if !per_wave && !remaining.is_empty() && !self.cancelled() {
+            self.note_overrun(since.elapsed().saturating_sub(wait), wait, report);
+        }
+    }
+
+    /// Shortens the grace period `wait` to the [`quorum`](Self::quorum)'s
+    /// straggler grace once enough of the targets have exited, the first
+    /// time they have.
+    fn check_quorum(
+        &self,
+        remaining: &[u32],
+        since: Instant,
+        wait: &mut Duration,
+        report: &mut KillReport,
+    ) {
+        let Some((percent, stragglers)) = self.quorum else {
+            return;
+        };
+        let exited = report.graceful();
+        let total = exited + remaining.len();
+        if report.quorum_reached.is_some()
+            || remaining.is_empty()
+            || exited * 100 < usize::from(percent) * total
+        {
+            return;
+        }
+        let elapsed = since.elapsed();
+        report.quorum_reached = Some(elapsed);
+        let end = elapsed + stragglers;
+        let more = if end < *wait {
+            *wait = end;
+            format!("giving them {} more", log::duration(stragglers))
+        } else {
+            "which leaves them the rest of the grace period".to_string()
+        };
+        log(&format!(
+            "Quorum of {percent}% reached after {}: {exited} of {total} process(es) exited gracefully, {} still running, {more}",
+            log::duration(elapsed),
+            remaining.len()
+        ));
+    }
+
+    /// Records that a wait of `wait` ran `overrun` past its end, warning if
+    /// that is more than [`max_grace_overrun`](Self::max_grace_overrun).
+    fn note_overrun(&self, overrun: Duration, wait: Duration, report: &mut KillReport) {
+        report.grace_overrun = report.grace_overrun.max(overrun);
+        if let Some(limit) = self.max_grace_overrun
+            && overrun > limit
+        {
+            log::warn(&format!(
+                "The {} wait ran {} past its end, more than the {} allowed: signal timing is degraded, e.g. by slow /proc reads or scheduling delays",
+                log::duration(wait),
+                log::duration(overrun),
+                log::duration(limit)
+            ));
+        }
+    }
+
+    /// Takes the targets whose own grace period is over out of `remaining`,
+    /// for [`per_wave_grace`](Self::per_wave_grace), and kills them, or
+    /// leaves them running with [`no_kill`](Self::no_kill). A target's grace
+    /// period counts from its graceful signal, or from `since` for one that
+    /// wasn't sent it, such as a followed daemon.
+    fn end_own_grace(
+        &mut self,
+        remaining: &mut Vec<u32>,
+        since: Instant,
+        grace: Duration,
+        report: &mut KillReport,
+    ) {
+        let mut over = Vec::new();
+        let mut overrun = Duration::ZERO;
+        remaining.retain(|&pid| {
+            let sent = self.first_sent.get(&pid).copied().unwrap_or(since);
+            if sent.elapsed() < grace {
+                return true;
+            }
+            overrun = overrun.max(sent.elapsed().saturating_sub(grace));
+            over.push(pid);
+            false
+        });
+        if over.is_empty() {
+            return;
+        }
+        self.note_overrun(overrun, grace, report);
+        self.classify_survivors(&over, report);
+        if self.no_kill {
+            log(&format!(
+                "{} process(es) still running {} after their signal, leaving them running",
+                over.len(),
+                log::duration(grace)
+            ));
+            for pid in over {
+                self.record(report, pid, Outcome::Survived);
+            }
+            return;
+        }
+        log(&format!(
+            "{} process(es) still running {} after their signal, sending {}",
+            over.len(),
+            log::duration(grace),
+            self.kill_signal
+        ));
+        self.kill(over, report);
+    }
+
+    /// Takes the targets that have used up their
+    /// [`cpu_time_grace`](Self::cpu_time_grace) budget out of `remaining`
+    /// and kills them. A target's CPU time is counted from the first poll
+    /// that sees it, right after the graceful signal.
+    #[cfg(target_os = "linux")]
+    fn kill_over_cpu_budget(&mut self, remaining: &mut Vec<u32>, report: &mut KillReport) {
+        let Some(budget) = self.cpu_grace else {
+            return;
+        };
+        let budget_ticks =
+            u64::try_from(budget.as_millis() * u128::from(procfs::ticks_per_second()) / 1000)
+                .unwrap_or(u64::MAX)
+                .max(1);
+        let mut over = Vec::new();
+        remaining.retain(|&pid| {
+            let Ok(stat) = procfs::stat(pid) else {
+                // Gone, or unreadable: the next poll decides
+                return true;
+            };
+            let used = stat.utime + stat.stime;
+            let (started, at_signal) = *self
+                .cpu_at_signal
+                .entry(pid)
+                .or_insert((stat.starttime, used));
+            if started != stat.starttime {
+                log(&format!(
+                    "Process {pid} exited gracefully, its PID now belongs to a newer process"
+                ));
+                self.record(report, pid, Outcome::Graceful);
+                return false;
+            }
+            if used.saturating_sub(at_signal) < budget_ticks {
+                return true;
+            }
+            over.push(pid);
+            false
+        });
+        if over.is_empty() {
+            return;
+        }
+        log(&format!(
+            "{} process(es) used {} of CPU time since the signal ({} elapsed), {}",
+            over.len(),
+            log::duration(budget),
+            log::duration(self.started.elapsed()),
+            if self.no_kill {
+                "leaving them running".to_string()
+            } else {
+                format!("sending {}", self.kill_signal)
+            }
+        ));
+        if self.no_kill {
+            for pid in over {
+                self.record(report, pid, Outcome::Survived);
+            }
+        } else {
+            self.kill(over, report);
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn kill_over_cpu_budget(&mut self, _remaining: &mut Vec<u32>, _report: &mut KillReport) {}
+
+    /// Sends an escalation stage's `signal` and returns the PIDs still to
+    /// wait for. A process that is already gone exited gracefully; one that
+    /// can't be signalled for another reason stays in line for the kill.
+    fn send_to_stage(&mut self, pids: &[u32], signal: Signal, report: &mut KillReport) -> Vec<u32> {
+        let mut remaining = Vec::with_capacity(pids.len());
+        for &pid in pids {
+            match self.deliver(pid, None, signal) {
+                Ok(()) => log(&format!("Sent {signal} to PID {pid}")),
+                Err(SignalError::NotFound) => {
+                    log(&format!("Process {pid} exited before {signal}"));
+                    self.record(report, pid, Outcome::Graceful);
+                    continue;
+                }
+                Err(e) => log::error(&format!("Failed to send {signal} to PID {pid}: {e}")),
+            }
+            remaining.push(pid);
+        }
+        remaining
+    }
+
+    /// Sends the kill signal to the processes in `remaining` that are still
+    /// running and not protected.
+    fn kill(&mut self, remaining: Vec<u32>, report: &mut KillReport) {
+        // Filter to only unprotected processes still running, then send the kill signal
+        let still_running: Vec<u32> = remaining
+            .into_iter()
+            .filter(|&pid| {
+                if !self.probe(pid, None, report) {
+                    log(&format!("Process {pid} exited before {}", self.kill_signal));
+                    self.record(report, pid, Outcome::Graceful);
+                    false
+                } else if self.protected.contains(&pid) {
+                    log::warn(&format!(
+                        "Policy forbids sending {} to protected PID {pid}, leaving it running",
+                        self.kill_signal
+                    ));
+                    self.record(report, pid, Outcome::Survived);
+                    false
+                } else {
+                    true
+                }
+            })
+            .collect();
+
+        if !still_running.is_empty() {
+            for callback in &mut self.before_kill {
+                callback(&still_running);
+            }
+        }
+        let started = start_times(&still_running);
+        let killed = if self.abort_on_kill_failure {
+            self.kill_until_failure(&still_running, report)
+        } else {
+            self.send_to_all(
+                &still_running,
+                self.kill_signal,
+                Outcome::KillFailed,
+                report,
+            )
+        };
+        if self.exit_status {
+            self.reap_killed(&killed, report);
+        }
+        self.verify_killed(&killed, &started, report);
+        for pid in killed {
+            self.record(report, pid, Outcome::Killed);
+        }
+    }
+
+    /// Polls `killed` until every process is gone or [`VERIFY_TIMEOUT`] has
+    /// passed, logging each one confirmed and recording the rest in
+    /// [`KillReport::lingering`]. Zombies count as gone: they have released
+    /// everything but their process table entry, and so does a PID whose
+    /// start time is no longer the one in `started`, taken just before the
+    /// kill signal, as it has been reused by another process. With a kill
+    /// signal that dumps core, processes seen dumping core are given up to
+    /// [`CORE_DUMP_TIMEOUT`] to finish instead.
+    fn verify_killed(
+        &mut self,
+        killed: &[u32],
+        started: &HashMap<u32, u64>,
+        report: &mut KillReport,
+    ) {
+        let start = Instant::now();
+        let mut present: Vec<u32> = killed.to_vec();
+        let mut dumping: Vec<u32> = Vec::new();
+        loop {
+            present.retain(|&pid| {
+                let gone =
+                    !self.controller.is_running(pid) || (self.check_zombies && is_zombie(pid));
+                if gone {
+                    log(&format!("Confirmed PID {pid} terminated"));
+                } else if is_reused(pid, started.get(&pid)) {
+                    log(&format!(
+                        "PID {pid} exited (PID reused by unrelated process)"
+                    ));
+                    return false;
+                }
+                !gone
+            });
+            if self.kill_signal.dumps_core() {
+                for &pid in &present {
+                    if !dumping.contains(&pid) && is_dumping_core(pid) {
+                        log(&format!(
+                            "PID {pid} is dumping core after {}, waiting up to {} for it to finish",
+                            self.kill_signal,
+                            log::duration(CORE_DUMP_TIMEOUT)
+                        ));
+                        dumping.push(pid);
+                    }
+                }
+            }
+            let timeout = if present.iter().any(|pid| dumping.contains(pid)) {
+                CORE_DUMP_TIMEOUT
+            } else {
+                VERIFY_TIMEOUT
+            };
+            if present.is_empty() || start.elapsed() >= timeout {
+                break;
+            }
+            thread::sleep(self.poll_interval.min(VERIFY_TIMEOUT));
+        }
+        for pid in present {
+            if dumping.contains(&pid) {
+                log::warn(&format!(
+                    "WARNING: PID {pid} still dumping core {} after {}",
+                    log::duration(CORE_DUMP_TIMEOUT),
+                    self.kill_signal
+                ));
+            } else {
+                log::warn(&format!(
+                    "WARNING: PID {pid} still present after {}",
+                    self.kill_signal
+                ));
+            }
+            report.lingering.push(pid);
+        }
+    }
+
+    /// Returns whether `pid` is still running, reaping it first if it is our
+    /// child and exit statuses are being collected. `running` is a snapshot
+    /// from [`ProcessController::running_among`], if one was taken.
+    fn probe(&mut self, pid: u32, running: Option<&HashSet<u32>>, report: &mut KillReport) -> bool {
+        if self.exit_status {
+            match self.controller.try_wait(pid) {
+                WaitResult::Running => return true,
+                WaitResult::Exited(status) => {
+                    log(&format!("Process {pid} {status}"));
+                    report.exit_statuses.push((pid, status));
+                    return false;
+                }
+                WaitResult::NotChild => {}
+            }
+        }
+        match running {
+            Some(running) => running.contains(&pid),
+            None => self.controller.is_running(pid),
+        }
+    }
+
+    /// Waits briefly for killed children to be reaped so their status can
+    /// be recorded. The kill signal is asynchronous, so the child may not
+    /// have been torn down yet when it returns.
+    fn reap_killed(&mut self, killed: &[u32], report: &mut KillReport) {
+        let deadline = Instant::now() + REAP_TIMEOUT;
+        let mut unreaped: Vec<u32> = killed.to_vec();
+        while !unreaped.is_empty() {
+            unreaped.retain(|&pid| match self.controller.try_wait(pid) {
+                WaitResult::Running => true,
+                WaitResult::Exited(status) => {
+                    log(&format!("Process {pid} {status}"));
+                    report.exit_statuses.push((pid, status));
+                    false
+                }
+                WaitResult::NotChild => false,
+            });
+            if Instant::now() >= deadline {
+                break;
+            }
+            thread::sleep(self.poll_interval.min(REAP_TIMEOUT));
+        }
+    }
+
+    /// Delivers `signal` to `pid`, or to its thread `tid`, retrying a few times
+    /// while the kernel reports its signal queue as full.
+    fn deliver(&mut self, pid: u32, tid: Option<u32>, signal: Signal) -> Result<(), SignalError> {
+        if let Some(interval) = self.send_interval {
+            if let Some(last) = self.last_send {
+                thread::sleep(interval.saturating_sub(last.elapsed()));
+            }
+            self.last_send = Some(Instant::now());
+        }
+        if signal == self.kill_signal
+            && let Some(limit) = self.kill_concurrency
+        {
+            if self.kills_in_burst == limit {
+                log::debug(&format!(
+                    "Sent {signal} to {limit} process(es), pausing for {}",
+                    log::duration(KILL_BURST_PAUSE)
+                ));
+                thread::sleep(KILL_BURST_PAUSE);
+                self.kills_in_burst = 0;
+            }
+            self.kills_in_burst += 1;
+        }
+        let mut attempts = 0;
+        loop {
+            let result = match tid {
+                Some(tid) => self.controller.send_thread_signal(pid, tid, signal),
+                None => self.controller.send_signal(pid, signal),
+            };
+            match result {
+                Err(SignalError::QueueFull) if attempts < QUEUE_FULL_RETRIES => {
+                    attempts += 1;
+                    log::debug(&format!(
+                        "Signal queue full sending {signal} to PID {pid}, retrying ({attempts}/{QUEUE_FULL_RETRIES})"
+                    ));
+                    thread::sleep(QUEUE_FULL_BACKOFF);
+                }
+                result => {
+                    if result.is_ok() {
+                        self.last_sent.insert(pid, signal);
+                    }
+                    return result;
+                }
+            }
+        }
+    }
+
+    /// Sends the graceful signal to thread `tid` of every PID and returns the
+    /// PIDs it reached.
+    fn send_to_thread(&mut self, pids: &[u32], tid: u32, report: &mut KillReport) -> Vec<u32> {
+        let mut successful_pids = Vec::with_capacity(pids.len());
+        for &pid in pids {
+            let signal = self.signal_of(pid);
+            match self.deliver(pid, Some(tid), signal) {
+                Ok(()) => {
+                    log(&format!("Sent {signal} to thread {tid} of PID {pid}"));
+                    self.first_sent.insert(pid, Instant::now());
+                    successful_pids.push(pid);
+                }
+                Err(e) => {
+                    log::error(&format!(
+                        "Failed to send {signal} to thread {tid} of PID {pid}: {e}"
+                    ));
+                    self.record(report, pid, Outcome::SignalFailed(e));
+                }
+            }
+        }
+        successful_pids
+    }
+
+    /// Sends the kill signal to each PID in turn until one fails, leaving the
+    /// rest running, and returns the PIDs it reached.
+    fn kill_until_failure(&mut self, pids: &[u32], report: &mut KillReport) -> Vec<u32> {
+        let signal = self.kill_signal;
+        let mut killed = Vec::with_capacity(pids.len());
+        for (index, &pid) in pids.iter().enumerate() {
+            if let Err(e) = self.deliver(pid, None, signal) {
+                log::error(&format!("Failed to send {signal} to PID {pid}: {e}"));
+                self.record(report, pid, Outcome::KillFailed(e));
+                let rest = &pids[index + 1..];
+                log::error(&format!(
+                    "Aborting the kill stage, leaving {} process(es) without {signal}",
+                    rest.len()
+                ));
+                for &pid in rest {
+                    self.record(report, pid, Outcome::Survived);
+                }
+                report.aborted = true;
+                break;
+            }
+            log(&format!("Sent {signal} to PID {pid}"));
+            killed.push(pid);
+        }
+        killed
+    }
+
+    /// Sends each PID its graceful signal, recording failures as
+    /// [`Outcome::SignalFailed`], and returns the PIDs it reached.
+    /// With [`ignore_missing`](Self::ignore_missing), PIDs that don't exist
+    /// are recorded as [`Outcome::Graceful`] instead, as are those of
+    /// [`exit_with_relatives`](Self::exit_with_relatives).
+    fn send_graceful(&mut self, pids: &[u32], report: &mut KillReport) -> Vec<u32> {
+        let mut successful_pids = Vec::with_capacity(pids.len());
+        let mut escalated = Vec::new();
+        for &pid in pids {
+            let signal = self.signal_of(pid);
+            if self.skip_exiting
+                && let Some((reason, exited)) = exiting(pid)
+            {
+                log(&format!(
+                    "PID {pid} is already {reason}, not sending {signal}"
+                ));
+                if exited {
+                    self.record(report, pid, Outcome::Graceful);
+                } else {
+                    self.first_sent.insert(pid, Instant::now());
+                    successful_pids.push(pid);
+                }
+                continue;
+            }
+            if self.missing_handler(pid, signal) {
+                escalated.push(pid);
+                continue;
+            }
+            match self.deliver(pid, None, signal) {
+                Ok(()) => {
+                    log(&format!("Sent {signal} to PID {pid}"));
+                    self.first_sent.insert(pid, Instant::now());
+                    successful_pids.push(pid);
+                }
+                Err(SignalError::NotFound) if self.exit_with_relatives.contains(&pid) => {
+                    log(&format!(
+                        "Process {pid} exited with its relatives before its own {signal}"
+                    ));
+                    self.record(report, pid, Outcome::Graceful);
+                }
+                Err(SignalError::NotFound) if self.ignore_missing => {
+                    log::debug(&format!("Process {pid} already exited"));
+                    report.vanished.push(pid);
+                    self.record(report, pid, Outcome::Graceful);
+                }
+                Err(e) => {
+                    if e == SignalError::NotFound {
+                        report.vanished.push(pid);
+                    }
+                    log::error(&format!("Failed to send {signal} to PID {pid}: {e}"));
+                    self.record(report, pid, Outcome::SignalFailed(e));
+                }
+            }
+        }
+        if !escalated.is_empty() {
+            self.kill(escalated, report);
+        }
+        successful_pids
+    }
+
+    /// With [`require_handler`](Self::require_handler), warns if `pid` has no
+    /// handler installed for `signal`, and returns whether to escalate it
+    /// straight away rather than send it.
+    #[cfg(target_os = "linux")]
+    fn missing_handler(&self, pid: u32, signal: Signal) -> bool {
+        let Some(policy) = self.require_handler else {
+            return false;
+        };
+        // SIGKILL can't be caught, so there's nothing to check
+        if signal == Signal::Kill {
+            return false;
+        }
+        let Ok(masks) = procfs::signal_masks(pid) else {
+            return false;
+        };
+        if procfs::SignalMasks::contains(masks.caught, signal) {
+            return false;
+        }
+        let action = if procfs::SignalMasks::contains(masks.ignored, signal) {
+            "ignores it"
+        } else {
+            "will be ended at once by its default action"
+        };
+        let escalate = policy == MissingHandler::Escalate && !self.no_kill;
+        if escalate {
+            log::warn(&format!(
+                "PID {pid} has no handler for {signal} and {action}, sending {} now",
+                self.kill_signal
+            ));
+        } else {
+            log::warn(&format!(
+                "PID {pid} has no handler for {signal} and {action}"
+            ));
+        }
+        escalate
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn missing_handler(&self, _pid: u32, _signal: Signal) -> bool {
+        false
+    }
+
+    /// Sends `signal` to every PID, recording failures with `on_error`, and
+    /// returns the PIDs it reached.
+    fn send_to_all(
+        &mut self,
+        pids: &[u32],
+        signal: Signal,
+        on_error: fn(SignalError) -> Outcome,
+        report: &mut KillReport,
+    ) -> Vec<u32> {
+        let mut successful_pids = Vec::with_capacity(pids.len());
+        for &pid in pids {
+            match self.deliver(pid, None, signal) {
+                Ok(()) => {
+                    log(&format!("Sent {signal} to PID {pid}"));
+                    successful_pids.push(pid);
+                }
+                Err(e) => {
+                    log::error(&format!("Failed to send {signal} to PID {pid}: {e}"));
+                    self.record(report, pid, on_error(e));
+                }
+            }
+        }
+        successful_pids
+    }
+}
@@ -0,0 +1,113 @@
+//! `--listen`: accept further targets over a Unix domain socket while the
+//! grace period runs, for drain coordinators that discover processes to
+//! stop as they go.
+//!
+//! Each connection sends PIDs, one per line, and may send as many as it
+//! likes before closing. Every valid PID is handed to the kill in progress;
+//! malformed lines are logged and skipped without dropping the connection.
+
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+
+use gracekill::log::{self, log};
+
+use crate::args::parse_and_validate_pid;
+
+/// Checks a received PID before it becomes a target.
+type Accept = Arc<dyn Fn(u32) -> Result<(), String> + Send + Sync>;
+
+/// The bound socket, removed again when dropped.
+pub struct Listener {
+    path: PathBuf,
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Binds a socket at `path` and starts accepting connections on a
+/// background thread. Received PIDs that `accept` allows come out of the
+/// returned receiver.
+///
+/// # Errors
+///
+/// Returns a message if the socket can't be bound, e.g. because `path`
+/// already exists.
+pub fn bind(
+    path: &str,
+    accept: impl Fn(u32) -> Result<(), String> + Send + Sync + 'static,
+) -> Result<(Listener, Receiver<u32>), String> {
+    let listener =
+        UnixListener::bind(path).map_err(|e| format!("Cannot listen on '{path}': {e}"))?;
+    let (sender, receiver) = mpsc::channel();
+    let accept: Accept = Arc::new(accept);
+    let name = path.to_string();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let sender = sender.clone();
+                    let accept = Arc::clone(&accept);
+                    let name = name.clone();
+                    thread::spawn(move || serve(stream, &sender, &accept, &name));
+                }
+                Err(e) => log::warn(&format!("Failed to accept a connection on '{name}': {e}")),
+            }
+        }
+    });
+    log(&format!("Listening on '{path}' for more targets"));
+    Ok((
+        Listener {
+            path: PathBuf::from(path),
+        },
+        receiver,
+    ))
+}
+
+/// Reads PIDs from one connection until it closes.
+fn serve(stream: UnixStream, sender: &Sender<u32>, accept: &Accept, name: &str) {
+    let mut received = Vec::new();
+    for line in BufReader::new(stream).lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(e) => {
+                log::warn(&format!("Connection on '{name}' failed: {e}"));
+                break;
+            }
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        match parse_and_validate_pid(line).and_then(|pid| accept(pid).map(|()| pid)) {
+            Ok(pid) => {
+                if sender.send(pid).is_err() {
+                    log::warn(&format!(
+                        "Received PID {pid} on '{name}' after the grace period, ignoring it"
+                    ));
+                    continue;
+                }
+                received.push(pid);
+            }
+            Err(e) => log::warn(&format!("Ignoring input on '{name}': {e}")),
+        }
+    }
+    if !received.is_empty() {
+        log(&format!(
+            "Received {} PID(s) on '{name}': {}",
+            received.len(),
+            received
+                .iter()
+                .map(u32::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+    }
+}
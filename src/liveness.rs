@@ -0,0 +1,106 @@
+//! `--liveness`: how to tell whether a target is still running.
+
+use std::str::FromStr;
+
+use gracekill::log;
+use gracekill::{ProcessController, System};
+
+/// A liveness check strategy.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Liveness {
+    /// `kill(pid, 0)`. Works everywhere, but a zombie still counts as running.
+    Signal0,
+    /// Whether `/proc/<pid>` exists. Needs no permission over the target,
+    /// but also sees zombies and only works on Linux.
+    Proc,
+    /// Polls a pidfd (Linux 5.3+), which reports exit as soon as the process
+    /// terminates, zombie or not.
+    Pidfd,
+}
+
+impl FromStr for Liveness {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "signal0" => Ok(Liveness::Signal0),
+            "proc" => Ok(Liveness::Proc),
+            "pidfd" => Ok(Liveness::Pidfd),
+            // /proc generates no inotify events when a process exits
+            "inotify" => Err(
+                "--liveness inotify can't work: /proc sends no inotify events when a \
+                 process exits. Use --liveness pidfd to see exits promptly"
+                    .to_string(),
+            ),
+            _ => Err(format!(
+                "Unknown liveness check: '{s}' (supported: signal0, proc, pidfd)"
+            )),
+        }
+    }
+}
+
+impl Liveness {
+    /// Checks that this strategy can work here.
+    pub fn check_supported(self) -> Result<(), String> {
+        match self {
+            Liveness::Signal0 => Ok(()),
+            Liveness::Proc if cfg!(target_os = "linux") => Ok(()),
+            Liveness::Proc => Err("--liveness proc is only supported on Linux".to_string()),
+            Liveness::Pidfd => pidfd_running(std::process::id())
+                .map(|_| ())
+                .ok_or_else(|| "--liveness pidfd needs Linux 5.3 or later".to_string()),
+        }
+    }
+
+    pub fn is_running(self, pid: u32) -> bool {
+        match self {
+            Liveness::Signal0 => System.is_running(pid),
+            Liveness::Proc => std::path::Path::new(&format!("/proc/{pid}")).exists(),
+            Liveness::Pidfd => pidfd_running(pid).unwrap_or_else(|| {
+                log::debug(&format!(
+                    "Could not open a pidfd for PID {pid}, using kill(pid, 0)"
+                ));
+                System.is_running(pid)
+            }),
+        }
+    }
+}
+
+/// Whether `pid` is running according to its pidfd, or `None` if a pidfd
+/// can't be opened for a reason other than the process being gone.
+#[cfg(target_os = "linux")]
+fn pidfd_running(pid: u32) -> Option<bool> {
+    let pid = libc::pid_t::try_from(pid).ok()?;
+    // SAFETY: pidfd_open takes two integers and touches no memory of ours.
+    let fd = unsafe { libc::syscall(libc::SYS_pidfd_open, pid, 0) };
+    if fd < 0 {
+        return match nix::errno::Errno::last() {
+            nix::errno::Errno::ESRCH => Some(false),
+            _ => None,
+        };
+    }
+    let fd = libc::c_int::try_from(fd).ok()?;
+    let mut pollfd = libc::pollfd {
+        fd,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    // SAFETY: pollfd is a valid pollfd for the one fd we own, which is closed
+    // right after and never used again.
+    let ready = unsafe {
+        let ready = libc::poll(&raw mut pollfd, 1, 0);
+        libc::close(fd);
+        ready
+    };
+    // The pidfd becomes readable once the process has terminated
+    match ready {
+        0 => Some(true),
+        1 => Some(false),
+        _ => None,
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn pidfd_running(_pid: u32) -> Option<bool> {
+    None
+}
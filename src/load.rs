@@ -0,0 +1,72 @@
+//! `--load-aware`: give targets more time on a machine under CPU pressure,
+//! where a shutdown that normally takes 10 seconds may take 30.
+//!
+//! The grace period is stretched by how much slower a runnable task makes
+//! progress. With pressure stall information, `/proc/pressure/cpu` says
+//! what share `s` of the last 10 seconds some task spent waiting for a CPU,
+//! and a target only running `1 - s` of the time needs `1 / (1 - s)` times
+//! as long. Without it, the 1-minute load average `L` on `N` CPUs gives each
+//! runnable task `N / L` of a CPU, so the factor is `L / N`. Either way it is
+//! never below 1 and at most [`MAX_FACTOR`]. Pressure is read once, when
+//! gracekill starts.
+
+use std::time::Duration;
+
+/// The most the grace period is stretched by.
+pub const MAX_FACTOR: f64 = 4.0;
+
+/// How much to stretch the grace period by, and a description of the
+/// pressure that called for it, e.g. `CPU pressure 40.00%`.
+#[cfg(target_os = "linux")]
+pub fn factor() -> Result<(f64, String), String> {
+    use std::fs;
+
+    let (factor, observed) = if let Ok(psi) = fs::read_to_string("/proc/pressure/cpu") {
+        let share = parse_psi(&psi).ok_or_else(|| "Cannot parse /proc/pressure/cpu".to_string())?;
+        let factor = if share >= 1.0 {
+            MAX_FACTOR
+        } else {
+            1.0 / (1.0 - share)
+        };
+        (factor, format!("CPU pressure {:.2}%", share * 100.0))
+    } else {
+        let loadavg = fs::read_to_string("/proc/loadavg")
+            .map_err(|e| format!("Cannot read /proc/loadavg: {e}"))?;
+        let load: f64 = loadavg
+            .split_whitespace()
+            .next()
+            .and_then(|load| load.parse().ok())
+            .ok_or_else(|| "Cannot parse /proc/loadavg".to_string())?;
+        let cpus = std::thread::available_parallelism().map_or(1, std::num::NonZero::get);
+        #[allow(clippy::cast_precision_loss)] // CPU counts are far below 2^52
+        let factor = load / cpus as f64;
+        (factor, format!("load average {load:.2} on {cpus} CPU(s)"))
+    };
+    Ok((factor.clamp(1.0, MAX_FACTOR), observed))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn factor() -> Result<(f64, String), String> {
+    Err("--load-aware is only supported on Linux".to_string())
+}
+
+/// The `some avg10` share, from 0 to 1, in the contents of a PSI file such
+/// as `some avg10=40.00 avg60=12.50 avg300=3.10 total=123456`.
+#[cfg(target_os = "linux")]
+fn parse_psi(contents: &str) -> Option<f64> {
+    let some = contents.lines().find(|line| line.starts_with("some "))?;
+    let avg10 = some
+        .split_whitespace()
+        .find_map(|field| field.strip_prefix("avg10="))?;
+    avg10.parse::<f64>().ok().map(|percent| percent / 100.0)
+}
+
+/// `grace` stretched by `factor`, to the millisecond.
+pub fn stretch(grace: Duration, factor: f64) -> Duration {
+    let stretched = grace.as_secs_f64() * factor;
+    if stretched >= Duration::MAX.as_secs_f64() {
+        return Duration::MAX;
+    }
+    let stretched = Duration::from_secs_f64(stretched);
+    Duration::from_millis(u64::try_from(stretched.as_millis()).unwrap_or(u64::MAX))
+}
@@ -0,0 +1,349 @@
+//! Diagnostic output shared by the library and the command-line tool.
+//!
+//! All lines go through a single locked writer, so lines logged concurrently
+//! from several threads never interleave.
+//!
+//! Lines below the current [`Level`] are discarded. The level starts out as
+//! whatever the `GRACEKILL_LOG` environment variable names (`error`, `warn`,
+//! `summary`, `info` or `debug`), defaulting to `info`, and can be overridden
+//! with [`set_level`].
+//!
+//! Lines are prefixed with `[gracekill]`, or with `[gracekill <id>]` once an
+//! [operation ID](set_operation_id) is set, so that the lines of concurrent
+//! runs sharing one log can be told apart.
+//!
+//! Durations in log lines are all written by [`duration`], in the
+//! [`DurationFormat`] set with [`set_duration_format`].
+//!
+//! Output can also be [held](hold) in memory and later either
+//! [released](release) or [discarded](discard), for callers that only want
+//! output when something goes wrong.
+//!
+//! With [coalescing](set_coalesce) on, a line logged again within
+//! [`COALESCE_WINDOW`] of the first time is only counted. Once a different
+//! line comes, or the output is [flushed](flush), the count is written as
+//! the line again ending `(repeated N times)`, so that a line repeated by
+//! every poll of a large kill takes two lines a window instead of hundreds.
+
+use std::fmt;
+use std::io::{self, BufWriter, Stderr, Write};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{LazyLock, Mutex, OnceLock, PoisonError};
+use std::time::{Duration, Instant};
+
+/// Environment variable consulted for the initial log level.
+pub const ENV_VAR: &str = "GRACEKILL_LOG";
+
+/// How long after a line is written that the same line again is only
+/// counted, with [coalescing](set_coalesce) on.
+pub const COALESCE_WINDOW: Duration = Duration::from_secs(1);
+
+/// The last line written with coalescing on, and how many times it was
+/// logged again since.
+struct Repeats {
+    line: String,
+    since: Instant,
+    count: u32,
+}
+
+struct Sink {
+    writer: BufWriter<Stderr>,
+    /// Lines logged while output is held, in order.
+    held: Option<Vec<String>>,
+    coalesce: bool,
+    last: Option<Repeats>,
+}
+
+impl Sink {
+    /// Writes `line` out, or holds it.
+    fn emit(&mut self, line: String) {
+        if let Some(held) = &mut self.held {
+            held.push(line);
+            return;
+        }
+        let _ = writeln!(self.writer, "{line}");
+        let _ = self.writer.flush();
+    }
+
+    /// Writes out how many times the last line was repeated, if it was.
+    fn flush_repeats(&mut self) {
+        if let Some(last) = self.last.take()
+            && last.count > 0
+        {
+            self.emit(format!("{} (repeated {} times)", last.line, last.count));
+        }
+    }
+}
+
+static SINK: LazyLock<Mutex<Sink>> = LazyLock::new(|| {
+    Mutex::new(Sink {
+        writer: BufWriter::new(io::stderr()),
+        held: None,
+        coalesce: false,
+        last: None,
+    })
+});
+
+fn sink() -> std::sync::MutexGuard<'static, Sink> {
+    SINK.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+static OPERATION_ID: OnceLock<String> = OnceLock::new();
+
+const UNSET: u8 = u8::MAX;
+static LEVEL: AtomicU8 = AtomicU8::new(UNSET);
+
+/// How much diagnostic output to produce, from least to most.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    /// Failures only.
+    Error,
+    /// Failures and anything that looks like a mistake.
+    Warn,
+    /// Failures, warnings and the end-of-run recap.
+    Summary,
+    /// Progress of the kill: signals sent and processes exiting. The default.
+    Info,
+    /// Everything, including each poll of the remaining processes.
+    Debug,
+}
+
+impl Level {
+    const ALL: [Level; 5] = [
+        Level::Error,
+        Level::Warn,
+        Level::Summary,
+        Level::Info,
+        Level::Debug,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Level::Error => "error",
+            Level::Warn => "warn",
+            Level::Summary => "summary",
+            Level::Info => "info",
+            Level::Debug => "debug",
+        }
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Level {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Level::ALL
+            .into_iter()
+            .find(|level| level.name().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| format!("Unknown log level: '{s}'"))
+    }
+}
+
+/// The current log level.
+pub fn level() -> Level {
+    let stored = LEVEL.load(Ordering::Relaxed);
+    if let Some(&level) = Level::ALL.get(usize::from(stored)) {
+        return level;
+    }
+    let level = std::env::var(ENV_VAR)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(Level::Info);
+    // Lose the race gracefully if set_level ran in the meantime
+    let _ = LEVEL.compare_exchange(UNSET, level as u8, Ordering::Relaxed, Ordering::Relaxed);
+    Level::ALL[usize::from(LEVEL.load(Ordering::Relaxed))]
+}
+
+/// How [`duration`] writes durations.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum DurationFormat {
+    /// The largest unit that fits, to the millisecond: `25s`, `1.5s`,
+    /// `200ms`. The default.
+    #[default]
+    Short,
+    /// Minutes and seconds to the millisecond, with hours once there are
+    /// any: `00:25.104`, `1:02:03.000`.
+    Clock,
+    /// Whole milliseconds: `25104ms`.
+    Millis,
+}
+
+impl DurationFormat {
+    const ALL: [DurationFormat; 3] = [
+        DurationFormat::Short,
+        DurationFormat::Clock,
+        DurationFormat::Millis,
+    ];
+
+    /// The name `--duration-format` takes.
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            DurationFormat::Short => "short",
+            DurationFormat::Clock => "clock",
+            DurationFormat::Millis => "ms",
+        }
+    }
+}
+
+impl FromStr for DurationFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        DurationFormat::ALL
+            .into_iter()
+            .find(|format| format.name().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| format!("Unknown duration format: '{s}' (expected short, clock or ms)"))
+    }
+}
+
+static DURATION_FORMAT: AtomicU8 = AtomicU8::new(DurationFormat::Short as u8);
+
+/// Sets how [`duration`] writes durations from now on.
+pub fn set_duration_format(format: DurationFormat) {
+    DURATION_FORMAT.store(format as u8, Ordering::Relaxed);
+}
+
+/// Writes `duration` for a log line, in the format set with
+/// [`set_duration_format`], truncated to the millisecond.
+#[must_use]
+pub fn duration(duration: Duration) -> String {
+    let millis = duration.as_millis();
+    let (secs, millis_part) = (millis / 1000, millis % 1000);
+    match DurationFormat::ALL[usize::from(DURATION_FORMAT.load(Ordering::Relaxed))] {
+        DurationFormat::Short if millis == 0 => "0s".to_string(),
+        DurationFormat::Short if secs == 0 => format!("{millis}ms"),
+        DurationFormat::Short if millis_part == 0 => format!("{secs}s"),
+        DurationFormat::Short => {
+            let fraction = format!("{millis_part:03}");
+            format!("{secs}.{}s", fraction.trim_end_matches('0'))
+        }
+        DurationFormat::Clock if secs >= 3600 => format!(
+            "{}:{:02}:{:02}.{millis_part:03}",
+            secs / 3600,
+            secs / 60 % 60,
+            secs % 60
+        ),
+        DurationFormat::Clock => format!("{:02}:{:02}.{millis_part:03}", secs / 60, secs % 60),
+        DurationFormat::Millis => format!("{millis}ms"),
+    }
+}
+
+/// Overrides the log level, taking precedence over `GRACEKILL_LOG`.
+pub fn set_level(level: Level) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+/// Sets the ID included in the prefix of every line from now on. Only the
+/// first call has any effect.
+pub fn set_operation_id(id: &str) {
+    let _ = OPERATION_ID.set(id.to_string());
+}
+
+/// The ID set with [`set_operation_id`], if any.
+pub fn operation_id() -> Option<&'static str> {
+    OPERATION_ID.get().map(String::as_str)
+}
+
+/// Writes a `[gracekill]`-prefixed diagnostic line to stderr at `level`.
+pub fn log_at(level: Level, message: &str) {
+    if level > self::level() {
+        return;
+    }
+    // A panic while holding the lock can't leave a partial line behind, since
+    // each line is written and flushed in full below.
+    let mut sink = sink();
+    let line = match operation_id() {
+        Some(id) => format!("[gracekill {id}] {message}"),
+        None => format!("[gracekill] {message}"),
+    };
+    if sink.coalesce {
+        let now = Instant::now();
+        if let Some(last) = &mut sink.last
+            && last.line == line
+            && now.duration_since(last.since) < COALESCE_WINDOW
+        {
+            last.count = last.count.saturating_add(1);
+            return;
+        }
+        sink.flush_repeats();
+        sink.last = Some(Repeats {
+            line: line.clone(),
+            since: now,
+            count: 0,
+        });
+    }
+    sink.emit(line);
+}
+
+/// Turns coalescing of repeated lines on or off, writing out the count of
+/// any repeats so far.
+pub fn set_coalesce(coalesce: bool) {
+    let mut sink = sink();
+    sink.flush_repeats();
+    sink.coalesce = coalesce;
+}
+
+/// Writes out how many times the last line was repeated, if it was, for
+/// callers about to exit with coalescing on. [`release`] does this too.
+pub fn flush() {
+    sink().flush_repeats();
+}
+
+/// Holds subsequent lines in memory instead of writing them.
+pub fn hold() {
+    let mut sink = sink();
+    sink.flush_repeats();
+    sink.held.get_or_insert_with(Vec::new);
+}
+
+/// Writes out every held line, and the count of any repeats of the last
+/// one, and stops holding.
+pub fn release() {
+    let mut sink = sink();
+    sink.flush_repeats();
+    for line in sink.held.take().unwrap_or_default() {
+        let _ = writeln!(sink.writer, "{line}");
+    }
+    let _ = sink.writer.flush();
+}
+
+/// Drops every held line, repeats included, and stops holding.
+pub fn discard() {
+    let mut sink = sink();
+    if sink.held.take().is_some() {
+        sink.last = None;
+    }
+}
+
+/// Logs progress at [`Level::Info`].
+pub fn log(message: &str) {
+    log_at(Level::Info, message);
+}
+
+/// Logs a failure at [`Level::Error`].
+pub fn error(message: &str) {
+    log_at(Level::Error, message);
+}
+
+/// Logs a probable mistake at [`Level::Warn`].
+pub fn warn(message: &str) {
+    log_at(Level::Warn, message);
+}
+
+/// Logs the end-of-run recap at [`Level::Summary`].
+pub fn summary(message: &str) {
+    log_at(Level::Summary, message);
+}
+
+/// Logs fine-grained detail at [`Level::Debug`].
+pub fn debug(message: &str) {
+    log_at(Level::Debug, message);
+}
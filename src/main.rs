@@ -1,230 +1,2582 @@
 #![warn(clippy::all, clippy::pedantic, clippy::cargo)]
 
+mod args;
+mod audit;
+mod batch;
+mod commands;
+mod confirm;
+mod container;
+mod core_dump;
+mod daemon;
+mod deadline;
+mod discover;
+mod drain;
+mod drift;
+mod dry_run;
+mod dump;
+mod duration;
+mod emit_script;
+mod enrich;
+mod error_format;
+mod exit_file;
+mod fifo;
+mod freeze;
+mod grace_table;
+mod handoff;
+mod json;
+mod listen;
+mod liveness;
+mod load;
+mod nagios;
+#[cfg(target_os = "linux")]
+mod netns;
+mod on_timeout;
+mod orphans;
+mod otlp;
+mod pid_file;
+mod pid_lock;
+mod plan;
+mod policy;
+mod port;
+mod process_set;
+mod protect_file;
+mod report_fd;
+mod sd_notify;
+mod selftest;
+mod shell_vars;
+mod show_config;
+mod shuffle;
+mod shutdown;
+mod simulate;
+mod size;
+mod snapshot;
+mod stage;
+mod stats;
+mod status_json;
+mod stdin_cmd;
+mod stdout;
+mod stream;
+mod tail;
+mod then_exec;
+#[cfg(target_os = "linux")]
+mod threads;
+mod threshold;
+mod trace;
+mod tree_order;
+mod tty;
+mod victim;
+mod watch;
+
 use std::env;
-use std::io::{self, Write};
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
 use std::process;
+use std::sync::{Arc, Mutex, PoisonError};
 use std::thread;
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime};
 
-use nix::sys::signal::{self, Signal as NixSignal};
-use nix::unistd::Pid;
+use gracekill::log::{self, log};
+use gracekill::{GraceKill, KillReport, Outcome, Signal, Stage, Tally};
 
-const DEFAULT_GRACE_SECONDS: u64 = 25;
+use args::{Options, parse_args, parse_kill_args, print_usage};
+use batch::Operation;
+use commands::Commands;
+use deadline::{IfPast, OverBudget};
+use enrich::Enrich;
+use error_format::{ErrorFormat, Kind};
+use liveness::Liveness;
+use simulate::Simulation;
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
-
-    if args.len() < 2 {
-        print_usage(&args[0]);
-        process::exit(1);
+    log::set_operation_id(&new_operation_id());
+    let mut options = parse_command_line();
+    error_format::set(options.error_format);
+    log::set_duration_format(options.duration_format);
+    stdout::set(options.epipe);
+    if options.stats {
+        stats::enable();
     }
-
-    let (pids, grace_period, exit_non_zero_if_sigkill_required) = match parse_args(&args[1..]) {
-        Ok(result) => result,
-        Err(e) => {
-            eprintln!("Error: {e}");
-            print_usage(&args[0]);
-            process::exit(1);
+    if let Some(url) = &options.otlp_endpoint {
+        otlp::enable(url);
+    }
+    if let Err(e) = apply_global_options(&mut options) {
+        fail(&e);
+    }
+    if options.show_config {
+        show_config::print(&options);
+        if !options.proceed {
+            finish(0);
         }
-    };
+    }
+
+    if let Some(pid) = options.list_threads {
+        list_threads(pid);
+    }
+    if options.selftest {
+        self_test(&options);
+    }
+    if options.sd_notify {
+        sd_notify::open();
+    }
+    run_other_modes(&mut options);
 
-    if pids.is_empty() {
-        eprintln!("Error: No PIDs provided");
-        process::exit(1);
+    if !options.has_targets() && options.listen.is_none() {
+        fail("No PIDs provided");
+    }
+    if let Err(e) = resolve_targets(&mut options) {
+        fail(&e);
     }
+    let operations =
+        graces_from_env(&options).or_else(|| policy::group(&options.policies, &options.pids));
+    describe_only(&options, operations.as_deref());
+    lock_targets(&options);
+    if let Some(operations) = operations {
+        // One kill with per-target grace periods, as for a plan
+        options.parallel = true;
+        finish_run(run_batch(&operations, &options), &options);
+    }
+    let handoff = options
+        .handoff
+        .as_deref()
+        .map(|socket| handoff::connect(socket).unwrap_or_else(|e| fail(&e)));
+    prepare_process(&options);
 
     log(&format!(
-        "Starting graceful kill for {} process(es) with {}s grace period",
-        pids.len(),
-        grace_period.as_secs()
+        "Starting graceful kill for {} process(es) with {} grace period",
+        options.pids.len(),
+        match options.cpu_time_grace {
+            Some(budget) => format!("{} CPU-time", log::duration(budget)),
+            None => log::duration(options.grace_period),
+        }
     ));
 
-    // Send SIGTERM to all processes
-    let active_pids = send_signal_to_all(&pids, Signal::Term);
+    let mut gracekill = kill_for(&options.pids, &options);
+    if let Some(tid) = options.tid {
+        gracekill = gracekill.thread(tid);
+    }
+    if let Some(idle) = options.drain_idle {
+        drain::wait_idle(&options.pids, idle, options.drain_max);
+    }
+    let (gracekill, listener) = listen_for_targets(gracekill, &options);
+    let children = snapshot_children(&options);
+    let tail = (!options.tail.is_empty()).then(|| tail::Tail::start(&options.tail));
+    let exit_files = exit_file::ExitFiles::note(&options.exit_files);
+    let (mut report, mut rows) = run_targets(gracekill, &options);
+    exit_files.check(&mut report);
+    if let Some(tail) = tail {
+        tail.stop();
+    }
+    drop(listener);
+    if let Some(children) = children {
+        kill_orphans(&children, &mut report, &mut rows, &options);
+    }
+    if let Some(handoff) = handoff {
+        hand_off(handoff, &report, &options);
+    }
+    write_report(&report, &rows, &options);
+    if options.reap {
+        reap_children(&report, options.reap_timeout);
+    }
+    if options.wait_children {
+        wait_children(options.grace_period);
+    }
+    if options.reset_tty {
+        tty::reset();
+    }
+    summarize(&report, &options);
+    finish_run(conclude(&report, &options), &options);
+}
+
+/// Ends the run for `--dry-run` and `--emit-script`, which only describe
+/// the kill, and for `--grace-table` without `--proceed`. `operations` are
+/// the targets grouped by their own grace periods, if any have one.
+fn describe_only(options: &Options, operations: Option<&[Operation]>) {
+    if options.grace_table {
+        grace_table::print(options, operations);
+        if !options.proceed {
+            finish(0);
+        }
+    }
+    if options.dry_run {
+        dry_run::run(options);
+        finish(0);
+    }
+    if let Some(path) = &options.emit_script {
+        let protected = protected_pids(&options.pids, options);
+        if let Err(e) = emit_script::write(path, options, &protected) {
+            fail(&e);
+        }
+        finish(0);
+    }
+}
 
-    if active_pids.is_empty() {
-        log("No processes to wait for");
-        process::exit(2); // No processes could be signaled
+/// Exits with `code` at the end of the main kill, or with `--then-exec`
+/// and a clean kill, replaces gracekill with its command.
+fn finish_run(code: i32, options: &Options) -> ! {
+    let Some(command) = &options.then_exec else {
+        finish(code);
+    };
+    if code != 0 {
+        log::warn(&format!(
+            "Not running --then-exec '{command}': the kill ended with exit code {code}"
+        ));
+    } else if options.simulate {
+        log(&format!("Would exec '{command}'"));
+    } else {
+        log(&format!("Replacing gracekill with '{command}'"));
+        flush_output();
+        let e = then_exec::exec(command);
+        fail(&format!("Cannot exec --then-exec '{command}': {e}"));
     }
+    finish(code)
+}
 
-    // Wait for processes to exit gracefully
-    let start = Instant::now();
-    let mut remaining = active_pids;
+/// Ends the output of the main kill: the summary line and what follows it.
+fn summarize(report: &KillReport, options: &Options) {
+    log::summary(&format!("Summary: {}", report.summary()));
+    drift::summarize(report);
+    stats::print(options.status_json);
+    otlp::export();
+    settle_held_log(report, options);
+}
 
-    while !remaining.is_empty() && start.elapsed() < grace_period {
-        thread::sleep(Duration::from_millis(100));
-        remaining.retain(|&pid| {
-            if is_process_running(pid) {
-                true
+/// The name gracekill takes kill(1)-style arguments under.
+const KILL_NAME: &str = "gracekill-kill";
+
+/// Parses the command line, exiting with the usage text if it is empty or
+/// malformed.
+fn parse_command_line() -> Options {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 {
+        print_usage(&args[0]);
+        finish(1);
+    }
+    if args[1] == "--as-victim" {
+        victim::run(&args[2..]);
+    }
+    // Installed as gracekill-kill, it stands in for kill(1)
+    let as_kill = Path::new(&args[0])
+        .file_name()
+        .is_some_and(|name| name == KILL_NAME);
+    let parsed = if as_kill {
+        parse_kill_args(&args[1..])
+    } else {
+        parse_args(&args[1..])
+    };
+    match parsed {
+        Ok(options) => options,
+        Err(e) => {
+            if error_format::requested_in(&args[1..]) {
+                error_format::set(ErrorFormat::Json);
+                error_format::report(Kind::Parse, &e);
             } else {
-                log(&format!("Process {pid} exited gracefully"));
-                false
+                eprintln!("Error: {e}");
+                print_usage(&args[0]);
             }
-        });
+            // Nagios reads exit code 1 as WARNING, so a broken check must say so
+            if args.iter().any(|arg| arg == "--nagios") {
+                finish(nagios::unknown(&e));
+            }
+            finish(1);
+        }
     }
+}
+
+/// A short ID telling this run's output apart from that of others, e.g.
+/// `3fa9c1`, mixed from the PID and the time.
+fn new_operation_id() -> String {
+    let seed = shuffle::random_seed();
+    format!("{:06x}", (seed ^ (seed >> 32) ^ (seed >> 48)) & 0xFF_FFFF)
+}
 
-    // Send SIGKILL to remaining processes
-    if remaining.is_empty() {
-        log("All processes exited gracefully");
-        process::exit(0);
+/// Reads the file at `path`, or stdin if it is `-`.
+fn read_input(path: &str) -> Result<String, String> {
+    if path == "-" {
+        let mut contents = String::new();
+        io::stdin()
+            .read_to_string(&mut contents)
+            .map_err(|e| e.to_string())?;
+        Ok(contents)
     } else {
-        log(&format!(
-            "{} process(es) still running after grace period, sending SIGKILL",
-            remaining.len()
+        fs::read_to_string(path).map_err(|e| e.to_string())
+    }
+}
+
+/// Applies the options that affect the whole process rather than one kill:
+/// logging, /proc timeouts, the liveness check, the shuffle seed and the
+/// trace file.
+fn apply_global_options(options: &mut Options) -> Result<(), String> {
+    check_combinations(options)?;
+    if let Some(path) = options.protect_file.clone() {
+        protect_file::load(&path, options)?;
+    }
+    if options.abort_with_core {
+        options.kill_signal = Signal::Abrt;
+    }
+    if options.nagios {
+        nagios::enable();
+    }
+    if let Some(level) = options.log_level {
+        log::set_level(level);
+    }
+    log::set_coalesce(options.coalesce_logs);
+    if !options.skipped.is_empty() {
+        for skipped in &options.skipped {
+            log::warn(&format!("{skipped}, skipping it"));
+        }
+        log::warn(&format!(
+            "Skipped {} invalid target(s)",
+            options.skipped.len()
         ));
+    }
+    if options.quiet_on_success {
+        log::hold();
+    }
+    if let Some(path) = options.discover.clone() {
+        discover::apply(&path, options)?;
+    }
+    #[cfg(target_os = "linux")]
+    gracekill::procfs::set_timeout(options.proc_timeout);
+    #[cfg(target_os = "linux")]
+    check_pid_max(options)?;
+    if let Some(liveness) = options.liveness {
+        liveness.check_supported()?;
+    }
+    if let Some(deadline) = options.deadline {
+        apply_deadline(options, deadline)?;
+    }
+    if options.load_aware {
+        apply_load(options)?;
+    }
+    if let Some(budget) = options.cpu_time_grace {
+        apply_cpu_time_grace(options, budget)?;
+    } else {
+        options.grace_period = clamp_grace(options.grace_period, options);
+    }
+    let stages = std::mem::take(&mut options.stages);
+    options.stages = stages
+        .into_iter()
+        .map(|stage| Stage {
+            grace: clamp_grace(stage.grace, options),
+            ..stage
+        })
+        .collect();
+    if options.shuffle {
+        let seed = *options.seed.get_or_insert_with(shuffle::random_seed);
+        log(&format!("Shuffling targets with seed {seed}"));
+    }
+    #[cfg(not(target_os = "linux"))]
+    if options.require_handler.is_some() {
+        log::warn("--require-handler is only supported on Linux, signalling every target as usual");
+    }
+    if options.simulate {
+        warn_simulated(options);
+    }
+    if let Some(url) = &options.shutdown_url {
+        shutdown::check_url(url)?;
+    }
+    if let Some(url) = &options.otlp_endpoint {
+        otlp::check_endpoint(url)?;
+    }
+    check_platform(options)?;
+    if let Some(fd) = options.report_pids_fd {
+        report_fd::check(fd)?;
+    }
+    if let Some(path) = &options.trace_file {
+        trace::open(path)?;
+    }
+    Ok(())
+}
 
-        // Filter to only processes still running, then send SIGKILL
-        let still_running: Vec<u32> = remaining
-            .into_iter()
-            .filter(|&pid| {
-                if is_process_running(pid) {
-                    true
-                } else {
-                    log(&format!("Process {pid} exited before SIGKILL"));
-                    false
-                }
-            })
-            .collect();
+/// Warns about the options that do nothing while simulating.
+fn warn_simulated(options: &Options) {
+    log::warn("Simulating every target, no real process will be signalled");
+    if options.dump_command.is_some() {
+        log::warn("--dump-before-kill is not run while simulating");
+    }
+    if options.on_timeout.is_some() {
+        log::warn("--on-timeout is not run while simulating");
+    }
+    if options.shutdown_url.is_some() {
+        log::warn("--shutdown-url is not requested while simulating");
+    }
+    if options.stdin_cmd.is_some() {
+        log::warn("--stdin-cmd is not written while simulating");
+    }
+    if options.snapshot {
+        log::warn("--snapshot is not taken while simulating");
+    }
+    if options.freeze {
+        log::warn("--freeze does not freeze simulated targets");
+    }
+    if !options.expected_commands.is_empty() {
+        log::warn("--expect-cmd is not checked against simulated targets");
+    }
+}
 
-        send_signal_to_all(&still_running, Signal::Kill);
-        if exit_non_zero_if_sigkill_required {
-            process::exit(3);
-        } else {
-            process::exit(0);
+/// Refuses the options that are only supported on Linux elsewhere.
+fn check_platform(options: &Options) -> Result<(), String> {
+    if options.freeze && cfg!(not(target_os = "linux")) {
+        return Err("--freeze is only supported on Linux".to_string());
+    }
+    if !options.expected_commands.is_empty() && cfg!(not(target_os = "linux")) {
+        return Err("--expect-cmd is only supported on Linux".to_string());
+    }
+    if options.grace_from_env.is_some() && cfg!(not(target_os = "linux")) {
+        return Err("--grace-from-env is only supported on Linux".to_string());
+    }
+    if !options.policies.is_empty() && cfg!(not(target_os = "linux")) {
+        return Err("--policy is only supported on Linux".to_string());
+    }
+    if (options.save_set.is_some() || options.kill_new_since.is_some())
+        && cfg!(not(target_os = "linux"))
+    {
+        return Err("--save-set and --kill-new-since are only supported on Linux".to_string());
+    }
+    if options.stdin_cmd.is_some() && cfg!(not(target_os = "linux")) {
+        return Err("--stdin-cmd is only supported on Linux".to_string());
+    }
+    if !options.thresholds.is_empty() && cfg!(not(target_os = "linux")) {
+        return Err(
+            "--if-rss-above, --if-cpu-above and --if-age-above are only supported on Linux"
+                .to_string(),
+        );
+    }
+    Ok(())
+}
+
+/// Goes into the background for `--daemonize` and becomes a subreaper for
+/// `--wait-children`, before the kill starts.
+fn prepare_process(options: &Options) {
+    if options.daemonize
+        && let Err(e) = daemon::daemonize(options.log_file.as_deref())
+    {
+        fail(&e);
+    }
+    if options.wait_children {
+        #[cfg(target_os = "linux")]
+        if let Err(e) = gracekill::children::become_subreaper() {
+            log::warn(&e);
         }
     }
 }
 
-fn print_usage(program: &str) {
-    eprintln!("Usage: {program} [options] <pid>[,pid...]");
-    eprintln!();
-    eprintln!("Arguments:");
-    eprintln!("  pid                    Process ID(s) to kill (comma or space separated)");
-    eprintln!();
-    eprintln!("Options:");
-    eprintln!(
-        "  -g, --grace-seconds                    Grace period in seconds (default: {DEFAULT_GRACE_SECONDS})"
-    );
-    eprintln!("  --exit-non-zero-if-sigkill-required   Exit with code 3 if SIGKILL was required");
-    eprintln!();
-    eprintln!("Examples:");
-    eprintln!("  {program} 1234 5678");
-    eprintln!("  {program} -g 10 1234 5678");
-    eprintln!("  {program} --grace-seconds 30 1234,5678,9012");
+/// Checks that `--background-escalate` isn't combined with options that
+/// run several kills, or threads, neither of which survives the fork.
+fn check_background_escalate(options: &Options) -> Result<(), String> {
+    if options.background_escalate
+        && (options.daemonize
+            || options.listen.is_some()
+            || !options.tail.is_empty()
+            || options.stream
+            || options.batch_file.is_some()
+            || options.plan.is_some()
+            || options.watch_pid_file.is_some()
+            || options.watch
+            || options.handoff.is_some()
+            || options.grace_from_env.is_some()
+            || !options.policies.is_empty()
+            || options.fifo.is_some()
+            || options.dry_run)
+    {
+        return Err(
+            "--background-escalate cannot be combined with --daemonize, --listen, --tail, --stream, --batch-file, --plan, --watch-pid-file, --watch, --handoff, --grace-from-env, --policy, --fifo or --dry-run"
+                .to_string(),
+        );
+    }
+    Ok(())
+}
+
+/// Checks that `--emit-script` isn't combined with options whose runs are
+/// more than one list of targets signalled and waited for.
+fn check_emit_script(options: &Options) -> Result<(), String> {
+    if options.emit_script.is_some()
+        && (options.batch_file.is_some()
+            || options.plan.is_some()
+            || options.watch_pid_file.is_some()
+            || options.watch
+            || options.listen.is_some()
+            || options.fifo.is_some()
+            || options.stream
+            || !options.policies.is_empty()
+            || options.grace_from_env.is_some()
+            || options.cpu_time_grace.is_some()
+            || options.tid.is_some()
+            || options.dry_run)
+    {
+        return Err(
+            "--emit-script cannot be combined with --batch-file, --plan, --watch-pid-file, --watch, --listen, --fifo, --stream, --policy, --grace-from-env, --cpu-time-grace, --tid or --dry-run"
+                .to_string(),
+        );
+    }
+    Ok(())
+}
+
+/// Checks that `--grace-table` is only given to a kill of targets known up
+/// front.
+fn check_grace_table(options: &Options) -> Result<(), String> {
+    if options.grace_table
+        && (options.batch_file.is_some()
+            || options.plan.is_some()
+            || options.watch_pid_file.is_some()
+            || options.watch
+            || options.listen.is_some()
+            || options.fifo.is_some()
+            || options.stream
+            || options.dry_run
+            || options.emit_script.is_some())
+    {
+        return Err(
+            "--grace-table cannot be combined with --batch-file, --plan, --watch-pid-file, --watch, --listen, --fifo, --stream, --dry-run or --emit-script"
+                .to_string(),
+        );
+    }
+    Ok(())
+}
+
+/// Checks for options that only apply together with another, or can't be
+/// combined.
+fn check_combinations(options: &Options) -> Result<(), String> {
+    if options.proceed && !options.show_config && !options.grace_table {
+        return Err("--proceed only applies to --show-config and --grace-table".to_string());
+    }
+    if options.log_file.is_some() && !options.daemonize && !options.background_escalate {
+        return Err("--log-file only applies to --daemonize and --background-escalate".to_string());
+    }
+    check_background_escalate(options)?;
+    check_emit_script(options)?;
+    check_grace_table(options)?;
+    if options.abort_with_core && options.kill_signal != Signal::Kill {
+        return Err(
+            "--abort-with-core cannot be combined with --kill-signal, as it sends SIGABRT"
+                .to_string(),
+        );
+    }
+    if options.daemonize
+        && (options.batch_file.is_some()
+            || options.plan.is_some()
+            || options.watch_pid_file.is_some())
+    {
+        return Err(
+            "--daemonize cannot be combined with --batch-file, --plan or --watch-pid-file"
+                .to_string(),
+        );
+    }
+    if options.nagios && (options.status_json || options.print_commands || options.snapshot) {
+        return Err(
+            "--nagios cannot be combined with --output-pid-status-json, --print-commands or --snapshot, which also write to stdout"
+                .to_string(),
+        );
+    }
+    if options.shell_vars
+        && (options.nagios
+            || options.status_json
+            || options.print_commands
+            || options.snapshot
+            || options.show_config
+            || options.daemonize
+            || options.dry_run)
+    {
+        return Err(
+            "--shell-vars cannot be combined with --nagios, --output-pid-status-json, --print-commands, --snapshot, --show-config, --daemonize or --dry-run"
+                .to_string(),
+        );
+    }
+    if options.handoff.is_some()
+        && (options.batch_file.is_some()
+            || options.plan.is_some()
+            || options.watch_pid_file.is_some()
+            || options.dry_run)
+    {
+        return Err(
+            "--handoff cannot be combined with --batch-file, --plan, --watch-pid-file or --dry-run"
+                .to_string(),
+        );
+    }
+    check_grouped_targets(options)?;
+    check_target_filters(options)?;
+    check_fifo(options)?;
+    check_then_exec(options)?;
+    check_per_wave_grace(options)?;
+    if options.stream
+        && (options.nagios
+            || options.shell_vars
+            || options.snapshot
+            || options.report.is_some()
+            || options.report_pids_fd.is_some()
+            || options.batch_file.is_some()
+            || options.plan.is_some()
+            || options.watch_pid_file.is_some()
+            || options.watch
+            || options.handoff.is_some()
+            || options.grace_from_env.is_some()
+            || !options.policies.is_empty()
+            || options.listen.is_some()
+            || options.daemonize
+            || options.dry_run)
+    {
+        return Err(
+            "--stream cannot be combined with --nagios, --shell-vars, --snapshot, --report, --report-pids-fd, --batch-file, --plan, --watch-pid-file, --watch, --handoff, --grace-from-env, --policy, --listen, --daemonize or --dry-run, which need every target at once"
+                .to_string(),
+        );
+    }
+    if options.simulate_timing && !options.dry_run {
+        return Err("--simulate-timing only applies to --dry-run".to_string());
+    }
+    if options.dry_run
+        && (options.batch_file.is_some()
+            || options.plan.is_some()
+            || options.watch_pid_file.is_some()
+            || options.listen.is_some()
+            || options.daemonize
+            || options.simulate)
+    {
+        return Err(
+            "--dry-run cannot be combined with --batch-file, --plan, --watch-pid-file, --listen, --daemonize or --simulate"
+                .to_string(),
+        );
+    }
+    Ok(())
+}
+
+/// Checks that `--per-wave-grace` isn't combined with options that time
+/// the targets all together.
+fn check_per_wave_grace(options: &Options) -> Result<(), String> {
+    if options.per_wave_grace
+        && (options.deadline.is_some()
+            || !options.stages.is_empty()
+            || options.grace_extension.is_some()
+            || options.quorum.is_some())
+    {
+        return Err(
+            "--per-wave-grace cannot be combined with --by, which sets one deadline for every target, --escalate, --grace-extend-if-progressing or --quorum"
+                .to_string(),
+        );
+    }
+    Ok(())
 }
 
-fn parse_and_validate_pid(pid_str: &str) -> Result<u32, String> {
-    let pid = pid_str
-        .parse::<u32>()
-        .map_err(|_| format!("Invalid PID: '{pid_str}'"))?;
-    if pid == 0 {
-        return Err("PID 0 not allowed (affects process group)".to_string());
+/// Checks the options that split the targets into groups with a grace
+/// period of their own.
+fn check_grouped_targets(options: &Options) -> Result<(), String> {
+    if options.grace_from_env.is_some()
+        && (options.deadline.is_some()
+            || options.cpu_time_grace.is_some()
+            || options.handoff.is_some()
+            || options.batch_file.is_some()
+            || options.plan.is_some()
+            || options.watch)
+    {
+        return Err(
+            "--grace-from-env cannot be combined with --by, --cpu-time-grace, --handoff, --batch-file, --plan or --watch"
+                .to_string(),
+        );
     }
-    Ok(pid)
+    if !options.policies.is_empty()
+        && (options.grace_from_env.is_some()
+            || options.deadline.is_some()
+            || options.cpu_time_grace.is_some()
+            || options.handoff.is_some()
+            || options.batch_file.is_some()
+            || options.plan.is_some()
+            || options.watch
+            || options.dry_run)
+    {
+        return Err(
+            "--policy cannot be combined with --grace-from-env, --by, --cpu-time-grace, --handoff, --batch-file, --plan, --watch or --dry-run"
+                .to_string(),
+        );
+    }
+    Ok(())
+}
+
+/// Checks that `--if-rss-above`, `--if-cpu-above`, `--if-age-above`,
+/// `--save-set`, `--kill-new-since` and `--rescan` apply to targets known
+/// before the kill starts, and `--netns` to `--port` targets.
+fn check_target_filters(options: &Options) -> Result<(), String> {
+    let others = options.stream
+        || options.watch
+        || options.watch_pid_file.is_some()
+        || options.batch_file.is_some()
+        || options.plan.is_some()
+        || options.simulate;
+    if !options.thresholds.is_empty() && others {
+        return Err(
+            "--if-rss-above, --if-cpu-above and --if-age-above cannot be combined with --stream, --watch, --watch-pid-file, --batch-file, --plan or --simulate"
+                .to_string(),
+        );
+    }
+    if (options.save_set.is_some() || options.kill_new_since.is_some())
+        && (others || options.listen.is_some() || options.dry_run)
+    {
+        return Err(
+            "--save-set and --kill-new-since cannot be combined with --stream, --watch, --watch-pid-file, --batch-file, --plan, --simulate, --listen or --dry-run"
+                .to_string(),
+        );
+    }
+    if options.rescan && others {
+        return Err(
+            "--rescan cannot be combined with --stream, --watch, --watch-pid-file, --batch-file, --plan or --simulate"
+                .to_string(),
+        );
+    }
+    if options.netns.is_some() && options.ports.is_empty() {
+        return Err("--netns only applies to --port".to_string());
+    }
+    if options.rescan && !drift::has_selectors(options) {
+        return Err(
+            "--rescan needs targets chosen by --name, --session, --holding, --port or process group"
+                .to_string(),
+        );
+    }
+    Ok(())
+}
+
+/// Checks that `--then-exec` follows a single kill that ends with its
+/// targets gone.
+fn check_then_exec(options: &Options) -> Result<(), String> {
+    if options.then_exec.is_some()
+        && (options.batch_file.is_some()
+            || options.plan.is_some()
+            || options.watch_pid_file.is_some()
+            || options.watch
+            || options.stream
+            || options.fifo.is_some()
+            || options.handoff.is_some())
+    {
+        return Err(
+            "--then-exec cannot be combined with --batch-file, --plan, --watch-pid-file, --watch, --stream, --fifo or --handoff"
+                .to_string(),
+        );
+    }
+    Ok(())
+}
+
+/// Checks that `--fifo`, which starts a kill for each command it reads,
+/// isn't combined with other ways of choosing the targets.
+fn check_fifo(options: &Options) -> Result<(), String> {
+    if options.fifo.is_some()
+        && (options.batch_file.is_some()
+            || options.plan.is_some()
+            || options.watch_pid_file.is_some()
+            || options.watch
+            || options.stream
+            || options.listen.is_some()
+            || options.handoff.is_some()
+            || options.grace_from_env.is_some()
+            || !options.policies.is_empty()
+            || !options.thresholds.is_empty()
+            || options.save_set.is_some()
+            || options.kill_new_since.is_some()
+            || options.daemonize
+            || options.dry_run)
+    {
+        return Err(
+            "--fifo cannot be combined with --batch-file, --plan, --watch-pid-file, --watch, --stream, --listen, --handoff, --grace-from-env, --policy, --if-rss-above, --if-cpu-above, --if-age-above, --save-set, --kill-new-since, --daemonize or --dry-run"
+                .to_string(),
+        );
+    }
+    Ok(())
+}
+
+/// Compares the targets with the `--kill-new-since` checkpoint, keeping
+/// only the new ones, and records them all with `--save-set`. With
+/// `--save-set` alone, recording them is all the run does.
+fn checkpoint_targets(options: &mut Options) -> Result<(), String> {
+    let resolved = options.pids.clone();
+    if let Some(path) = &options.kill_new_since {
+        process_set::keep_new(path, &mut options.pids)?;
+    }
+    if let Some(path) = &options.save_set {
+        process_set::save(path, &resolved)?;
+        if options.kill_new_since.is_none() {
+            finish(0);
+        }
+    }
+    if options.kill_new_since.is_some() && options.pids.is_empty() {
+        log::summary("No target is new since the process set, nothing to signal");
+        finish(0);
+    }
+    Ok(())
+}
+
+/// Writes out the trace and any held log lines, and exits with `code`.
+/// Every exit goes through here: `process::exit` runs no destructors, so
+/// anything still buffered would otherwise be lost.
+fn finish(code: i32) -> ! {
+    flush_output();
+    process::exit(code);
+}
+
+/// Reports a fatal `message` after everything logged before it, and exits
+/// with code 1, or as UNKNOWN with `--nagios`.
+fn fail(message: &str) -> ! {
+    flush_output();
+    error_format::report(Kind::Usage, message);
+    if nagios::enabled() {
+        process::exit(nagios::unknown(message));
+    }
+    process::exit(1);
+}
+
+/// Writes out the trace and any log lines held by `--quiet-on-success`,
+/// since an early exit means something went wrong, tells systemd that the
+/// run is over and lets go of the targets' locks.
+fn flush_output() {
+    pid_lock::release();
+    trace::finish();
+    sd_notify::finish();
+    log::release();
+}
+
+/// Warns about target PIDs that the kernel can never hand out, being at or
+/// above `pid_max`, or refuses them with `--strict-pids`.
+#[cfg(target_os = "linux")]
+fn check_pid_max(options: &Options) -> Result<(), String> {
+    let pid_max = match gracekill::procfs::pid_max() {
+        Ok(pid_max) => pid_max,
+        Err(e) => {
+            log::debug(&format!(
+                "Cannot read pid_max, not checking PIDs against it: {e}"
+            ));
+            return Ok(());
+        }
+    };
+    let implausible: Vec<String> = options
+        .pids
+        .iter()
+        .chain(&options.groups)
+        .chain(&options.sessions)
+        .filter(|&&pid| pid >= pid_max)
+        .map(u32::to_string)
+        .collect();
+    if implausible.is_empty() {
+        return Ok(());
+    }
+    let message = format!(
+        "PID(s) {} can never exist, being at or above the system's pid_max of {pid_max}",
+        implausible.join(", ")
+    );
+    if options.strict_pids {
+        return Err(message);
+    }
+    log::warn(&format!("{message}. Use --strict-pids to refuse them"));
+    Ok(())
 }
 
-fn send_signal_to_all(pids: &[u32], signal: Signal) -> Vec<u32> {
-    let signal_name = match signal {
-        Signal::Term => "SIGTERM",
-        Signal::Kill => "SIGKILL",
+/// Sets the grace period to the time left until `--by`, or with
+/// `--escalate` checks that the ladder's waits fit in it.
+fn apply_deadline(options: &mut Options, deadline: SystemTime) -> Result<(), String> {
+    if options.cpu_time_grace.is_some() {
+        return Err("--by cannot be combined with --cpu-time-grace".to_string());
+    }
+    if options.escalate {
+        return fit_ladder(options, deadline);
+    }
+    options.grace_period = match deadline::until(deadline) {
+        Some(left) => {
+            let left = Duration::from_millis(u64::try_from(left.as_millis()).unwrap_or(u64::MAX));
+            log(&format!("--by time is {} away", log::duration(left)));
+            left
+        }
+        None if options.if_past == IfPast::Error => {
+            return Err("The --by time has already passed".to_string());
+        }
+        None => {
+            log::warn("The --by time has already passed, escalating without a grace period");
+            Duration::ZERO
+        }
     };
+    Ok(())
+}
 
-    let mut successful_pids = Vec::with_capacity(pids.len());
-    for &pid in pids {
-        match send_signal(pid, signal) {
-            Ok(()) => {
-                log(&format!("Sent {signal_name} to PID {pid}"));
-                successful_pids.push(pid);
-            }
-            Err(e) => {
-                log(&format!("Failed to send {signal_name} to PID {pid}: {e}"));
-            }
+/// Checks that the `--escalate` waits add up to no more than the time left
+/// until `--by`, shortening the last one to fit for `--if-over-budget clamp`.
+fn fit_ladder(options: &mut Options, deadline: SystemTime) -> Result<(), String> {
+    let Some(left) = deadline::until(deadline) else {
+        if options.if_past == IfPast::Error {
+            return Err("The --by time has already passed".to_string());
         }
+        log::warn("The --by time has already passed, escalating without waiting");
+        options.grace_period = Duration::ZERO;
+        for stage in &mut options.stages {
+            stage.grace = Duration::ZERO;
+        }
+        return Ok(());
+    };
+    let left = Duration::from_millis(u64::try_from(left.as_millis()).unwrap_or(u64::MAX));
+    // The jitter counts in full, as it may all be added. Waits too long to
+    // add up saturate, and are over any budget
+    let graces: Vec<Duration> = std::iter::once(options.grace_period)
+        .chain(options.stages.iter().map(|stage| stage.grace))
+        .collect();
+    let jitter = std::iter::once(options.jitter)
+        .chain(options.stages.iter().map(|stage| stage.jitter))
+        .fold(Duration::ZERO, Duration::saturating_add);
+    let (earlier, _) = graces.split_at(graces.len() - 1);
+    let before = earlier
+        .iter()
+        .fold(jitter, |sum, &grace| sum.saturating_add(grace));
+    let total = before.saturating_add(graces[graces.len() - 1]);
+    if total <= left {
+        log::debug(&format!(
+            "The --escalate waits add up to {}, within the {} until --by",
+            log::duration(total),
+            log::duration(left)
+        ));
+        return Ok(());
+    }
+    let over = format!(
+        "The --escalate waits add up to {}, more than the {} until --by",
+        log::duration(total),
+        log::duration(left)
+    );
+    if options.over_budget == OverBudget::Error {
+        return Err(format!(
+            "{over}. Use --if-over-budget clamp to shorten the last wait"
+        ));
     }
-    successful_pids
+    let last = match options.stages.last_mut() {
+        Some(stage) => &mut stage.grace,
+        None => &mut options.grace_period,
+    };
+    let Some(clamped) = left.checked_sub(before) else {
+        return Err(format!(
+            "{over}, even without the last wait, which is all --if-over-budget clamp shortens"
+        ));
+    };
+    log::warn(&format!(
+        "{over}, shortening the last wait from {} to {}",
+        log::duration(*last),
+        log::duration(clamped)
+    ));
+    *last = clamped;
+    Ok(())
 }
 
-fn parse_args(args: &[String]) -> Result<(Vec<u32>, Duration, bool), String> {
-    let mut pids = Vec::new();
-    let mut grace_seconds = DEFAULT_GRACE_SECONDS;
-    let mut exit_non_zero_if_sigkill_required = false;
-    let mut i = 0;
-
-    while i < args.len() {
-        let arg = &args[i];
-
-        if arg == "-g" || arg == "--grace-seconds" {
-            i += 1;
-            if i >= args.len() {
-                return Err("Missing value for grace-seconds".to_string());
-            }
-            grace_seconds = args[i]
-                .parse::<u64>()
-                .map_err(|_| format!("Invalid grace-seconds value: '{}'", args[i]))?;
-        } else if arg.starts_with("--grace-seconds=") {
-            let value = arg.strip_prefix("--grace-seconds=").unwrap();
-            grace_seconds = value
-                .parse::<u64>()
-                .map_err(|_| format!("Invalid grace-seconds value: '{value}'"))?;
-        } else if arg == "--exit-non-zero-if-sigkill-required" {
-            exit_non_zero_if_sigkill_required = true;
-        } else if arg.starts_with('-') {
-            return Err(format!("Unknown option: '{arg}'"));
-        } else {
-            // Parse PIDs (comma or space separated)
-            let pid_strings = if arg.contains(',') {
-                arg.split(',').map(str::trim).collect::<Vec<_>>()
-            } else {
-                vec![arg.as_str()]
-            };
+/// Stretches the grace period and every `--escalate` wait for
+/// `--load-aware`, by how much CPU pressure the machine is under.
+fn apply_load(options: &mut Options) -> Result<(), String> {
+    if options.deadline.is_some() || options.cpu_time_grace.is_some() {
+        return Err("--load-aware cannot be combined with --by or --cpu-time-grace".to_string());
+    }
+    let (factor, observed) = load::factor()?;
+    if factor <= 1.0 {
+        log::debug(&format!(
+            "No CPU pressure ({observed}), keeping the grace period"
+        ));
+        return Ok(());
+    }
+    let grace = load::stretch(options.grace_period, factor);
+    log(&format!(
+        "Machine under pressure ({observed}), stretching the grace period {factor:.2}x from {} to {}",
+        log::duration(options.grace_period),
+        log::duration(grace)
+    ));
+    options.grace_period = grace;
+    for stage in &mut options.stages {
+        stage.grace = load::stretch(stage.grace, factor);
+    }
+    Ok(())
+}
 
-            for pid_str in pid_strings {
-                let pid = parse_and_validate_pid(pid_str)?;
-                pids.push(pid);
-            }
+/// Makes `--max-grace`, if given, the only wall-clock bound on the wait, as
+/// the grace period is measured in CPU time with `--cpu-time-grace`.
+fn apply_cpu_time_grace(options: &mut Options, budget: Duration) -> Result<(), String> {
+    if cfg!(not(target_os = "linux")) {
+        return Err("--cpu-time-grace is only supported on Linux".to_string());
+    }
+    if !options.stages.is_empty() {
+        return Err(
+            "--cpu-time-grace cannot be combined with --escalate, or more than one --stage"
+                .to_string(),
+        );
+    }
+    if options.simulate {
+        return Err(
+            "--cpu-time-grace cannot be simulated, as simulated targets use no CPU time"
+                .to_string(),
+        );
+    }
+    options.grace_period = options.max_grace.unwrap_or(Duration::MAX);
+    log(&format!(
+        "Grace period is {} of CPU time for each target, {}",
+        log::duration(budget),
+        match options.max_grace {
+            Some(max) => format!("for at most {} of wall-clock time", log::duration(max)),
+            None => "with no wall-clock limit".to_string(),
         }
+    ));
+    Ok(())
+}
 
-        i += 1;
+/// Applies `--min-grace` and `--max-grace` to a wait, logging any change.
+fn clamp_grace(grace: Duration, options: &Options) -> Duration {
+    let clamped = options
+        .min_grace
+        .map_or(grace, |min| grace.max(min))
+        .min(options.max_grace.unwrap_or(Duration::MAX));
+    if clamped > grace {
+        log::warn(&format!(
+            "Grace period {} is below --min-grace, using {}",
+            log::duration(grace),
+            log::duration(clamped)
+        ));
+    } else if clamped < grace {
+        log::warn(&format!(
+            "Grace period {} is above --max-grace, using {}",
+            log::duration(grace),
+            log::duration(clamped)
+        ));
     }
+    clamped
+}
 
-    Ok((
-        pids,
-        Duration::from_secs(grace_seconds),
-        exit_non_zero_if_sigkill_required,
-    ))
+/// Settles the final list of target PIDs and checks the options referring to
+/// them.
+fn resolve_targets(options: &mut Options) -> Result<(), String> {
+    if let Some(reference) = options.pid_namespace {
+        translate_namespace(options, reference)?;
+    }
+    if !options.containers.is_empty() {
+        expand_containers(options)?;
+    }
+    expand_groups(options)?;
+    expand_names(options)?;
+    expand_holding(options)?;
+    expand_ports(options)?;
+    if !options.include_foreground && !options.simulate {
+        exclude_foreground(&mut options.pids);
+    }
+    if options.save_set.is_some() || options.kill_new_since.is_some() {
+        checkpoint_targets(options)?;
+    }
+    if !options.thresholds.is_empty() {
+        threshold::filter(&options.thresholds, &mut options.pids);
+        if options.pids.is_empty() {
+            log::summary("No target is over the threshold, nothing to signal");
+            finish(0);
+        }
+    }
+    if options.pids.is_empty() && options.listen.is_none() {
+        return Err("No processes to signal".to_string());
+    }
+    validate_targets(options)?;
+    #[cfg(target_os = "linux")]
+    if !options.simulate {
+        check_proc_access(options);
+    }
+    // A dry run signals nothing, so there is nothing to confirm
+    if let Some(limit) = options.confirm_count
+        && !options.dry_run
+    {
+        confirm::check(&options.pids, limit, options.yes)?;
+    }
+    Ok(())
 }
 
-#[derive(Copy, Clone)]
-enum Signal {
-    Term,
-    Kill,
+/// Adds the main process of each `--container`, and every descendant of
+/// it, to the targets.
+#[cfg(all(feature = "container", target_os = "linux"))]
+fn expand_containers(options: &mut Options) -> Result<(), String> {
+    let socket = container::socket_path(options.container_socket.as_deref());
+    for name in &options.containers {
+        let main = container::main_pid(&socket, name)?;
+        let mut family = vec![main];
+        family.extend(gracekill::procfs::descendants(main));
+        log(&format!(
+            "Container {name} has main PID {main} and {} process(es) in all",
+            family.len()
+        ));
+        for pid in family {
+            if !options.pids.contains(&pid) {
+                options.pids.push(pid);
+            }
+        }
+    }
+    Ok(())
 }
 
-fn send_signal(pid: u32, signal: Signal) -> Result<(), String> {
-    let nix_signal = match signal {
-        Signal::Term => NixSignal::SIGTERM,
-        Signal::Kill => NixSignal::SIGKILL,
-    };
+#[cfg(not(all(feature = "container", target_os = "linux")))]
+fn expand_containers(_options: &mut Options) -> Result<(), String> {
+    Err("Container targets (--container) are only supported on Linux".to_string())
+}
 
-    let nix_pid =
-        Pid::from_raw(i32::try_from(pid).map_err(|_| "PID too large for system".to_string())?);
+/// Falls back to `kill(pid, 0)` alone, with a single warning, when targets
+/// that exist can't be read in `/proc`, as happens for other users'
+/// processes when it is mounted with `hidepid`. Reading it anyway would
+/// fail for every field, and a `/proc`-based liveness check would take
+/// the hidden targets for gone.
+#[cfg(target_os = "linux")]
+fn check_proc_access(options: &mut Options) {
+    if options.enrich == Enrich::Off && options.liveness != Some(Liveness::Proc) {
+        return;
+    }
+    let hidden: Vec<String> = options
+        .pids
+        .iter()
+        .copied()
+        .filter(|&pid| {
+            let unreadable = gracekill::procfs::stat(pid).is_err_and(|e| {
+                matches!(
+                    e.kind(),
+                    io::ErrorKind::NotFound | io::ErrorKind::PermissionDenied
+                )
+            });
+            // EPERM still means the process exists
+            unreadable
+                && i32::try_from(pid).is_ok_and(|pid| {
+                    nix::sys::signal::kill(nix::unistd::Pid::from_raw(pid), None)
+                        != Err(nix::errno::Errno::ESRCH)
+                })
+        })
+        .map(|pid| pid.to_string())
+        .collect();
+    if hidden.is_empty() {
+        return;
+    }
+    log::warn(&format!(
+        "Cannot read /proc for PID(s) {}, hidepid may be enabled. Continuing with kill(pid, 0) only",
+        hidden.join(", ")
+    ));
+    options.enrich = Enrich::Off;
+    if options.liveness == Some(Liveness::Proc) {
+        options.liveness = Some(Liveness::Signal0);
+    }
+}
 
-    match signal::kill(nix_pid, nix_signal) {
-        Ok(()) => Ok(()),
-        Err(nix::errno::Errno::ESRCH) => Err("Process not found".to_string()),
-        Err(nix::errno::Errno::EPERM) => Err("Permission denied".to_string()),
-        Err(e) => Err(format!("Failed to send signal: {e}")),
+/// Drops the targets in the controlling terminal's foreground process group,
+/// the job an interactive user is running gracekill from, so that a broad
+/// selector doesn't take down the user's own command line. Does nothing
+/// without a controlling terminal.
+fn exclude_foreground(pids: &mut Vec<u32>) {
+    let Ok(tty) = fs::File::open("/dev/tty") else {
+        return;
+    };
+    let Ok(foreground) = nix::unistd::tcgetpgrp(&tty) else {
+        return;
+    };
+    let mut excluded = Vec::new();
+    pids.retain(|&pid| {
+        let in_foreground = i32::try_from(pid).is_ok_and(|pid| {
+            nix::unistd::getpgid(Some(nix::unistd::Pid::from_raw(pid))) == Ok(foreground)
+        });
+        if in_foreground {
+            excluded.push(pid.to_string());
+        }
+        !in_foreground
+    });
+    if !excluded.is_empty() {
+        log::warn(&format!(
+            "Excluding {} target(s) in the terminal's foreground process group {foreground}: {}. Use --include-foreground-group to signal them",
+            excluded.len(),
+            excluded.join(", ")
+        ));
     }
 }
 
-fn is_process_running(pid: u32) -> bool {
-    let Ok(nix_pid) = i32::try_from(pid).map(Pid::from_raw) else {
-        return false; // PID too large, can't exist
+/// Replaces target PIDs given as seen in the PID namespace of `reference`
+/// with the PIDs of the same processes in ours.
+#[cfg(target_os = "linux")]
+fn translate_namespace(options: &mut Options, reference: u32) -> Result<(), String> {
+    let map = gracekill::procfs::namespace_pid_map(reference)
+        .map_err(|e| format!("Cannot read the PID namespace of process {reference}: {e}"))?;
+    let translate = |pid: &mut u32| -> Result<(), String> {
+        let host = *map.get(pid).ok_or_else(|| {
+            format!("No process {pid} in the PID namespace of process {reference}")
+        })?;
+        if host != *pid {
+            log::debug(&format!(
+                "PID {pid} in the namespace of {reference} is PID {host}"
+            ));
+        }
+        *pid = host;
+        Ok(())
     };
-    // Send signal 0 to check if process exists
-    signal::kill(nix_pid, None).is_ok()
+    for pid in &mut options.pids {
+        translate(pid)?;
+    }
+    for (pid, _) in &mut options.pid_signals {
+        translate(pid)?;
+    }
+    for entry in &mut options.forbid_kill {
+        if let Ok(mut pid) = entry.parse::<u32>() {
+            translate(&mut pid)?;
+            *entry = pid.to_string();
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn translate_namespace(_options: &mut Options, _reference: u32) -> Result<(), String> {
+    Err("--pid-namespace is only supported on Linux".to_string())
+}
+
+/// Adds the members of every targeted process group and session to the PIDs.
+fn expand_groups(options: &mut Options) -> Result<(), String> {
+    for &sid in &options.sessions {
+        #[cfg(target_os = "linux")]
+        {
+            let own = process::id();
+            if gracekill::procfs::stat(own).is_ok_and(|stat| stat.session == sid) {
+                let message = format!("Session {sid} is the session gracekill is running in");
+                if !options.force {
+                    return Err(format!("{message}. Use --force to signal it anyway"));
+                }
+                log::warn(&message);
+            }
+            let members: Vec<u32> = gracekill::procfs::processes_in_session(sid)
+                .map_err(|e| format!("Failed to list session {sid}: {e}"))?
+                .into_iter()
+                .filter(|&pid| pid != own)
+                .collect();
+            drift::note_selected(&members);
+            if members.is_empty() {
+                log::warn(&format!("Session {sid} has no processes"));
+            } else {
+                log(&format!("Session {sid} has {} process(es)", members.len()));
+            }
+            for pid in members {
+                if !options.pids.contains(&pid) {
+                    options.pids.push(pid);
+                }
+            }
+        }
+        #[cfg(not(target_os = "linux"))]
+        return Err(format!(
+            "Session targets (--session {sid}) are only supported on Linux"
+        ));
+    }
+    for &pgid in &options.groups {
+        #[cfg(target_os = "linux")]
+        {
+            let members = gracekill::procfs::processes_in_group(pgid)
+                .map_err(|e| format!("Failed to list process group {pgid}: {e}"))?;
+            drift::note_selected(&members);
+            if members.is_empty() {
+                log::warn(&format!("Process group {pgid} has no processes"));
+            } else {
+                log(&format!(
+                    "Process group {pgid} has {} process(es)",
+                    members.len()
+                ));
+            }
+            for pid in members {
+                if !options.pids.contains(&pid) {
+                    options.pids.push(pid);
+                }
+            }
+        }
+        #[cfg(not(target_os = "linux"))]
+        return Err(format!(
+            "Process group targets (-{pgid}) are only supported on Linux"
+        ));
+    }
+    Ok(())
 }
 
-fn log(message: &str) {
-    let _ = writeln!(io::stderr(), "[gracekill] {message}");
+/// Adds every process with a `--name` to the PIDs.
+fn expand_names(options: &mut Options) -> Result<(), String> {
+    if options.names.is_empty() {
+        return Ok(());
+    }
+    let members = processes_named(&options.names)?;
+    drift::note_selected(&members);
+    if members.is_empty() {
+        log::warn(&format!("No processes named {}", options.names.join(", ")));
+    } else {
+        log(&format!(
+            "Found {} process(es) named {}",
+            members.len(),
+            options.names.join(", ")
+        ));
+    }
+    for pid in members {
+        if !options.pids.contains(&pid) {
+            options.pids.push(pid);
+        }
+    }
+    Ok(())
+}
+
+/// Every process other than gracekill itself whose command name is one of
+/// `names`.
+#[cfg(target_os = "linux")]
+fn processes_named(names: &[String]) -> Result<Vec<u32>, String> {
+    let own = process::id();
+    let mut pids = Vec::new();
+    for name in names {
+        let found = gracekill::procfs::processes_named(name)
+            .map_err(|e| format!("Failed to look for processes named {name}: {e}"))?;
+        for pid in found {
+            if pid != own && !pids.contains(&pid) {
+                pids.push(pid);
+            }
+        }
+    }
+    Ok(pids)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn processes_named(_names: &[String]) -> Result<Vec<u32>, String> {
+    Err("Name targets (--name) are only supported on Linux".to_string())
+}
+
+/// Adds every process holding a `--holding` file open to the PIDs.
+fn expand_holding(options: &mut Options) -> Result<(), String> {
+    for path in &options.holding {
+        let (holders, unreadable) = processes_holding(path)?;
+        if unreadable > 0 {
+            log::warn(&format!(
+                "Cannot read the open files of {unreadable} process(es) for lack of permission, so any holding '{path}' are left alone; run as root to find every holder"
+            ));
+        }
+        drift::note_selected(&holders);
+        if holders.is_empty() {
+            log::warn(&format!("No process has '{path}' open"));
+        } else {
+            log(&format!(
+                "Found {} process(es) holding '{path}'",
+                holders.len()
+            ));
+        }
+        for pid in holders {
+            if !options.pids.contains(&pid) {
+                options.pids.push(pid);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Adds every process with a socket on a `--port` port to the PIDs.
+fn expand_ports(options: &mut Options) -> Result<(), String> {
+    let namespace = options.netns.as_ref().map_or_else(String::new, |netns| {
+        format!(" in network namespace '{netns}'")
+    });
+    for &port in &options.ports {
+        let (holders, unreadable) = port::processes_on(port, options.netns.as_deref())?;
+        if unreadable > 0 {
+            log::warn(&format!(
+                "Cannot read the open files of {unreadable} process(es) for lack of permission, so any on port {port} are left alone; run as root to find every one"
+            ));
+        }
+        drift::note_selected(&holders);
+        if holders.is_empty() {
+            log::warn(&format!(
+                "No process has a socket on port {port}{namespace}"
+            ));
+        } else {
+            log(&format!(
+                "Found {} process(es) with a socket on port {port}{namespace}",
+                holders.len()
+            ));
+        }
+        for pid in holders {
+            if !options.pids.contains(&pid) {
+                options.pids.push(pid);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Every process other than gracekill itself with `path` open or mapped,
+/// each once however many times it holds it, and how many processes were
+/// skipped for not having their open files readable, such as other users'
+/// without root.
+#[cfg(target_os = "linux")]
+fn processes_holding(path: &str) -> Result<(Vec<u32>, usize), String> {
+    let file =
+        fs::canonicalize(path).map_err(|e| format!("Cannot find --holding '{path}': {e}"))?;
+    let own = process::id();
+    let mut holders = Vec::new();
+    let mut unreadable = 0;
+    for pid in gracekill::procfs::pids()
+        .map_err(|e| format!("Failed to look for processes holding '{path}': {e}"))?
+    {
+        match gracekill::procfs::open_files(pid) {
+            Ok(files) => {
+                if pid != own && files.contains(&file) {
+                    holders.push(pid);
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::PermissionDenied => unreadable += 1,
+            // Exited while /proc was being read
+            Err(_) => {}
+        }
+    }
+    Ok((holders, unreadable))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn processes_holding(_path: &str) -> Result<(Vec<u32>, usize), String> {
+    Err("Open-file targets (--holding) are only supported on Linux".to_string())
+}
+
+/// Checks the options that refer to particular targets against the final
+/// target list.
+fn validate_targets(options: &Options) -> Result<(), String> {
+    if let Some(&(pid, signal)) = options
+        .pid_signals
+        .iter()
+        .find(|(pid, _)| !options.pids.contains(pid))
+    {
+        return Err(format!(
+            "PID {pid} is given {signal} with --pid-signal but is not a target"
+        ));
+    }
+    if let Some((pid, _)) = options
+        .simulated_exits
+        .iter()
+        .find(|(pid, _)| !options.pids.contains(pid))
+    {
+        return Err(format!(
+            "PID {pid} is given an exit time with --simulate-exit-after but is not a target"
+        ));
+    }
+    if let Some(pid) = options
+        .orphan_parents
+        .iter()
+        .find(|pid| !options.pids.contains(pid))
+    {
+        return Err(format!(
+            "PID {pid} is given with --kill-orphans-after-parent but is not a target"
+        ));
+    }
+    if let Some((pid, _)) = options
+        .expected_commands
+        .iter()
+        .find(|(pid, _)| !options.pids.contains(pid))
+    {
+        return Err(format!(
+            "PID {pid} is given a command with --expect-cmd but is not a target"
+        ));
+    }
+    if let Some((pid, _)) = options
+        .exit_files
+        .iter()
+        .find(|(pid, _)| !options.pids.contains(pid))
+    {
+        return Err(format!(
+            "PID {pid} is given a file with --expect-exit-file but is not a target"
+        ));
+    }
+    #[cfg(target_os = "linux")]
+    if !options.simulate {
+        check_ancestors(&options.pids, options.force)?;
+    }
+    match options.tid {
+        Some(tid) => validate_tid(&options.pids, tid),
+        None => Ok(()),
+    }
+}
+
+/// Refuses targets that are ancestors of gracekill, such as the shell it was
+/// started from, since killing one may end the session gracekill runs in.
+/// With `force` they are only warned about.
+#[cfg(target_os = "linux")]
+fn check_ancestors(pids: &[u32], force: bool) -> Result<(), String> {
+    let describe = |pid: u32| match gracekill::procfs::comm(pid) {
+        Some(comm) => format!("{pid} ({comm})"),
+        None => pid.to_string(),
+    };
+    let own = process::id();
+    let mut chain = vec![describe(own)];
+    for ancestor in gracekill::procfs::ancestors(own) {
+        chain.push(describe(ancestor));
+        if !pids.contains(&ancestor) {
+            continue;
+        }
+        let message = format!(
+            "PID {ancestor} is an ancestor of gracekill and signalling it may end this session: {}",
+            chain.join(" <- ")
+        );
+        if !force {
+            return Err(format!("{message}. Use --force to signal it anyway"));
+        }
+        log::warn(&message);
+    }
+    Ok(())
+}
+
+/// With `--listen`, starts accepting more targets on its socket. Received
+/// PIDs get the same ancestor check as the ones given up front.
+fn listen_for_targets(
+    gracekill: GraceKill<Commands>,
+    options: &Options,
+) -> (GraceKill<Commands>, Option<listen::Listener>) {
+    let Some(path) = &options.listen else {
+        return (gracekill, None);
+    };
+    let force = options.force;
+    let simulate = options.simulate;
+    let accept = move |pid: u32| {
+        #[cfg(target_os = "linux")]
+        if !simulate {
+            check_ancestors(&[pid], force)?;
+        }
+        #[cfg(not(target_os = "linux"))]
+        let _ = (force, simulate);
+        Ok(())
+    };
+    match listen::bind(path, accept) {
+        Ok((listener, incoming)) => (gracekill.add_targets_from(incoming), Some(listener)),
+        Err(e) => fail(&e),
+    }
+}
+
+/// Prints the threads of `pid` for `--list-threads`, and exits.
+fn list_threads(pid: u32) -> ! {
+    #[cfg(target_os = "linux")]
+    let listed = threads::list(pid);
+    #[cfg(not(target_os = "linux"))]
+    let listed: Result<(), String> = {
+        let _ = pid;
+        Err("--list-threads is only supported on Linux".to_string())
+    };
+    match listed {
+        Ok(()) => finish(0),
+        Err(e) => fail(&e),
+    }
+}
+
+/// Runs `--selftest`, and exits.
+fn self_test(options: &Options) -> ! {
+    if options.has_targets() {
+        fail("PIDs cannot be combined with --selftest, which kills a child of its own");
+    }
+    if options.simulate {
+        fail("--selftest cannot be combined with --simulate");
+    }
+    finish(selftest::run(configure(options)))
+}
+
+/// Checks that `--tid` names a thread of the single target process.
+fn validate_tid(pids: &[u32], tid: u32) -> Result<(), String> {
+    let &[pid] = pids else {
+        return Err("--tid requires exactly one PID".to_string());
+    };
+    if cfg!(not(target_os = "linux")) {
+        return Err("--tid is only supported on Linux".to_string());
+    }
+    #[cfg(target_os = "linux")]
+    match gracekill::procfs::has_thread(pid, tid) {
+        Ok(true) => {}
+        Ok(false) => return Err(format!("Thread {tid} does not belong to PID {pid}")),
+        // tgkill itself rejects a TID from another process
+        Err(e) => log::warn(&format!("Could not verify thread {tid} of PID {pid}: {e}")),
+    }
+    Ok(())
+}
+
+/// Targets matching a `--forbid-signal-to` entry, either by PID or by
+/// command name, or a name pattern from `--protect-file`.
+fn protected_pids(pids: &[u32], options: &Options) -> Vec<u32> {
+    pids.iter()
+        .copied()
+        .filter(|&pid| {
+            if options
+                .forbid_kill
+                .iter()
+                .any(|entry| entry.parse::<u32>() == Ok(pid))
+            {
+                return true;
+            }
+            #[cfg(target_os = "linux")]
+            if let Some(name) = gracekill::procfs::comm(pid) {
+                return options.forbid_kill.contains(&name)
+                    || options
+                        .protect_patterns
+                        .iter()
+                        .any(|pattern| policy::glob_matches(pattern, &name));
+            }
+            false
+        })
+        .inspect(|pid| log::debug(&format!("PID {pid} is protected from SIGKILL")))
+        .collect()
+}
+
+/// A graceful kill with the settings shared by every operation.
+fn configure(options: &Options) -> GraceKill<Commands> {
+    let mut controller = Commands::new(
+        options.print_commands,
+        options.privilege_helper.as_deref(),
+        options
+            .liveness
+            .or((options.enrich == Enrich::Off).then_some(Liveness::Signal0)),
+    );
+    if options.simulate {
+        controller = controller.simulate(Simulation::new(&options.simulated_exits));
+    }
+    controller = controller.expect_commands(&options.expected_commands);
+    let mut gracekill = GraceKill::with_controller(controller);
+    if let Some(threshold) = options.warn_long_exit {
+        gracekill = gracekill.warn_slow_exit(threshold);
+    }
+    if let Some(limit) = options.max_grace_overrun {
+        gracekill = gracekill.max_grace_overrun(limit);
+    }
+    gracekill = gracekill.poll_schedule(options.poll_schedule);
+    if let Some(policy) = options.require_handler {
+        gracekill = gracekill.require_handler(policy);
+    }
+    if let Some(cap) = options.grace_extension {
+        gracekill = gracekill.extend_if_progressing(cap);
+    }
+    if let Some(budget) = options.cpu_time_grace {
+        gracekill = gracekill.cpu_time_grace(budget);
+    }
+    if let Some((percent, stragglers)) = options.quorum {
+        gracekill = gracekill.quorum(percent, stragglers);
+    }
+    if options.background_escalate {
+        gracekill = gracekill.after_signal(daemon::background_escalate(options.log_file.clone()));
+    }
+    if let Some(command) = &options.on_timeout
+        && !options.simulate
+    {
+        gracekill = gracekill.before_kill(on_timeout::hook(command, options.on_timeout_limit));
+    }
+    if options.abort_with_core && !options.simulate {
+        gracekill = gracekill.before_kill(core_dump::hook());
+    }
+    if let Some(command) = &options.dump_command
+        && !options.simulate
+    {
+        gracekill = gracekill.before_kill(dump::dumper(command, options.dump_timeout));
+    }
+    if let Some(callback) = error_format::signal_failures() {
+        gracekill = gracekill.on_result(callback);
+    }
+    if trace::enabled() {
+        gracekill = gracekill
+            .on_result(|pid, outcome| {
+                let outcome = status_json::status(outcome);
+                trace::event("outcome", &format!("pid={pid} outcome={outcome}"));
+            })
+            .on_poll({
+                let mut traced = 0;
+                move |_, remaining, report| {
+                    for (pid, survival) in report.survivals.iter().skip(traced) {
+                        trace::event("survival", &format!("pid={pid} reason={survival}"));
+                    }
+                    traced = report.survivals.len();
+                    trace::event("poll", &format!("running={}", trace::pid_list(remaining)));
+                }
+            });
+    }
+    gracekill
+        .follow_daemons(options.follow_daemon)
+        .diagnose(options.diagnose)
+        .abort_on_kill_failure(options.abort_on_kill_failure)
+        .ignore_missing(options.ignore_missing)
+        .skip_exiting(options.skip_exiting)
+        .snapshot(options.snapshot && !options.simulate)
+        .double_tap(options.double_tap)
+        .check_zombies(options.enrich > Enrich::Off)
+        .max_send_rate(options.rate.unwrap_or(0))
+        .per_wave_grace(options.per_wave_grace)
+        .kill_concurrency(options.kill_concurrency.unwrap_or(0))
+        .exit_status(options.exit_status)
+}
+
+/// A kill of `pids` with the command-line signals, waits and protections.
+fn kill_for(pids: &[u32], options: &Options) -> GraceKill<Commands> {
+    let mut gracekill = configure(options)
+        .stage(Stage {
+            signal: options.signal,
+            grace: options.grace_period,
+            // --double-tap is one retry, and can't be combined with --stage
+            retries: options.retries.max(u32::from(options.double_tap)),
+            jitter: options.jitter,
+        })
+        .kill_signal(options.kill_signal)
+        .escalate(options.stages.iter().copied())
+        // The reaper sends the kill signal instead
+        .no_kill(options.handoff.is_some());
+    for &(pid, signal) in &options.pid_signals {
+        if pids.contains(&pid) {
+            gracekill = gracekill.signal_for(pid, signal);
+        }
+    }
+    if options.forbid_kill.is_empty() && options.protect_patterns.is_empty() {
+        gracekill
+    } else {
+        gracekill.protect(protected_pids(pids, options))
+    }
+}
+
+/// Reaps our own descendants for `--wait-children`, for at most `timeout`.
+fn wait_children(timeout: Duration) {
+    let wait = gracekill::children::wait_children(timeout, gracekill::DEFAULT_POLL_INTERVAL);
+    if wait.timed_out {
+        log::warn("Timed out waiting for child processes to exit");
+    } else {
+        log(&format!("Reaped {} child process(es)", wait.reaped.len()));
+    }
+}
+
+/// Reads each target's grace period from the `--grace-from-env` variable,
+/// returning the targets grouped by grace period if any set a grace period
+/// of their own. Targets whose environment can't be read, or that don't set
+/// the variable to a duration, keep the global grace period.
+#[cfg(target_os = "linux")]
+fn graces_from_env(options: &Options) -> Option<Vec<Operation>> {
+    let var = options.grace_from_env.as_deref()?;
+    let global = options.grace_period;
+    let mut groups: Vec<(Duration, Vec<u32>)> = Vec::new();
+    for &pid in &options.pids {
+        let grace = match gracekill::procfs::environ_var(pid, var) {
+            Ok(Some(value)) => {
+                if let Ok(grace) = duration::parse_duration(&value) {
+                    log(&format!(
+                        "PID {pid} sets {var}={value}, a {} grace period",
+                        log::duration(grace)
+                    ));
+                    grace
+                } else {
+                    log::warn(&format!(
+                        "PID {pid} sets {var}='{value}', which isn't a duration, keeping the {} grace period",
+                        log::duration(global)
+                    ));
+                    global
+                }
+            }
+            Ok(None) => {
+                log::debug(&format!(
+                    "PID {pid} doesn't set {var}, keeping the {} grace period",
+                    log::duration(global)
+                ));
+                global
+            }
+            Err(e) => {
+                log::warn(&format!(
+                    "Cannot read the environment of PID {pid} ({e}), keeping the {} grace period",
+                    log::duration(global)
+                ));
+                global
+            }
+        };
+        match groups.iter_mut().find(|(wait, _)| *wait == grace) {
+            Some((_, pids)) => pids.push(pid),
+            None => groups.push((grace, vec![pid])),
+        }
+    }
+    if groups.iter().all(|&(grace, _)| grace == global) {
+        return None;
+    }
+    Some(
+        groups
+            .into_iter()
+            .map(|(grace, pids)| Operation {
+                source: if grace == global {
+                    "the command line".to_string()
+                } else {
+                    format!("{var}={}", log::duration(grace))
+                },
+                pids,
+                grace: Some(grace),
+                signal: None,
+                kill_signal: None,
+            })
+            .collect(),
+    )
+}
+
+#[cfg(not(target_os = "linux"))]
+fn graces_from_env(_options: &Options) -> Option<Vec<Operation>> {
+    None
+}
+
+/// Runs `--batch-file`, `--plan` and `--watch-pid-file`, each of which
+/// replaces the single kill and finishes once it is done.
+fn run_other_modes(options: &mut Options) {
+    if let Some(path) = &options.batch_file {
+        if options.has_targets() {
+            fail("PIDs cannot be combined with --batch-file");
+        }
+        let operations = match fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|contents| batch::parse_batch(&contents))
+        {
+            Ok(operations) => operations,
+            Err(e) => fail(&format!("Invalid batch file '{path}': {e}")),
+        };
+        finish(run_batch(&operations, options));
+    }
+    if let Some(path) = options.plan.clone() {
+        if options.has_targets() || options.batch_file.is_some() {
+            fail("PIDs cannot be combined with --plan");
+        }
+        let operations = match read_input(&path).and_then(|contents| plan::parse_plan(&contents)) {
+            Ok(operations) => operations,
+            Err(e) => fail(&format!("Invalid plan '{path}': {e}")),
+        };
+        // A plan is one kill with per-target settings, so its groups run side by side
+        options.parallel = true;
+        finish(run_batch(&operations, options));
+    }
+    if let Some(path) = &options.watch_pid_file {
+        finish(run_watch(path, options));
+    }
+    if options.watch {
+        finish(run_name_watch(options));
+    }
+    if options.stream {
+        finish(run_stream(options));
+    }
+    if let Some(path) = &options.fifo {
+        finish(run_fifo(path, options));
+    }
+}
+
+/// Hands the targets left running, other than protected ones, off to the
+/// `--handoff` reaper.
+fn hand_off(handoff: handoff::Handoff, report: &KillReport, options: &Options) {
+    let survivors: Vec<u32> = report
+        .outcomes
+        .iter()
+        .filter(|(_, outcome)| *outcome == Outcome::Survived)
+        .map(|&(pid, _)| pid)
+        .collect();
+    let protected = protected_pids(&survivors, options);
+    let survivors: Vec<u32> = survivors
+        .into_iter()
+        .filter(|pid| !protected.contains(pid))
+        .collect();
+    let socket = handoff.socket().to_string();
+    match handoff.send(&survivors) {
+        Ok(()) => log(&format!(
+            "Handed {} survivor(s) off to the reaper at {socket}",
+            survivors.len()
+        )),
+        Err(e) => log::error(&e),
+    }
+}
+
+/// Reaps our own defunct children for `--reap`, waiting at most `timeout`
+/// for the killed targets among them.
+fn reap_children(report: &KillReport, timeout: Duration) {
+    let killed: Vec<u32> = report
+        .outcomes
+        .iter()
+        .filter(|(_, outcome)| *outcome == Outcome::Killed)
+        .map(|&(pid, _)| pid)
+        .collect();
+    let wait = gracekill::children::reap(&killed, timeout, gracekill::DEFAULT_POLL_INTERVAL);
+    if wait.timed_out {
+        log::warn(&format!(
+            "Timed out after {} reaping killed child processes",
+            log::duration(timeout)
+        ));
+    }
+    log(&format!(
+        "Reaped {} defunct child process(es)",
+        wait.reaped.len()
+    ));
+}
+
+/// Records the children of the `--kill-orphans-after-parent` targets, if any.
+fn snapshot_children(options: &Options) -> Option<orphans::Children> {
+    if options.orphan_parents.is_empty() {
+        return None;
+    }
+    if options.simulate {
+        log::warn("--kill-orphans-after-parent is not simulated, no orphans will be looked for");
+        return None;
+    }
+    Some(orphans::snapshot(&options.orphan_parents))
+}
+
+/// Locks the targets against other gracekill runs, until gracekill is done.
+fn lock_targets(options: &Options) {
+    if options.simulate {
+        return;
+    }
+    if let Err(e) = pid_lock::acquire(&options.pids, options.no_wait_lock) {
+        fail(&e);
+    }
+}
+
+/// Takes the targets through `--shutdown-url` and `--stdin-cmd`, if
+/// given, and then through `gracekill`, frozen with `--freeze`.
+fn run_targets(gracekill: GraceKill<Commands>, options: &Options) -> (KillReport, Vec<audit::Row>) {
+    let mut shut_down = request_shutdown(options);
+    let mut remaining: Vec<u32> = options
+        .pids
+        .iter()
+        .copied()
+        .filter(|pid| !shut_down.contains(pid))
+        .collect();
+    if let Some(bytes) = &options.stdin_cmd
+        && !options.simulate
+    {
+        use gracekill::ProcessController;
+
+        let mut liveness = Commands::new(false, None, options.liveness);
+        let exited = stdin_cmd::request_shutdown(bytes, &remaining, options.stdin_wait, |pid| {
+            liveness.is_running(pid)
+        });
+        remaining.retain(|pid| !exited.contains(pid));
+        shut_down.extend(exited);
+    }
+    if options.rescan {
+        drift::rescan(options);
+    }
+    let (gracekill, remaining, frozen) = freeze_targets(gracekill, remaining, options);
+    let (mut report, mut rows) = run_recorded(gracekill, &remaining, options);
+    if let Some(cgroups) = frozen {
+        cgroups.thaw();
+    }
+    add_shut_down(&shut_down, &mut report, &mut rows);
+    (report, rows)
+}
+
+/// Freezes the cgroups of `pids` for `--freeze` and adds their descendants
+/// to them, setting up `gracekill` to thaw the cgroups once the graceful
+/// signal has gone out and to freeze them again for the kill. The cgroups
+/// are returned for thawing at the end.
+fn freeze_targets(
+    gracekill: GraceKill<Commands>,
+    pids: Vec<u32>,
+    options: &Options,
+) -> (GraceKill<Commands>, Vec<u32>, Option<Arc<freeze::Cgroups>>) {
+    if !options.freeze || options.simulate || pids.is_empty() {
+        return (gracekill, pids, None);
+    }
+    let cgroups = match freeze::cgroups(&pids) {
+        Ok(cgroups) => Arc::new(cgroups),
+        Err(e) => fail(&e),
+    };
+    let tree = match freeze::freeze_tree(&cgroups, &pids) {
+        Ok(tree) => tree,
+        Err(e) => {
+            cgroups.thaw();
+            fail(&e);
+        }
+    };
+
+    let thawing = Arc::clone(&cgroups);
+    let refreezing = Arc::clone(&cgroups);
+    let targets = tree.clone();
+    let kill_signal = options.kill_signal;
+    let gracekill = gracekill
+        .after_signal(move |_| thawing.thaw())
+        .before_kill(move |_| {
+            if let Err(e) = refreezing.freeze() {
+                log::warn(&e);
+            }
+            kill_forked(&targets, kill_signal);
+        });
+    (gracekill, tree, Some(cgroups))
+}
+
+/// Sends `signal` to the children that `targets` forked since they were
+/// frozen, which the kill signal would otherwise miss.
+fn kill_forked(targets: &[u32], signal: gracekill::Signal) {
+    use gracekill::ProcessController;
+
+    let mut forked: Vec<u32> = Vec::new();
+    for &pid in targets {
+        for descendant in gracekill::procfs::descendants(pid) {
+            if !targets.contains(&descendant) && !forked.contains(&descendant) {
+                forked.push(descendant);
+            }
+        }
+    }
+    if forked.is_empty() {
+        return;
+    }
+    log::warn(&format!(
+        "{} process(es) forked by the targets during the grace period, sending them {signal} too",
+        forked.len()
+    ));
+    for pid in forked {
+        if let Err(e) = gracekill::System.send_signal(pid, signal) {
+            log::warn(&format!("Failed to send {signal} to PID {pid}: {e}"));
+        }
+    }
+}
+
+/// Requests `--shutdown-url`, if given, and returns the targets that exited
+/// because of it.
+#[cfg(feature = "shutdown-url")]
+fn request_shutdown(options: &Options) -> Vec<u32> {
+    use gracekill::ProcessController;
+
+    let Some(url) = &options.shutdown_url else {
+        return Vec::new();
+    };
+    if options.simulate {
+        return Vec::new();
+    }
+    let mut liveness = Commands::new(false, None, options.liveness);
+    shutdown::request_shutdown(
+        url,
+        options.shutdown_method,
+        &options.pids,
+        options.shutdown_wait,
+        |pid| liveness.is_running(pid),
+    )
+}
+
+#[cfg(not(feature = "shutdown-url"))]
+fn request_shutdown(_options: &Options) -> Vec<u32> {
+    Vec::new()
+}
+
+/// Records the targets that exited on the `--shutdown-url` request or the
+/// `--stdin-cmd` as having exited gracefully, ahead of those that had to
+/// be signalled.
+fn add_shut_down(shut_down: &[u32], report: &mut KillReport, rows: &mut Vec<audit::Row>) {
+    if shut_down.is_empty() {
+        return;
+    }
+    report
+        .outcomes
+        .splice(0..0, shut_down.iter().map(|&pid| (pid, Outcome::Graceful)));
+    rows.splice(
+        0..0,
+        shut_down.iter().map(|&pid| audit::Row::unsignalled(pid)),
+    );
+}
+
+/// Runs a second graceful kill over the `children` left behind by
+/// `--kill-orphans-after-parent` targets that exited, adding its outcomes to
+/// `report` and `rows`.
+fn kill_orphans(
+    children: &orphans::Children,
+    report: &mut KillReport,
+    rows: &mut Vec<audit::Row>,
+    options: &Options,
+) {
+    let exited: Vec<u32> = options
+        .orphan_parents
+        .iter()
+        .copied()
+        .filter(|&pid| {
+            matches!(
+                report.outcome(pid),
+                Some(Outcome::Graceful | Outcome::Killed)
+            )
+        })
+        .collect();
+    let targets: Vec<u32> = report.outcomes.iter().map(|&(pid, _)| pid).collect();
+    let orphans = children.orphaned(&exited, &targets);
+    if orphans.is_empty() {
+        if !exited.is_empty() {
+            log("Orphan cleanup: no processes were left behind");
+        }
+        return;
+    }
+    log(&format!(
+        "Orphan cleanup: starting graceful kill for {} process(es) with {} grace period",
+        orphans.len(),
+        log::duration(options.grace_period)
+    ));
+    let (orphan_report, orphan_rows) = run_recorded(kill_for(&orphans, options), &orphans, options);
+    log::summary(&format!("Orphan cleanup: {}", orphan_report.summary()));
+    report.outcomes.extend(orphan_report.outcomes);
+    report.exit_statuses.extend(orphan_report.exit_statuses);
+    report.lingering.extend(orphan_report.lingering);
+    report.aborted |= orphan_report.aborted;
+    report.grace_overrun = report.grace_overrun.max(orphan_report.grace_overrun);
+    report.exit_signals.extend(orphan_report.exit_signals);
+    report.survivals.extend(orphan_report.survivals);
+    report.vanished.extend(orphan_report.vanished);
+    merge_stages(&mut report.stages, &orphan_report.stages);
+    rows.extend(orphan_rows);
+}
+
+/// `pids` in the order they are signalled, after `--shuffle` and
+/// `--tree-order`, and those of them signalled after a parent or child.
+fn signal_order(pids: &[u32], options: &Options) -> (Vec<u32>, Vec<u32>) {
+    let mut pids = match options.seed {
+        Some(seed) if options.shuffle => shuffle::shuffled(pids, seed),
+        _ => pids.to_vec(),
+    };
+    let followers = tree_order::ordered(&mut pids, options.tree_order);
+    (pids, followers)
+}
+
+/// Runs `gracekill` over `pids`, collecting `--report` rows if requested.
+fn run_recorded(
+    mut gracekill: GraceKill<Commands>,
+    pids: &[u32],
+    options: &Options,
+) -> (KillReport, Vec<audit::Row>) {
+    let (pids, followers) = signal_order(pids, options);
+    gracekill = gracekill.exit_with_relatives(followers);
+    let pids = &pids[..];
+    trace::event("run", &format!("pids={}", trace::pid_list(pids)));
+    if options.enrich == Enrich::Full {
+        enrich::describe(pids);
+    }
+    let recorder = options
+        .report
+        .as_ref()
+        .map(|_| audit::Recorder::new(pids, options.enrich > Enrich::Off));
+    if let Some(recorder) = &recorder {
+        gracekill = gracekill.on_result(recorder.callback());
+    }
+    if options.status_json {
+        gracekill = gracekill.on_poll(status_json::printer(pids));
+    }
+    if let Some(collector) = stats::collector() {
+        gracekill = gracekill.on_result(collector);
+    }
+    if let Some(collector) = otlp::collector() {
+        gracekill = gracekill.on_result(collector);
+    }
+    if let (Some(progress), Some(counter)) = (sd_notify::progress(), sd_notify::counter()) {
+        gracekill = gracekill.on_poll(progress).on_result(counter);
+    }
+    let report = gracekill.run(pids);
+    let rows = recorder.map(|recorder| recorder.finish(gracekill.controller()));
+    (report, rows.unwrap_or_default())
+}
+
+/// Runs `--watch-pid-file` and returns the exit code.
+fn run_watch(path: &str, options: &Options) -> i32 {
+    if options.has_targets() || options.batch_file.is_some() {
+        fail("--watch-pid-file cannot be combined with PIDs or --batch-file");
+    }
+    let mut controller = Commands::new(
+        options.print_commands,
+        options.privilege_helper.as_deref(),
+        options.liveness,
+    );
+    let report = match pid_file::watch(
+        &mut controller,
+        path,
+        options.grace_period,
+        options.kill_signal,
+        gracekill::DEFAULT_POLL_INTERVAL,
+    ) {
+        Ok(report) => report,
+        Err(e) => fail(&e),
+    };
+    log::summary(&format!("Summary: {}", report.summary()));
+    settle_held_log(&report, options);
+    conclude(&report, options)
+}
+
+/// Runs `--watch`, killing processes with a `--name` as they appear until
+/// SIGINT or SIGTERM.
+fn run_name_watch(options: &Options) -> i32 {
+    if options.names.is_empty() {
+        fail("--watch needs a --name to watch for");
+    }
+    if !options.pids.is_empty() || !options.groups.is_empty() || !options.sessions.is_empty() {
+        fail("--watch only watches for --name targets and cannot be combined with PIDs");
+    }
+    if !options.containers.is_empty() || options.listen.is_some() || options.dry_run {
+        fail("--watch cannot be combined with --container, --listen or --dry-run");
+    }
+    let start = Instant::now();
+    log(&format!(
+        "Watching for processes named {} every {}",
+        options.names.join(", "),
+        log::duration(options.scan_interval)
+    ));
+    let scan = || processes_named(&options.names).unwrap_or_else(|e| fail(&e));
+    let mut rows = Vec::new();
+    let kill = |pids: &[u32]| {
+        let (report, kill_rows) = run_recorded(kill_for(pids, options), pids, options);
+        rows.extend(kill_rows);
+        report
+    };
+    let reports = watch::run(options.scan_interval, scan, kill).unwrap_or_else(|e| fail(&e));
+    let combined = combine(&reports, start.elapsed());
+    write_report(&combined, &rows, options);
+    log::summary(&format!(
+        "Watch ended after {} kill(s): {}",
+        reports.len(),
+        combined.summary()
+    ));
+    stats::print(options.status_json);
+    otlp::export();
+    settle_held_log(&combined, options);
+    finish_reports(&combined, &reports, options)
+}
+
+/// Runs `--stream`, killing the PIDs on stdin a chunk at a time, and
+/// returns the exit code.
+fn run_stream(options: &Options) -> i32 {
+    if options.has_targets() {
+        fail("--stream reads its PIDs from stdin and cannot be combined with PIDs");
+    }
+    let start = Instant::now();
+    let own = process::id();
+    // Looked up once: the chain from gracekill to init doesn't change
+    #[cfg(target_os = "linux")]
+    let ancestors = if options.simulate {
+        Vec::new()
+    } else {
+        gracekill::procfs::ancestors(own)
+    };
+    #[cfg(not(target_os = "linux"))]
+    let ancestors: Vec<u32> = Vec::new();
+    let kill = |chunk: &[u32]| {
+        let mut pids: Vec<u32> = chunk
+            .iter()
+            .copied()
+            .filter(|&pid| {
+                pid != own && (!ancestors.contains(&pid) || ancestor_allowed(pid, options))
+            })
+            .collect();
+        if !options.include_foreground && !options.simulate {
+            exclude_foreground(&mut pids);
+        }
+        if pids.is_empty() {
+            return KillReport::default();
+        }
+        run_recorded(kill_for(&pids, options), &pids, options).0
+    };
+    log(&format!(
+        "Streaming PIDs from stdin in chunks of {} with {} grace period",
+        options.chunk_size,
+        log::duration(options.grace_period)
+    ));
+    let streamed = stream::run(
+        io::stdin().lock(),
+        options.chunk_size,
+        options.skip_invalid,
+        kill,
+    );
+    log::summary(&format!(
+        "Stream complete: {} PID(s) in {} chunk(s): {}",
+        streamed.pids,
+        streamed.chunks,
+        streamed.tally.summary()
+    ));
+    stats::print(options.status_json);
+    otlp::export();
+    if options.quiet_on_success {
+        if streamed.tally.all_graceful() {
+            log::discard();
+        } else {
+            log::release();
+        }
+    }
+    if let Some(e) = streamed.error {
+        fail(&e);
+    }
+    print_result_line(&streamed.tally, start.elapsed(), options);
+    tally_exit_code(&streamed.tally, options)
+}
+
+/// Whether `--stream` may signal `pid`, an ancestor of gracekill: only with
+/// `--force`, as for PIDs given up front.
+fn ancestor_allowed(pid: u32, options: &Options) -> bool {
+    #[cfg(target_os = "linux")]
+    if let Err(e) = check_ancestors(&[pid], options.force) {
+        log::warn(&format!("Skipping PID {pid}: {e}"));
+        return false;
+    }
+    #[cfg(not(target_os = "linux"))]
+    let _ = (pid, options);
+    true
+}
+
+/// Writes the `--report` CSV file, the `--report-pids-fd` results and the
+/// `--snapshot` line, each if requested.
+fn write_report(report: &KillReport, rows: &[audit::Row], options: &Options) {
+    if options.snapshot {
+        snapshot::print(report);
+    }
+    if let Some(path) = &options.report
+        && let Err(e) = audit::write(path, options.report_append, rows)
+    {
+        log::error(&format!("Failed to write report '{path}': {e}"));
+    }
+    if let Some(fd) = options.report_pids_fd
+        && let Err(e) = report_fd::write(fd, report)
+    {
+        log::error(&format!("Failed to write to --report-pids-fd {fd}: {e}"));
+    }
+}
+
+/// With `--quiet-on-success`, drops the held log if every process exited
+/// gracefully and prints it otherwise.
+fn settle_held_log(report: &KillReport, options: &Options) {
+    if !options.quiet_on_success {
+        return;
+    }
+    if report.all_graceful() {
+        log::discard();
+    } else {
+        log::release();
+    }
+}
+
+/// Prints the `--nagios` status line if requested, or else the `RESULT`
+/// line, and returns the exit code for `report`.
+fn conclude(report: &KillReport, options: &Options) -> i32 {
+    if options.nagios {
+        nagios::conclude(report)
+    } else {
+        print_result(report, options);
+        exit_code(report, options)
+    }
+}
+
+/// Prints the `--shell-vars` assignments if requested, or else the `RESULT`
+/// line that ends every run, e.g.
+/// `RESULT graceful=3 killed=1 failed=0 elapsed_ms=31200`, unless the log
+/// level is `error` or `--quiet-on-success` dropped the log.
+fn print_result(report: &KillReport, options: &Options) {
+    if options.shell_vars {
+        shell_vars::print(report);
+        return;
+    }
+    print_result_line(&Tally::of(report), report.elapsed, options);
+}
+
+/// Prints the RESULT line for `tally`, a run that took `elapsed`.
+fn print_result_line(tally: &Tally, elapsed: Duration, options: &Options) {
+    if log::level() == log::Level::Error || (options.quiet_on_success && tally.all_graceful()) {
+        return;
+    }
+    stdout::line(&format!(
+        "RESULT graceful={} killed={} failed={} elapsed_ms={}",
+        tally.graceful,
+        tally.killed,
+        tally.failed,
+        elapsed.as_millis()
+    ));
+}
+
+fn exit_code(report: &KillReport, options: &Options) -> i32 {
+    tally_exit_code(&Tally::of(report), options)
+}
+
+fn tally_exit_code(tally: &Tally, options: &Options) -> i32 {
+    if tally.signalled == 0 {
+        return 2; // No processes could be signaled
+    }
+    if tally.aborted {
+        return 5;
+    }
+    if tally.survived > 0 || tally.lingering > 0 {
+        return 4;
+    }
+    if tally.killed > 0 && options.exit_non_zero_if_sigkill_required {
+        return 3;
+    }
+    0
+}
+
+/// Adds the per-stage exit counts of `from` to `into`, keeping the order
+/// the signals were first sent in.
+fn merge_stages(into: &mut Vec<(gracekill::Signal, usize)>, from: &[(gracekill::Signal, usize)]) {
+    for &(signal, exited) in from {
+        match into.iter_mut().find(|(s, _)| *s == signal) {
+            Some((_, total)) => *total += exited,
+            None => into.push((signal, exited)),
+        }
+    }
+}
+
+/// Ranks exit codes from best to worst outcome.
+fn severity(code: i32) -> u8 {
+    match code {
+        0 => 0,
+        3 => 1,
+        4 => 2,
+        5 => 3,
+        _ => 4,
+    }
+}
+
+/// Runs every batch operation, optionally concurrently, and returns the exit
+/// code of the worst outcome.
+fn run_batch(operations: &[Operation], options: &Options) -> i32 {
+    let start = Instant::now();
+    let run = |operation: &Operation| run_operation(operation, options);
+
+    let results: Vec<(KillReport, Vec<audit::Row>)> = if options.parallel {
+        thread::scope(|scope| {
+            let handles: Vec<_> = operations
+                .iter()
+                .map(|operation| scope.spawn(move || run(operation)))
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap_or_default())
+                .collect()
+        })
+    } else {
+        let mut results = Vec::with_capacity(operations.len());
+        for operation in operations {
+            let result = run(operation);
+            let aborted = result.0.aborted;
+            results.push(result);
+            if aborted {
+                log::error("Skipping the remaining batch operations");
+                break;
+            }
+        }
+        results
+    };
+
+    let (reports, rows): (Vec<KillReport>, Vec<Vec<audit::Row>>) = results.into_iter().unzip();
+    let combined = combine(&reports, start.elapsed());
+    write_report(
+        &combined,
+        &rows.into_iter().flatten().collect::<Vec<_>>(),
+        options,
+    );
+    log::summary(&format!(
+        "Batch complete: {} operation(s), {}",
+        reports.len(),
+        combined.summary()
+    ));
+    stats::print(options.status_json);
+    otlp::export();
+    settle_held_log(&combined, options);
+    finish_reports(&combined, &reports, options)
+}
+
+/// Kills the targets of one batch operation with its own settings,
+/// falling back to the command-line ones.
+fn run_operation(operation: &Operation, options: &Options) -> (KillReport, Vec<audit::Row>) {
+    let grace = operation_grace(operation, options);
+    log(&format!(
+        "Starting operation from {} for {} process(es) with {} grace period",
+        operation.source,
+        operation.pids.len(),
+        log::duration(grace)
+    ));
+    run_recorded(
+        operation_kill(operation, grace, options),
+        &operation.pids,
+        options,
+    )
+}
+
+/// The grace period of a batch operation: its own, within `--min-grace` and
+/// `--max-grace`, or else the command-line one.
+fn operation_grace(operation: &Operation, options: &Options) -> Duration {
+    operation
+        .grace
+        .map_or(options.grace_period, |grace| clamp_grace(grace, options))
+}
+
+/// The kill of a batch operation's targets with `grace`: the command-line
+/// one, with its escalation stages, retries and protections, but the
+/// operation's own signals where it has them.
+fn operation_kill(
+    operation: &Operation,
+    grace: Duration,
+    options: &Options,
+) -> GraceKill<Commands> {
+    let mut gracekill = kill_for(&operation.pids, options).grace(grace);
+    if let Some(signal) = operation.signal {
+        gracekill = gracekill.signal(signal);
+    }
+    if let Some(signal) = operation.kill_signal {
+        gracekill = gracekill.kill_signal(signal);
+    }
+    gracekill
+}
+
+/// Runs `--fifo`, starting a kill for each command read from the pipe
+/// until SIGINT or SIGTERM.
+fn run_fifo(path: &str, options: &Options) -> i32 {
+    if options.has_targets() {
+        fail("PIDs cannot be combined with --fifo");
+    }
+    let start = Instant::now();
+    let rows = Mutex::new(Vec::new());
+    let kill = |operation: &Operation| {
+        let (report, operation_rows) = run_operation(operation, options);
+        rows.lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .extend(operation_rows);
+        report
+    };
+    let reports = fifo::run(path, kill).unwrap_or_else(|e| fail(&e));
+    let combined = combine(&reports, start.elapsed());
+    write_report(
+        &combined,
+        &rows.into_inner().unwrap_or_else(PoisonError::into_inner),
+        options,
+    );
+    log::summary(&format!(
+        "Stopped reading FIFO '{path}' after {} operation(s): {}",
+        reports.len(),
+        combined.summary()
+    ));
+    stats::print(options.status_json);
+    otlp::export();
+    settle_held_log(&combined, options);
+    finish_reports(&combined, &reports, options)
+}
+
+/// Concludes a run made of several kills, exiting with the most severe of
+/// their exit codes.
+fn finish_reports(combined: &KillReport, reports: &[KillReport], options: &Options) -> i32 {
+    if options.nagios {
+        return nagios::conclude(combined);
+    }
+    print_result(combined, options);
+    reports
+        .iter()
+        .map(|report| exit_code(report, options))
+        .max_by_key(|&code| severity(code))
+        .unwrap_or(0)
+}
+
+/// One report for several kills that took `elapsed` in all.
+fn combine(reports: &[KillReport], elapsed: Duration) -> KillReport {
+    KillReport {
+        outcomes: reports
+            .iter()
+            .flat_map(|report| report.outcomes.iter().cloned())
+            .collect(),
+        exit_statuses: reports
+            .iter()
+            .flat_map(|report| report.exit_statuses.iter().copied())
+            .collect(),
+        lingering: reports
+            .iter()
+            .flat_map(|report| report.lingering.iter().copied())
+            .collect(),
+        aborted: reports.iter().any(|report| report.aborted),
+        cancelled: reports.iter().any(|report| report.cancelled),
+        snapshots: reports
+            .iter()
+            .flat_map(|report| report.snapshots.iter().cloned())
+            .collect(),
+        exit_signals: reports
+            .iter()
+            .flat_map(|report| report.exit_signals.iter().copied())
+            .collect(),
+        survivals: reports
+            .iter()
+            .flat_map(|report| report.survivals.iter().copied())
+            .collect(),
+        vanished: reports
+            .iter()
+            .flat_map(|report| report.vanished.iter().copied())
+            .collect(),
+        stages: reports.iter().fold(Vec::new(), |mut stages, report| {
+            merge_stages(&mut stages, &report.stages);
+            stages
+        }),
+        grace_overrun: reports
+            .iter()
+            .map(|report| report.grace_overrun)
+            .max()
+            .unwrap_or_default(),
+        quorum_reached: reports
+            .iter()
+            .filter_map(|report| report.quorum_reached)
+            .min(),
+        elapsed,
+    }
 }
@@ -0,0 +1,89 @@
+//! `--nagios`: finish with a single status line on stdout and a Nagios
+//! plugin exit code, so that gracekill can run as a check:
+//!
+//! ```text
+//! WARNING: 3 exited gracefully, 1 killed, 0 failed | graceful=3 killed=1 failed=0
+//! ```
+//!
+//! This replaces the exit codes listed under EXIT STATUS for the whole run,
+//! including usage errors, which come out as UNKNOWN.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use gracekill::KillReport;
+
+use crate::stdout;
+
+/// Whether `--nagios` is in effect, for exits that have no `Options` at hand.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// A Nagios service state, with its plugin exit code as discriminant.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum State {
+    Ok = 0,
+    Warning = 1,
+    Critical = 2,
+    Unknown = 3,
+}
+
+impl State {
+    fn label(self) -> &'static str {
+        match self {
+            State::Ok => "OK",
+            State::Warning => "WARNING",
+            State::Critical => "CRITICAL",
+            State::Unknown => "UNKNOWN",
+        }
+    }
+}
+
+/// Switches every later exit to Nagios conventions.
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Whether `--nagios` is in effect.
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// The state for `report`: OK if every target exited gracefully, WARNING if
+/// some had to be killed, and CRITICAL if any could not be signalled or
+/// killed, or was left running. UNKNOWN if no target could be signalled at
+/// all, as there was nothing to check.
+pub fn state(report: &KillReport) -> State {
+    if report.signalled() == 0 {
+        State::Unknown
+    } else if report.aborted
+        || report.failed() > 0
+        || report.survived() > 0
+        || !report.lingering.is_empty()
+    {
+        State::Critical
+    } else if report.killed() > 0 {
+        State::Warning
+    } else {
+        State::Ok
+    }
+}
+
+/// Prints the status line for `report` and returns the exit code.
+pub fn conclude(report: &KillReport) -> i32 {
+    let state = state(report);
+    stdout::line(&format!(
+        "{}: {} | graceful={} killed={} failed={}",
+        state.label(),
+        report.summary(),
+        report.graceful(),
+        report.killed(),
+        report.failed()
+    ));
+    state as i32
+}
+
+/// Prints the status line for a run that could not be carried out and
+/// returns the exit code.
+pub fn unknown(message: &str) -> i32 {
+    stdout::line(&format!("{}: {message}", State::Unknown.label()));
+    State::Unknown as i32
+}
@@ -0,0 +1,70 @@
+//! `--netns`: look up `--port` sockets in another network namespace, such
+//! as a container's, whose socket tables gracekill's own `/proc/net` does
+//! not show. The thread doing the lookup enters the namespace with
+//! `setns(2)` for as long as it reads the tables, then goes back to the
+//! namespace it started in; everything else gracekill does, signals
+//! included, happens where it always does, as PIDs are not per network
+//! namespace. Entering a namespace needs `CAP_SYS_ADMIN`.
+
+use std::fs::File;
+use std::io;
+use std::os::fd::AsRawFd;
+
+use gracekill::log;
+
+/// The namespace file for a `--netns` value: `/proc/<pid>/ns/net` for a
+/// PID, or else the value as a path, e.g. a namespace bound under
+/// `/run/netns` by `ip netns add`.
+fn namespace_file(target: &str) -> String {
+    if !target.is_empty() && target.bytes().all(|b| b.is_ascii_digit()) {
+        format!("/proc/{target}/ns/net")
+    } else {
+        target.to_string()
+    }
+}
+
+/// What to do about a namespace that can't be opened or entered for lack
+/// of privileges.
+fn hint(e: &io::Error) -> &'static str {
+    if e.kind() == io::ErrorKind::PermissionDenied {
+        " (entering a network namespace needs CAP_SYS_ADMIN)"
+    } else {
+        ""
+    }
+}
+
+/// Moves the calling thread into the network namespace `file` refers to.
+fn enter(file: &File) -> io::Result<()> {
+    // SAFETY: setns only reads the descriptor, which file keeps open
+    if unsafe { libc::setns(file.as_raw_fd(), libc::CLONE_NEWNET) } == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+/// Runs `scan` in the network namespace given by `target`, a PID or a
+/// path, and returns the calling thread to its own network namespace
+/// afterwards.
+pub fn within<T>(target: &str, scan: impl FnOnce() -> T) -> Result<T, String> {
+    let own = File::open("/proc/thread-self/ns/net")
+        .map_err(|e| format!("Cannot open gracekill's own network namespace: {e}"))?;
+    let path = namespace_file(target);
+    let namespace = File::open(&path).map_err(|e| {
+        format!(
+            "Cannot open network namespace '{path}' for --netns: {e}{}",
+            hint(&e)
+        )
+    })?;
+    enter(&namespace).map_err(|e| {
+        format!(
+            "Cannot enter network namespace '{path}' for --netns: {e}{}",
+            hint(&e)
+        )
+    })?;
+    log::debug(&format!("Entered network namespace '{path}'"));
+    let result = scan();
+    enter(&own).map_err(|e| format!("Cannot return to gracekill's own network namespace: {e}"))?;
+    log::debug("Returned to gracekill's own network namespace");
+    Ok(result)
+}
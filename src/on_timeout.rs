@@ -0,0 +1,108 @@
+//! `--on-timeout`: run one command when the grace period runs out with
+//! targets still running, just before they are killed, for paging someone
+//! or a last attempt at recovery.
+//!
+//! The command is split on whitespace and run once, with the PIDs still
+//! running appended as arguments. Its environment also has:
+//!
+//! - `GRACEKILL_SURVIVORS`: the same PIDs, space-separated;
+//! - `GRACEKILL_REMAINING_MS`: how long it has left to run, in milliseconds.
+//!
+//! Its output goes to gracekill's stderr, alongside the log. A command still
+//! running after `--on-timeout-limit` is killed, so that the kill is only
+//! ever delayed that long.
+
+use std::io;
+use std::os::fd::AsFd;
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use gracekill::log::{self, log};
+
+/// How long the command may run without `--on-timeout-limit`.
+pub const DEFAULT_LIMIT: Duration = Duration::from_secs(10);
+
+/// The time left until `deadline` in milliseconds, for
+/// `GRACEKILL_REMAINING_MS`.
+pub fn remaining_ms(deadline: Instant) -> String {
+    deadline
+        .saturating_duration_since(Instant::now())
+        .as_millis()
+        .to_string()
+}
+
+/// How often the running command is checked for completion.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// A callback for [`GraceKill::before_kill`](gracekill::GraceKill::before_kill)
+/// running `command` with the targets about to be killed.
+pub fn hook(command: &str, limit: Duration) -> impl FnMut(&[u32]) + Send + 'static {
+    let command: Vec<String> = command.split_whitespace().map(String::from).collect();
+    move |pids| run(&command, pids, limit)
+}
+
+fn run(command: &[String], pids: &[u32], limit: Duration) {
+    let Some((program, args)) = command.split_first() else {
+        return;
+    };
+    let deadline = Instant::now() + limit;
+    let mut child = match spawn(program, args, pids, deadline) {
+        Ok(child) => child,
+        Err(e) => {
+            log::warn(&format!("Failed to run the --on-timeout command: {e}"));
+            return;
+        }
+    };
+    log(&format!(
+        "Running '{}' for {} process(es) still running after the grace period",
+        command.join(" "),
+        pids.len()
+    ));
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) if status.success() => {
+                log("The --on-timeout command finished");
+                return;
+            }
+            Ok(Some(status)) => {
+                log::warn(&format!("The --on-timeout command failed ({status})"));
+                return;
+            }
+            Ok(None) => {}
+            Err(e) => {
+                log::warn(&format!("Failed to wait for the --on-timeout command: {e}"));
+                return;
+            }
+        }
+        if Instant::now() >= deadline {
+            log::warn(&format!(
+                "The --on-timeout command is still running after {}, stopping it",
+                log::duration(limit)
+            ));
+            let _ = child.kill();
+            let _ = child.wait();
+            return;
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+fn spawn(program: &str, args: &[String], pids: &[u32], deadline: Instant) -> Result<Child, String> {
+    let pids: Vec<String> = pids.iter().map(u32::to_string).collect();
+    let output = io::stderr()
+        .as_fd()
+        .try_clone_to_owned()
+        .map_err(|e| e.to_string())?;
+    let errors = output.try_clone().map_err(|e| e.to_string())?;
+    Command::new(program)
+        .args(args)
+        .args(&pids)
+        .env("GRACEKILL_SURVIVORS", pids.join(" "))
+        .env("GRACEKILL_REMAINING_MS", remaining_ms(deadline))
+        .stdin(Stdio::null())
+        .stdout(output)
+        .stderr(errors)
+        .spawn()
+        .map_err(|e| format!("'{program}' could not be run: {e}"))
+}
@@ -0,0 +1,100 @@
+//! `--kill-orphans-after-parent`: clean up the children a target leaves
+//! behind.
+//!
+//! A parent that exits gracefully may still strand children of its own,
+//! which the kernel reparents to init. Since they can no longer be traced
+//! back through their PPID, the parent's children are recorded just before
+//! it is signalled; once it has exited, those now parented by PID 1 are
+//! taken through the graceful kill as well. Children are matched by start
+//! time too, so a reused PID is never mistaken for one of them.
+
+#[cfg(target_os = "linux")]
+use gracekill::log::log;
+
+/// A child of a `--kill-orphans-after-parent` target, as recorded before the
+/// target was signalled.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+struct Child {
+    parent: u32,
+    pid: u32,
+    /// Start time in clock ticks after boot.
+    starttime: u64,
+}
+
+/// The children of the parents given to [`snapshot`].
+pub struct Children(Vec<Child>);
+
+/// Records the current children of each of `parents`.
+#[cfg(target_os = "linux")]
+pub fn snapshot(parents: &[u32]) -> Children {
+    use gracekill::procfs;
+
+    let own = std::process::id();
+    let children: Vec<Child> = procfs::pids()
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|&pid| pid != own)
+        .filter_map(|pid| procfs::stat(pid).ok())
+        .filter(|stat| parents.contains(&stat.ppid))
+        .map(|stat| Child {
+            parent: stat.ppid,
+            pid: stat.pid,
+            starttime: stat.starttime,
+        })
+        .collect();
+    for &parent in parents {
+        let count = children
+            .iter()
+            .filter(|child| child.parent == parent)
+            .count();
+        log(&format!(
+            "PID {parent} has {count} child process(es) to look for once it exits"
+        ));
+    }
+    Children(children)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn snapshot(_parents: &[u32]) -> Children {
+    gracekill::log::warn("--kill-orphans-after-parent is only supported on Linux");
+    Children(Vec::new())
+}
+
+impl Children {
+    /// The recorded children of `exited` parents that have since been
+    /// reparented to init, leaving out any in `exclude`.
+    #[cfg(target_os = "linux")]
+    pub fn orphaned(&self, exited: &[u32], exclude: &[u32]) -> Vec<u32> {
+        let mut orphans = Vec::new();
+        for &parent in exited {
+            let left: Vec<u32> = self
+                .0
+                .iter()
+                .filter(|child| child.parent == parent && !exclude.contains(&child.pid))
+                .filter(|child| {
+                    gracekill::procfs::stat(child.pid).is_ok_and(|stat| {
+                        stat.ppid == 1 && stat.starttime == child.starttime && stat.state != 'Z'
+                    })
+                })
+                .map(|child| child.pid)
+                .collect();
+            if !left.is_empty() {
+                log(&format!(
+                    "Orphan cleanup: PID {parent} left {} process(es) reparented to init: {}",
+                    left.len(),
+                    left.iter()
+                        .map(u32::to_string)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ));
+            }
+            orphans.extend(left);
+        }
+        orphans
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn orphaned(&self, _exited: &[u32], _exclude: &[u32]) -> Vec<u32> {
+        Vec::new()
+    }
+}
@@ -0,0 +1,349 @@
+//! `--otlp-endpoint`: send the run to an OpenTelemetry collector as a
+//! trace, once it is over, so that shutdowns can be followed alongside
+//! the rest of the operation they were part of.
+//!
+//! The trace has a `gracekill` span for the whole run, from start to
+//! finish, with a `gracekill.target` child span for each target, from
+//! the start of its kill to when its outcome was settled:
+//!
+//! ```text
+//! gracekill                  gracekill.operation_id=3fa9c1 gracekill.targets=2
+//!                            gracekill.graceful=1 gracekill.killed=1 gracekill.failed=0
+//! ├─ gracekill.target        process.pid=1234 gracekill.outcome=graceful
+//! └─ gracekill.target        process.pid=5678 gracekill.outcome=killed
+//! ```
+//!
+//! `gracekill.outcome` is named as in `--output-pid-status-json`; a span
+//! whose target failed, and the run's span if any did, has an error
+//! status. With a W3C `TRACEPARENT` in the environment, as set by tools
+//! like `otel-cli`, the run's span joins that trace as a child of its
+//! span; otherwise it starts a trace of its own.
+//!
+//! The spans go out in a single OTLP/HTTP request with a JSON body, to
+//! `/v1/traces` at the endpoint unless its URL has a path. Like
+//! `--shutdown-url`, the client is minimal, plain `http://` only, and sits
+//! behind a feature, `otlp`, which is off by default. A failed export is
+//! a warning; it doesn't change the exit code.
+
+use std::collections::hash_map::RandomState;
+use std::fmt::Write as _;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::{Mutex, PoisonError};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use gracekill::Outcome;
+use gracekill::log;
+
+use crate::{json, shutdown, status_json};
+
+/// The run being recorded, if `--otlp-endpoint` was given.
+static RUN: Mutex<Option<Run>> = Mutex::new(None);
+
+struct Run {
+    endpoint: String,
+    started: SystemTime,
+    targets: Vec<Target>,
+}
+
+/// What happened to one target, and when.
+struct Target {
+    pid: u32,
+    outcome: Outcome,
+    start: SystemTime,
+    end: SystemTime,
+}
+
+/// Checks that spans can be sent to `url`.
+pub fn check_endpoint(url: &str) -> Result<(), String> {
+    if !cfg!(feature = "otlp") {
+        return Err("--otlp-endpoint needs gracekill built with the otlp feature".to_string());
+    }
+    shutdown::parse_url(url, "OTLP endpoint").map(|_| ())
+}
+
+/// Starts recording the run for `url`.
+pub fn enable(url: &str) {
+    *RUN.lock().unwrap_or_else(PoisonError::into_inner) = Some(Run {
+        endpoint: url.to_string(),
+        started: SystemTime::now(),
+        targets: Vec::new(),
+    });
+}
+
+/// A [`GraceKill::on_result`](gracekill::GraceKill::on_result) callback
+/// recording each outcome as settled now, in a kill that started now, if
+/// `--otlp-endpoint` is on.
+pub fn collector() -> Option<impl FnMut(u32, &Outcome) + Send + 'static> {
+    let start = SystemTime::now();
+    RUN.lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .is_some()
+        .then_some(move |pid, outcome: &Outcome| {
+            if let Some(run) = RUN.lock().unwrap_or_else(PoisonError::into_inner).as_mut() {
+                run.targets.push(Target {
+                    pid,
+                    outcome: outcome.clone(),
+                    start,
+                    end: SystemTime::now(),
+                });
+            }
+        })
+}
+
+/// Sends the spans of the run, if `--otlp-endpoint` is on.
+pub fn export() {
+    let Some(run) = RUN.lock().unwrap_or_else(PoisonError::into_inner).take() else {
+        return;
+    };
+    let body = spans(&run, SystemTime::now(), parent());
+    let mut endpoint = match shutdown::parse_url(&run.endpoint, "OTLP endpoint") {
+        Ok(endpoint) => endpoint,
+        Err(e) => {
+            log::warn(&e);
+            return;
+        }
+    };
+    if endpoint.path == "/" {
+        endpoint.path = "/v1/traces".to_string();
+    }
+    match client::post(&endpoint, &body) {
+        Ok(status) if (200..300).contains(&status.0) => {
+            log::debug(&format!(
+                "Sent {} span(s) to {}: {}",
+                run.targets.len() + 1,
+                run.endpoint,
+                status.1
+            ));
+        }
+        Ok(status) => log::warn(&format!(
+            "OTLP endpoint {} answered {}, spans not sent",
+            run.endpoint, status.1
+        )),
+        Err(e) => log::warn(&format!(
+            "Cannot send spans to OTLP endpoint {}: {e}",
+            run.endpoint
+        )),
+    }
+}
+
+/// The trace and span IDs from a W3C `TRACEPARENT`, e.g.
+/// `00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01`.
+fn parent() -> Option<(String, String)> {
+    let traceparent = std::env::var("TRACEPARENT").ok()?;
+    let mut fields = traceparent.trim().split('-');
+    let (_version, trace_id, span_id) = (fields.next()?, fields.next()?, fields.next()?);
+    let hex = |id: &str, len: usize| {
+        id.len() == len
+            && id.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+            && id.bytes().any(|b| b != b'0')
+    };
+    if hex(trace_id, 32) && hex(span_id, 16) {
+        Some((trace_id.to_string(), span_id.to_string()))
+    } else {
+        log::warn(&format!("Ignoring invalid TRACEPARENT '{traceparent}'"));
+        None
+    }
+}
+
+/// A random ID of `bytes` bytes, in hex.
+fn random_id(bytes: usize) -> String {
+    let mut id = String::with_capacity(bytes * 2);
+    while id.len() < bytes * 2 {
+        // Each RandomState is seeded afresh by the standard library
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_usize(id.len());
+        let _ = write!(id, "{:016x}", hasher.finish());
+    }
+    id.truncate(bytes * 2);
+    id
+}
+
+fn nanos(time: SystemTime) -> String {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos()
+        .to_string()
+}
+
+/// The OTLP JSON body for `run`, ending at `end`, as a child of `parent`
+/// if given.
+fn spans(run: &Run, end: SystemTime, parent: Option<(String, String)>) -> String {
+    let (trace_id, parent_id) = match parent {
+        Some((trace_id, span_id)) => (trace_id, Some(span_id)),
+        None => (random_id(16), None),
+    };
+    let root_id = random_id(8);
+    let count = |wanted: fn(&Outcome) -> bool| {
+        run.targets
+            .iter()
+            .filter(|target| wanted(&target.outcome))
+            .count()
+    };
+    let graceful = count(|outcome| *outcome == Outcome::Graceful);
+    let killed = count(|outcome| *outcome == Outcome::Killed);
+    let failed = count(is_failure);
+    let mut attributes = vec![
+        ("gracekill.targets", int(run.targets.len())),
+        ("gracekill.graceful", int(graceful)),
+        ("gracekill.killed", int(killed)),
+        ("gracekill.failed", int(failed)),
+    ];
+    if let Some(id) = log::operation_id() {
+        attributes.insert(0, ("gracekill.operation_id", string(id)));
+    }
+    let mut body = String::new();
+    span(
+        &mut body,
+        &Span {
+            trace_id: &trace_id,
+            id: &root_id,
+            parent: parent_id.as_deref(),
+            name: "gracekill",
+            start: run.started,
+            end,
+            attributes: &attributes,
+            failed: failed > 0,
+        },
+    );
+    for target in &run.targets {
+        body.push(',');
+        span(
+            &mut body,
+            &Span {
+                trace_id: &trace_id,
+                id: &random_id(8),
+                parent: Some(&root_id),
+                name: "gracekill.target",
+                start: target.start,
+                end: target.end,
+                attributes: &[
+                    ("process.pid", int(target.pid)),
+                    (
+                        "gracekill.outcome",
+                        string(status_json::status(&target.outcome)),
+                    ),
+                ],
+                failed: is_failure(&target.outcome),
+            },
+        );
+    }
+    format!(
+        "{{\"resourceSpans\":[{{\"resource\":{{\"attributes\":[{{\"key\":\"service.name\",\"value\":{}}}]}},\"scopeSpans\":[{{\"scope\":{{\"name\":\"gracekill\",\"version\":\"{}\"}},\"spans\":[{body}]}}]}}]}}",
+        string("gracekill"),
+        env!("CARGO_PKG_VERSION")
+    )
+}
+
+fn is_failure(outcome: &Outcome) -> bool {
+    matches!(outcome, Outcome::SignalFailed(_) | Outcome::KillFailed(_))
+}
+
+fn int(value: impl std::fmt::Display) -> String {
+    format!("{{\"intValue\":\"{value}\"}}")
+}
+
+fn string(value: &str) -> String {
+    format!("{{\"stringValue\":{}}}", json::quote(value))
+}
+
+struct Span<'a> {
+    trace_id: &'a str,
+    id: &'a str,
+    parent: Option<&'a str>,
+    name: &'a str,
+    start: SystemTime,
+    end: SystemTime,
+    /// Each key and its OTLP `AnyValue`, as JSON.
+    attributes: &'a [(&'a str, String)],
+    failed: bool,
+}
+
+/// Appends `span` to `body` as an OTLP JSON span.
+fn span(body: &mut String, span: &Span) {
+    let _ = write!(
+        body,
+        "{{\"traceId\":\"{}\",\"spanId\":\"{}\",",
+        span.trace_id, span.id
+    );
+    if let Some(parent_id) = span.parent {
+        let _ = write!(body, "\"parentSpanId\":\"{parent_id}\",");
+    }
+    // Kind 1 is internal; status code 1 is OK and 2 an error
+    let _ = write!(
+        body,
+        "\"name\":\"{}\",\"kind\":1,\"startTimeUnixNano\":\"{}\",\"endTimeUnixNano\":\"{}\",\"attributes\":[",
+        span.name,
+        nanos(span.start),
+        nanos(span.end)
+    );
+    for (index, (key, value)) in span.attributes.iter().enumerate() {
+        let separator = if index == 0 { "" } else { "," };
+        let _ = write!(body, "{separator}{{\"key\":\"{key}\",\"value\":{value}}}");
+    }
+    let _ = write!(
+        body,
+        "],\"status\":{{\"code\":{}}}}}",
+        if span.failed { 2 } else { 1 }
+    );
+}
+
+#[cfg(feature = "otlp")]
+mod client {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::{TcpStream, ToSocketAddrs};
+    use std::time::Duration;
+
+    use crate::shutdown::Endpoint;
+
+    /// How long connecting to the endpoint and each read or write may take.
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// Posts `body` to `endpoint` and returns the response status code and
+    /// line, e.g. `(200, "200 OK")`.
+    pub fn post(endpoint: &Endpoint, body: &str) -> Result<(u16, String), String> {
+        let address = (endpoint.host.as_str(), endpoint.port)
+            .to_socket_addrs()
+            .map_err(|e| format!("cannot resolve {}: {e}", endpoint.host))?
+            .next()
+            .ok_or_else(|| format!("cannot resolve {}", endpoint.host))?;
+        let mut stream =
+            TcpStream::connect_timeout(&address, TIMEOUT).map_err(|e| e.to_string())?;
+        stream
+            .set_read_timeout(Some(TIMEOUT))
+            .and_then(|()| stream.set_write_timeout(Some(TIMEOUT)))
+            .map_err(|e| e.to_string())?;
+        let request = format!(
+            "POST {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: gracekill\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            endpoint.path,
+            endpoint.authority,
+            body.len()
+        );
+        stream
+            .write_all(request.as_bytes())
+            .map_err(|e| e.to_string())?;
+        let mut line = String::new();
+        BufReader::new(stream)
+            .read_line(&mut line)
+            .map_err(|e| e.to_string())?;
+        let status = line
+            .split_once(' ')
+            .map(|(_, status)| status.trim_end())
+            .filter(|status| !status.is_empty())
+            .ok_or_else(|| format!("not an HTTP response: '{}'", line.trim_end()))?;
+        let code = status
+            .get(..3)
+            .and_then(|code| code.parse().ok())
+            .ok_or_else(|| format!("not an HTTP status: '{status}'"))?;
+        Ok((code, status.to_string()))
+    }
+}
+
+#[cfg(not(feature = "otlp"))]
+mod client {
+    use crate::shutdown::Endpoint;
+
+    /// Never called: `--otlp-endpoint` is refused without the feature.
+    pub fn post(_endpoint: &Endpoint, _body: &str) -> Result<(u16, String), String> {
+        Err("gracekill was built without the otlp feature".to_string())
+    }
+}
@@ -0,0 +1,87 @@
+//! `--watch-pid-file`: wait, without signalling, for the process a PID file
+//! names to exit, and kill it only if it outstays the grace period.
+//!
+//! The file is re-read every poll. If it starts naming a different PID (say
+//! the supervisor restarted the service), the new process is watched instead;
+//! the deadline still counts from the start of the watch. The watch ends once
+//! the process the file names has exited, or the file has been removed and
+//! the last process it named has exited.
+
+use std::fs;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use gracekill::log::{self, log};
+use gracekill::{KillReport, Outcome, ProcessController, Signal};
+
+use crate::args::parse_and_validate_pid;
+
+/// Reads the PID in `path`. A missing or unparseable file yields `None`,
+/// since supervisors may be in the middle of rewriting it.
+fn read_pid(path: &str) -> Option<u32> {
+    let contents = fs::read_to_string(path).ok()?;
+    parse_and_validate_pid(contents.trim()).ok()
+}
+
+/// Watches the PID file at `path` for up to `grace`, then sends
+/// `kill_signal` to the process it names if that is still running.
+pub fn watch(
+    controller: &mut impl ProcessController,
+    path: &str,
+    grace: Duration,
+    kill_signal: Signal,
+    poll_interval: Duration,
+) -> Result<KillReport, String> {
+    let mut pid = read_pid(path).ok_or_else(|| format!("No valid PID in '{path}'"))?;
+    log(&format!(
+        "Watching PID {pid} from '{path}' for up to {}",
+        log::duration(grace)
+    ));
+    let start = Instant::now();
+    let mut report = KillReport::default();
+
+    loop {
+        if let Some(current) = read_pid(path)
+            && current != pid
+        {
+            log(&format!("'{path}' now names PID {current} (was {pid})"));
+            if !controller.is_running(pid) {
+                report.outcomes.push((pid, Outcome::Graceful));
+            }
+            // A replaced process still running stays the supervisor's concern
+            pid = current;
+            continue;
+        }
+        if !controller.is_running(pid) {
+            // The supervisor may have already restarted it under a new PID
+            if read_pid(path).is_some_and(|current| current != pid) {
+                continue;
+            }
+            log(&format!(
+                "Process {pid} exited after {:.3?}",
+                start.elapsed()
+            ));
+            report.outcomes.push((pid, Outcome::Graceful));
+            break;
+        }
+        if start.elapsed() >= grace {
+            log(&format!(
+                "Process {pid} still running after {}, sending {kill_signal}",
+                log::duration(grace)
+            ));
+            let outcome = match controller.send_signal(pid, kill_signal) {
+                Ok(()) => Outcome::Killed,
+                Err(e) => {
+                    log::error(&format!("Failed to send {kill_signal} to PID {pid}: {e}"));
+                    Outcome::KillFailed(e)
+                }
+            };
+            report.outcomes.push((pid, outcome));
+            break;
+        }
+        thread::sleep(poll_interval);
+    }
+
+    report.elapsed = start.elapsed();
+    Ok(report)
+}
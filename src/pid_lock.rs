@@ -0,0 +1,227 @@
+//! Keeps two gracekill runs from working on the same target at once, with
+//! an advisory lock per PID: a `flock` on `<dir>/<pid>.lock`, taken before
+//! any signal is sent and held until gracekill exits. A run that finds a
+//! target locked waits for the other to finish with it, or with
+//! `--no-wait-lock` gives up straight away. Each lock file holds the PID of
+//! the run holding it, to say which run is in the way.
+//!
+//! `<dir>` is `GRACEKILL_LOCK_DIR` if set, else the first of
+//! `/run/gracekill`, `$XDG_RUNTIME_DIR/gracekill` and
+//! `/run/user/<uid>/gracekill` that gracekill can create and write to; with
+//! none of them, the targets go unlocked. A target whose lock file can't be
+//! opened or locked goes unlocked too. The locks are best-effort: they only
+//! keep out other gracekill runs using the same directory, so not runs by
+//! another user falling back to their own runtime directory, and nothing
+//! stops anything else from signalling the targets. A PID reused by a new
+//! process shares the lock of the one before.
+//!
+//! The lock files are removed again when gracekill is done with them, as
+//! PIDs come and go. A run that was waiting on a file removed that way
+//! opens it afresh, so that it can't hold a lock on a file nobody else
+//! sees.
+
+use std::ffi::CString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, Write};
+use std::os::fd::AsRawFd;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use gracekill::log::{self, log};
+
+/// The locks held, until [`release`].
+static LOCKS: Mutex<Vec<Lock>> = Mutex::new(Vec::new());
+
+/// A held lock on one target.
+struct Lock {
+    path: PathBuf,
+    /// Holds the `flock` for as long as it is open
+    _file: File,
+}
+
+/// Whether gracekill can create files in `dir`.
+fn writable(dir: &Path) -> bool {
+    let Ok(path) = CString::new(dir.as_os_str().as_bytes()) else {
+        return false;
+    };
+    // SAFETY: access only reads the NUL-terminated path
+    unsafe { libc::access(path.as_ptr(), libc::W_OK | libc::X_OK) == 0 }
+}
+
+/// The directory the lock files go in, created if need be.
+fn lock_dir() -> Option<PathBuf> {
+    if let Some(dir) = std::env::var_os("GRACEKILL_LOCK_DIR") {
+        return Some(PathBuf::from(dir));
+    }
+    // SAFETY: getuid has no preconditions and can't fail
+    let uid = unsafe { libc::getuid() };
+    let candidates = std::iter::once(PathBuf::from("/run/gracekill"))
+        .chain(std::env::var_os("XDG_RUNTIME_DIR").map(|dir| PathBuf::from(dir).join("gracekill")))
+        .chain(std::iter::once(PathBuf::from(format!(
+            "/run/user/{uid}/gracekill"
+        ))));
+    for dir in candidates {
+        match fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o755)
+            .create(&dir)
+        {
+            Ok(()) if writable(&dir) => return Some(dir),
+            Ok(()) => log::debug(&format!(
+                "Cannot use lock directory '{}': not writable",
+                dir.display()
+            )),
+            Err(e) => log::debug(&format!(
+                "Cannot use lock directory '{}': {e}",
+                dir.display()
+            )),
+        }
+    }
+    None
+}
+
+/// `flock` on `file`, waiting for it with `wait`. `Ok(false)` if another
+/// run holds it and `wait` is false.
+fn flock(file: &File, wait: bool) -> io::Result<bool> {
+    let operation = if wait {
+        libc::LOCK_EX
+    } else {
+        libc::LOCK_EX | libc::LOCK_NB
+    };
+    loop {
+        // SAFETY: the fd is open for as long as `file` is borrowed
+        if unsafe { libc::flock(file.as_raw_fd(), operation) } == 0 {
+            return Ok(true);
+        }
+        let error = io::Error::last_os_error();
+        match error.raw_os_error() {
+            Some(libc::EINTR) => {}
+            Some(libc::EWOULDBLOCK) => return Ok(false),
+            _ => return Err(error),
+        }
+    }
+}
+
+/// The run holding the lock in `file`, as written by it.
+fn holder(file: &mut File) -> String {
+    let mut contents = String::new();
+    let _ = file.read_to_string(&mut contents);
+    match contents.trim().parse::<u32>() {
+        Ok(pid) => format!("gracekill PID {pid}"),
+        Err(_) => "another gracekill run".to_string(),
+    }
+}
+
+/// Whether `file` is still the one at `path`, rather than one removed by
+/// the run that held it.
+fn still_at(file: &File, path: &Path) -> io::Result<bool> {
+    let (held, current) = match (file.metadata(), fs::symlink_metadata(path)) {
+        (Ok(held), Ok(current)) => (held, current),
+        (_, Err(e)) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+        (Err(e), _) | (_, Err(e)) => return Err(e),
+    };
+    Ok(held.dev() == current.dev() && held.ino() == current.ino())
+}
+
+/// Why a target couldn't be locked.
+enum LockError {
+    /// Another run holds the lock, as said by it, and `--no-wait-lock` is on
+    Held(String),
+    /// The lock file can't be opened, locked or written
+    Io(io::Error),
+}
+
+impl From<io::Error> for LockError {
+    fn from(e: io::Error) -> Self {
+        LockError::Io(e)
+    }
+}
+
+/// Locks `pid` with the file at `path`, waiting for another run holding it
+/// unless `no_wait`.
+fn lock(pid: u32, path: &Path, no_wait: bool) -> Result<File, LockError> {
+    let mut waited = false;
+    loop {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .mode(0o644)
+            .custom_flags(libc::O_NOFOLLOW)
+            .open(path)?;
+        if !flock(&file, false)? {
+            let holder = holder(&mut file);
+            if no_wait {
+                return Err(LockError::Held(holder));
+            }
+            log(&format!(
+                "PID {pid} is locked by {holder}, waiting for it to finish"
+            ));
+            flock(&file, true)?;
+            waited = true;
+        }
+        if still_at(&file, path)? {
+            if waited {
+                log(&format!("PID {pid} is no longer locked, going ahead"));
+            }
+            file.set_len(0)
+                .and_then(|()| file.rewind())
+                .and_then(|()| writeln!(file, "{}", std::process::id()))?;
+            return Ok(file);
+        }
+    }
+}
+
+/// Locks every one of `pids` it can, waiting for other runs holding any of
+/// them unless `no_wait`. The locks last until [`release`].
+pub fn acquire(pids: &[u32], no_wait: bool) -> Result<(), String> {
+    let Some(dir) = lock_dir() else {
+        log::debug("No lock directory, not locking the targets");
+        return Ok(());
+    };
+    // Always taken in the same order, so two runs can't each hold a lock
+    // the other is waiting for
+    let mut sorted = pids.to_vec();
+    sorted.sort_unstable();
+    sorted.dedup();
+    let mut locks = LOCKS
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner);
+    for pid in sorted {
+        let path = dir.join(format!("{pid}.lock"));
+        match lock(pid, &path, no_wait) {
+            Ok(file) => locks.push(Lock { path, _file: file }),
+            Err(LockError::Held(holder)) => {
+                return Err(format!(
+                    "PID {pid} is locked by {holder}, not waiting for it with --no-wait-lock"
+                ));
+            }
+            Err(LockError::Io(e)) => log::debug(&format!(
+                "Cannot lock PID {pid} at '{}': {e}, going ahead without locking it",
+                path.display()
+            )),
+        }
+    }
+    Ok(())
+}
+
+/// Removes the lock files and lets go of the locks, removing each file while
+/// still holding its lock so that no other run locks it in between.
+pub fn release() {
+    let locks = std::mem::take(
+        &mut *LOCKS
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner),
+    );
+    for lock in locks {
+        if let Err(e) = fs::remove_file(&lock.path) {
+            log::debug(&format!(
+                "Cannot remove lock file '{}': {e}",
+                lock.path.display()
+            ));
+        }
+    }
+}
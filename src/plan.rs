@@ -0,0 +1,160 @@
+//! `--plan`: the whole operation as JSON, for programmatic drivers.
+//!
+//! ```json
+//! {
+//!   "targets": [
+//!     {"pid": 1234, "signal": "TERM", "grace": "30s", "kill_signal": "KILL"},
+//!     {"pid": 1235, "grace": 30},
+//!     {"pid": 2001, "signal": "INT", "grace": "5s"}
+//!   ]
+//! }
+//! ```
+//!
+//! `pid` is required. `signal`, `grace` and `kill_signal` fall back to the
+//! command-line settings; `grace` is a duration string or a number of
+//! seconds. Targets sharing the same settings are gathered into one
+//! [`Operation`], and the operations run side by side, so a plan behaves
+//! like a single kill with per-target settings. Errors name the offending
+//! field by its path, e.g. `targets[2].grace`.
+
+use std::fmt::Write as _;
+use std::time::Duration;
+
+use gracekill::Signal;
+
+use crate::args::parse_and_validate_pid;
+use crate::batch::Operation;
+use crate::duration::{bounded, parse_duration};
+use crate::json::{self, Value};
+
+/// One entry of `targets`.
+struct Target {
+    pid: u32,
+    signal: Option<Signal>,
+    grace: Option<Duration>,
+    kill_signal: Option<Signal>,
+}
+
+/// Parses and validates a plan, returning its operations.
+///
+/// # Errors
+///
+/// Returns a message naming the line and column of a syntax error, or the
+/// path of a field that doesn't match the schema.
+pub fn parse_plan(contents: &str) -> Result<Vec<Operation>, String> {
+    let document = json::parse(contents).map_err(|e| e.to_string())?;
+    let Value::Object(members) = document else {
+        return Err(format!("expected an object, got {}", document.kind()));
+    };
+    let mut targets = None;
+    for (name, value) in members {
+        match name.as_str() {
+            "targets" => targets = Some(parse_targets(value)?),
+            _ => return Err(format!("{name}: unknown field")),
+        }
+    }
+    let targets = targets.ok_or("missing required field 'targets'")?;
+    if targets.is_empty() {
+        return Err("targets: no targets given".to_string());
+    }
+
+    let mut operations: Vec<Operation> = Vec::new();
+    for (index, target) in targets.into_iter().enumerate() {
+        let existing = operations.iter_mut().find(|operation| {
+            (operation.signal, operation.grace, operation.kill_signal)
+                == (target.signal, target.grace, target.kill_signal)
+        });
+        match existing {
+            Some(operation) => {
+                operation.pids.push(target.pid);
+                let _ = write!(operation.source, ", {index}");
+            }
+            None => operations.push(Operation {
+                source: format!("plan targets {index}"),
+                pids: vec![target.pid],
+                grace: target.grace,
+                signal: target.signal,
+                kill_signal: target.kill_signal,
+            }),
+        }
+    }
+    Ok(operations)
+}
+
+fn parse_targets(value: Value) -> Result<Vec<Target>, String> {
+    let Value::Array(elements) = value else {
+        return Err(format!("targets: expected an array, got {}", value.kind()));
+    };
+    let mut targets: Vec<Target> = Vec::with_capacity(elements.len());
+    for (index, element) in elements.into_iter().enumerate() {
+        let path = format!("targets[{index}]");
+        let target = parse_target(&path, element)?;
+        if let Some(first) = targets.iter().position(|other| other.pid == target.pid) {
+            return Err(format!(
+                "{path}.pid: PID {} is already given in targets[{first}]",
+                target.pid
+            ));
+        }
+        targets.push(target);
+    }
+    Ok(targets)
+}
+
+fn parse_target(path: &str, value: Value) -> Result<Target, String> {
+    let Value::Object(members) = value else {
+        return Err(format!("{path}: expected an object, got {}", value.kind()));
+    };
+    let mut pid = None;
+    let mut target = Target {
+        pid: 0,
+        signal: None,
+        grace: None,
+        kill_signal: None,
+    };
+    for (name, value) in members {
+        let field = format!("{path}.{name}");
+        let result = match name.as_str() {
+            "pid" => parse_pid(value).map(|value| pid = Some(value)),
+            "signal" => parse_signal(value).map(|value| target.signal = Some(value)),
+            "grace" => parse_grace(value).map(|value| target.grace = Some(value)),
+            "kill_signal" => parse_signal(value).map(|value| target.kill_signal = Some(value)),
+            _ => Err("unknown field".to_string()),
+        };
+        result.map_err(|e| format!("{field}: {e}"))?;
+    }
+    target.pid = pid.ok_or_else(|| format!("{path}: missing required field 'pid'"))?;
+    Ok(target)
+}
+
+fn parse_pid(value: Value) -> Result<u32, String> {
+    match value {
+        Value::Number(number) => parse_and_validate_pid(&number),
+        other => Err(format!("expected a PID, got {}", other.kind())),
+    }
+}
+
+fn parse_signal(value: Value) -> Result<Signal, String> {
+    match value {
+        Value::String(name) => name.parse(),
+        other => Err(format!(
+            "expected a signal name such as \"TERM\", got {}",
+            other.kind()
+        )),
+    }
+}
+
+fn parse_grace(value: Value) -> Result<Duration, String> {
+    match value {
+        Value::String(text) => parse_duration(&text),
+        Value::Number(number) => number
+            .parse::<f64>()
+            .ok()
+            .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+            .ok_or_else(|| format!("Invalid number of seconds: {number}"))
+            .and_then(|grace| bounded(grace, &number)),
+        other => Err(format!(
+            "expected a duration such as \"30s\" or a number of seconds, got {}",
+            other.kind()
+        )),
+    }
+}
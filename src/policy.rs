@@ -0,0 +1,148 @@
+//! `--policy`: a signal and grace period for each target according to its
+//! command name, for shutting down several kinds of process in one run:
+//!
+//! ```text
+//! gracekill --name web-1 --name batch-7 \
+//!     --policy 'web-*:TERM:30s' --policy 'batch-*:INT:120s'
+//! ```
+//!
+//! A pattern matches the whole command name, with `*` standing for any run
+//! of characters and `?` for any one. A target takes the first rule that
+//! matches it; targets no rule matches keep the command-line settings.
+
+use std::time::Duration;
+
+use gracekill::Signal;
+use gracekill::log::{self, log};
+
+use crate::batch::Operation;
+use crate::duration::parse_duration;
+
+/// One `--policy PATTERN:SIGNAL:GRACE` rule.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Policy {
+    pub pattern: String,
+    pub signal: Signal,
+    pub grace: Duration,
+}
+
+impl Policy {
+    /// Parses `PATTERN:SIGNAL:GRACE`; the pattern may itself contain `:`.
+    pub fn parse(value: &str) -> Result<Policy, String> {
+        let invalid = |reason: String| format!("Invalid --policy: '{value}' ({reason})");
+        let mut parts = value.rsplitn(3, ':');
+        let (Some(grace), Some(signal), Some(pattern)) = (parts.next(), parts.next(), parts.next())
+        else {
+            return Err(invalid("expected PATTERN:SIGNAL:GRACE".to_string()));
+        };
+        if pattern.is_empty() {
+            return Err(invalid("the pattern is empty".to_string()));
+        }
+        Ok(Policy {
+            pattern: pattern.to_string(),
+            signal: signal.parse().map_err(invalid)?,
+            grace: parse_duration(grace).map_err(invalid)?,
+        })
+    }
+
+    /// Whether the pattern matches all of `name`.
+    pub fn matches(&self, name: &str) -> bool {
+        glob_matches(&self.pattern, name)
+    }
+}
+
+/// Whether `pattern` matches all of `name`, with `*` standing for any run
+/// of characters and `?` for any one.
+pub fn glob_matches(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // Where the last `*` was, and how much of the name it has taken
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, n));
+            p += 1;
+        } else if let Some((star_p, star_n)) = star {
+            p = star_p + 1;
+            n = star_n + 1;
+            star = Some((star_p, star_n + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Groups `pids` by the first of `policies` their command name matches,
+/// logging the rule each one takes. Targets matching none, or whose name
+/// can't be read, make up a group of their own with the command-line
+/// settings. `None` if there are no policies.
+#[cfg(target_os = "linux")]
+pub fn group(policies: &[Policy], pids: &[u32]) -> Option<Vec<Operation>> {
+    if policies.is_empty() {
+        return None;
+    }
+    let mut groups: Vec<(Option<usize>, Vec<u32>)> = Vec::new();
+    for &pid in pids {
+        let rule = rule_for(policies, pid);
+        match groups.iter_mut().find(|(index, _)| *index == rule) {
+            Some((_, pids)) => pids.push(pid),
+            None => groups.push((rule, vec![pid])),
+        }
+    }
+    Some(
+        groups
+            .into_iter()
+            .map(|(rule, pids)| match rule {
+                Some(index) => Operation {
+                    source: format!("--policy '{}'", policies[index].pattern),
+                    pids,
+                    grace: Some(policies[index].grace),
+                    signal: Some(policies[index].signal),
+                    kill_signal: None,
+                },
+                None => Operation {
+                    source: "the command line".to_string(),
+                    pids,
+                    grace: None,
+                    signal: None,
+                    kill_signal: None,
+                },
+            })
+            .collect(),
+    )
+}
+
+/// The index of the first of `policies` matching `pid`, logging it.
+#[cfg(target_os = "linux")]
+fn rule_for(policies: &[Policy], pid: u32) -> Option<usize> {
+    let Some(name) = gracekill::procfs::comm(pid) else {
+        log::warn(&format!(
+            "Cannot read the command name of PID {pid}, keeping the command-line settings"
+        ));
+        return None;
+    };
+    let Some(index) = policies.iter().position(|policy| policy.matches(&name)) else {
+        log::debug(&format!(
+            "PID {pid} ({name}) matches no --policy, keeping the command-line settings"
+        ));
+        return None;
+    };
+    let policy = &policies[index];
+    log(&format!(
+        "PID {pid} ({name}) matches --policy '{}': {} with a {} grace period",
+        policy.pattern,
+        policy.signal,
+        log::duration(policy.grace)
+    ));
+    Some(index)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn group(_policies: &[Policy], _pids: &[u32]) -> Option<Vec<Operation>> {
+    None
+}
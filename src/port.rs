@@ -0,0 +1,98 @@
+//! `--port`: target every process with a TCP or UDP socket on a local
+//! port, e.g. whatever is listening on 8080, like `fuser -k 8080/tcp` but
+//! with a grace period. The sockets on the port are looked up by inode in
+//! `/proc/net/{tcp,tcp6,udp,udp6}`, of the network namespace given with
+//! `--netns` if any, and their holders among the open descriptors in
+//! `/proc/<pid>/fd`.
+
+#[cfg(target_os = "linux")]
+use std::collections::HashSet;
+#[cfg(target_os = "linux")]
+use std::path::PathBuf;
+#[cfg(target_os = "linux")]
+use std::{fs, io, process};
+
+/// The socket tables read, as under `/proc/net`.
+#[cfg(target_os = "linux")]
+const TABLES: [&str; 4] = ["tcp", "tcp6", "udp", "udp6"];
+
+/// Parses a `--port` value.
+pub fn parse(value: &str) -> Result<u16, String> {
+    value
+        .parse::<u16>()
+        .ok()
+        .filter(|&port| port > 0)
+        .ok_or_else(|| format!("Invalid --port: '{value}' (expected a port from 1 to 65535)"))
+}
+
+/// The inode of the socket on a line of a socket table, if its local
+/// address, such as `0100007F:1F90`, is on `port`. Sockets with no inode,
+/// such as TCP connections in `TIME_WAIT`, belong to no process.
+#[cfg(target_os = "linux")]
+fn inode_on(line: &str, port: u16) -> Option<u64> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    let (_, local_port) = fields.get(1)?.rsplit_once(':')?;
+    if u16::from_str_radix(local_port, 16).ok()? != port {
+        return None;
+    }
+    fields.get(9)?.parse().ok().filter(|&inode| inode != 0)
+}
+
+/// The inodes of the sockets on `port` in the calling thread's network
+/// namespace.
+#[cfg(target_os = "linux")]
+fn socket_inodes(port: u16) -> HashSet<u64> {
+    TABLES
+        .iter()
+        .filter_map(|table| fs::read_to_string(format!("/proc/thread-self/net/{table}")).ok())
+        .flat_map(|contents| {
+            contents
+                .lines()
+                .skip(1)
+                .filter_map(|line| inode_on(line, port))
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/// Every process other than gracekill itself with a socket on `port`, in
+/// the network namespace `netns` if given, and how many processes were
+/// skipped for not having their open files readable, such as other users'
+/// without root.
+#[cfg(target_os = "linux")]
+pub fn processes_on(port: u16, netns: Option<&str>) -> Result<(Vec<u32>, usize), String> {
+    let inodes = match netns {
+        Some(target) => crate::netns::within(target, || socket_inodes(port))?,
+        None => socket_inodes(port),
+    };
+    if inodes.is_empty() {
+        return Ok((Vec::new(), 0));
+    }
+    let sockets: HashSet<PathBuf> = inodes
+        .iter()
+        .map(|inode| PathBuf::from(format!("socket:[{inode}]")))
+        .collect();
+    let own = process::id();
+    let mut holders = Vec::new();
+    let mut unreadable = 0;
+    for pid in gracekill::procfs::pids()
+        .map_err(|e| format!("Failed to look for processes on port {port}: {e}"))?
+    {
+        match gracekill::procfs::open_files(pid) {
+            Ok(files) => {
+                if pid != own && files.iter().any(|file| sockets.contains(file)) {
+                    holders.push(pid);
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::PermissionDenied => unreadable += 1,
+            // Exited while /proc was being read
+            Err(_) => {}
+        }
+    }
+    Ok((holders, unreadable))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn processes_on(_port: u16, _netns: Option<&str>) -> Result<(Vec<u32>, usize), String> {
+    Err("Socket targets (--port) are only supported on Linux".to_string())
+}
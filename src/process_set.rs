@@ -0,0 +1,166 @@
+//! `--save-set` and `--kill-new-since`: checkpoint the targets, and on a
+//! later run only kill the ones that have appeared since, for rolling
+//! operations:
+//!
+//! ```text
+//! gracekill --name worker --save-set /run/workers.set
+//! # ... later
+//! gracekill --name worker --kill-new-since /run/workers.set
+//! ```
+//!
+//! The file is plain text: a header naming the boot it was written in,
+//! then one target per line, its PID and its start time in clock ticks
+//! since boot (field 22 of `/proc/<pid>/stat`):
+//!
+//! ```text
+//! # gracekill process set
+//! boot_id 4a1f0e5c-8d2b-4f7e-9c3a-2b6d1e0f9a87
+//! 1234 81234
+//! 1240 81290
+//! ```
+//!
+//! A target is in the checkpoint only if both match, so a PID reused by
+//! a later process counts as new. A checkpoint from an earlier boot,
+//! whose start times mean nothing now, holds none of the targets.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+
+use gracekill::log::{self, log};
+
+const HEADER: &str = "# gracekill process set";
+
+/// A process, told apart from any later one given the same PID.
+type Key = (u32, u64);
+
+/// The key of `pid`, or `None`, with a warning, if it can't be read.
+#[cfg(target_os = "linux")]
+fn key(pid: u32) -> Option<Key> {
+    match gracekill::procfs::stat(pid) {
+        Ok(stat) => Some((pid, stat.starttime)),
+        Err(e) => {
+            log::warn(&format!("Cannot read the start time of PID {pid}: {e}"));
+            None
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn key(_pid: u32) -> Option<Key> {
+    None
+}
+
+#[cfg(target_os = "linux")]
+fn boot_id() -> io::Result<String> {
+    gracekill::procfs::boot_id()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn boot_id() -> io::Result<String> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "not on Linux"))
+}
+
+/// A saved checkpoint.
+struct Checkpoint {
+    boot_id: String,
+    keys: Vec<Key>,
+}
+
+fn parse(contents: &str) -> Result<Checkpoint, String> {
+    let mut lines = contents.lines().enumerate();
+    if lines.next().map(|(_, line)| line) != Some(HEADER) {
+        return Err(format!("expected '{HEADER}' on line 1"));
+    }
+    let boot_id = match lines.next() {
+        Some((_, line)) => line
+            .strip_prefix("boot_id ")
+            .ok_or_else(|| "expected 'boot_id <ID>' on line 2".to_string())?,
+        None => return Err("expected 'boot_id <ID>' on line 2".to_string()),
+    };
+    let mut keys = Vec::new();
+    for (index, line) in lines {
+        let invalid = || {
+            format!(
+                "line {}: expected 'PID START_TIME', got '{line}'",
+                index + 1
+            )
+        };
+        let (pid, start) = line.split_once(' ').ok_or_else(invalid)?;
+        keys.push((
+            pid.parse().map_err(|_| invalid())?,
+            start.parse().map_err(|_| invalid())?,
+        ));
+    }
+    Ok(Checkpoint {
+        boot_id: boot_id.to_string(),
+        keys,
+    })
+}
+
+/// Records `pids` in the checkpoint at `path`, replacing what it held.
+/// Targets whose start time can't be read are left out.
+pub fn save(path: &str, pids: &[u32]) -> Result<(), String> {
+    let boot_id = boot_id().map_err(|e| format!("Cannot read the boot ID: {e}"))?;
+    let keys: Vec<Key> = pids.iter().filter_map(|&pid| key(pid)).collect();
+    let mut contents = format!("{HEADER}\nboot_id {boot_id}\n");
+    for (pid, start) in &keys {
+        let _ = writeln!(contents, "{pid} {start}");
+    }
+    // Written beside it and renamed over it, so that a run reading it
+    // never sees half a checkpoint
+    let temporary = format!("{path}.tmp");
+    fs::write(&temporary, contents)
+        .and_then(|()| fs::rename(&temporary, path))
+        .map_err(|e| format!("Cannot write process set '{path}': {e}"))?;
+    log(&format!(
+        "Saved {} target(s) to process set '{path}'",
+        keys.len()
+    ));
+    Ok(())
+}
+
+/// Keeps only the `pids` that aren't in the checkpoint at `path`, logging
+/// how many are new and which of the checkpoint are gone.
+pub fn keep_new(path: &str, pids: &mut Vec<u32>) -> Result<(), String> {
+    let contents =
+        fs::read_to_string(path).map_err(|e| format!("Cannot read process set '{path}': {e}"))?;
+    let checkpoint = parse(&contents).map_err(|e| format!("Invalid process set '{path}': {e}"))?;
+    let boot_id = boot_id().map_err(|e| format!("Cannot read the boot ID: {e}"))?;
+    if checkpoint.boot_id != boot_id {
+        log::warn(&format!(
+            "Process set '{path}' is from an earlier boot, taking every target as new"
+        ));
+        return Ok(());
+    }
+    let before = pids.len();
+    let mut current = Vec::new();
+    pids.retain(|&pid| {
+        let Some(key) = key(pid) else {
+            return false;
+        };
+        current.push(key);
+        if checkpoint.keys.contains(&key) {
+            log::debug(&format!("Skipping PID {pid}: in process set '{path}'"));
+            return false;
+        }
+        true
+    });
+    let gone: Vec<&Key> = checkpoint
+        .keys
+        .iter()
+        .filter(|key| !current.contains(key))
+        .collect();
+    for (pid, start) in &gone {
+        log::debug(&format!(
+            "PID {pid} (started at tick {start}) in process set '{path}' is gone from the targets"
+        ));
+    }
+    log(&format!(
+        "{} of {before} target(s) are new since process set '{path}', {} of its {} gone",
+        pids.len(),
+        gone.len(),
+        checkpoint.keys.len()
+    ));
+    Ok(())
+}
@@ -0,0 +1,616 @@
+//! Readers for Linux's `/proc` filesystem.
+//!
+//! On a machine with a wedged filesystem a `/proc` read can block
+//! indefinitely. [`set_timeout`] bounds every read made through this module:
+//! a read that doesn't finish in time fails with [`io::ErrorKind::TimedOut`]
+//! and callers fall back to the plain `kill(pid, 0)` liveness check. The
+//! stuck read itself can't be cancelled and is left to finish on a detached
+//! thread.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use crate::{Signal, Survival, log};
+
+/// Read timeout in milliseconds; zero means reads are unbounded.
+static TIMEOUT_MS: AtomicU64 = AtomicU64::new(0);
+
+/// Bounds every subsequent `/proc` read to `timeout`, or removes the bound
+/// with `None`.
+pub fn set_timeout(timeout: Option<Duration>) {
+    let millis = timeout.map_or(0, |t| {
+        u64::try_from(t.as_millis()).unwrap_or(u64::MAX).max(1)
+    });
+    TIMEOUT_MS.store(millis, Ordering::Relaxed);
+}
+
+fn pid_dir(pid: u32) -> PathBuf {
+    PathBuf::from(format!("/proc/{pid}"))
+}
+
+/// Runs `op` on `path`, giving up after the configured timeout.
+fn bounded<T: Send + 'static>(path: &Path, op: fn(&Path) -> io::Result<T>) -> io::Result<T> {
+    let millis = TIMEOUT_MS.load(Ordering::Relaxed);
+    if millis == 0 {
+        return op(path);
+    }
+
+    let (sender, receiver) = mpsc::channel();
+    let reader_path = path.to_path_buf();
+    thread::spawn(move || {
+        let _ = sender.send(op(&reader_path));
+    });
+    receiver
+        .recv_timeout(Duration::from_millis(millis))
+        .unwrap_or_else(|_| {
+            log::warn(&format!(
+                "Reading {} did not complete within {millis}ms, falling back to kill(pid, 0)",
+                path.display()
+            ));
+            Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "/proc read timed out",
+            ))
+        })
+}
+
+/// Returns whether `tid` is one of the threads of process `pid`.
+///
+/// # Errors
+///
+/// Returns the underlying error if `/proc/<pid>/task` can't be inspected.
+pub fn has_thread(pid: u32, tid: u32) -> io::Result<bool> {
+    let path = pid_dir(pid).join("task").join(tid.to_string());
+    match bounded(&path, |path| fs::metadata(path).map(|_| ())) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// The command name of `pid` from `/proc/<pid>/comm`, or `None` if it can't
+/// be read.
+#[must_use]
+pub fn comm(pid: u32) -> Option<String> {
+    let comm = bounded(&pid_dir(pid).join("comm"), |path| fs::read_to_string(path)).ok()?;
+    Some(comm.trim_end_matches('\n').to_string())
+}
+
+/// The effective user ID of `pid`, the owner of `/proc/<pid>`.
+///
+/// # Errors
+///
+/// Returns the underlying error if `/proc/<pid>` can't be inspected.
+pub fn uid(pid: u32) -> io::Result<u32> {
+    let metadata = bounded(&pid_dir(pid), |path| fs::metadata(path))?;
+    Ok(std::os::unix::fs::MetadataExt::uid(&metadata))
+}
+
+/// How many file descriptors `pid` has open, from `/proc/<pid>/fd`.
+///
+/// # Errors
+///
+/// Returns the underlying error if the directory can't be listed, e.g. for
+/// lack of permission.
+pub fn open_fds(pid: u32) -> io::Result<usize> {
+    bounded(&pid_dir(pid).join("fd"), |path| {
+        Ok(fs::read_dir(path)?.count())
+    })
+}
+
+/// The files `pid` has open, as the targets of its `/proc/<pid>/fd` links,
+/// followed by the files it has mapped into memory, from `/proc/<pid>/maps`.
+/// Pipes, sockets and the like appear as e.g. `pipe:[1234]`, and deleted
+/// files with ` (deleted)` after their path. A file may be listed more than
+/// once.
+///
+/// # Errors
+///
+/// Returns the underlying error if `/proc/<pid>/fd` can't be listed, e.g.
+/// for lack of permission. Maps that can't be read are left out.
+pub fn open_files(pid: u32) -> io::Result<Vec<PathBuf>> {
+    let mut files = bounded(&pid_dir(pid).join("fd"), |path| {
+        Ok(fs::read_dir(path)?
+            // A descriptor closed while the directory is read is gone
+            .filter_map(|entry| fs::read_link(entry.ok()?.path()).ok())
+            .collect::<Vec<_>>())
+    })?;
+    if let Ok(maps) = bounded(&pid_dir(pid).join("maps"), |path| fs::read_to_string(path)) {
+        files.extend(maps.lines().filter_map(mapped_file));
+    }
+    Ok(files)
+}
+
+/// The file mapped by a line of `/proc/<pid>/maps`, if it maps one: the
+/// sixth field onwards, after the address, permissions, offset, device and
+/// inode.
+fn mapped_file(line: &str) -> Option<PathBuf> {
+    let mut rest = line;
+    for _ in 0..5 {
+        rest = rest.trim_start().split_once(' ')?.1;
+    }
+    let path = rest.trim_start();
+    path.starts_with('/').then(|| PathBuf::from(path))
+}
+
+/// The clock ticks per second that the CPU and start times in
+/// `/proc/<pid>/stat` are counted in.
+#[must_use]
+pub fn ticks_per_second() -> u64 {
+    // SAFETY: sysconf has no preconditions.
+    u64::try_from(unsafe { libc::sysconf(libc::_SC_CLK_TCK) })
+        .ok()
+        .filter(|&ticks| ticks > 0)
+        .unwrap_or(100)
+}
+
+/// How long the system has been up, from `/proc/uptime`.
+///
+/// # Errors
+///
+/// Returns the underlying error if the file can't be read or parsed.
+pub fn uptime() -> io::Result<Duration> {
+    let contents = bounded(Path::new("/proc/uptime"), |path| fs::read_to_string(path))?;
+    contents
+        .split_whitespace()
+        .next()
+        .and_then(|seconds| seconds.parse::<f64>().ok())
+        .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "malformed uptime"))
+}
+
+/// The random ID the kernel picks at each boot, from
+/// `/proc/sys/kernel/random/boot_id`.
+///
+/// # Errors
+///
+/// Returns the underlying error if the file can't be read.
+pub fn boot_id() -> io::Result<String> {
+    let contents = bounded(Path::new("/proc/sys/kernel/random/boot_id"), |path| {
+        fs::read_to_string(path)
+    })?;
+    Ok(contents.trim().to_string())
+}
+
+/// The largest PID the kernel hands out plus one, from
+/// `/proc/sys/kernel/pid_max`.
+///
+/// # Errors
+///
+/// Returns the underlying error if the file can't be read or parsed.
+pub fn pid_max() -> io::Result<u32> {
+    let contents = bounded(Path::new("/proc/sys/kernel/pid_max"), |path| {
+        fs::read_to_string(path)
+    })?;
+    contents
+        .trim()
+        .parse()
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "malformed pid_max"))
+}
+
+/// The raw command line of `pid` from `/proc/<pid>/cmdline`, arguments
+/// separated by NUL bytes, or `None` if it can't be read or is empty (as for
+/// kernel threads and zombies).
+#[must_use]
+pub fn cmdline(pid: u32) -> Option<Vec<u8>> {
+    let cmdline = bounded(&pid_dir(pid).join("cmdline"), |path| fs::read(path)).ok()?;
+    (!cmdline.is_empty()).then_some(cmdline)
+}
+
+/// The value of the environment variable `name` in `/proc/<pid>/environ`:
+/// the environment the process started with, as it may since have changed
+/// its own.
+///
+/// # Errors
+///
+/// Returns the underlying error if the file can't be read, as for another
+/// user's process.
+pub fn environ_var(pid: u32, name: &str) -> io::Result<Option<String>> {
+    let environ = bounded(&pid_dir(pid).join("environ"), |path| fs::read(path))?;
+    Ok(environ.split(|&byte| byte == 0).find_map(|entry| {
+        let value = entry.strip_prefix(name.as_bytes())?.strip_prefix(b"=")?;
+        Some(String::from_utf8_lossy(value).into_owned())
+    }))
+}
+
+/// Selected fields of `/proc/<pid>/stat`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Stat {
+    pub pid: u32,
+    pub comm: String,
+    /// One-letter state, e.g. `R`, `S`, `D`, `Z` or `T`.
+    pub state: char,
+    pub ppid: u32,
+    pub pgrp: u32,
+    pub session: u32,
+    /// Kernel `PF_*` process flags.
+    pub flags: u64,
+    /// User-mode CPU time in clock ticks.
+    pub utime: u64,
+    /// Kernel-mode CPU time in clock ticks.
+    pub stime: u64,
+    pub num_threads: u64,
+    /// Start time in clock ticks after boot.
+    pub starttime: u64,
+}
+
+impl Stat {
+    /// Parses the contents of a `stat` file.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`io::ErrorKind::InvalidData`] if the contents are malformed.
+    pub fn parse(contents: &str) -> io::Result<Stat> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "malformed stat line");
+        // comm is parenthesized and may itself contain spaces and parentheses
+        let open = contents.find('(').ok_or_else(invalid)?;
+        let close = contents.rfind(')').ok_or_else(invalid)?;
+        let pid = contents[..open].trim().parse().map_err(|_| invalid())?;
+        let comm = contents
+            .get(open + 1..close)
+            .ok_or_else(invalid)?
+            .to_string();
+        // Fields after comm, starting with field 3 (state)
+        let fields: Vec<&str> = contents[close + 1..].split_whitespace().collect();
+        let field = |n: usize| fields.get(n - 3).copied().ok_or_else(invalid);
+        let number = |n: usize| field(n)?.parse::<u64>().map_err(|_| invalid());
+        let id = |n: usize| field(n)?.parse::<u32>().map_err(|_| invalid());
+
+        Ok(Stat {
+            pid,
+            comm,
+            state: field(3)?.chars().next().ok_or_else(invalid)?,
+            ppid: id(4)?,
+            pgrp: id(5)?,
+            session: id(6)?,
+            flags: number(9)?,
+            utime: number(14)?,
+            stime: number(15)?,
+            num_threads: number(20)?,
+            starttime: number(22)?,
+        })
+    }
+}
+
+/// The `PF_EXITING` flag, set once a process has started to exit.
+const PF_EXITING: u64 = 0x4;
+
+/// The `PF_DUMPCORE` flag, set once a process has started to dump core.
+const PF_DUMPCORE: u64 = 0x200;
+
+impl Stat {
+    /// Why the process is already on its way out, if it is: it has started
+    /// exiting, or it is a zombie or dead.
+    #[must_use]
+    pub fn exiting(&self) -> Option<&'static str> {
+        match self.state {
+            'Z' => Some("a zombie"),
+            'X' | 'x' => Some("dead"),
+            _ if self.flags & PF_EXITING != 0 => Some("exiting"),
+            _ => None,
+        }
+    }
+
+    /// Whether the process is writing a core dump, during which it stays
+    /// around however it was killed.
+    #[must_use]
+    pub fn dumping_core(&self) -> bool {
+        self.flags & PF_DUMPCORE != 0 && !matches!(self.state, 'Z' | 'X' | 'x')
+    }
+}
+
+/// Reads and parses `/proc/<pid>/stat`.
+///
+/// # Errors
+///
+/// Returns the underlying error if the file can't be read or parsed.
+pub fn stat(pid: u32) -> io::Result<Stat> {
+    let contents = bounded(&pid_dir(pid).join("stat"), |path| fs::read_to_string(path))?;
+    Stat::parse(&contents)
+}
+
+/// The stat of each thread of `pid`, from `/proc/<pid>/task/<tid>/stat`,
+/// in TID order. Threads that exit while they are read are left out.
+///
+/// # Errors
+///
+/// Returns the underlying error if `/proc/<pid>/task` can't be listed.
+pub fn threads(pid: u32) -> io::Result<Vec<Stat>> {
+    let task = pid_dir(pid).join("task");
+    let entries = bounded(&task, |path| {
+        fs::read_dir(path)?
+            .map(|entry| entry.map(|entry| entry.file_name()))
+            .collect::<io::Result<Vec<_>>>()
+    })?;
+    let mut tids: Vec<u32> = entries
+        .iter()
+        .filter_map(|name| name.to_str()?.parse().ok())
+        .collect();
+    tids.sort_unstable();
+    Ok(tids
+        .into_iter()
+        .filter_map(|tid| {
+            let contents = bounded(&task.join(tid.to_string()).join("stat"), |path| {
+                fs::read_to_string(path)
+            })
+            .ok()?;
+            Stat::parse(&contents).ok()
+        })
+        .collect())
+}
+
+/// The kernel function thread `tid` of `pid` is blocked in, from
+/// `/proc/<pid>/task/<tid>/wchan`, or `None` if it isn't blocked or that
+/// can't be read.
+#[must_use]
+pub fn wchan(pid: u32, tid: u32) -> Option<String> {
+    let path = pid_dir(pid)
+        .join("task")
+        .join(tid.to_string())
+        .join("wchan");
+    let wchan = bounded(&path, |path| fs::read_to_string(path)).ok()?;
+    let wchan = wchan.trim();
+    (!wchan.is_empty() && wchan != "0").then(|| wchan.to_string())
+}
+
+/// The resident set size of `pid` in bytes, from `/proc/<pid>/statm`.
+///
+/// # Errors
+///
+/// Returns the underlying error if the file can't be read or parsed.
+pub fn rss(pid: u32) -> io::Result<u64> {
+    let contents = bounded(&pid_dir(pid).join("statm"), |path| fs::read_to_string(path))?;
+    let pages: u64 = contents
+        .split_whitespace()
+        .nth(1)
+        .and_then(|pages| pages.parse().ok())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "malformed statm line"))?;
+    // SAFETY: sysconf has no preconditions.
+    let page_size = u64::try_from(unsafe { libc::sysconf(libc::_SC_PAGESIZE) })
+        .ok()
+        .filter(|&size| size > 0)
+        .unwrap_or(4096);
+    Ok(pages * page_size)
+}
+
+/// The signal masks of a process from `/proc/<pid>/status`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct SignalMasks {
+    /// Signals blocked by every thread (`SigBlk`).
+    pub blocked: u64,
+    /// Signals whose disposition is `SIG_IGN` (`SigIgn`).
+    pub ignored: u64,
+    /// Signals with a handler installed (`SigCgt`).
+    pub caught: u64,
+}
+
+impl SignalMasks {
+    /// Parses the mask lines out of the contents of a `status` file.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`io::ErrorKind::InvalidData`] if a mask is malformed.
+    pub fn parse(contents: &str) -> io::Result<SignalMasks> {
+        let mut masks = SignalMasks::default();
+        for line in contents.lines() {
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let mask = match key {
+                "SigBlk" => &mut masks.blocked,
+                "SigIgn" => &mut masks.ignored,
+                "SigCgt" => &mut masks.caught,
+                _ => continue,
+            };
+            *mask = u64::from_str_radix(value.trim(), 16)
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "malformed signal mask"))?;
+        }
+        Ok(masks)
+    }
+
+    /// Whether `signal`'s bit is set in `mask`.
+    #[must_use]
+    pub fn contains(mask: u64, signal: Signal) -> bool {
+        let bit = signal.to_nix() as u32 - 1;
+        mask & (1 << bit) != 0
+    }
+}
+
+/// Reads the signal masks of `pid` from `/proc/<pid>/status`.
+///
+/// # Errors
+///
+/// Returns the underlying error if the file can't be read or parsed.
+pub fn signal_masks(pid: u32) -> io::Result<SignalMasks> {
+    let contents = bounded(&pid_dir(pid).join("status"), |path| {
+        fs::read_to_string(path)
+    })?;
+    SignalMasks::parse(&contents)
+}
+
+/// Why a process with `stat` and `masks` is still running after being sent
+/// `signal`. Its state comes first: a process in uninterruptible sleep or
+/// stopped can't act on a signal whatever its masks say.
+#[must_use]
+pub fn classify_survivor(stat: &Stat, masks: &SignalMasks, signal: Signal) -> Survival {
+    match stat.state {
+        'D' => Survival::Uninterruptible,
+        'T' | 't' => Survival::Stopped,
+        _ if SignalMasks::contains(masks.ignored, signal) => Survival::IgnoredSignal,
+        _ if SignalMasks::contains(masks.blocked, signal) => Survival::BlockedSignal,
+        _ => Survival::SlowShutdown,
+    }
+}
+
+/// The ancestors of `pid` from its parent up to the root of the process
+/// tree, following the PPID in each `stat` file. The walk stops early at a
+/// process whose `stat` can't be read.
+#[must_use]
+pub fn ancestors(pid: u32) -> Vec<u32> {
+    let mut ancestors = Vec::new();
+    let mut current = pid;
+    while let Ok(stat) = stat(current) {
+        // PPID 0 marks the root; the same PID twice would mean a loop
+        if stat.ppid == 0 || stat.ppid == pid || ancestors.contains(&stat.ppid) {
+            break;
+        }
+        ancestors.push(stat.ppid);
+        current = stat.ppid;
+    }
+    ancestors
+}
+
+/// Every process descended from `pid`, children first, found by following
+/// the PPID in each `stat` file. Processes whose `stat` can't be read are
+/// left out.
+#[must_use]
+pub fn descendants(pid: u32) -> Vec<u32> {
+    let parents: Vec<(u32, u32)> = pids()
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|pid| stat(pid).ok())
+        .map(|stat| (stat.pid, stat.ppid))
+        .collect();
+    let mut family = vec![pid];
+    let mut next = 0;
+    while let Some(&parent) = family.get(next) {
+        for &(child, ppid) in &parents {
+            if ppid == parent && !family.contains(&child) {
+                family.push(child);
+            }
+        }
+        next += 1;
+    }
+    family.remove(0);
+    family
+}
+
+/// The PIDs of `pid` in each PID namespace it belongs to, from the one
+/// `/proc` was mounted in to its own innermost one, from the `NSpid` line of
+/// `/proc/<pid>/status`.
+///
+/// # Errors
+///
+/// Returns the underlying error if the file can't be read, or
+/// [`io::ErrorKind::Unsupported`] if the kernel doesn't report `NSpid`.
+pub fn namespace_pids(pid: u32) -> io::Result<Vec<u32>> {
+    let contents = bounded(&pid_dir(pid).join("status"), |path| {
+        fs::read_to_string(path)
+    })?;
+    let line = contents
+        .lines()
+        .find_map(|line| line.strip_prefix("NSpid:"))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::Unsupported, "no NSpid in status"))?;
+    line.split_whitespace()
+        .map(|id| {
+            id.parse()
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "malformed NSpid"))
+        })
+        .collect()
+}
+
+/// Identifies the PID namespace of `pid`, e.g. `pid:[4026531836]`.
+///
+/// # Errors
+///
+/// Returns the underlying error if `/proc/<pid>/ns/pid` can't be read.
+pub fn pid_namespace(pid: u32) -> io::Result<PathBuf> {
+    bounded(&pid_dir(pid).join("ns").join("pid"), |path| {
+        fs::read_link(path)
+    })
+}
+
+/// Maps the PIDs of every process in the same PID namespace as `reference`,
+/// as seen inside that namespace, to their PIDs here.
+///
+/// # Errors
+///
+/// Returns the underlying error if `/proc` can't be listed or the namespace
+/// of `reference` can't be read.
+pub fn namespace_pid_map(reference: u32) -> io::Result<HashMap<u32, u32>> {
+    let namespace = pid_namespace(reference)?;
+    Ok(pids()?
+        .into_iter()
+        .filter(|&pid| pid_namespace(pid).is_ok_and(|ns| ns == namespace))
+        .filter_map(|pid| Some((*namespace_pids(pid).ok()?.last()?, pid)))
+        .collect())
+}
+
+/// Every PID currently listed in `/proc`.
+///
+/// # Errors
+///
+/// Returns the underlying error if `/proc` can't be listed.
+pub fn pids() -> io::Result<Vec<u32>> {
+    let entries = bounded(Path::new("/proc"), |path| {
+        fs::read_dir(path)?
+            .map(|entry| entry.map(|entry| entry.file_name()))
+            .collect::<io::Result<Vec<_>>>()
+    })?;
+    Ok(entries
+        .iter()
+        .filter_map(|name| name.to_str()?.parse().ok())
+        .collect())
+}
+
+/// Every process whose process group is `pgid`.
+///
+/// # Errors
+///
+/// Returns the underlying error if `/proc` can't be listed.
+pub fn processes_in_group(pgid: u32) -> io::Result<Vec<u32>> {
+    Ok(pids()?
+        .into_iter()
+        .filter(|&pid| stat(pid).is_ok_and(|stat| stat.pgrp == pgid))
+        .collect())
+}
+
+/// Every process whose session is `sid`.
+///
+/// # Errors
+///
+/// Returns the underlying error if `/proc` can't be listed.
+pub fn processes_in_session(sid: u32) -> io::Result<Vec<u32>> {
+    Ok(pids()?
+        .into_iter()
+        .filter(|&pid| stat(pid).is_ok_and(|stat| stat.session == sid))
+        .collect())
+}
+
+/// Every process whose command name is `name` and that isn't already on its
+/// way out.
+///
+/// # Errors
+///
+/// Returns the underlying error if `/proc` can't be listed.
+pub fn processes_named(name: &str) -> io::Result<Vec<u32>> {
+    Ok(pids()?
+        .into_iter()
+        .filter(|&pid| stat(pid).is_ok_and(|stat| stat.comm == name && stat.exiting().is_none()))
+        .collect())
+}
+
+/// Finds a process that looks like the daemon left behind by a double fork:
+/// one reparented to init (PPID 1) running exactly `cmdline`, started no
+/// earlier than `since` (a `starttime` in clock ticks) and not in `exclude`.
+/// If several match, the most recently started one wins.
+///
+/// # Errors
+///
+/// Returns the underlying error if `/proc` can't be listed.
+pub fn reparented_daemon(cmdline: &[u8], since: u64, exclude: &[u32]) -> io::Result<Option<u32>> {
+    Ok(pids()?
+        .into_iter()
+        .filter(|pid| !exclude.contains(pid))
+        .filter_map(|pid| stat(pid).ok())
+        .filter(|stat| stat.ppid == 1 && stat.starttime >= since)
+        .filter(|stat| self::cmdline(stat.pid).as_deref() == Some(cmdline))
+        .max_by_key(|stat| stat.starttime)
+        .map(|stat| stat.pid))
+}
@@ -0,0 +1,46 @@
+//! `--protect-file`: the targets that must never be sent SIGKILL, kept in
+//! a file rather than on every command line, e.g. as an organisation-wide
+//! default:
+//!
+//! ```text
+//! # Never force-kill the database or anything of the monitoring stack
+//! postgres
+//! monitor-*
+//! 4120
+//! ```
+//!
+//! Each line is a PID or a command name pattern, as in `--policy`: `*`
+//! stands for any run of characters and `?` for any one, matched against
+//! the whole of `/proc/<pid>/comm` (Linux only). Blank lines and lines
+//! starting with `#` are skipped. The file is read once at startup; changes
+//! made to it during the run are not picked up.
+
+use std::fs;
+
+use gracekill::log;
+
+use crate::args::Options;
+
+/// Reads the entries of the protect file at `path` into `options`: PIDs
+/// join those of `--forbid-signal-to`, and names the patterns.
+pub fn load(path: &str, options: &mut Options) -> Result<(), String> {
+    let contents =
+        fs::read_to_string(path).map_err(|e| format!("Cannot read protect file '{path}': {e}"))?;
+    let mut entries = 0;
+    for line in contents.lines() {
+        let entry = line.trim();
+        if entry.is_empty() || entry.starts_with('#') {
+            continue;
+        }
+        if entry.parse::<u32>().is_ok() {
+            options.forbid_kill.push(entry.to_string());
+        } else {
+            options.protect_patterns.push(entry.to_string());
+        }
+        entries += 1;
+    }
+    log::debug(&format!(
+        "Read {entries} protected PID(s) and name pattern(s) from '{path}'"
+    ));
+    Ok(())
+}
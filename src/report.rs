@@ -0,0 +1,323 @@
+use std::fmt::{self, Write};
+use std::time::Duration;
+
+use nix::sys::signal::Signal as NixSignal;
+
+use crate::{Signal, SignalError};
+
+/// How a reaped child process terminated.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ExitStatus {
+    /// It exited normally with this code.
+    Code(i32),
+    /// It was terminated by this signal number.
+    Signaled(i32),
+}
+
+impl fmt::Display for ExitStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            ExitStatus::Code(code) => write!(f, "exited with code {code}"),
+            ExitStatus::Signaled(signal) => match NixSignal::try_from(signal) {
+                Ok(signal) => write!(f, "killed by signal {}", signal.as_str()),
+                Err(_) => write!(f, "killed by signal {signal}"),
+            },
+        }
+    }
+}
+
+/// What happened to a single target process.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// The graceful signal could not be delivered.
+    SignalFailed(SignalError),
+    /// The process exited before the kill signal was needed.
+    Graceful,
+    /// The process outlived the grace period and was sent the kill signal.
+    Killed,
+    /// The process outlived the grace period but the kill signal failed.
+    KillFailed(SignalError),
+    /// The process outlived the grace period and no kill signal was sent.
+    Survived,
+}
+
+/// Why a target was still running when its grace period ran out, read
+/// from `/proc` just before it was escalated.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Survival {
+    /// The signal's disposition is `SIG_IGN`, so it was discarded.
+    IgnoredSignal,
+    /// The signal is blocked, so it is still pending.
+    BlockedSignal,
+    /// The process is in uninterruptible sleep (state `D`), usually waiting
+    /// on I/O, and won't act on any signal until it wakes up.
+    Uninterruptible,
+    /// The process is stopped or being traced (state `T` or `t`), so the
+    /// signal waits for it to be continued.
+    Stopped,
+    /// None of the above: the signal was delivered, and the process is
+    /// taking longer than the grace period to act on it.
+    SlowShutdown,
+}
+
+impl Survival {
+    /// The name used for it in structured output, e.g. `ignored_signal`.
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Survival::IgnoredSignal => "ignored_signal",
+            Survival::BlockedSignal => "blocked_signal",
+            Survival::Uninterruptible => "uninterruptible",
+            Survival::Stopped => "stopped",
+            Survival::SlowShutdown => "slow_shutdown",
+        }
+    }
+}
+
+impl fmt::Display for Survival {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// What a target looked like just before it was first signalled, taken with
+/// [`GraceKill::snapshot`](crate::GraceKill::snapshot).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Snapshot {
+    pub pid: u32,
+    /// The command line, arguments separated by spaces, or the command name
+    /// in brackets for a process without one, such as a zombie.
+    pub command: String,
+    /// The effective user ID.
+    pub uid: u32,
+    /// The state letter from `/proc/<pid>/stat`, e.g. `S` for sleeping.
+    pub state: char,
+    /// When the process started, as time since boot.
+    pub start_time: Duration,
+    /// Resident set size in bytes.
+    pub rss: u64,
+}
+
+/// The result of a [`GraceKill::run`](crate::GraceKill::run).
+#[derive(Clone, Debug, Default)]
+pub struct KillReport {
+    /// Outcome for every target, in the order the targets were given.
+    pub outcomes: Vec<(u32, Outcome)>,
+    /// Exit status of every target that was our child and has been reaped.
+    /// Only collected when [`GraceKill::exit_status`](crate::GraceKill::exit_status)
+    /// is enabled.
+    pub exit_statuses: Vec<(u32, ExitStatus)>,
+    /// Wall-clock time spent between the graceful signal and the end of the run.
+    pub elapsed: Duration,
+    /// Whether the kill stage was cut short by a failure, with
+    /// [`GraceKill::abort_on_kill_failure`](crate::GraceKill::abort_on_kill_failure).
+    pub aborted: bool,
+    /// Whether the run was cut short by its
+    /// [`CancelToken`](crate::CancelToken). The targets still running then
+    /// are reported as [`Outcome::Survived`]; cancelled before the run
+    /// started, no target was signalled and none has an outcome.
+    pub cancelled: bool,
+    /// Killed processes still present shortly after the kill signal, e.g.
+    /// stuck in uninterruptible sleep. Their outcome is still
+    /// [`Outcome::Killed`].
+    pub lingering: Vec<u32>,
+    /// A snapshot of every target that could be read before the first
+    /// signal, in the order the targets were given. Only taken when
+    /// [`GraceKill::snapshot`](crate::GraceKill::snapshot) is enabled;
+    /// targets that exited before their snapshot was complete are left out.
+    pub snapshots: Vec<Snapshot>,
+    /// The signal each process that exited gracefully exited on: the last
+    /// one it was sent, the graceful signal or an escalation stage's.
+    /// Processes that exited before being sent anything are left out.
+    pub exit_signals: Vec<(u32, Signal)>,
+    /// With [`GraceKill::escalate`](crate::GraceKill::escalate) stages, how
+    /// many processes exited gracefully on each signal, in the order they
+    /// were sent, including stages nothing exited on. Empty without stages.
+    pub stages: Vec<(Signal, usize)>,
+    /// How far past its end the grace period, or the longest-overrunning
+    /// escalation stage's wait, ran before the next signal was sent. Zero
+    /// if every wait ended with every target gone.
+    pub grace_overrun: Duration,
+    /// Why each target still running at the end of its grace period
+    /// outlived its signal, in the order they were escalated. Only
+    /// classified on Linux; targets whose `/proc` entries couldn't be read
+    /// are left out.
+    pub survivals: Vec<(u32, Survival)>,
+    /// Targets already gone when their graceful signal was to be sent,
+    /// having exited since they were chosen, in the order they were found.
+    /// Their outcome is [`Outcome::SignalFailed`] with
+    /// [`SignalError::NotFound`], or [`Outcome::Graceful`] with
+    /// [`GraceKill::ignore_missing`](crate::GraceKill::ignore_missing).
+    pub vanished: Vec<u32>,
+    /// How long after the graceful signal the [`GraceKill::quorum`](crate::GraceKill::quorum)
+    /// was reached, if it was with targets still running.
+    pub quorum_reached: Option<Duration>,
+}
+
+impl KillReport {
+    /// The outcome recorded for `pid`, if it was a target.
+    #[must_use]
+    pub fn outcome(&self, pid: u32) -> Option<&Outcome> {
+        self.outcomes
+            .iter()
+            .find(|(p, _)| *p == pid)
+            .map(|(_, outcome)| outcome)
+    }
+
+    /// The exit status collected for `pid`, if it was our child.
+    #[must_use]
+    pub fn exit_status(&self, pid: u32) -> Option<ExitStatus> {
+        self.exit_statuses
+            .iter()
+            .find(|(p, _)| *p == pid)
+            .map(|&(_, status)| status)
+    }
+
+    /// The snapshot taken of `pid` before it was signalled, if any.
+    #[must_use]
+    pub fn snapshot(&self, pid: u32) -> Option<&Snapshot> {
+        self.snapshots.iter().find(|snapshot| snapshot.pid == pid)
+    }
+
+    /// The signal `pid` exited on, if it exited gracefully after being sent
+    /// one.
+    #[must_use]
+    pub fn exit_signal(&self, pid: u32) -> Option<Signal> {
+        self.exit_signals
+            .iter()
+            .find(|(p, _)| *p == pid)
+            .map(|&(_, signal)| signal)
+    }
+
+    /// Why `pid` outlived its grace period, if it did and it could be told.
+    #[must_use]
+    pub fn survival(&self, pid: u32) -> Option<Survival> {
+        self.survivals
+            .iter()
+            .find(|(p, _)| *p == pid)
+            .map(|&(_, survival)| survival)
+    }
+
+    /// Whether every target exited within the grace period, with nothing
+    /// killed, failed or left running.
+    #[must_use]
+    pub fn all_graceful(&self) -> bool {
+        !self.outcomes.is_empty() && self.graceful() == self.outcomes.len()
+    }
+
+    /// Number of processes the graceful signal reached.
+    #[must_use]
+    pub fn signalled(&self) -> usize {
+        self.count(|o| !matches!(o, Outcome::SignalFailed(_)))
+    }
+
+    /// Number of processes that exited within the grace period.
+    #[must_use]
+    pub fn graceful(&self) -> usize {
+        self.count(|o| matches!(o, Outcome::Graceful))
+    }
+
+    /// Number of processes that were sent the kill signal.
+    #[must_use]
+    pub fn killed(&self) -> usize {
+        self.count(|o| matches!(o, Outcome::Killed))
+    }
+
+    /// Number of processes for which a signal could not be delivered.
+    #[must_use]
+    pub fn failed(&self) -> usize {
+        self.count(|o| matches!(o, Outcome::SignalFailed(_) | Outcome::KillFailed(_)))
+    }
+
+    /// Number of processes left running because no kill signal was sent.
+    #[must_use]
+    pub fn survived(&self) -> usize {
+        self.count(|o| matches!(o, Outcome::Survived))
+    }
+
+    /// A one-line recap of the outcomes, e.g.
+    /// `3 exited gracefully, 1 killed, 0 failed`, or with escalation stages
+    /// `3 exited gracefully (2 on SIGTERM, 1 on SIGINT), 1 killed, 0 failed`.
+    #[must_use]
+    pub fn summary(&self) -> String {
+        Tally::of(self).summary()
+    }
+
+    fn count(&self, predicate: impl Fn(&Outcome) -> bool) -> usize {
+        self.outcomes.iter().filter(|(_, o)| predicate(o)).count()
+    }
+}
+
+/// Running totals of the outcomes of one or more [`KillReport`]s, for runs
+/// with too many targets to keep the outcome of each.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Tally {
+    pub targets: usize,
+    pub signalled: usize,
+    pub graceful: usize,
+    pub killed: usize,
+    pub failed: usize,
+    pub survived: usize,
+    pub lingering: usize,
+    pub aborted: bool,
+    /// Graceful exits on each escalation stage's signal, as in
+    /// [`KillReport::stages`].
+    pub stages: Vec<(Signal, usize)>,
+}
+
+impl Tally {
+    /// The totals of `report` alone.
+    #[must_use]
+    pub fn of(report: &KillReport) -> Tally {
+        let mut tally = Tally::default();
+        tally.add(report);
+        tally
+    }
+
+    /// Adds the outcomes in `report`.
+    pub fn add(&mut self, report: &KillReport) {
+        self.targets += report.outcomes.len();
+        self.signalled += report.signalled();
+        self.graceful += report.graceful();
+        self.killed += report.killed();
+        self.failed += report.failed();
+        self.survived += report.survived();
+        self.lingering += report.lingering.len();
+        self.aborted |= report.aborted;
+        for &(signal, exited) in &report.stages {
+            match self.stages.iter_mut().find(|(s, _)| *s == signal) {
+                Some((_, total)) => *total += exited,
+                None => self.stages.push((signal, exited)),
+            }
+        }
+    }
+
+    /// Whether every target exited within the grace period, as in
+    /// [`KillReport::all_graceful`].
+    #[must_use]
+    pub fn all_graceful(&self) -> bool {
+        self.targets > 0 && self.graceful == self.targets
+    }
+
+    /// A one-line recap of the outcomes, as in [`KillReport::summary`].
+    #[must_use]
+    pub fn summary(&self) -> String {
+        let mut summary = format!("{} exited gracefully", self.graceful);
+        if !self.stages.is_empty() {
+            let stages: Vec<String> = self
+                .stages
+                .iter()
+                .map(|(signal, exited)| format!("{exited} on {signal}"))
+                .collect();
+            let _ = write!(summary, " ({})", stages.join(", "));
+        }
+        let _ = write!(summary, ", {} killed, {} failed", self.killed, self.failed);
+        if self.survived > 0 {
+            let _ = write!(summary, ", {} left running", self.survived);
+        }
+        if self.lingering > 0 {
+            let _ = write!(summary, ", {} still present after kill", self.lingering);
+        }
+        summary
+    }
+}
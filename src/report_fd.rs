@@ -0,0 +1,56 @@
+//! `--report-pids-fd`: the targets that were signalled and what became of
+//! them, one per line, written to an inherited file descriptor so that a
+//! supervising parent can read the result over a pipe without parsing logs:
+//!
+//! ```text
+//! 1234 graceful
+//! 5678 killed
+//! ```
+//!
+//! Outcomes are named as in `--output-pid-status-json`. Targets that could
+//! never be signalled are left out. The descriptor is closed once written,
+//! so the reader sees end-of-file.
+
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{self, Write};
+use std::os::fd::{FromRawFd, RawFd};
+
+use gracekill::{KillReport, Outcome};
+
+use crate::status_json;
+
+/// Checks that `fd` is open for writing.
+pub fn check(fd: RawFd) -> Result<(), String> {
+    // SAFETY: F_GETFL only reads the descriptor's flags.
+    let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+    if flags < 0 {
+        return Err(format!(
+            "--report-pids-fd {fd} is not an open file descriptor"
+        ));
+    }
+    if flags & libc::O_ACCMODE == libc::O_RDONLY {
+        return Err(format!("--report-pids-fd {fd} is not open for writing"));
+    }
+    Ok(())
+}
+
+/// Writes the signalled targets in `report` to `fd` and closes it.
+///
+/// # Errors
+///
+/// Returns the underlying error if the write fails, e.g. because the
+/// reading end of a pipe was closed.
+pub fn write(fd: RawFd, report: &KillReport) -> io::Result<()> {
+    let mut lines = String::new();
+    for (pid, outcome) in &report.outcomes {
+        if !matches!(outcome, Outcome::SignalFailed(_)) {
+            let _ = writeln!(lines, "{pid} {}", status_json::status(outcome));
+        }
+    }
+    // SAFETY: the descriptor was checked at startup, is not used anywhere
+    // else, and this is its only owner.
+    let mut file = unsafe { File::from_raw_fd(fd) };
+    file.write_all(lines.as_bytes())?;
+    file.flush()
+}
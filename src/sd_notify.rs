@@ -0,0 +1,152 @@
+//! `--sd-notify`: keep systemd informed while gracekill runs as a service's
+//! `ExecStop=`, so that a long drain isn't cut short by the service's own
+//! stop timeout.
+//!
+//! While targets are being waited for, every [`INTERVAL`] the socket in
+//! `NOTIFY_SOCKET` is sent
+//!
+//! ```text
+//! EXTEND_TIMEOUT_USEC=15000000
+//! STATUS=Waiting for 3 process(es) to exit, 5.1s elapsed
+//! ```
+//!
+//! each extension outlasting the next message, and once gracekill is done
+//! `STOPPING=1` with the totals as the status. Without `NOTIFY_SOCKET`
+//! nothing is sent. A message systemd can't be sent is logged and the run
+//! carries on: the kill matters more than the notification.
+
+use std::os::unix::net::UnixDatagram;
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use gracekill::log;
+use gracekill::{KillReport, Outcome, Tally};
+
+/// How often progress is sent.
+pub const INTERVAL: Duration = Duration::from_secs(5);
+
+/// How far each message pushes back systemd's stop timeout: a few
+/// intervals, so that one late message doesn't let it run out.
+const EXTENSION: Duration = Duration::from_secs(15);
+
+/// The connection to systemd, once `NOTIFY_SOCKET` has been opened.
+static NOTIFIER: Mutex<Option<Notifier>> = Mutex::new(None);
+
+struct Notifier {
+    socket: UnixDatagram,
+    last_sent: Option<Instant>,
+    /// The outcomes of every kill so far.
+    tally: Tally,
+}
+
+impl Notifier {
+    fn send(&self, message: &str) {
+        if let Err(e) = self.socket.send(message.as_bytes()) {
+            log::warn(&format!("Failed to notify systemd: {e}"));
+        }
+    }
+}
+
+fn notifier() -> std::sync::MutexGuard<'static, Option<Notifier>> {
+    NOTIFIER.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Connects to the socket in `NOTIFY_SOCKET`, if it is set.
+pub fn open() {
+    let Some(path) = std::env::var_os("NOTIFY_SOCKET") else {
+        log::debug("NOTIFY_SOCKET is not set, not notifying systemd");
+        return;
+    };
+    match connect(&path.to_string_lossy()) {
+        Ok(socket) => {
+            *notifier() = Some(Notifier {
+                socket,
+                last_sent: None,
+                tally: Tally::default(),
+            });
+        }
+        Err(e) => log::warn(&format!(
+            "Cannot connect to NOTIFY_SOCKET '{}', not notifying systemd: {e}",
+            path.to_string_lossy()
+        )),
+    }
+}
+
+fn connect(path: &str) -> Result<UnixDatagram, String> {
+    let socket = UnixDatagram::unbound().map_err(|e| e.to_string())?;
+    if let Some(name) = path.strip_prefix('@') {
+        #[cfg(target_os = "linux")]
+        {
+            use std::os::linux::net::SocketAddrExt;
+            let address = std::os::unix::net::SocketAddr::from_abstract_name(name.as_bytes())
+                .map_err(|e| e.to_string())?;
+            socket.connect_addr(&address).map_err(|e| e.to_string())?;
+            return Ok(socket);
+        }
+        #[cfg(not(target_os = "linux"))]
+        return Err(format!(
+            "abstract socket '{name}' is only supported on Linux"
+        ));
+    }
+    if !path.starts_with('/') {
+        return Err("not a socket path".to_string());
+    }
+    socket.connect(path).map_err(|e| e.to_string())?;
+    Ok(socket)
+}
+
+/// A [`GraceKill::on_poll`](gracekill::GraceKill::on_poll) callback sending
+/// progress every [`INTERVAL`] while targets are running, if systemd is
+/// being notified.
+pub fn progress() -> Option<impl FnMut(Duration, &[u32], &KillReport) + Send + 'static> {
+    notifier()
+        .is_some()
+        .then_some(|elapsed: Duration, remaining: &[u32], _: &KillReport| {
+            if remaining.is_empty() {
+                return;
+            }
+            let mut notifier = notifier();
+            let Some(notifier) = notifier.as_mut() else {
+                return;
+            };
+            if notifier
+                .last_sent
+                .is_some_and(|sent| sent.elapsed() < INTERVAL)
+            {
+                return;
+            }
+            notifier.last_sent = Some(Instant::now());
+            notifier.send(&format!(
+                "EXTEND_TIMEOUT_USEC={}\nSTATUS=Waiting for {} process(es) to exit, {} elapsed",
+                EXTENSION.as_micros(),
+                remaining.len(),
+                log::duration(elapsed)
+            ));
+        })
+}
+
+/// A [`GraceKill::on_result`](gracekill::GraceKill::on_result) callback
+/// counting each outcome towards the final status, if systemd is being
+/// notified.
+pub fn counter() -> Option<impl FnMut(u32, &Outcome) + Send + 'static> {
+    notifier().is_some().then_some(|pid, outcome: &Outcome| {
+        if let Some(notifier) = notifier().as_mut() {
+            notifier.tally.add(&KillReport {
+                outcomes: vec![(pid, outcome.clone())],
+                ..KillReport::default()
+            });
+        }
+    })
+}
+
+/// Tells systemd that gracekill is done, with the totals of its kills.
+pub fn finish() {
+    if let Some(notifier) = notifier().take() {
+        let status = if notifier.tally.targets == 0 {
+            "Stopped".to_string()
+        } else {
+            format!("Stopped: {}", notifier.tally.summary())
+        };
+        notifier.send(&format!("STOPPING=1\nSTATUS={status}"));
+    }
+}
@@ -0,0 +1,192 @@
+//! `--selftest`: check that gracekill works where it is installed, by
+//! putting a sacrificial child of its own through a whole kill:
+//!
+//! ```text
+//! ok    Forked child 4120, which ignores SIGTERM
+//! ok    Read /proc/4120/status, which shows SIGTERM ignored
+//! ok    Sent SIGTERM
+//! ok    Waited out the 500ms grace period
+//! ok    Escalated to SIGKILL
+//! ok    Confirmed the child killed by signal SIGKILL
+//! ```
+//!
+//! The child ignores SIGTERM, so a kill that works signals it, waits out
+//! the grace period and has to escalate. The kill is set up as for any
+//! other run, so options such as `--liveness` and `--escalate-privilege`
+//! are tested too, but always with SIGTERM, SIGKILL and a short grace
+//! period. A check that fails is marked `FAIL` with the reason.
+
+use std::time::Duration;
+
+use gracekill::log;
+use gracekill::{ExitStatus, GraceKill, KillReport, Outcome, Signal};
+use nix::sys::signal::{self as nix_signal, SigHandler, Signal as NixSignal};
+use nix::sys::wait::waitpid;
+use nix::unistd::{ForkResult, Pid, fork, pause};
+
+use crate::commands::Commands;
+use crate::stdout;
+
+/// The grace period the child is given.
+pub const GRACE: Duration = Duration::from_millis(500);
+
+/// The checks done so far.
+#[derive(Default)]
+struct Checks {
+    passed: usize,
+    failed: usize,
+}
+
+impl Checks {
+    fn check(&mut self, result: Result<String, String>) -> bool {
+        match result {
+            Ok(done) => {
+                self.passed += 1;
+                stdout::line(&format!("ok    {done}"));
+                true
+            }
+            Err(failure) => {
+                self.failed += 1;
+                stdout::line(&format!("FAIL  {failure}"));
+                false
+            }
+        }
+    }
+
+    /// Logs the result, and returns the exit code.
+    fn conclude(&self) -> i32 {
+        if self.failed == 0 {
+            log::summary(&format!("Self-test passed: {} check(s)", self.passed));
+            0
+        } else {
+            log::summary(&format!(
+                "Self-test failed: {} of {} check(s) failed",
+                self.failed,
+                self.passed + self.failed
+            ));
+            1
+        }
+    }
+}
+
+/// Forks a child that ignores SIGTERM and waits to be killed.
+fn spawn_child() -> Result<Pid, String> {
+    // Ignored before the fork, so that the child can't be signalled before
+    // it ignores SIGTERM; gracekill's own disposition is put back after
+    // SAFETY: SIG_IGN runs no code in the handler
+    let previous = unsafe { nix_signal::signal(NixSignal::SIGTERM, SigHandler::SigIgn) }
+        .map_err(|e| format!("Fork a child: cannot ignore SIGTERM: {e}"))?;
+    // SAFETY: no other thread has been started yet, and the child only
+    // calls pause
+    let forked = match unsafe { fork() } {
+        Ok(ForkResult::Child) => loop {
+            pause();
+        },
+        Ok(ForkResult::Parent { child }) => Ok(child),
+        Err(e) => Err(format!("Fork a child: {e}")),
+    };
+    // SAFETY: restores the disposition gracekill had
+    let _ = unsafe { nix_signal::signal(NixSignal::SIGTERM, previous) };
+    forked
+}
+
+/// Checks that `/proc` shows the child ignoring SIGTERM.
+#[cfg(target_os = "linux")]
+fn check_proc(child: u32) -> Result<String, String> {
+    let path = format!("/proc/{child}/status");
+    let masks = gracekill::procfs::signal_masks(child).map_err(|e| format!("Read {path}: {e}"))?;
+    if gracekill::procfs::SignalMasks::contains(masks.ignored, Signal::Term) {
+        Ok(format!("Read {path}, which shows SIGTERM ignored"))
+    } else {
+        Err(format!("Read {path}, which doesn't show SIGTERM ignored"))
+    }
+}
+
+/// Checks each stage of the kill in `report` of `child`.
+fn check_kill(checks: &mut Checks, report: &KillReport, child: u32) {
+    let outcome = report
+        .outcomes
+        .iter()
+        .find(|&&(pid, _)| pid == child)
+        .map(|(_, outcome)| outcome);
+    let sent = match outcome {
+        Some(Outcome::SignalFailed(e)) => Err(format!("Send SIGTERM: {e}")),
+        Some(_) => Ok("Sent SIGTERM".to_string()),
+        None => Err("Send SIGTERM: the child was not signalled".to_string()),
+    };
+    if !checks.check(sent) {
+        return;
+    }
+    let waited = match outcome {
+        Some(Outcome::Graceful) => Err(
+            "Wait out the grace period: the child was taken to have exited, though it ignores SIGTERM"
+                .to_string(),
+        ),
+        _ if report.elapsed < GRACE => Err(format!(
+            "Wait out the grace period: escalated after {}",
+            log::duration(report.elapsed)
+        )),
+        _ => Ok(format!(
+            "Waited out the {} grace period",
+            log::duration(GRACE)
+        )),
+    };
+    if !checks.check(waited) {
+        return;
+    }
+    let escalated = match outcome {
+        Some(Outcome::Killed) => Ok("Escalated to SIGKILL".to_string()),
+        Some(Outcome::KillFailed(e)) => Err(format!("Escalate to SIGKILL: {e}")),
+        _ => Err("Escalate to SIGKILL: it was never sent".to_string()),
+    };
+    if !checks.check(escalated) {
+        return;
+    }
+    let status = report
+        .exit_statuses
+        .iter()
+        .find(|&&(pid, _)| pid == child)
+        .map(|&(_, status)| status);
+    checks.check(match status {
+        Some(status @ ExitStatus::Signaled(signal)) if signal == Signal::Kill.number() => {
+            Ok(format!("Confirmed the child {status}"))
+        }
+        Some(status) => Err(format!("Confirm the kill: the child {status}")),
+        None if report.lingering.contains(&child) => {
+            Err("Confirm the kill: the child was still present after SIGKILL".to_string())
+        }
+        None => Err("Confirm the kill: the child's exit status was not collected".to_string()),
+    });
+}
+
+/// Runs the self-test with `gracekill`, returning the exit code: 0 if
+/// every check passed, 1 otherwise.
+pub fn run(gracekill: GraceKill<Commands>) -> i32 {
+    let mut checks = Checks::default();
+    let (forked, child) = match spawn_child() {
+        Ok(forked) => (forked, forked.as_raw().unsigned_abs()),
+        Err(e) => {
+            checks.check(Err(e));
+            return checks.conclude();
+        }
+    };
+    checks.check(Ok(format!("Forked child {child}, which ignores SIGTERM")));
+    #[cfg(target_os = "linux")]
+    checks.check(check_proc(child));
+
+    let mut gracekill = gracekill
+        .grace(GRACE)
+        .signal(Signal::Term)
+        .kill_signal(Signal::Kill)
+        .exit_status(true);
+    let report = gracekill.run(&[child]);
+    check_kill(&mut checks, &report, child);
+
+    // Not reaped means it may still be running; reaped, its PID may
+    // already belong to another process
+    if !report.exit_statuses.iter().any(|&(pid, _)| pid == child) {
+        let _ = nix_signal::kill(forked, NixSignal::SIGKILL);
+        let _ = waitpid(forked, None);
+    }
+    checks.conclude()
+}
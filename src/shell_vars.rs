@@ -0,0 +1,59 @@
+//! `--shell-vars`: finish with shell variable assignments on stdout, for
+//! scripts that `eval "$(gracekill --shell-vars ...)"`:
+//!
+//! ```text
+//! GRACEKILL_GRACEFUL=3
+//! GRACEKILL_KILLED=1
+//! GRACEKILL_FAILED=0
+//! GRACEKILL_SURVIVORS='9012'
+//! GRACEKILL_STAGES='SIGTERM=3 SIGINT=0'
+//! ```
+//!
+//! Every value that isn't a plain number is single-quoted, so that nothing
+//! in it can be taken for shell syntax.
+
+use gracekill::{KillReport, Outcome};
+
+use crate::stdout;
+
+/// Prints the assignments for `report`.
+pub fn print(report: &KillReport) {
+    let mut survivors: Vec<u32> = report
+        .outcomes
+        .iter()
+        .filter(|(_, outcome)| *outcome == Outcome::Survived)
+        .map(|&(pid, _)| pid)
+        .collect();
+    survivors.extend(&report.lingering);
+    let survivors: Vec<String> = survivors.iter().map(u32::to_string).collect();
+    let stages: Vec<String> = report
+        .stages
+        .iter()
+        .map(|(signal, exited)| format!("{signal}={exited}"))
+        .collect();
+
+    let mut assignments = vec![
+        ("GRACEKILL_GRACEFUL", report.graceful().to_string()),
+        ("GRACEKILL_KILLED", report.killed().to_string()),
+        ("GRACEKILL_FAILED", report.failed().to_string()),
+        ("GRACEKILL_SURVIVORS", quote(&survivors.join(" "))),
+        (
+            "GRACEKILL_ELAPSED_MS",
+            report.elapsed.as_millis().to_string(),
+        ),
+        ("GRACEKILL_SUMMARY", quote(&report.summary())),
+        ("GRACEKILL_STAGES", quote(&stages.join(" "))),
+    ];
+    if let Some(id) = gracekill::log::operation_id() {
+        assignments.push(("GRACEKILL_OPERATION_ID", quote(id)));
+    }
+    for (name, value) in assignments {
+        stdout::line(&format!("{name}={value}"));
+    }
+}
+
+/// `value` in single quotes, each single quote in it closing the quotes,
+/// escaped, and reopening them: `it's` becomes `'it'\''s'`.
+pub fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
@@ -0,0 +1,320 @@
+//! `--show-config`: print the settings a run would use, once every option
+//! has been applied, as one JSON object on stdout:
+//!
+//! ```text
+//! {"grace_period_ms":{"value":10000,"source":"default"},"signal":{"value":"SIGINT","source":"flag"},...}
+//! ```
+//!
+//! `source` is `flag` for a value that differs from the default, `env` for
+//! a log level taken from `GRACEKILL_LOG`, and `default` otherwise. A flag
+//! that repeats the default value shows as `default`, as it changes nothing.
+//! The run then stops, unless `--proceed` is given too.
+
+use std::fmt::Write as _;
+use std::time::Duration;
+
+use gracekill::log::{self, Level};
+use gracekill::{Signal, Stage};
+
+use crate::args::Options;
+use crate::{json, stdout};
+
+/// Where a setting came from.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Source {
+    Default,
+    Env,
+    Flag,
+}
+
+impl Source {
+    fn name(self) -> &'static str {
+        match self {
+            Source::Default => "default",
+            Source::Env => "env",
+            Source::Flag => "flag",
+        }
+    }
+
+    fn of<T: PartialEq>(value: &T, default: &T) -> Source {
+        if value == default {
+            Source::Default
+        } else {
+            Source::Flag
+        }
+    }
+}
+
+/// The JSON object being written.
+struct Config(String);
+
+impl Config {
+    fn entry(&mut self, key: &str, value: &str, source: Source) {
+        let separator = if self.0.is_empty() { "{" } else { "," };
+        let _ = write!(
+            self.0,
+            "{separator}\"{key}\":{{\"value\":{value},\"source\":\"{}\"}}",
+            source.name()
+        );
+    }
+
+    fn duration(&mut self, key: &str, value: Option<Duration>, default: Option<Duration>) {
+        let json = value.map_or_else(|| "null".to_string(), |value| millis(value).to_string());
+        self.entry(key, &json, Source::of(&value, &default));
+    }
+
+    fn flag(&mut self, key: &str, value: bool) {
+        self.entry(key, &value.to_string(), Source::of(&value, &false));
+    }
+
+    fn list(&mut self, key: &str, values: &[u32]) {
+        let json = values
+            .iter()
+            .map(u32::to_string)
+            .collect::<Vec<_>>()
+            .join(",");
+        self.entry(key, &format!("[{json}]"), Source::of(&values.len(), &0));
+    }
+}
+
+fn millis(duration: Duration) -> u128 {
+    duration.as_millis()
+}
+
+fn signal(signal: Signal) -> String {
+    json::quote(signal.name())
+}
+
+/// The effective log level and where it came from.
+fn log_level(options: &Options) -> (Level, Source) {
+    if let Some(level) = options.log_level {
+        return (level, Source::Flag);
+    }
+    let from_env = std::env::var(log::ENV_VAR)
+        .ok()
+        .is_some_and(|value| value.parse::<Level>().is_ok());
+    let source = if from_env {
+        Source::Env
+    } else {
+        Source::Default
+    };
+    (log::level(), source)
+}
+
+/// The escalation stages as a JSON array.
+fn stages(stages: &[Stage]) -> String {
+    let stages = stages
+        .iter()
+        .map(|stage| {
+            format!(
+                "{{\"signal\":{},\"grace_ms\":{},\"retries\":{},\"jitter_ms\":{}}}",
+                signal(stage.signal),
+                millis(stage.grace),
+                stage.retries,
+                millis(stage.jitter)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(",");
+    format!("[{stages}]")
+}
+
+/// Prints the configuration `options` resolve to.
+pub fn print(options: &Options) {
+    let defaults = Options::default();
+    let mut config = Config(String::new());
+
+    config.list("pids", &options.pids);
+    config.list("groups", &options.groups);
+    config.list("sessions", &options.sessions);
+    let ports: Vec<u32> = options.ports.iter().map(|&port| u32::from(port)).collect();
+    config.list("ports", &ports);
+    config.duration(
+        "grace_period_ms",
+        Some(options.grace_period),
+        Some(defaults.grace_period),
+    );
+    config.entry(
+        "signal",
+        &signal(options.signal),
+        Source::of(&options.signal, &defaults.signal),
+    );
+    config.entry(
+        "retries",
+        &options.retries.to_string(),
+        Source::of(&options.retries, &defaults.retries),
+    );
+    config.duration("jitter_ms", Some(options.jitter), Some(defaults.jitter));
+    config.entry(
+        "kill_signal",
+        &signal(options.kill_signal),
+        Source::of(&options.kill_signal, &defaults.kill_signal),
+    );
+    config.entry(
+        "escalate",
+        &stages(&options.stages),
+        Source::of(&options.stages.len(), &0),
+    );
+    config.entry(
+        "poll_interval_ms",
+        &millis(gracekill::DEFAULT_POLL_INTERVAL).to_string(),
+        Source::Default,
+    );
+    config.entry(
+        "poll_schedule",
+        &json::quote(options.poll_schedule.name()),
+        Source::of(&options.poll_schedule, &defaults.poll_schedule),
+    );
+    grace_bounds(&mut config, options, &defaults);
+    config.duration(
+        "warn_long_exit_ms",
+        options.warn_long_exit,
+        defaults.warn_long_exit,
+    );
+    config.duration(
+        "max_grace_overrun_ms",
+        options.max_grace_overrun,
+        defaults.max_grace_overrun,
+    );
+    config.duration(
+        "proc_timeout_ms",
+        options.proc_timeout,
+        defaults.proc_timeout,
+    );
+    limits(&mut config, options, &defaults);
+    let liveness = options.liveness.map_or_else(
+        || "null".to_string(),
+        |liveness| json::quote(&format!("{liveness:?}").to_ascii_lowercase()),
+    );
+    config.entry(
+        "liveness",
+        &liveness,
+        Source::of(&options.liveness, &defaults.liveness),
+    );
+    config.entry(
+        "enrich",
+        &json::quote(&format!("{:?}", options.enrich).to_ascii_lowercase()),
+        Source::of(&options.enrich, &defaults.enrich),
+    );
+    config.entry(
+        "tree_order",
+        &json::quote(options.tree_order.name()),
+        Source::of(&options.tree_order, &defaults.tree_order),
+    );
+    config.entry(
+        "duration_format",
+        &json::quote(options.duration_format.name()),
+        Source::of(&options.duration_format, &defaults.duration_format),
+    );
+    let (level, source) = log_level(options);
+    config.entry("log_level", &json::quote(&level.to_string()), source);
+    switches(&mut config, options);
+
+    config.0.push('}');
+    stdout::line(&config.0);
+}
+
+/// Adds the options that shorten or stretch the grace period.
+fn grace_bounds(config: &mut Config, options: &Options, defaults: &Options) {
+    config.entry(
+        "quorum_percent",
+        &options
+            .quorum
+            .map_or_else(|| "null".to_string(), |(percent, _)| percent.to_string()),
+        Source::of(&options.quorum, &defaults.quorum),
+    );
+    config.duration(
+        "quorum_stragglers_ms",
+        options.quorum.map(|(_, stragglers)| stragglers),
+        defaults.quorum.map(|(_, stragglers)| stragglers),
+    );
+    config.duration("min_grace_ms", options.min_grace, defaults.min_grace);
+    config.duration("max_grace_ms", options.max_grace, defaults.max_grace);
+    config.duration(
+        "cpu_time_grace_ms",
+        options.cpu_time_grace,
+        defaults.cpu_time_grace,
+    );
+    config.duration(
+        "grace_extend_if_progressing_ms",
+        options.grace_extension,
+        defaults.grace_extension,
+    );
+}
+
+/// Adds the options that limit how many targets are handled at a time.
+fn limits(config: &mut Config, options: &Options, defaults: &Options) {
+    config.entry(
+        "rate",
+        &options
+            .rate
+            .map_or_else(|| "null".to_string(), |rate| rate.to_string()),
+        Source::of(&options.rate, &defaults.rate),
+    );
+    config.entry(
+        "kill_concurrency",
+        &options
+            .kill_concurrency
+            .map_or_else(|| "null".to_string(), |limit| limit.to_string()),
+        Source::of(&options.kill_concurrency, &defaults.kill_concurrency),
+    );
+    config.entry(
+        "confirm_count",
+        &options
+            .confirm_count
+            .map_or_else(|| "null".to_string(), |limit| limit.to_string()),
+        Source::of(&options.confirm_count, &defaults.confirm_count),
+    );
+    config.flag("yes", options.yes);
+    config.entry(
+        "chunk_size",
+        &options.chunk_size.to_string(),
+        Source::of(&options.chunk_size, &defaults.chunk_size),
+    );
+}
+
+/// Adds the options that are plain on/off switches.
+fn switches(config: &mut Config, options: &Options) {
+    config.flag("force", options.force);
+    config.flag("kill_compat", options.kill_compat);
+    config.flag("double_tap", options.double_tap);
+    config.flag("abort_with_core", options.abort_with_core);
+    config.flag("per_wave_grace", options.per_wave_grace);
+    config.flag("load_aware", options.load_aware);
+    config.flag("rescan", options.rescan);
+    config.flag("ignore_missing", options.ignore_missing);
+    config.flag("no_wait_lock", options.no_wait_lock);
+    config.flag("skip_invalid", options.skip_invalid);
+    config.flag("skip_exiting", options.skip_exiting);
+    config.flag("strict_pids", options.strict_pids);
+    config.flag("include_foreground_group", options.include_foreground);
+    config.flag("follow_daemon", options.follow_daemon);
+    config.flag("freeze", options.freeze);
+    config.flag("diagnose", options.diagnose);
+    config.flag("wait_children", options.wait_children);
+    config.flag("reap", options.reap);
+    config.flag("reset_tty", options.reset_tty);
+    config.flag("snapshot", options.snapshot);
+    config.flag("stats", options.stats);
+    config.flag("sd_notify", options.sd_notify);
+    config.flag("stream", options.stream);
+    config.flag("shell_vars", options.shell_vars);
+    config.flag("shuffle", options.shuffle);
+    config.flag("parallel", options.parallel);
+    config.flag("dry_run", options.dry_run);
+    config.flag("grace_table", options.grace_table);
+    config.flag("selftest", options.selftest);
+    config.flag("simulate_timing", options.simulate_timing);
+    config.flag("simulate", options.simulate);
+    config.flag("daemonize", options.daemonize);
+    config.flag("background_escalate", options.background_escalate);
+    config.flag("abort_on_kill_failure", options.abort_on_kill_failure);
+    config.flag(
+        "exit_non_zero_if_sigkill_required",
+        options.exit_non_zero_if_sigkill_required,
+    );
+    config.flag("exit_status", options.exit_status);
+    config.flag("nagios", options.nagios);
+    config.flag("quiet_on_success", options.quiet_on_success);
+    config.flag("coalesce_logs", options.coalesce_logs);
+}
@@ -0,0 +1,44 @@
+//! `--shuffle`: randomize the order targets are signalled in, reproducibly.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A seed that differs from run to run, for when none is given.
+pub fn random_seed() -> u64 {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    // Truncating the nanoseconds is fine, only the low bits vary anyway
+    #[allow(clippy::cast_possible_truncation)]
+    let nanos = now.as_nanos() as u64;
+    nanos ^ u64::from(std::process::id()).rotate_left(32)
+}
+
+/// The splitmix64 generator: tiny, fast, and good enough for ordering.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// A value in `0..=bound`.
+    fn up_to(&mut self, bound: usize) -> usize {
+        let bound = bound as u64 + 1;
+        // The remainder's bias is negligible for target lists
+        usize::try_from(self.next() % bound).unwrap_or(0)
+    }
+}
+
+/// Returns `pids` in an order determined by `seed` (Fisher-Yates).
+pub fn shuffled(pids: &[u32], seed: u64) -> Vec<u32> {
+    let mut rng = SplitMix64(seed);
+    let mut pids = pids.to_vec();
+    for i in (1..pids.len()).rev() {
+        pids.swap(i, rng.up_to(i));
+    }
+    pids
+}
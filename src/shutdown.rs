@@ -0,0 +1,255 @@
+//! `--shutdown-url`: ask the targets to shut down over HTTP before any
+//! signal is sent.
+//!
+//! Many services offer a shutdown endpoint that does more than a signal
+//! handler would, such as deregistering from a load balancer first. The
+//! endpoint is sent a single request; if it answers with a 2xx status, the
+//! targets are given `--shutdown-wait` to exit. Those still running then, or
+//! all of them if the request failed, go through the usual signals.
+//!
+//! The client is deliberately minimal: plain `http://` only, no redirects,
+//! one request with `Connection: close`. It is behind the `shutdown-url`
+//! feature, which is on by default.
+
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use gracekill::log::{self, log};
+
+/// How long `--shutdown-url` waits for the targets to exit without
+/// `--shutdown-wait`.
+pub const DEFAULT_WAIT: Duration = Duration::from_secs(10);
+
+/// How often the targets are checked while waiting for them to exit.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The `--shutdown-method`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Method {
+    #[default]
+    Post,
+    Get,
+}
+
+impl Method {
+    #[cfg_attr(not(feature = "shutdown-url"), allow(dead_code))]
+    fn name(self) -> &'static str {
+        match self {
+            Method::Post => "POST",
+            Method::Get => "GET",
+        }
+    }
+}
+
+impl FromStr for Method {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "post" => Ok(Method::Post),
+            "get" => Ok(Method::Get),
+            _ => Err(format!(
+                "Unknown shutdown method: '{s}' (supported: post, get)"
+            )),
+        }
+    }
+}
+
+/// Where a request goes, from an `http://host[:port][/path]` URL.
+#[cfg_attr(not(any(feature = "shutdown-url", feature = "otlp")), allow(dead_code))]
+pub struct Endpoint {
+    /// `host:port` as written, for the `Host` header.
+    pub authority: String,
+    pub host: String,
+    pub port: u16,
+    pub path: String,
+}
+
+/// Checks that `url` is one the client can request.
+pub fn check_url(url: &str) -> Result<(), String> {
+    if !cfg!(feature = "shutdown-url") {
+        return Err(
+            "--shutdown-url needs gracekill built with the shutdown-url feature".to_string(),
+        );
+    }
+    parse_url(url, "shutdown URL").map(|_| ())
+}
+
+/// Parses `url`, the `what` named in the error if it can't be requested.
+pub fn parse_url(url: &str, what: &str) -> Result<Endpoint, String> {
+    let invalid = |why: &str| format!("Invalid {what} '{url}': {why}");
+    let rest = url
+        .strip_prefix("http://")
+        .ok_or_else(|| invalid("only http:// URLs are supported"))?;
+    let (authority, path) = match rest.find('/') {
+        Some(slash) => rest.split_at(slash),
+        None => (rest, "/"),
+    };
+    // [::1]:8080 or localhost:8080
+    let (host, port) = match authority.strip_prefix('[') {
+        Some(bracketed) => {
+            let (host, after) = bracketed
+                .split_once(']')
+                .ok_or_else(|| invalid("unclosed '['"))?;
+            (host, after.strip_prefix(':'))
+        }
+        None => match authority.rsplit_once(':') {
+            Some((host, port)) => (host, Some(port)),
+            None => (authority, None),
+        },
+    };
+    let port = match port {
+        Some(port) => port
+            .parse()
+            .map_err(|_| invalid(&format!("bad port '{port}'")))?,
+        None => 80,
+    };
+    if host.is_empty() {
+        return Err(invalid("no host"));
+    }
+    Ok(Endpoint {
+        authority: authority.to_string(),
+        host: host.to_string(),
+        port,
+        path: path.to_string(),
+    })
+}
+
+/// Waits up to `wait` for `running` to exit after being asked to in some
+/// other way than a signal, `after`, e.g. "the shutdown request", checked
+/// with `is_running`. Returns the PIDs that exited; the rest are left to
+/// the signals. Also used by `--stdin-cmd`.
+pub fn wait_for_exit(
+    mut running: Vec<u32>,
+    wait: Duration,
+    after: &str,
+    mut is_running: impl FnMut(u32) -> bool,
+) -> Vec<u32> {
+    let deadline = Instant::now() + wait;
+    let mut exited = Vec::new();
+    loop {
+        running.retain(|&pid| {
+            if is_running(pid) {
+                return true;
+            }
+            log(&format!("Process {pid} exited after {after}"));
+            exited.push(pid);
+            false
+        });
+        if running.is_empty() {
+            log(&format!("Every target exited after {after}"));
+            return exited;
+        }
+        if Instant::now() >= deadline {
+            log::warn(&format!(
+                "{} process(es) still running {} after {after}, falling back to signals",
+                running.len(),
+                log::duration(wait)
+            ));
+            return exited;
+        }
+        thread::sleep(POLL_INTERVAL.min(deadline.saturating_duration_since(Instant::now())));
+    }
+}
+
+#[cfg(feature = "shutdown-url")]
+pub use client::request_shutdown;
+
+#[cfg(feature = "shutdown-url")]
+mod client {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::{TcpStream, ToSocketAddrs};
+    use std::time::Duration;
+
+    use gracekill::log::{self, log};
+
+    use super::{Endpoint, Method, parse_url, wait_for_exit};
+
+    /// How long connecting to the endpoint and each read or write may take.
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// Requests `url` and waits up to `wait` for `pids` to exit, checked
+    /// with `is_running`. Returns the PIDs that exited, leaving out any that
+    /// were already gone beforehand, which the signals will report.
+    pub fn request_shutdown(
+        url: &str,
+        method: Method,
+        pids: &[u32],
+        wait: Duration,
+        mut is_running: impl FnMut(u32) -> bool,
+    ) -> Vec<u32> {
+        let running: Vec<u32> = pids
+            .iter()
+            .copied()
+            .filter(|&pid| is_running(pid))
+            .collect();
+        if running.is_empty() {
+            return Vec::new();
+        }
+        log(&format!("Requesting shutdown: {} {url}", method.name()));
+        let status = parse_url(url, "shutdown URL").and_then(|endpoint| send(&endpoint, method));
+        match status {
+            Ok(status) if (200..300).contains(&status.0) => {
+                log(&format!("Shutdown endpoint answered {}", status.1));
+            }
+            Ok(status) => {
+                log::warn(&format!(
+                    "Shutdown endpoint answered {}, falling back to signals",
+                    status.1
+                ));
+                return Vec::new();
+            }
+            Err(e) => {
+                log::warn(&format!(
+                    "Shutdown request failed: {e}, falling back to signals"
+                ));
+                return Vec::new();
+            }
+        }
+
+        wait_for_exit(running, wait, "the shutdown request", is_running)
+    }
+
+    /// Sends the request and returns the response status code and line,
+    /// e.g. `(202, "202 Accepted")`.
+    fn send(endpoint: &Endpoint, method: Method) -> Result<(u16, String), String> {
+        let address = (endpoint.host.as_str(), endpoint.port)
+            .to_socket_addrs()
+            .map_err(|e| format!("cannot resolve {}: {e}", endpoint.host))?
+            .next()
+            .ok_or_else(|| format!("cannot resolve {}", endpoint.host))?;
+        let mut stream =
+            TcpStream::connect_timeout(&address, TIMEOUT).map_err(|e| e.to_string())?;
+        stream
+            .set_read_timeout(Some(TIMEOUT))
+            .and_then(|()| stream.set_write_timeout(Some(TIMEOUT)))
+            .map_err(|e| e.to_string())?;
+        // In one write: an endpoint may answer and hang up as soon as it
+        // has the request line, leaving a later write a broken pipe
+        let request = format!(
+            "{} {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: gracekill\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            method.name(),
+            endpoint.path,
+            endpoint.authority
+        );
+        stream
+            .write_all(request.as_bytes())
+            .map_err(|e| e.to_string())?;
+        let mut line = String::new();
+        BufReader::new(stream)
+            .read_line(&mut line)
+            .map_err(|e| e.to_string())?;
+        // HTTP/1.1 202 Accepted
+        let status = line
+            .split_once(' ')
+            .map(|(_, status)| status.trim_end())
+            .filter(|status| !status.is_empty())
+            .ok_or_else(|| format!("not an HTTP response: '{}'", line.trim_end()))?;
+        let code = status
+            .get(..3)
+            .and_then(|code| code.parse().ok())
+            .ok_or_else(|| format!("not an HTTP status: '{status}'"))?;
+        Ok((code, status.to_string()))
+    }
+}
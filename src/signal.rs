@@ -0,0 +1,112 @@
+use std::fmt;
+use std::str::FromStr;
+
+use nix::sys::signal::Signal as NixSignal;
+
+/// A signal that can be delivered to a target process.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Signal {
+    Hup,
+    Int,
+    Quit,
+    Abrt,
+    Kill,
+    Usr1,
+    Usr2,
+    Term,
+}
+
+impl Signal {
+    /// Every signal gracekill can deliver.
+    pub const ALL: [Signal; 8] = [
+        Signal::Hup,
+        Signal::Int,
+        Signal::Quit,
+        Signal::Abrt,
+        Signal::Kill,
+        Signal::Usr1,
+        Signal::Usr2,
+        Signal::Term,
+    ];
+
+    /// The conventional `SIG`-prefixed name, e.g. `SIGTERM`.
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Signal::Hup => "SIGHUP",
+            Signal::Int => "SIGINT",
+            Signal::Quit => "SIGQUIT",
+            Signal::Abrt => "SIGABRT",
+            Signal::Kill => "SIGKILL",
+            Signal::Usr1 => "SIGUSR1",
+            Signal::Usr2 => "SIGUSR2",
+            Signal::Term => "SIGTERM",
+        }
+    }
+
+    /// The signal's number on this platform, e.g. 15 for `SIGTERM`.
+    #[must_use]
+    pub fn number(self) -> i32 {
+        self.to_nix() as i32
+    }
+
+    /// The signal with number `number`, as in `kill -15`, if gracekill can
+    /// deliver it.
+    #[must_use]
+    pub fn from_number(number: i32) -> Option<Signal> {
+        Signal::ALL
+            .into_iter()
+            .find(|signal| signal.number() == number)
+    }
+
+    /// Whether the signal's default action dumps core, which can keep a big
+    /// process around for a while after it is sent.
+    #[must_use]
+    pub fn dumps_core(self) -> bool {
+        matches!(self, Signal::Quit | Signal::Abrt)
+    }
+
+    pub(crate) fn to_nix(self) -> NixSignal {
+        match self {
+            Signal::Hup => NixSignal::SIGHUP,
+            Signal::Int => NixSignal::SIGINT,
+            Signal::Quit => NixSignal::SIGQUIT,
+            Signal::Abrt => NixSignal::SIGABRT,
+            Signal::Kill => NixSignal::SIGKILL,
+            Signal::Usr1 => NixSignal::SIGUSR1,
+            Signal::Usr2 => NixSignal::SIGUSR2,
+            Signal::Term => NixSignal::SIGTERM,
+        }
+    }
+}
+
+impl fmt::Display for Signal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Signal {
+    type Err = String;
+
+    /// Parses a signal name such as `TERM`, `SIGTERM` or `sigterm`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bare = match s.get(..3) {
+            Some(prefix) if prefix.eq_ignore_ascii_case("SIG") => &s[3..],
+            _ => s,
+        };
+        Signal::ALL
+            .into_iter()
+            .find(|signal| signal.name()[3..].eq_ignore_ascii_case(bare))
+            .ok_or_else(|| {
+                let supported: Vec<&str> = Signal::ALL
+                    .iter()
+                    .map(|signal| &signal.name()[3..])
+                    .collect();
+                format!(
+                    "Unknown signal: '{s}' (supported: {})",
+                    supported.join(", ")
+                )
+            })
+    }
+}
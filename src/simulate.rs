@@ -0,0 +1,63 @@
+//! `--simulate`: scripted stand-ins for real processes, so that gracekill
+//! and the scripts driving it can be exercised end to end without spawning
+//! anything to kill. A testing aid, not meant for production use.
+//!
+//! Every PID names a simulated process that is running until it exits. One
+//! given with `--simulate-exit-after PID=DURATION` exits that long after it
+//! is first sent a signal; any other only exits when sent SIGKILL. No real
+//! process is ever signalled.
+
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+use gracekill::{ProcessController, Signal, SignalError};
+
+/// Simulated processes with scripted exit times.
+pub struct Simulation {
+    exit_after: HashMap<u32, Duration>,
+    /// When each process was first signalled.
+    signalled: HashMap<u32, Instant>,
+    killed: HashSet<u32>,
+}
+
+impl Simulation {
+    pub fn new(exit_after: &[(u32, Duration)]) -> Self {
+        Self {
+            exit_after: exit_after.iter().copied().collect(),
+            signalled: HashMap::new(),
+            killed: HashSet::new(),
+        }
+    }
+}
+
+impl ProcessController for Simulation {
+    fn send_signal(&mut self, pid: u32, signal: Signal) -> Result<(), SignalError> {
+        if !self.is_running(pid) {
+            return Err(SignalError::NotFound);
+        }
+        self.signalled.entry(pid).or_insert_with(Instant::now);
+        if signal == Signal::Kill {
+            self.killed.insert(pid);
+        }
+        Ok(())
+    }
+
+    fn is_running(&mut self, pid: u32) -> bool {
+        if self.killed.contains(&pid) {
+            return false;
+        }
+        match (self.signalled.get(&pid), self.exit_after.get(&pid)) {
+            (Some(signalled), Some(&after)) => signalled.elapsed() < after,
+            _ => true,
+        }
+    }
+
+    fn send_thread_signal(
+        &mut self,
+        pid: u32,
+        _tid: u32,
+        signal: Signal,
+    ) -> Result<(), SignalError> {
+        self.send_signal(pid, signal)
+    }
+}
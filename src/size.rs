@@ -0,0 +1,51 @@
+/// Parses a size in bytes such as `4096`, `512K`, `2G` or `1.5GiB`. The
+/// units are powers of 1024, in either case, with an optional `B` or `iB`;
+/// a bare number is taken as bytes.
+pub fn parse_size(value: &str) -> Result<u64, String> {
+    let invalid = || format!("Invalid size: '{value}'");
+    let split = value
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let unit = unit.to_ascii_uppercase();
+    let unit = unit
+        .strip_suffix("IB")
+        .or_else(|| unit.strip_suffix('B'))
+        .unwrap_or(&unit);
+    let shift = match unit {
+        "" => 0,
+        "K" => 10,
+        "M" => 20,
+        "G" => 30,
+        "T" => 40,
+        _ => return Err(invalid()),
+    };
+    let (whole, fraction) = number.split_once('.').unwrap_or((number, ""));
+    let whole: u64 = whole.parse().map_err(|_| invalid())?;
+    let mut bytes = whole.checked_mul(1 << shift).ok_or_else(invalid)?;
+    if !fraction.is_empty() {
+        if fraction.len() > 3 || !fraction.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(invalid());
+        }
+        // Thousandths of the unit, rounded down to a byte
+        let thousandths: u64 = format!("{fraction:0<3}").parse().map_err(|_| invalid())?;
+        bytes = bytes
+            .checked_add((thousandths << shift) / 1000)
+            .ok_or_else(invalid)?;
+    }
+    Ok(bytes)
+}
+
+/// Formats `bytes` in the largest unit it makes at least one of, e.g.
+/// `1.5GiB`.
+pub fn format_size(bytes: u64) -> String {
+    let units = [(40, "TiB"), (30, "GiB"), (20, "MiB"), (10, "KiB")];
+    for (shift, unit) in units {
+        if bytes >= 1 << shift {
+            // Tenths of the unit, rounded down
+            let tenths = (u128::from(bytes) * 10) >> shift;
+            return format!("{}.{}{unit}", tenths / 10, tenths % 10);
+        }
+    }
+    format!("{bytes}B")
+}
@@ -0,0 +1,41 @@
+//! `--snapshot`: a record of what every target looked like just before the
+//! first signal, printed on stdout as one line of JSON once the kill is
+//! over:
+//!
+//! ```text
+//! {"operation_id":"3fa9c1","snapshot":[{"pid":1234,"command":"nginx -g daemon off;","uid":33,"state":"S","start_time_ms":81234,"rss_bytes":10485760}]}
+//! ```
+//!
+//! `start_time_ms` counts from boot. Targets that were gone before they
+//! could be read are left out.
+
+use std::fmt::Write as _;
+
+use gracekill::KillReport;
+use gracekill::log;
+
+use crate::{json, stdout};
+
+/// Prints the snapshots in `report`.
+pub fn print(report: &KillReport) {
+    let mut line = String::from("{");
+    if let Some(id) = log::operation_id() {
+        let _ = write!(line, "\"operation_id\":\"{id}\",");
+    }
+    line.push_str("\"snapshot\":[");
+    for (index, snapshot) in report.snapshots.iter().enumerate() {
+        let separator = if index == 0 { "" } else { "," };
+        let _ = write!(
+            line,
+            "{separator}{{\"pid\":{},\"command\":{},\"uid\":{},\"state\":\"{}\",\"start_time_ms\":{},\"rss_bytes\":{}}}",
+            snapshot.pid,
+            json::quote(&snapshot.command),
+            snapshot.uid,
+            snapshot.state,
+            snapshot.start_time.as_millis(),
+            snapshot.rss
+        );
+    }
+    line.push_str("]}");
+    stdout::line(&line);
+}
@@ -0,0 +1,121 @@
+//! `--stage`: a kill written out one step at a time, each with its own
+//! signal, wait, retries and jitter:
+//!
+//! ```text
+//! gracekill --stage 'stage{signal=INT,grace=10s,retries=2,jitter=1s}' \
+//!     --stage 'stage{signal=TERM,grace=5s}' 1234
+//! ```
+//!
+//! In each block `signal` is required and the rest are optional: `grace`
+//! falls back to `--grace-per-signal-class` and then `--grace-seconds`,
+//! `retries`, at most 100, to 0 and `jitter` to none. Keys may come in any
+//! order, but only once each, and the `stage` before the braces may be left
+//! out. One value may hold several blocks, separated by spaces or commas;
+//! blocks from every `--stage` are taken in order. The first block is the
+//! graceful signal, the others the escalation stages, and `--kill-signal`
+//! still follows the last.
+
+use std::time::Duration;
+
+use gracekill::{MAX_RETRIES, Signal};
+
+use crate::duration::parse_duration;
+
+/// One `stage{...}` block as written, without the grace period if it was
+/// left out.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Block {
+    pub signal: Signal,
+    pub grace: Option<Duration>,
+    pub retries: u32,
+    pub jitter: Duration,
+}
+
+impl From<(Signal, Option<Duration>)> for Block {
+    fn from((signal, grace): (Signal, Option<Duration>)) -> Self {
+        Block {
+            signal,
+            grace,
+            retries: 0,
+            jitter: Duration::ZERO,
+        }
+    }
+}
+
+/// Parses every block in one `--stage` value.
+pub fn parse(value: &str) -> Result<Vec<Block>, String> {
+    let invalid = |reason: String| format!("Invalid --stage: '{value}' ({reason})");
+    let mut blocks = Vec::new();
+    let mut rest = value.trim_start();
+    while !rest.is_empty() {
+        let body = rest.strip_prefix("stage").unwrap_or(rest).trim_start();
+        let Some(body) = body.strip_prefix('{') else {
+            return Err(invalid("expected stage{KEY=VALUE,...}".to_string()));
+        };
+        let Some((fields, after)) = body.split_once('}') else {
+            return Err(invalid("missing '}'".to_string()));
+        };
+        blocks.push(parse_block(fields).map_err(invalid)?);
+        rest = after.trim_start();
+        if let Some(after) = rest.strip_prefix(',') {
+            rest = after.trim_start();
+            if rest.is_empty() {
+                return Err(invalid("expected a stage after ','".to_string()));
+            }
+        }
+    }
+    if blocks.is_empty() {
+        return Err(invalid("no stages".to_string()));
+    }
+    Ok(blocks)
+}
+
+/// Parses the `KEY=VALUE,...` between a block's braces.
+fn parse_block(fields: &str) -> Result<Block, String> {
+    let mut signal = None;
+    let mut grace = None;
+    let mut retries = None;
+    let mut jitter = None;
+    for field in fields.split(',').map(str::trim) {
+        if field.is_empty() && !fields.trim().is_empty() {
+            return Err("empty field".to_string());
+        }
+        if field.is_empty() {
+            continue;
+        }
+        let (key, value) = field
+            .split_once('=')
+            .map(|(key, value)| (key.trim(), value.trim()))
+            .ok_or_else(|| format!("expected KEY=VALUE, got '{field}'"))?;
+        let duplicate = match key {
+            "signal" => signal.replace(value.parse::<Signal>()?).is_some(),
+            "grace" => grace.replace(parse_duration(value)?).is_some(),
+            "retries" => retries
+                .replace(
+                    value
+                        .parse::<u32>()
+                        .ok()
+                        .filter(|&retries| retries <= MAX_RETRIES)
+                        .ok_or_else(|| {
+                            format!("invalid retries: '{value}' (expected at most {MAX_RETRIES})")
+                        })?,
+                )
+                .is_some(),
+            "jitter" => jitter.replace(parse_duration(value)?).is_some(),
+            _ => {
+                return Err(format!(
+                    "unknown key '{key}' (supported: signal, grace, retries, jitter)"
+                ));
+            }
+        };
+        if duplicate {
+            return Err(format!("'{key}' given twice"));
+        }
+    }
+    Ok(Block {
+        signal: signal.ok_or_else(|| "every stage needs a signal".to_string())?,
+        grace,
+        retries: retries.unwrap_or(0),
+        jitter: jitter.unwrap_or(Duration::ZERO),
+    })
+}
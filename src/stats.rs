@@ -0,0 +1,155 @@
+//! `--stats`: how long the targets that exited gracefully took to, across
+//! every kill in the run, for characterizing shutdown behavior across a
+//! fleet:
+//!
+//! ```text
+//! Exit latency over 412 graceful exit(s): min 8ms, p50 120ms, p95 1.28s, max 4.1s, mean 210ms
+//! ```
+//!
+//! Each latency is timed from the start of its kill to when the target was
+//! seen gone, so to the nearest poll. Rather than kept, they are counted in
+//! buckets no wider than 1/128 of the latencies in them, so that memory
+//! stays the same however many targets there are: the percentiles are the
+//! low end of their bucket, within 1% of the exact value. The minimum,
+//! maximum and mean are exact.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use gracekill::Outcome;
+use gracekill::log;
+
+use crate::stdout;
+
+/// Significant bits kept of each latency, in microseconds.
+const PRECISION: u32 = 8;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static LATENCIES: Mutex<Latencies> = Mutex::new(Latencies::new());
+
+/// Graceful exit latencies, in microseconds.
+struct Latencies {
+    count: u64,
+    min: u64,
+    max: u64,
+    sum: u128,
+    /// Number of latencies in each bucket, by the bucket's low end.
+    buckets: BTreeMap<u64, u64>,
+}
+
+impl Latencies {
+    const fn new() -> Self {
+        Latencies {
+            count: 0,
+            min: u64::MAX,
+            max: 0,
+            sum: 0,
+            buckets: BTreeMap::new(),
+        }
+    }
+
+    fn add(&mut self, micros: u64) {
+        self.count += 1;
+        self.min = self.min.min(micros);
+        self.max = self.max.max(micros);
+        self.sum += u128::from(micros);
+        let shift = (u64::BITS - micros.leading_zeros()).saturating_sub(PRECISION);
+        *self.buckets.entry(micros >> shift << shift).or_default() += 1;
+    }
+
+    /// The latency `percent`% of the exits took at most, to within its
+    /// bucket.
+    fn percentile(&self, percent: u64) -> u64 {
+        // The rank of the percentile, rounded up: 1-based, and at least 1
+        let rank = (self.count * percent).div_ceil(100).max(1);
+        let mut seen = 0;
+        for (&low, &count) in &self.buckets {
+            seen += count;
+            if seen >= rank {
+                // Never below the exact minimum
+                return low.max(self.min);
+            }
+        }
+        self.max
+    }
+
+    fn mean(&self) -> u64 {
+        u64::try_from(self.sum / u128::from(self.count.max(1))).unwrap_or(u64::MAX)
+    }
+}
+
+/// Starts collecting latencies.
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// A [`GraceKill::on_result`](gracekill::GraceKill::on_result) callback
+/// timing each graceful exit from now, if `--stats` is on.
+pub fn collector() -> Option<impl FnMut(u32, &Outcome) + Send + 'static> {
+    let start = Instant::now();
+    ENABLED
+        .load(Ordering::Relaxed)
+        .then_some(move |_, outcome: &Outcome| {
+            if *outcome == Outcome::Graceful {
+                let micros = u64::try_from(start.elapsed().as_micros()).unwrap_or(u64::MAX);
+                LATENCIES
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .add(micros);
+            }
+        })
+}
+
+/// Logs the statistics, and with `json` also prints them to stdout as a
+/// JSON line, if `--stats` is on.
+pub fn print(json: bool) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    let latencies = LATENCIES.lock().unwrap_or_else(PoisonError::into_inner);
+    if latencies.count == 0 {
+        log::summary("Exit latency: no graceful exits to measure");
+    } else {
+        let time = |micros: u64| log::duration(Duration::from_micros(micros));
+        log::summary(&format!(
+            "Exit latency over {} graceful exit(s): min {}, p50 {}, p95 {}, max {}, mean {}",
+            latencies.count,
+            time(latencies.min),
+            time(latencies.percentile(50)),
+            time(latencies.percentile(95)),
+            time(latencies.max),
+            time(latencies.mean())
+        ));
+    }
+    if json {
+        let (min, p50, p95, max, mean) = if latencies.count == 0 {
+            (0, 0, 0, 0, 0)
+        } else {
+            (
+                latencies.min,
+                latencies.percentile(50),
+                latencies.percentile(95),
+                latencies.max,
+                latencies.mean(),
+            )
+        };
+        let mut line = String::from("{");
+        if let Some(id) = log::operation_id() {
+            let _ = write!(line, "\"operation_id\":\"{id}\",");
+        }
+        let _ = write!(
+            line,
+            "\"stats\":{{\"graceful_exits\":{},\"min_ms\":{},\"p50_ms\":{},\"p95_ms\":{},\"max_ms\":{},\"mean_ms\":{}}}}}",
+            latencies.count,
+            min / 1000,
+            p50 / 1000,
+            p95 / 1000,
+            max / 1000,
+            mean / 1000
+        );
+        stdout::line(&line);
+    }
+}
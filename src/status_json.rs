@@ -0,0 +1,70 @@
+//! `--output-pid-status-json`: a full JSON snapshot of every target on each
+//! poll, one per line, for front-ends that render a live table.
+//!
+//! ```text
+//! {"operation_id":"3fa9c1","elapsed_ms":300,"pids":[{"pid":1234,"status":"running","in_state_ms":300}]}
+//! ```
+//!
+//! `operation_id` is the ID in this run's log lines, `status` is `running`
+//! or the outcome's name, and `in_state_ms` how long the target has had that
+//! status, to the nearest poll. A target that outlived its grace period also
+//! has a `survival` saying why, on Linux: `ignored_signal`, `blocked_signal`,
+//! `uninterruptible`, `stopped` or `slow_shutdown`.
+
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::time::Duration;
+
+use gracekill::log;
+use gracekill::{KillReport, Outcome};
+
+use crate::stdout;
+
+/// The `status` of a resolved target.
+pub fn status(outcome: &Outcome) -> &'static str {
+    match outcome {
+        Outcome::SignalFailed(_) => "signal_failed",
+        Outcome::Graceful => "graceful",
+        Outcome::Killed => "killed",
+        Outcome::KillFailed(_) => "kill_failed",
+        Outcome::Survived => "survived",
+    }
+}
+
+/// A [`GraceKill::on_poll`](gracekill::GraceKill::on_poll) callback printing
+/// a snapshot of `pids` to stdout on every poll.
+pub fn printer(pids: &[u32]) -> impl FnMut(Duration, &[u32], &KillReport) + Send + 'static {
+    let pids = pids.to_vec();
+    // Each target's current status and when it took it on
+    let mut states: HashMap<u32, (&'static str, Duration)> = pids
+        .iter()
+        .map(|&pid| (pid, ("running", Duration::ZERO)))
+        .collect();
+    move |elapsed, _remaining, report| {
+        let mut line = String::from("{");
+        if let Some(id) = log::operation_id() {
+            let _ = write!(line, "\"operation_id\":\"{id}\",");
+        }
+        let _ = write!(line, "\"elapsed_ms\":{},\"pids\":[", elapsed.as_millis());
+        for (index, &pid) in pids.iter().enumerate() {
+            let current = report.outcome(pid).map_or("running", status);
+            let state = states.entry(pid).or_insert((current, Duration::ZERO));
+            if state.0 != current {
+                *state = (current, elapsed);
+            }
+            let since = state.1;
+            let separator = if index == 0 { "" } else { "," };
+            let _ = write!(
+                line,
+                "{separator}{{\"pid\":{pid},\"status\":\"{current}\",\"in_state_ms\":{}",
+                elapsed.saturating_sub(since).as_millis()
+            );
+            if let Some(survival) = report.survival(pid) {
+                let _ = write!(line, ",\"survival\":\"{survival}\"");
+            }
+            line.push('}');
+        }
+        line.push_str("]}");
+        stdout::line(&line);
+    }
+}
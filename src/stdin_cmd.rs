@@ -0,0 +1,154 @@
+//! `--stdin-cmd`: ask the targets to shut down through their standard
+//! input before any signal is sent, for REPL-style programs that exit on a
+//! command such as `quit` rather than on a signal:
+//!
+//! ```text
+//! gracekill --stdin-cmd 'quit\n' 1234
+//! ```
+//!
+//! The bytes are written to `/proc/<pid>/fd/0`, which reopens the target's
+//! own stdin. That only works in narrow conditions, all of which must hold:
+//!
+//! 1. on Linux;
+//! 2. the target's stdin is a pipe or a FIFO, not a terminal, file, socket
+//!    or `/dev/null`;
+//! 3. gracekill may read the target's file descriptors, as the same user
+//!    or root, and may write to the pipe, which for an anonymous pipe
+//!    usually means being its owner or root;
+//! 4. the pipe has room for the bytes: they are written without blocking,
+//!    and a pipe the target has stopped reading may be full.
+//!
+//! A target for which any of these fails goes straight to the signals.
+//! Only bytes can be sent, never EOF: the pipe stays open for as long as
+//! the process that feeds it holds its write end, whatever gracekill does.
+//! The targets written to are given `--stdin-wait` to exit; those that do
+//! count as exited gracefully, and those still running then are signalled
+//! as usual, with the full grace period.
+
+use std::time::Duration;
+
+use gracekill::log::{self, log};
+
+use crate::shutdown::wait_for_exit;
+
+/// How long `--stdin-cmd` waits for the targets to exit without
+/// `--stdin-wait`.
+pub const DEFAULT_WAIT: Duration = Duration::from_secs(10);
+
+/// Parses `--stdin-cmd` bytes, with the escapes `\n`, `\r`, `\t`, `\0`,
+/// `\\` and `\xHH`.
+pub fn parse_bytes(value: &str) -> Result<Vec<u8>, String> {
+    let invalid = |why: &str| format!("Invalid --stdin-cmd '{value}': {why}");
+    let mut bytes = Vec::with_capacity(value.len());
+    let mut rest = value.as_bytes();
+    while let Some((&byte, after)) = rest.split_first() {
+        rest = after;
+        if byte != b'\\' {
+            bytes.push(byte);
+            continue;
+        }
+        let Some((&escape, after)) = rest.split_first() else {
+            return Err(invalid("ends in a lone '\\'"));
+        };
+        rest = after;
+        bytes.push(match escape {
+            b'n' => b'\n',
+            b'r' => b'\r',
+            b't' => b'\t',
+            b'0' => 0,
+            b'\\' => b'\\',
+            b'x' => {
+                let hex = rest
+                    .get(..2)
+                    .and_then(|hex| std::str::from_utf8(hex).ok())
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                    .ok_or_else(|| invalid("\\x needs two hex digits"))?;
+                rest = &rest[2..];
+                hex
+            }
+            other => {
+                return Err(invalid(&format!(
+                    "unknown escape '\\{}'",
+                    char::from(other)
+                )));
+            }
+        });
+    }
+    if bytes.is_empty() {
+        return Err(invalid("nothing to write"));
+    }
+    Ok(bytes)
+}
+
+/// Writes `bytes` to the stdin of `pid`, if it is a pipe that can be.
+#[cfg(target_os = "linux")]
+fn write_stdin(pid: u32, bytes: &[u8]) -> Result<(), String> {
+    use std::fs::{self, OpenOptions};
+    use std::io::{ErrorKind, Write};
+    use std::os::unix::fs::{FileTypeExt, OpenOptionsExt};
+
+    let path = format!("/proc/{pid}/fd/0");
+    let metadata = fs::metadata(&path).map_err(|e| format!("cannot read its stdin: {e}"))?;
+    if !metadata.file_type().is_fifo() {
+        let target = fs::read_link(&path).map_or_else(
+            |_| "not a pipe".to_string(),
+            |target| target.display().to_string(),
+        );
+        return Err(format!("its stdin is {target}, not a pipe"));
+    }
+    let mut pipe = OpenOptions::new()
+        .write(true)
+        .custom_flags(libc::O_NONBLOCK)
+        .open(&path)
+        .map_err(|e| format!("cannot open its stdin: {e}"))?;
+    match pipe.write(bytes) {
+        Ok(written) if written == bytes.len() => Ok(()),
+        Ok(written) => Err(format!(
+            "its stdin only had room for {written} of {} byte(s)",
+            bytes.len()
+        )),
+        Err(e) if e.kind() == ErrorKind::WouldBlock => Err("its stdin is full".to_string()),
+        Err(e) => Err(format!("cannot write to its stdin: {e}")),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn write_stdin(_pid: u32, _bytes: &[u8]) -> Result<(), String> {
+    Err("--stdin-cmd is only supported on Linux".to_string())
+}
+
+/// Writes `bytes` to the stdin of each of `pids` and waits up to `wait`
+/// for those written to to exit, checked with `is_running`. Returns the
+/// PIDs that exited, leaving out any that were already gone beforehand,
+/// which the signals will report.
+pub fn request_shutdown(
+    bytes: &[u8],
+    pids: &[u32],
+    wait: Duration,
+    mut is_running: impl FnMut(u32) -> bool,
+) -> Vec<u32> {
+    let running: Vec<u32> = pids
+        .iter()
+        .copied()
+        .filter(|&pid| is_running(pid))
+        .filter(|&pid| match write_stdin(pid, bytes) {
+            Ok(()) => {
+                log(&format!(
+                    "Wrote {} byte(s) to the stdin of PID {pid}",
+                    bytes.len()
+                ));
+                true
+            }
+            Err(e) => {
+                log::warn(&format!(
+                    "Cannot write to the stdin of PID {pid}: {e}, falling back to signals"
+                ));
+                false
+            }
+        })
+        .collect();
+    if running.is_empty() {
+        return Vec::new();
+    }
+    wait_for_exit(running, wait, "the stdin command", is_running)
+}
@@ -0,0 +1,70 @@
+//! Lines written to stdout, whose reader may go away before gracekill is
+//! done, as in `gracekill --print-commands ... | head -1`.
+//!
+//! Once a write fails with EPIPE, nothing more is written to stdout. By
+//! default the kill carries on regardless, as a signalled target left
+//! half-handled is worse than output nobody reads; `--epipe abort` ends
+//! the run there instead, with exit code [`ABORT_CODE`]. Other write errors
+//! are dropped, as they always were.
+
+use std::io::{self, ErrorKind, Write};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use gracekill::log;
+
+/// The exit code of a run ended by `--epipe abort`, as a shell reports a
+/// command killed by SIGPIPE.
+pub const ABORT_CODE: i32 = 128 + libc::SIGPIPE;
+
+/// Whether `--epipe abort` is in effect.
+static ABORT: AtomicBool = AtomicBool::new(false);
+/// Whether stdout has been found closed.
+static CLOSED: AtomicBool = AtomicBool::new(false);
+
+/// The `--epipe` behavior.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Epipe {
+    #[default]
+    Ignore,
+    Abort,
+}
+
+impl FromStr for Epipe {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "ignore" => Ok(Epipe::Ignore),
+            "abort" => Ok(Epipe::Abort),
+            _ => Err(format!(
+                "Unknown EPIPE behavior: '{s}' (supported: ignore, abort)"
+            )),
+        }
+    }
+}
+
+/// Sets what happens once stdout is found closed.
+pub fn set(epipe: Epipe) {
+    ABORT.store(epipe == Epipe::Abort, Ordering::Relaxed);
+}
+
+/// Writes `line` and a newline to stdout, and flushes it.
+pub fn line(line: &str) {
+    if CLOSED.load(Ordering::Relaxed) {
+        return;
+    }
+    let mut stdout = io::stdout().lock();
+    let written = writeln!(stdout, "{line}").and_then(|()| stdout.flush());
+    drop(stdout);
+    if let Err(e) = written
+        && e.kind() == ErrorKind::BrokenPipe
+        && !CLOSED.swap(true, Ordering::Relaxed)
+    {
+        if ABORT.load(Ordering::Relaxed) {
+            log::error("stdout was closed, aborting the run (--epipe abort)");
+            crate::finish(ABORT_CODE);
+        }
+        log::warn("stdout was closed, carrying on without writing to it");
+    }
+}
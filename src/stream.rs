@@ -0,0 +1,151 @@
+//! `--stream`: kill PIDs read from stdin in chunks, for inputs too large to
+//! hold with everything gracekill tracks about each target.
+//!
+//! PIDs are separated by whitespace or commas. Once `--chunk-size` of them
+//! have been read, the chunk goes through the usual kill while the next one
+//! is read and signalled, so that waiting out one chunk's grace period
+//! overlaps with the next. At most two chunks are under way at once, each
+//! waited for before a third is started, and only the totals of a finished
+//! chunk are kept: memory stays the same however many PIDs there are.
+//!
+//! A token that isn't a PID is skipped with a warning under
+//! `--skip-invalid`; otherwise reading stops there, the chunks already
+//! signalled are seen through, and the stream fails.
+
+use std::io::{self, BufRead};
+use std::thread::{self, ScopedJoinHandle};
+
+use gracekill::log::{self, log};
+use gracekill::{KillReport, Tally};
+
+use crate::args::parse_and_validate_pid;
+
+/// How many PIDs a chunk holds by default.
+pub const DEFAULT_CHUNK_SIZE: usize = 1000;
+
+/// The longest token kept whole for an error message; no PID is near it.
+const MAX_TOKEN: usize = 32;
+
+/// The outcome of a stream.
+pub struct Streamed {
+    pub tally: Tally,
+    /// PIDs read and handed to the kill.
+    pub pids: usize,
+    pub chunks: usize,
+    /// Why the stream stopped before the end of its input, if it did.
+    pub error: Option<String>,
+}
+
+/// Reads the next whitespace- or comma-separated token from `input`,
+/// truncated to [`MAX_TOKEN`] bytes, or `None` at the end of it.
+fn next_token(input: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut token = Vec::new();
+    let mut length = 0;
+    loop {
+        let buffer = input.fill_buf()?;
+        if buffer.is_empty() {
+            break;
+        }
+        let mut used = 0;
+        let mut ended = false;
+        for &byte in buffer {
+            used += 1;
+            if byte.is_ascii_whitespace() || byte == b',' {
+                if length > 0 {
+                    ended = true;
+                    break;
+                }
+                continue;
+            }
+            length += 1;
+            if token.len() < MAX_TOKEN {
+                token.push(byte);
+            }
+        }
+        input.consume(used);
+        if ended {
+            break;
+        }
+    }
+    if length == 0 {
+        return Ok(None);
+    }
+    let mut token = String::from_utf8_lossy(&token).into_owned();
+    if length > MAX_TOKEN {
+        token.push_str("...");
+    }
+    Ok(Some(token))
+}
+
+/// Reads the next chunk of up to `size` PIDs from `input`, empty at the end
+/// of it.
+fn next_chunk(
+    input: &mut impl BufRead,
+    size: usize,
+    skip_invalid: bool,
+) -> Result<Vec<u32>, String> {
+    let mut chunk = Vec::with_capacity(size);
+    while chunk.len() < size {
+        let token = next_token(input).map_err(|e| format!("Cannot read PIDs from stdin: {e}"))?;
+        let Some(token) = token else {
+            break;
+        };
+        match parse_and_validate_pid(&token) {
+            Ok(pid) => chunk.push(pid),
+            Err(e) if skip_invalid => log::warn(&format!("{e} in --stream input, skipping it")),
+            Err(e) => return Err(format!("{e} in --stream input")),
+        }
+    }
+    Ok(chunk)
+}
+
+/// Kills the PIDs in `input` with `kill`, `size` at a time.
+pub fn run(
+    mut input: impl BufRead,
+    size: usize,
+    skip_invalid: bool,
+    kill: impl Fn(&[u32]) -> KillReport + Sync,
+) -> Streamed {
+    let mut streamed = Streamed {
+        tally: Tally::default(),
+        pids: 0,
+        chunks: 0,
+        error: None,
+    };
+    thread::scope(|scope| {
+        let kill = &kill;
+        let mut in_flight: Option<ScopedJoinHandle<KillReport>> = None;
+        loop {
+            let chunk = match next_chunk(&mut input, size, skip_invalid) {
+                Ok(chunk) if chunk.is_empty() => break,
+                Ok(chunk) => chunk,
+                Err(e) => {
+                    streamed.error = Some(e);
+                    break;
+                }
+            };
+            streamed.chunks += 1;
+            streamed.pids += chunk.len();
+            log(&format!(
+                "Chunk {}: signalling {} process(es)",
+                streamed.chunks,
+                chunk.len()
+            ));
+            let next = scope.spawn(move || kill(&chunk));
+            // The previous chunk's wait overlaps this one's signalling
+            if let Some(previous) = in_flight.replace(next) {
+                streamed.tally.add(&join(previous));
+            }
+        }
+        if let Some(last) = in_flight {
+            streamed.tally.add(&join(last));
+        }
+    });
+    streamed
+}
+
+fn join(chunk: ScopedJoinHandle<KillReport>) -> KillReport {
+    chunk
+        .join()
+        .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+}
@@ -0,0 +1,140 @@
+//! `--tail`: follow the targets' own log files during the kill, so their
+//! shutdown messages show up between gracekill's progress lines.
+//!
+//! Each file is followed from its end at the time the kill starts, checked
+//! every [`POLL_INTERVAL`]. Log rotation is followed along: a file that
+//! shrinks was truncated and is read again from the start, and a file that
+//! was moved away or deleted is read to its end before the new file at the
+//! same path is picked up.
+
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::os::unix::fs::MetadataExt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use gracekill::log::{self, log};
+
+/// How often the files are checked for new lines.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The files being followed, until [`Tail::stop`].
+pub struct Tail {
+    stop: Arc<AtomicBool>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl Tail {
+    /// Starts following each of `paths` from its current end, on a thread
+    /// of its own.
+    pub fn start(paths: &[String]) -> Tail {
+        let stop = Arc::new(AtomicBool::new(false));
+        let threads = paths
+            .iter()
+            .map(|path| {
+                let followed = match Followed::open(path, true) {
+                    Ok(followed) => Some(followed),
+                    Err(e) => {
+                        log::warn(&format!("Cannot tail '{path}' yet, waiting for it: {e}"));
+                        None
+                    }
+                };
+                let path = path.clone();
+                let stop = Arc::clone(&stop);
+                thread::spawn(move || follow(&path, followed, &stop))
+            })
+            .collect();
+        Tail { stop, threads }
+    }
+
+    /// Logs whatever was written since the last check and stops following.
+    pub fn stop(self) {
+        self.stop.store(true, Ordering::Relaxed);
+        for thread in self.threads {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// The followed file: the open handle, where reading got to, and the
+/// identity of the file, to notice when it is replaced.
+struct Followed {
+    file: File,
+    position: u64,
+    inode: (u64, u64),
+    /// Text after the last complete line.
+    partial: String,
+}
+
+impl Followed {
+    fn open(path: &str, from_end: bool) -> io::Result<Followed> {
+        let mut file = File::open(path)?;
+        let metadata = file.metadata()?;
+        let position = if from_end { metadata.len() } else { 0 };
+        file.seek(SeekFrom::Start(position))?;
+        Ok(Followed {
+            file,
+            position,
+            inode: (metadata.dev(), metadata.ino()),
+            partial: String::new(),
+        })
+    }
+
+    /// Logs the complete lines written since the last read, and with `all`
+    /// any unfinished one too.
+    fn read(&mut self, path: &str, all: bool) -> io::Result<()> {
+        let length = self.file.metadata()?.len();
+        if length < self.position {
+            log(&format!("{path}: truncated, reading from the start"));
+            self.file.seek(SeekFrom::Start(0))?;
+            self.position = 0;
+            self.partial.clear();
+        }
+        let mut bytes = Vec::new();
+        self.position += self.file.read_to_end(&mut bytes)? as u64;
+        self.partial.push_str(&String::from_utf8_lossy(&bytes));
+        while let Some(end) = self.partial.find('\n') {
+            let line: String = self.partial.drain(..=end).collect();
+            log(&format!("{path}: {}", line.trim_end_matches(['\n', '\r'])));
+        }
+        if all && !self.partial.is_empty() {
+            log(&format!("{path}: {}", self.partial));
+            self.partial.clear();
+        }
+        Ok(())
+    }
+
+    /// Whether `path` now names a different file from the one being read.
+    fn replaced(&self, path: &str) -> bool {
+        fs::metadata(path).is_ok_and(|metadata| (metadata.dev(), metadata.ino()) != self.inode)
+    }
+}
+
+/// Logs the lines appended to `path` until `stop` is set, starting from
+/// `followed` if the file could be opened.
+fn follow(path: &str, mut followed: Option<Followed>, stop: &AtomicBool) {
+    loop {
+        let stopping = stop.load(Ordering::Relaxed);
+        match &mut followed {
+            Some(current) => {
+                if let Err(e) = current.read(path, stopping) {
+                    log::warn(&format!("Failed to read '{path}': {e}"));
+                }
+                if current.replaced(path) {
+                    // Finish the old file first, it may have gained lines since
+                    let _ = current.read(path, true);
+                    log(&format!("{path}: replaced, following the new file"));
+                    followed = Followed::open(path, false).ok();
+                    continue;
+                }
+            }
+            None => followed = Followed::open(path, false).ok(),
+        }
+        if stopping {
+            return;
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
@@ -0,0 +1,30 @@
+//! `--then-exec`: once the kill is over, replace gracekill with another
+//! command, for restarting a service in place:
+//!
+//! ```text
+//! gracekill --then-exec '/usr/sbin/server --port 8080' 1234
+//! ```
+//!
+//! The command is split on whitespace and looked up on `PATH` as by
+//! `execvp(3)`. It takes over gracekill's PID, stdio and environment, and
+//! only once every target is gone; with a survivor or any other exit code
+//! but 0, gracekill exits with that code instead, without running it.
+
+use std::io;
+use std::os::unix::process::CommandExt;
+use std::process::Command;
+
+/// Checks that `command` names something to run.
+pub fn check(command: &str) -> Result<(), String> {
+    if command.split_whitespace().next().is_none() {
+        return Err("--then-exec needs a command to run".to_string());
+    }
+    Ok(())
+}
+
+/// Replaces gracekill with `command`, returning only if that fails.
+pub fn exec(command: &str) -> io::Error {
+    let mut words = command.split_whitespace();
+    let program = words.next().unwrap_or_default();
+    Command::new(program).args(words).exec()
+}
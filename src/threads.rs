@@ -0,0 +1,61 @@
+//! `--list-threads`: show each thread of a wedged process, to find the one
+//! that is stuck before signalling it with `--tid`:
+//!
+//! ```text
+//! TID      STATE                     CPU  NAME             WAITING IN
+//! 4120     S (sleeping)            120ms  server           do_epoll_wait
+//! 4127     D (disk sleep)           3.4s  writer-2         io_schedule
+//! ```
+//!
+//! CPU is the thread's user and system time so far, and WAITING IN the
+//! kernel function it is blocked in, where the kernel tells.
+
+use std::time::Duration;
+
+use gracekill::log::{self, log};
+
+use crate::stdout;
+
+/// What a one-letter state from `/proc/<pid>/task/<tid>/stat` means.
+fn describe(state: char) -> &'static str {
+    match state {
+        'R' => "running",
+        'S' => "sleeping",
+        'D' => "disk sleep",
+        'T' => "stopped",
+        't' => "tracing stop",
+        'Z' => "zombie",
+        'X' | 'x' => "dead",
+        'I' => "idle",
+        'P' => "parked",
+        'W' => "waking",
+        _ => "unknown",
+    }
+}
+
+/// Prints a line for each thread of `pid` to stdout.
+pub fn list(pid: u32) -> Result<(), String> {
+    let threads = gracekill::procfs::threads(pid)
+        .map_err(|e| format!("Cannot list the threads of PID {pid}: {e}"))?;
+    let ticks = gracekill::procfs::ticks_per_second().max(1);
+    stdout::line(&format!(
+        "{:<8} {:<20} {:>8}  {:<16} WAITING IN",
+        "TID", "STATE", "CPU", "NAME"
+    ));
+    for thread in &threads {
+        let cpu = Duration::from_millis((thread.utime + thread.stime) * 1000 / ticks);
+        let state = format!("{} ({})", thread.state, describe(thread.state));
+        let wchan = gracekill::procfs::wchan(pid, thread.pid).unwrap_or_else(|| "-".to_string());
+        stdout::line(&format!(
+            "{:<8} {state:<20} {:>8}  {:<16} {wchan}",
+            thread.pid,
+            log::duration(cpu),
+            thread.comm
+        ));
+    }
+    log(&format!(
+        "PID {pid} has {} thread(s); --tid signals just one of them",
+        threads.len()
+    ));
+    Ok(())
+}
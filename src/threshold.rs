@@ -0,0 +1,109 @@
+//! `--if-rss-above`, `--if-cpu-above` and `--if-age-above`: only signal
+//! targets that have grown too big, used too much CPU time or run too
+//! long, for cleaning up runaway jobs:
+//!
+//! ```text
+//! gracekill --if-rss-above 2G --name worker
+//! ```
+//!
+//! Each target is read from `/proc` once its targets are resolved, before
+//! any is signalled. A target must exceed every threshold given; one that
+//! doesn't, or can't be read, is skipped with a log line.
+
+use std::time::Duration;
+
+use gracekill::log::{self, log};
+
+use crate::size::format_size;
+
+/// The thresholds a target must exceed to be signalled.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Thresholds {
+    /// Resident set size in bytes.
+    pub rss: Option<u64>,
+    /// User and system CPU time.
+    pub cpu: Option<Duration>,
+    /// Time since the process started.
+    pub age: Option<Duration>,
+}
+
+impl Thresholds {
+    pub fn is_empty(&self) -> bool {
+        *self == Thresholds::default()
+    }
+}
+
+/// What `pid` measures against `thresholds`: `Ok` with every value above
+/// its threshold, `Err` with the first that isn't or couldn't be read.
+#[cfg(target_os = "linux")]
+fn measure(thresholds: &Thresholds, pid: u32) -> Result<Vec<String>, String> {
+    let mut above = Vec::new();
+    if let Some(limit) = thresholds.rss {
+        let rss = gracekill::procfs::rss(pid).map_err(|e| format!("cannot read its RSS: {e}"))?;
+        if rss <= limit {
+            return Err(format!(
+                "RSS {} is not above {}",
+                format_size(rss),
+                format_size(limit)
+            ));
+        }
+        above.push(format!("RSS {}", format_size(rss)));
+    }
+    if thresholds.cpu.is_none() && thresholds.age.is_none() {
+        return Ok(above);
+    }
+    let stat = gracekill::procfs::stat(pid).map_err(|e| format!("cannot read its stat: {e}"))?;
+    let ticks = gracekill::procfs::ticks_per_second();
+    if let Some(limit) = thresholds.cpu {
+        let cpu = Duration::from_millis((stat.utime + stat.stime) * 1000 / ticks);
+        if cpu <= limit {
+            return Err(format!(
+                "CPU time {} is not above {}",
+                log::duration(cpu),
+                log::duration(limit)
+            ));
+        }
+        above.push(format!("CPU time {}", log::duration(cpu)));
+    }
+    if let Some(limit) = thresholds.age {
+        let uptime =
+            gracekill::procfs::uptime().map_err(|e| format!("cannot read the uptime: {e}"))?;
+        let age = uptime.saturating_sub(Duration::from_millis(stat.starttime * 1000 / ticks));
+        if age <= limit {
+            return Err(format!(
+                "age {} is not above {}",
+                log::duration(age),
+                log::duration(limit)
+            ));
+        }
+        above.push(format!("age {}", log::duration(age)));
+    }
+    Ok(above)
+}
+
+/// Keeps only the `pids` exceeding every one of `thresholds`, logging why
+/// each of the others is skipped.
+#[cfg(target_os = "linux")]
+pub fn filter(thresholds: &Thresholds, pids: &mut Vec<u32>) {
+    let before = pids.len();
+    pids.retain(|&pid| match measure(thresholds, pid) {
+        Ok(above) => {
+            log::debug(&format!(
+                "PID {pid} is over the threshold: {}",
+                above.join(", ")
+            ));
+            true
+        }
+        Err(reason) => {
+            log(&format!("Skipping PID {pid}: {reason}"));
+            false
+        }
+    });
+    log(&format!(
+        "{} of {before} target(s) over the threshold",
+        pids.len()
+    ));
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn filter(_thresholds: &Thresholds, _pids: &mut Vec<u32>) {}
@@ -0,0 +1,129 @@
+//! `--trace-file`: a chronological record of everything a run did, for
+//! reviewing an incident afterwards.
+//!
+//! The trace is plain text, one event per line. The first line is a header
+//! giving the wall-clock start of the trace and the ID in the run's log lines; every event after it starts with
+//! the milliseconds since then, followed by the event name and `key=value`
+//! fields:
+//!
+//! ```text
+//! # gracekill trace v1 started_unix_ms=1767225600000 operation_id=3fa9c1
+//! 0 run pids=1234,5678
+//! 0 signal pid=1234 signal=SIGTERM result=ok
+//! 0 signal pid=5678 signal=SIGTERM result=ok
+//! 100 poll running=1234,5678
+//! 200 outcome pid=5678 outcome=graceful
+//! 200 poll running=1234
+//! 25000 escalate pid=1234 from=SIGTERM to=SIGKILL
+//! 25000 signal pid=1234 signal=SIGKILL result=ok
+//! 25000 outcome pid=1234 outcome=killed
+//! 25000 survival pid=1234 reason=ignored_signal
+//! 25000 poll running=
+//! ```
+//!
+//! `result` is `ok` or the reason delivery failed (`not_found`,
+//! `permission_denied`, `pid_too_large`, `queue_full` or `error`), and
+//! `outcome` and a `survival`'s `reason` are named as in
+//! `--output-pid-status-json`. The last `poll` of a
+//! run lists nothing as running.
+//!
+//! Events are buffered and only written out by [`finish`], so tracing costs
+//! no I/O while targets are being waited for.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::sync::Mutex;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use gracekill::SignalError;
+use gracekill::log;
+
+/// The trace being written, if `--trace-file` was given.
+static TRACE: Mutex<Option<Trace>> = Mutex::new(None);
+
+struct Trace {
+    start: Instant,
+    out: BufWriter<File>,
+}
+
+/// Starts tracing to a new file at `path`, replacing any existing one.
+///
+/// # Errors
+///
+/// Returns a message if the file can't be created.
+pub fn open(path: &str) -> Result<(), String> {
+    let file = File::create(path).map_err(|e| format!("Cannot create trace file '{path}': {e}"))?;
+    let started = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let mut out = BufWriter::new(file);
+    let _ = write!(
+        out,
+        "# gracekill trace v1 started_unix_ms={}",
+        started.as_millis()
+    );
+    if let Some(id) = log::operation_id() {
+        let _ = write!(out, " operation_id={id}");
+    }
+    let _ = writeln!(out);
+    let trace = Trace {
+        start: Instant::now(),
+        out,
+    };
+    *TRACE
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner) = Some(trace);
+    Ok(())
+}
+
+/// Whether a trace is being written.
+pub fn enabled() -> bool {
+    TRACE
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+        .is_some()
+}
+
+/// Adds an event with its `key=value` fields to the trace, if there is one.
+pub fn event(name: &str, fields: &str) {
+    let mut trace = TRACE
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner);
+    if let Some(trace) = trace.as_mut() {
+        let elapsed = trace.start.elapsed().as_millis();
+        let _ = writeln!(trace.out, "{elapsed} {name} {fields}");
+    }
+}
+
+/// Writes out the buffered trace and stops tracing.
+pub fn finish() {
+    let trace = TRACE
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+        .take();
+    if let Some(mut trace) = trace
+        && let Err(e) = trace.out.flush()
+    {
+        log::error(&format!("Failed to write trace file: {e}"));
+    }
+}
+
+/// PIDs as a trace field value: `1234,5678`.
+pub fn pid_list(pids: &[u32]) -> String {
+    pids.iter()
+        .map(u32::to_string)
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// The `result` field for a signal delivery.
+pub fn result_name(result: &Result<(), SignalError>) -> &'static str {
+    match result {
+        Ok(()) => "ok",
+        Err(SignalError::NotFound) => "not_found",
+        Err(SignalError::PermissionDenied) => "permission_denied",
+        Err(SignalError::PidTooLarge) => "pid_too_large",
+        Err(SignalError::QueueFull) => "queue_full",
+        Err(SignalError::Other(_)) => "error",
+    }
+}
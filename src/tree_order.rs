@@ -0,0 +1,130 @@
+//! `--tree-order`: which of a parent and its child among the targets is
+//! signalled first, when the targets hold a process tree (e.g. from
+//! `--container` or `--freeze`).
+//!
+//! The default is children first. A supervisor signalled first may kill
+//! its children itself, far less gently, or exit and leave them orphaned
+//! before their own signal arrives; signalled first, the children shut
+//! down as they would on their own, and the parent sees them go as it
+//! would in normal operation.
+
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+
+use gracekill::log;
+
+/// How far up the tree to look for a target's ancestors, against a loop
+/// in PPIDs read while processes come and go.
+const MAX_DEPTH: usize = 4096;
+
+/// The order parents and their descendants are signalled in.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum TreeOrder {
+    /// Every descendant before any of its ancestors.
+    #[default]
+    ChildrenFirst,
+    /// Every ancestor before any of its descendants.
+    ParentsFirst,
+    /// The order the targets were given in, without regard to the tree.
+    Simultaneous,
+}
+
+impl FromStr for TreeOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "children-first" => Ok(TreeOrder::ChildrenFirst),
+            "parents-first" => Ok(TreeOrder::ParentsFirst),
+            "simultaneous" => Ok(TreeOrder::Simultaneous),
+            _ => Err(format!(
+                "Unknown tree order: '{s}' (supported: children-first, parents-first, simultaneous)"
+            )),
+        }
+    }
+}
+
+impl TreeOrder {
+    pub fn name(self) -> &'static str {
+        match self {
+            TreeOrder::ChildrenFirst => "children-first",
+            TreeOrder::ParentsFirst => "parents-first",
+            TreeOrder::Simultaneous => "simultaneous",
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn ppid(pid: u32) -> Option<u32> {
+    gracekill::procfs::stat(pid).ok().map(|stat| stat.ppid)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn ppid(_pid: u32) -> Option<u32> {
+    None
+}
+
+/// How many of `targets` are ancestors of `pid`, reading PPIDs through
+/// `parents`. Adds those ancestors to `ancestors`.
+fn depth(
+    pid: u32,
+    targets: &HashSet<u32>,
+    parents: &mut HashMap<u32, Option<u32>>,
+    ancestors: &mut HashSet<u32>,
+) -> usize {
+    let mut depth = 0;
+    let mut current = pid;
+    for _ in 0..MAX_DEPTH {
+        let parent = *parents.entry(current).or_insert_with(|| ppid(current));
+        match parent {
+            Some(parent) if parent > 1 && parent != pid => {
+                if targets.contains(&parent) {
+                    depth += 1;
+                    ancestors.insert(parent);
+                }
+                current = parent;
+            }
+            _ => break,
+        }
+    }
+    depth
+}
+
+/// Puts `pids` in `order`, keeping the order they were given in between
+/// targets neither of which is an ancestor of the other. Returns the
+/// targets now signalled after an ancestor or descendant of theirs.
+pub fn ordered(pids: &mut [u32], order: TreeOrder) -> Vec<u32> {
+    if order == TreeOrder::Simultaneous || pids.len() < 2 {
+        return Vec::new();
+    }
+    let targets: HashSet<u32> = pids.iter().copied().collect();
+    let mut parents = HashMap::new();
+    let mut ancestors = HashSet::new();
+    let depths: HashMap<u32, usize> = pids
+        .iter()
+        .map(|&pid| (pid, depth(pid, &targets, &mut parents, &mut ancestors)))
+        .collect();
+    if ancestors.is_empty() {
+        return Vec::new();
+    }
+    let followers = if order == TreeOrder::ChildrenFirst {
+        pids.sort_by_key(|pid| std::cmp::Reverse(depths[pid]));
+        ancestors.into_iter().collect()
+    } else {
+        pids.sort_by_key(|pid| depths[pid]);
+        depths
+            .into_iter()
+            .filter(|&(_, depth)| depth > 0)
+            .map(|(pid, _)| pid)
+            .collect()
+    };
+    log::debug(&format!(
+        "Signalling the targets {}: {}",
+        order.name().replace('-', " "),
+        pids.iter()
+            .map(u32::to_string)
+            .collect::<Vec<_>>()
+            .join(", ")
+    ));
+    followers
+}
@@ -0,0 +1,56 @@
+//! `--reset-tty`: put the terminal back into a usable state after the kill,
+//! for when a full-screen program killed before it could clean up has left
+//! it in raw mode, on the alternate screen or with the cursor hidden.
+//!
+//! The terminal is the one stderr or stdout is connected to; if neither is
+//! a terminal, nothing is done. Its line settings are turned back to the
+//! cooked ones a shell expects, and the escape sequences undoing what such
+//! programs typically switch on are written to it. A full reset, which
+//! would also clear the screen and with it the log, is left to `reset(1)`.
+
+use std::io::{self, IsTerminal, Write};
+use std::os::fd::{AsFd, BorrowedFd};
+
+use gracekill::log;
+use nix::sys::termios::{self, InputFlags, LocalFlags, OutputFlags, SetArg};
+
+/// Leaves the alternate screen, shows the cursor, resets colors and turns
+/// off mouse reporting and bracketed paste.
+const RESET_SEQUENCE: &str =
+    "\x1b[?1049l\x1b[?25h\x1b[0m\x1b[?1000l\x1b[?1002l\x1b[?1003l\x1b[?1006l\x1b[?2004l";
+
+/// Resets the terminal on stderr or stdout, if either is one.
+pub fn reset() {
+    let stderr = io::stderr();
+    let stdout = io::stdout();
+    let (fd, mut terminal): (BorrowedFd, Box<dyn Write>) = if stderr.is_terminal() {
+        (stderr.as_fd(), Box::new(stderr.lock()))
+    } else if stdout.is_terminal() {
+        (stdout.as_fd(), Box::new(stdout.lock()))
+    } else {
+        log::debug("Neither stderr nor stdout is a terminal, not resetting it");
+        return;
+    };
+
+    match termios::tcgetattr(fd) {
+        Ok(mut settings) => {
+            settings.input_flags |= InputFlags::BRKINT | InputFlags::ICRNL | InputFlags::IXON;
+            settings.input_flags &= !(InputFlags::INLCR | InputFlags::IGNCR);
+            settings.output_flags |= OutputFlags::OPOST | OutputFlags::ONLCR;
+            settings.local_flags |= LocalFlags::ICANON
+                | LocalFlags::ECHO
+                | LocalFlags::ECHOE
+                | LocalFlags::ECHOK
+                | LocalFlags::ISIG
+                | LocalFlags::IEXTEN;
+            if let Err(e) = termios::tcsetattr(fd, SetArg::TCSANOW, &settings) {
+                log::warn(&format!("Failed to reset the terminal settings: {e}"));
+            }
+        }
+        Err(e) => log::warn(&format!("Failed to read the terminal settings: {e}")),
+    }
+    let _ = terminal
+        .write_all(RESET_SEQUENCE.as_bytes())
+        .and_then(|()| terminal.flush());
+    log::debug("Reset the terminal");
+}
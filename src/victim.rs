@@ -0,0 +1,250 @@
+//! `--as-victim`: instead of killing anything, be a process that reacts to
+//! signals in a set way, for trying gracekill out, and its features, on
+//! something that behaves the same on every run:
+//!
+//! ```text
+//! gracekill --as-victim --ignore TERM --fork 2
+//! ready 4120 4121 4122
+//! ```
+//!
+//! Once set up, the victim prints `ready` and its PID, followed by those of
+//! the children it forked, on one line of stdout, then waits to be
+//! signalled. It runs until a signal it doesn't handle ends it, an
+//! `--exit-on` signal arrives or `--exit-after` has passed. None of it needs
+//! root.
+
+use std::io::Write;
+use std::process;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use gracekill::Signal;
+use nix::sys::signal::{self as nix_signal, SigHandler, Signal as NixSignal};
+use nix::unistd::{ForkResult, fork};
+
+use crate::duration::parse_duration;
+
+/// How often the victim checks whether it is time to exit.
+const TICK: Duration = Duration::from_millis(10);
+
+/// The last signal caught by [`caught`], or 0 for none yet.
+static CAUGHT: AtomicI32 = AtomicI32::new(0);
+
+extern "C" fn caught(signal: i32) {
+    CAUGHT.store(signal, Ordering::SeqCst);
+}
+
+/// How the victim behaves, from its arguments.
+#[derive(Default)]
+struct Victim {
+    /// Signals set to `SIG_IGN`.
+    ignore: Vec<Signal>,
+    /// Signals caught and otherwise ignored.
+    catch: Vec<Signal>,
+    /// Signals caught, each followed by exiting after its delay.
+    exit_on: Vec<(Signal, Duration)>,
+    exit_after: Option<Duration>,
+    fork: usize,
+    stop: bool,
+    uninterruptible: Option<Duration>,
+}
+
+/// The usage text for the victim's options.
+pub fn print_usage() {
+    eprintln!("Usage: gracekill --as-victim [options]");
+    eprintln!("  --ignore <SIGNAL>                      Ignore SIGNAL (SIG_IGN); may be repeated");
+    eprintln!(
+        "  --catch <SIGNAL>                       Handle SIGNAL and carry on running; may be repeated"
+    );
+    eprintln!(
+        "  --exit-on <SIGNAL[=DELAY]>             Handle SIGNAL by exiting DELAY later (default: at once)"
+    );
+    eprintln!("  --exit-after <DURATION>                Exit on its own after DURATION");
+    eprintln!(
+        "  --fork <N>                             Fork N children that behave the same, once set up"
+    );
+    eprintln!("  --stop                                 Stop itself (state T) once ready");
+    eprintln!(
+        "  --uninterruptible <DURATION>           Spend DURATION in uninterruptible sleep (state D) once ready (Linux)"
+    );
+}
+
+fn parse(args: &[String]) -> Result<Victim, String> {
+    let mut victim = Victim::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .cloned()
+                .ok_or_else(|| format!("Option '{arg}' needs a value"))
+        };
+        match arg.as_str() {
+            "--ignore" => victim.ignore.push(value()?.parse()?),
+            "--catch" => victim.catch.push(value()?.parse()?),
+            "--exit-on" => {
+                let value = value()?;
+                let (signal, delay) = match value.split_once('=') {
+                    Some((signal, delay)) => (signal, parse_duration(delay)?),
+                    None => (value.as_str(), Duration::ZERO),
+                };
+                victim.exit_on.push((signal.parse()?, delay));
+            }
+            "--exit-after" => victim.exit_after = Some(parse_duration(&value()?)?),
+            "--fork" => {
+                let value = value()?;
+                victim.fork = value
+                    .parse()
+                    .map_err(|_| format!("Invalid number of children: '{value}'"))?;
+            }
+            "--stop" => victim.stop = true,
+            "--uninterruptible" => victim.uninterruptible = Some(parse_duration(&value()?)?),
+            _ => return Err(format!("Unknown --as-victim option: '{arg}'")),
+        }
+    }
+    if victim.stop && victim.uninterruptible.is_some() {
+        return Err("--stop cannot be combined with --uninterruptible".to_string());
+    }
+    Ok(victim)
+}
+
+fn to_nix(signal: Signal) -> Result<NixSignal, String> {
+    NixSignal::try_from(signal.number()).map_err(|e| format!("Cannot handle {signal}: {e}"))
+}
+
+/// Sets up the signal dispositions of `victim`.
+fn set_dispositions(victim: &Victim) -> Result<(), String> {
+    let handled = victim
+        .catch
+        .iter()
+        .chain(victim.exit_on.iter().map(|(signal, _)| signal));
+    let dispositions = victim
+        .ignore
+        .iter()
+        .map(|&signal| (signal, SigHandler::SigIgn))
+        .chain(handled.map(|&signal| (signal, SigHandler::Handler(caught))));
+    for (signal, handler) in dispositions {
+        // SAFETY: the handler only stores to an atomic
+        unsafe { nix_signal::signal(to_nix(signal)?, handler) }
+            .map_err(|e| format!("Cannot set the disposition of {signal}: {e}"))?;
+    }
+    Ok(())
+}
+
+/// Forks `count` children that carry on as the victim does, returning
+/// their PIDs in the parent and an empty list in each child.
+fn fork_children(count: usize) -> Result<Vec<u32>, String> {
+    let mut children = Vec::with_capacity(count);
+    for _ in 0..count {
+        // SAFETY: the victim has started no other threads
+        match unsafe { fork() } {
+            Ok(ForkResult::Child) => return Ok(Vec::new()),
+            Ok(ForkResult::Parent { child }) => children.push(child.as_raw().cast_unsigned()),
+            Err(e) => return Err(format!("Cannot fork a child: {e}")),
+        }
+    }
+    Ok(children)
+}
+
+/// Blocks in uninterruptible sleep for `duration`: the parent of a
+/// `CLONE_VFORK` child waits for it in state `D`, which a caught signal
+/// doesn't interrupt and only a fatal one does. The child, on its own copy
+/// of memory, sleeps, and dies with the parent.
+#[cfg(target_os = "linux")]
+fn sleep_uninterruptibly(duration: Duration) {
+    extern "C" fn sleep_child(time: *mut libc::c_void) -> libc::c_int {
+        // SAFETY: `time` points at the timespec in the child's copy of the
+        // parent's stack, and the child exits without returning
+        unsafe {
+            libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGKILL);
+            libc::nanosleep(time.cast(), std::ptr::null_mut());
+            libc::_exit(0)
+        }
+    }
+
+    let mut time = libc::timespec {
+        tv_sec: libc::time_t::try_from(duration.as_secs()).unwrap_or(libc::time_t::MAX),
+        tv_nsec: libc::c_long::from(duration.subsec_nanos().cast_signed()),
+    };
+    let mut stack = vec![0u8; 64 * 1024];
+    // SAFETY: the stack is the child's for as long as it runs, with its top
+    // aligned as the ABI requires
+    let result = unsafe {
+        let top = stack.as_mut_ptr().add(stack.len() & !15);
+        libc::clone(
+            sleep_child,
+            top.cast(),
+            libc::CLONE_VFORK | libc::SIGCHLD,
+            (&raw mut time).cast(),
+        )
+    };
+    if result < 0 {
+        eprintln!(
+            "Error: Cannot enter uninterruptible sleep: {}",
+            std::io::Error::last_os_error()
+        );
+        process::exit(1);
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn sleep_uninterruptibly(_duration: Duration) {
+    eprintln!("--uninterruptible is only supported on Linux");
+    process::exit(1);
+}
+
+/// Runs as the victim described by `args`, never returning.
+pub fn run(args: &[String]) -> ! {
+    let victim = match parse(args) {
+        Ok(victim) => victim,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            print_usage();
+            process::exit(1);
+        }
+    };
+    let start = Instant::now();
+    if let Err(e) = set_dispositions(&victim) {
+        eprintln!("Error: {e}");
+        process::exit(1);
+    }
+    let children = fork_children(victim.fork).unwrap_or_else(|e| {
+        eprintln!("Error: {e}");
+        process::exit(1);
+    });
+    // Only the victim itself reports, stops or sleeps
+    let parent = children.len() == victim.fork;
+    if parent {
+        let pids: Vec<String> = std::iter::once(process::id())
+            .chain(children)
+            .map(|pid| pid.to_string())
+            .collect();
+        let mut stdout = std::io::stdout();
+        let _ = writeln!(stdout, "ready {}", pids.join(" "));
+        let _ = stdout.flush();
+        if victim.stop {
+            let _ = nix_signal::raise(NixSignal::SIGSTOP);
+        }
+        if let Some(duration) = victim.uninterruptible {
+            sleep_uninterruptibly(duration);
+        }
+    }
+    loop {
+        let signal = CAUGHT.load(Ordering::SeqCst);
+        if let Some(&(_, delay)) = victim
+            .exit_on
+            .iter()
+            .find(|(exit_on, _)| exit_on.number() == signal)
+        {
+            thread::sleep(delay);
+            process::exit(0);
+        }
+        if victim
+            .exit_after
+            .is_some_and(|after| start.elapsed() >= after)
+        {
+            process::exit(0);
+        }
+        thread::sleep(TICK);
+    }
+}
@@ -0,0 +1,87 @@
+//! `--watch`: keep processes with a `--name` from running, for cleanup jobs
+//! that have to outlast whatever keeps starting them.
+//!
+//! Every scan interval the targets are looked up again, and any found run
+//! through the usual kill. SIGINT or SIGTERM ends the watch: a kill under
+//! way is seen through first, then the watch reports every kill it made.
+//! A target the kill couldn't get rid of is left alone from then on, so
+//! that a process gracekill may not signal isn't retried every scan.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use gracekill::log::{self, log};
+use gracekill::{KillReport, Outcome};
+use nix::sys::signal::{SaFlags, SigAction, SigHandler, SigSet, Signal as NixSignal, sigaction};
+
+/// How often the targets are looked up by default.
+pub const DEFAULT_SCAN_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The longest the watch sleeps before checking whether it was stopped.
+pub const STOP_CHECK: Duration = Duration::from_millis(100);
+
+/// Whether SIGINT or SIGTERM has been received.
+static STOPPED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn stop(_: libc::c_int) {
+    STOPPED.store(true, Ordering::Relaxed);
+}
+
+/// Makes SIGINT and SIGTERM stop a mode that runs until either arrives,
+/// `option`, rather than gracekill itself.
+pub fn catch_stop_signals(option: &str) -> Result<(), String> {
+    let action = SigAction::new(SigHandler::Handler(stop), SaFlags::empty(), SigSet::empty());
+    for signal in [NixSignal::SIGINT, NixSignal::SIGTERM] {
+        // SAFETY: the handler only stores to an atomic
+        unsafe { sigaction(signal, &action) }
+            .map_err(|e| format!("Cannot handle {signal} for {option}: {e}"))?;
+    }
+    Ok(())
+}
+
+/// Whether SIGINT or SIGTERM has arrived since
+/// [`catch_stop_signals`].
+pub fn stopped() -> bool {
+    STOPPED.load(Ordering::Relaxed)
+}
+
+/// Looks up the targets with `scan` every `interval` and kills any found
+/// with `kill`, until SIGINT or SIGTERM. Returns the report of each kill.
+pub fn run(
+    interval: Duration,
+    mut scan: impl FnMut() -> Vec<u32>,
+    mut kill: impl FnMut(&[u32]) -> KillReport,
+) -> Result<Vec<KillReport>, String> {
+    catch_stop_signals("--watch")?;
+
+    let mut reports = Vec::new();
+    let mut left_alone: Vec<u32> = Vec::new();
+    while !stopped() {
+        let found: Vec<u32> = scan()
+            .into_iter()
+            .filter(|pid| !left_alone.contains(pid))
+            .collect();
+        if !found.is_empty() {
+            log(&format!("Found {} process(es) to kill", found.len()));
+            let report = kill(&found);
+            left_alone.extend(
+                report
+                    .outcomes
+                    .iter()
+                    .filter(|(_, outcome)| !matches!(outcome, Outcome::Graceful | Outcome::Killed))
+                    .map(|&(pid, _)| pid)
+                    .chain(report.lingering.iter().copied()),
+            );
+            reports.push(report);
+        }
+        let mut slept = Duration::ZERO;
+        while slept < interval && !stopped() {
+            let nap = STOP_CHECK.min(interval.saturating_sub(slept));
+            thread::sleep(nap);
+            slept += nap;
+        }
+    }
+    log::debug("Watch stopped by a signal");
+    Ok(reports)
+}
@@ -0,0 +1,225 @@
+//! Throws random argument vectors at the command line, checking that every
+//! one is either accepted or refused with an error, never a panic.
+//!
+//! Each case starts with `--show-config`, so that gracekill exits once the
+//! arguments are parsed and checked, before anything is signalled. The
+//! options are taken from the usage text, so new ones are covered as they
+//! are added. Set `GRACEKILL_FUZZ_CASES` for a longer run, and
+//! `GRACEKILL_FUZZ_SEED` to repeat one. Inputs found to panic past the
+//! checks, where the fuzzing doesn't reach, are kept as regression cases.
+
+use std::process::{Command, Stdio};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Values that have tripped up parsers, or sit at the edges of a type.
+const VALUES: &[&str] = &[
+    "",
+    "-",
+    "--",
+    "=",
+    "0",
+    "1",
+    "-1",
+    "100",
+    "4294967295",
+    "4294967296",
+    "18446744073709551615",
+    "99999999999999999999",
+    "1.5",
+    "0x10",
+    "1ms",
+    "1h",
+    "18446744073709551615h",
+    "ms",
+    "s",
+    "TERM",
+    "SIG",
+    "sigkill",
+    "TERM:",
+    "TERM:1s,KILL",
+    ":",
+    ",",
+    "1,,2",
+    "1/INT",
+    "1/",
+    "/INT",
+    "100=1s",
+    "100=",
+    "=1s",
+    "TERM=5s,QUIT=1s",
+    "stage{signal=INT,grace=10s,retries=2,jitter=1s}",
+    "{signal=TERM} {signal=QUIT,retries=4294967296}",
+    "stage{signal=INT,retries=4294967295}",
+    "stage{signal=",
+    "stage{}",
+    "23:59",
+    "TERM:5124095576030431h,INT:5124095576030431h,KILL",
+    "TERM:87600000h,INT:87600000h,KILL",
+    "24:00",
+    "9999999999999999-01-01T00:00:00Z",
+    "2026-02-30T00:00:00Z",
+    "2026-03-01T02:00:00+99:99",
+    "2026-03-01T02:00:00.5-01:00",
+    "@",
+    "@/nonexistent",
+    "@/dev/null",
+    "é",
+    "\u{1F480}",
+    "-TERM",
+    "-9",
+    "-s",
+    "--x=y",
+    "---",
+    "-g=1",
+    "abc",
+];
+
+/// A xorshift generator, enough to pick arguments from a seed.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        usize::try_from(self.next() % n as u64).unwrap()
+    }
+}
+
+fn env_number(name: &str) -> Option<u64> {
+    std::env::var(name).ok()?.parse().ok()
+}
+
+/// Every long and short option named in the usage text.
+fn options() -> Vec<String> {
+    let usage = Command::new(env!("CARGO_BIN_EXE_gracekill"))
+        .output()
+        .expect("failed to run gracekill");
+    let mut options: Vec<String> = String::from_utf8_lossy(&usage.stderr)
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|word| word.starts_with('-') && word.len() > 1 && !word.starts_with("---"))
+        .map(str::to_string)
+        // Going on past --show-config would signal the targets
+        .filter(|option| option != "--proceed")
+        .collect();
+    options.sort();
+    options.dedup();
+    assert!(options.len() > 50, "the usage text lists the options");
+    options
+}
+
+#[test]
+fn cli_never_panics_on_any_arguments() {
+    let options = options();
+    let cases = env_number("GRACEKILL_FUZZ_CASES").unwrap_or(300);
+    let seed = env_number("GRACEKILL_FUZZ_SEED").unwrap_or_else(|| {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(1, |since| since.as_secs())
+    });
+    let mut rng = Rng(seed | 1);
+    // Anything written to a file by an option goes here
+    let dir = std::env::temp_dir().join(format!("gracekill-fuzz-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    for case in 0..cases {
+        let mut args = vec!["--show-config".to_string()];
+        for _ in 0..=rng.below(8) {
+            let arg = match rng.below(4) {
+                0 | 1 => options[rng.below(options.len())].clone(),
+                _ => VALUES[rng.below(VALUES.len())].to_string(),
+            };
+            // Also as --option=value
+            let arg = if arg.starts_with("--") && rng.below(4) == 0 {
+                format!("{arg}={}", VALUES[rng.below(VALUES.len())])
+            } else {
+                arg
+            };
+            let option = arg.starts_with('-');
+            if arg != "--proceed" && !arg.starts_with("--proceed=") {
+                args.push(arg);
+            }
+            // Most options take a value
+            if option && rng.below(2) == 0 {
+                args.push(VALUES[rng.below(VALUES.len())].to_string());
+            }
+        }
+        let output = Command::new(env!("CARGO_BIN_EXE_gracekill"))
+            .args(&args)
+            .current_dir(&dir)
+            .stdin(Stdio::null())
+            .output()
+            .expect("failed to run gracekill");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(
+            output.status.code().is_some_and(|code| code != 101) && !stderr.contains("panicked"),
+            "case {case} of seed {seed} panicked on {args:?}:\n{stderr}"
+        );
+    }
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn cli_never_panics_on_waits_too_long_to_add_up() {
+    // Each with the error it ends in, if any
+    let too_long = "TERM:5124095576030431h,INT:5124095576030431h,KILL";
+    let longest = "TERM:87600000h,INT:87600000h,KILL";
+    let cases: [(&[&str], Option<&str>); 4] = [
+        (
+            &["--by", "23:59", "--escalate", too_long],
+            Some("at most ten thousand years"),
+        ),
+        (
+            &["--dry-run", "--simulate-timing", "--escalate", too_long],
+            Some("at most ten thousand years"),
+        ),
+        // The longest waits accepted, which add up without overflowing
+        (
+            &[
+                "--by",
+                "23:59",
+                "--if-over-budget",
+                "clamp",
+                "--escalate",
+                longest,
+            ],
+            Some("more than the"),
+        ),
+        (
+            &["--dry-run", "--simulate-timing", "--escalate", longest],
+            None,
+        ),
+    ];
+    let mut target = Command::new("sleep")
+        .arg("30")
+        .spawn()
+        .expect("failed to spawn sleep");
+    let outputs: Vec<_> = cases
+        .iter()
+        .map(|(args, _)| {
+            Command::new(env!("CARGO_BIN_EXE_gracekill"))
+                .args(*args)
+                .arg(target.id().to_string())
+                .stdin(Stdio::null())
+                .output()
+                .expect("failed to run gracekill")
+        })
+        .collect();
+    let _ = target.kill();
+    let _ = target.wait();
+
+    for ((args, error), output) in cases.iter().zip(outputs) {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        match error {
+            Some(error) => {
+                assert_eq!(output.status.code(), Some(1), "{args:?}:\n{stderr}");
+                assert!(stderr.contains(error), "{args:?}:\n{stderr}");
+            }
+            None => assert_eq!(output.status.code(), Some(0), "{args:?}:\n{stderr}"),
+        }
+    }
+}
@@ -0,0 +1,598 @@
+//! Runs graceful kills against a scripted controller, for behavior real
+//! processes can't easily be made to show.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use gracekill::{
+    CancelToken, GraceKill, KILL_BURST_PAUSE, Outcome, PollSchedule, ProcessController, Signal,
+    SignalError, Stage,
+};
+
+/// Processes that ignore every signal but SIGKILL, which fails for the PIDs
+/// in `unkillable` and is accepted but has no effect on those in `stuck`.
+#[derive(Default)]
+struct Mock {
+    alive: HashSet<u32>,
+    unkillable: HashSet<u32>,
+    stuck: HashSet<u32>,
+    sent: Vec<(u32, Signal)>,
+    /// When each SIGKILL was accepted.
+    killed_at: Vec<Instant>,
+}
+
+impl Mock {
+    fn new(alive: &[u32], unkillable: &[u32]) -> Self {
+        Self {
+            alive: alive.iter().copied().collect(),
+            unkillable: unkillable.iter().copied().collect(),
+            stuck: HashSet::new(),
+            sent: Vec::new(),
+            killed_at: Vec::new(),
+        }
+    }
+}
+
+impl ProcessController for Mock {
+    fn send_signal(&mut self, pid: u32, signal: Signal) -> Result<(), SignalError> {
+        if !self.alive.contains(&pid) {
+            return Err(SignalError::NotFound);
+        }
+        self.sent.push((pid, signal));
+        if signal == Signal::Kill {
+            if self.unkillable.contains(&pid) {
+                return Err(SignalError::PermissionDenied);
+            }
+            if !self.stuck.contains(&pid) {
+                self.alive.remove(&pid);
+            }
+            self.killed_at.push(Instant::now());
+        }
+        Ok(())
+    }
+
+    fn is_running(&mut self, pid: u32) -> bool {
+        self.alive.contains(&pid)
+    }
+}
+
+fn gracekill(mock: &mut Mock) -> GraceKill<&mut Mock> {
+    GraceKill::with_controller(mock)
+        .grace(Duration::from_millis(20))
+        .poll_interval(Duration::from_millis(5))
+}
+
+#[test]
+fn kill_failure_continues_by_default() {
+    let mut mock = Mock::new(&[1, 2, 3], &[2]);
+
+    let report = gracekill(&mut mock).run(&[1, 2, 3]);
+
+    assert_eq!(
+        report.outcomes,
+        vec![
+            (1, Outcome::Killed),
+            (2, Outcome::KillFailed(SignalError::PermissionDenied)),
+            (3, Outcome::Killed),
+        ]
+    );
+    assert!(!report.aborted);
+}
+
+#[test]
+fn kill_failure_aborts_the_kill_stage() {
+    let mut mock = Mock::new(&[1, 2, 3], &[2]);
+
+    let report = gracekill(&mut mock)
+        .abort_on_kill_failure(true)
+        .run(&[1, 2, 3]);
+
+    assert_eq!(
+        report.outcomes,
+        vec![
+            (1, Outcome::Killed),
+            (2, Outcome::KillFailed(SignalError::PermissionDenied)),
+            (3, Outcome::Survived),
+        ]
+    );
+    assert!(report.aborted);
+    assert!(mock.alive.contains(&3));
+}
+
+#[test]
+fn per_pid_signal_overrides_the_graceful_signal() {
+    let mut mock = Mock::new(&[1, 2], &[]);
+
+    gracekill(&mut mock).signal_for(1, Signal::Int).run(&[1, 2]);
+
+    assert_eq!(
+        mock.sent,
+        vec![
+            (1, Signal::Int),
+            (2, Signal::Term),
+            (1, Signal::Kill),
+            (2, Signal::Kill),
+        ]
+    );
+}
+
+#[test]
+fn targets_gone_before_their_signal_are_reported_as_vanished() {
+    let mut mock = Mock::new(&[1, 3], &[]);
+
+    let report = gracekill(&mut mock).run(&[1, 2, 3, 4]);
+    assert_eq!(report.vanished, vec![2, 4]);
+    assert_eq!(
+        report.outcome(2),
+        Some(&Outcome::SignalFailed(SignalError::NotFound))
+    );
+
+    let mut mock = Mock::new(&[1], &[]);
+    let report = gracekill(&mut mock).ignore_missing(true).run(&[1, 2]);
+    assert_eq!(report.vanished, vec![2]);
+    assert_eq!(report.outcome(2), Some(&Outcome::Graceful));
+}
+
+/// Processes that each exit on one particular signal, or on SIGKILL.
+struct ExitsOn {
+    alive: HashMap<u32, Signal>,
+}
+
+impl ProcessController for ExitsOn {
+    fn send_signal(&mut self, pid: u32, signal: Signal) -> Result<(), SignalError> {
+        match self.alive.get(&pid) {
+            None => Err(SignalError::NotFound),
+            Some(&exits_on) => {
+                if signal == exits_on || signal == Signal::Kill {
+                    self.alive.remove(&pid);
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn is_running(&mut self, pid: u32) -> bool {
+        self.alive.contains_key(&pid)
+    }
+}
+
+#[test]
+fn run_reports_how_each_target_went_and_the_totals() {
+    let mut controller = ExitsOn {
+        alive: HashMap::from([(1, Signal::Term), (2, Signal::Int)]),
+    };
+
+    let report = GraceKill::with_controller(&mut controller)
+        .grace(Duration::from_millis(20))
+        .poll_interval(Duration::from_millis(5))
+        .run(&[1, 2, 3]);
+
+    assert_eq!(
+        report.outcomes,
+        vec![
+            (1, Outcome::Graceful),
+            (2, Outcome::Killed),
+            (3, Outcome::SignalFailed(SignalError::NotFound)),
+        ]
+    );
+    assert_eq!(report.exit_signal(1), Some(Signal::Term));
+    assert_eq!(report.exit_signal(2), None);
+    assert_eq!(report.vanished, vec![3]);
+    assert!(report.lingering.is_empty());
+    assert!(!report.aborted && !report.cancelled);
+    assert!(report.elapsed >= Duration::from_millis(20));
+    assert!(!report.all_graceful());
+    assert_eq!(report.signalled(), 2);
+    assert_eq!(
+        (report.graceful(), report.killed(), report.failed()),
+        (1, 1, 1)
+    );
+    assert!(controller.alive.is_empty());
+}
+
+#[test]
+fn exits_are_attributed_to_the_escalation_stage_they_happened_in() {
+    let mut controller = ExitsOn {
+        alive: HashMap::from([
+            (1, Signal::Term),
+            (2, Signal::Int),
+            (3, Signal::Int),
+            (4, Signal::Kill),
+        ]),
+    };
+
+    let report = GraceKill::with_controller(&mut controller)
+        .grace(Duration::from_millis(20))
+        .poll_interval(Duration::from_millis(5))
+        .escalate([
+            (Signal::Int, Duration::from_millis(20)),
+            (Signal::Quit, Duration::from_millis(20)),
+        ])
+        .run(&[1, 2, 3, 4]);
+
+    assert_eq!(report.exit_signal(1), Some(Signal::Term));
+    assert_eq!(report.exit_signal(2), Some(Signal::Int));
+    assert_eq!(report.exit_signal(3), Some(Signal::Int));
+    assert_eq!(report.exit_signal(4), None);
+    assert_eq!(report.outcome(4), Some(&Outcome::Killed));
+    assert_eq!(
+        report.stages,
+        vec![(Signal::Term, 1), (Signal::Int, 2), (Signal::Quit, 0)]
+    );
+    assert_eq!(
+        report.summary(),
+        "3 exited gracefully (1 on SIGTERM, 2 on SIGINT, 0 on SIGQUIT), 1 killed, 0 failed"
+    );
+}
+
+#[test]
+fn double_tap_repeats_the_graceful_signal_before_the_kill() {
+    let mut mock = Mock::new(&[1], &[]);
+
+    gracekill(&mut mock).double_tap(true).run(&[1]);
+
+    assert_eq!(
+        mock.sent,
+        vec![(1, Signal::Term), (1, Signal::Term), (1, Signal::Kill)]
+    );
+}
+
+#[test]
+fn retries_are_capped_however_many_are_asked_for() {
+    let mut mock = Mock::new(&[1], &[]);
+
+    gracekill(&mut mock)
+        .grace(Duration::from_millis(20))
+        .retries(u32::MAX)
+        .escalate([Stage {
+            retries: u32::MAX,
+            ..Stage::new(Signal::Quit, Duration::from_millis(20))
+        }])
+        .run(&[1]);
+
+    let again = |signal| mock.sent.iter().filter(|&&(_, s)| s == signal).count();
+    assert!(again(Signal::Term) <= 1 + gracekill::MAX_RETRIES as usize);
+    assert!(again(Signal::Quit) <= 1 + gracekill::MAX_RETRIES as usize);
+    assert_eq!(mock.sent.last(), Some(&(1, Signal::Kill)));
+}
+
+#[test]
+fn every_stage_sends_its_signal_again_and_waits_its_jitter() {
+    let mut mock = Mock::new(&[1], &[]);
+    let start = Instant::now();
+
+    gracekill(&mut mock)
+        .stage(Stage {
+            retries: 2,
+            ..Stage::new(Signal::Int, Duration::from_millis(30))
+        })
+        .escalate([Stage {
+            retries: 1,
+            jitter: Duration::from_millis(20),
+            ..Stage::new(Signal::Quit, Duration::from_millis(30))
+        }])
+        .run(&[1]);
+
+    assert_eq!(
+        mock.sent,
+        vec![
+            (1, Signal::Int),
+            (1, Signal::Int),
+            (1, Signal::Int),
+            (1, Signal::Quit),
+            (1, Signal::Quit),
+            (1, Signal::Kill),
+        ]
+    );
+    let elapsed = start.elapsed();
+    assert!(elapsed >= Duration::from_millis(60), "{elapsed:?}");
+}
+
+#[test]
+fn before_kill_is_called_with_the_survivors() {
+    let mut mock = Mock::new(&[1, 2], &[]);
+    let calls = Arc::new(Mutex::new(Vec::new()));
+    let recorded = Arc::clone(&calls);
+
+    gracekill(&mut mock)
+        .before_kill(move |pids| recorded.lock().unwrap().push(pids.to_vec()))
+        .run(&[1, 2, 3]);
+
+    assert_eq!(*calls.lock().unwrap(), vec![vec![1, 2]]);
+}
+
+#[test]
+fn process_surviving_the_kill_is_reported_as_lingering() {
+    let mut mock = Mock {
+        stuck: HashSet::from([2]),
+        ..Mock::new(&[1, 2], &[])
+    };
+
+    let report = gracekill(&mut mock).run(&[1, 2]);
+
+    assert_eq!(report.outcome(2), Some(&Outcome::Killed));
+    assert_eq!(report.lingering, vec![2]);
+    assert_eq!(
+        report.summary(),
+        "0 exited gracefully, 2 killed, 0 failed, 1 still present after kill"
+    );
+}
+
+const LINGERING_CHILD: &str = "GRACEKILL_TEST_LINGERING_CHILD";
+
+/// Runs a kill that leaves a process behind, when run as the child of the
+/// test below; otherwise does nothing.
+#[test]
+fn lingering_child() {
+    if std::env::var_os(LINGERING_CHILD).is_none() {
+        return;
+    }
+    let mut mock = Mock {
+        stuck: HashSet::from([2, 3]),
+        ..Mock::new(&[1, 2, 3], &[])
+    };
+    gracekill(&mut mock).run(&[1, 2, 3]);
+}
+
+#[test]
+fn process_surviving_the_kill_is_warned_about() {
+    if std::env::var_os(LINGERING_CHILD).is_some() {
+        return;
+    }
+    let child = std::process::Command::new(std::env::current_exe().expect("no test binary"))
+        .args([
+            "--exact",
+            "lingering_child",
+            "--nocapture",
+            "--test-threads=1",
+        ])
+        .env(LINGERING_CHILD, "1")
+        .env_remove("GRACEKILL_LOG")
+        .output()
+        .expect("failed to rerun the test binary");
+    let stderr = String::from_utf8_lossy(&child.stderr);
+    assert!(child.status.success(), "{stderr}");
+
+    let warnings: Vec<&str> = stderr
+        .lines()
+        .filter(|line| line.contains("still present"))
+        .collect();
+    assert_eq!(
+        warnings,
+        [
+            "[gracekill] WARNING: PID 2 still present after SIGKILL",
+            "[gracekill] WARNING: PID 3 still present after SIGKILL",
+        ],
+        "{stderr}"
+    );
+}
+
+#[test]
+fn kill_concurrency_pauses_between_bursts() {
+    let pids = [1, 2, 3, 4, 5];
+    let mut mock = Mock::new(&pids, &[]);
+
+    let report = gracekill(&mut mock).kill_concurrency(2).run(&pids);
+
+    assert_eq!(report.killed(), 5);
+    // Bursts of 2, 2 and 1, with a pause before each but the first
+    let gaps: Vec<Duration> = mock
+        .killed_at
+        .windows(2)
+        .map(|pair| pair[1] - pair[0])
+        .collect();
+    for (index, gap) in gaps.iter().enumerate() {
+        if index % 2 == 1 {
+            assert!(*gap >= KILL_BURST_PAUSE, "no pause after kill {index}");
+        } else {
+            assert!(
+                *gap < KILL_BURST_PAUSE,
+                "pause within a burst at kill {index}"
+            );
+        }
+    }
+}
+
+#[test]
+fn per_wave_grace_kills_each_target_at_the_end_of_its_own_grace() {
+    let mut mock = Mock::new(&[1, 2], &[]);
+    let (sender, incoming) = std::sync::mpsc::channel();
+    let late = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(60));
+        sender.send(2).unwrap();
+    });
+
+    let report = gracekill(&mut mock)
+        .grace(Duration::from_millis(100))
+        .add_targets_from(incoming)
+        .per_wave_grace(true)
+        .run(&[1]);
+    late.join().unwrap();
+
+    assert_eq!(report.killed(), 2);
+    // Without it, both would be killed when the first one's grace ran out
+    let apart = mock.killed_at[1] - mock.killed_at[0];
+    assert!(apart >= Duration::from_millis(40), "killed {apart:?} apart");
+}
+
+#[test]
+fn grace_overrun_is_reported() {
+    let mut mock = Mock::new(&[1], &[]);
+
+    // The first poll comes round well after the grace period is over
+    let report = gracekill(&mut mock)
+        .poll_interval(Duration::from_millis(60))
+        .max_grace_overrun(Duration::from_millis(10))
+        .run(&[1]);
+
+    assert_eq!(report.killed(), 1);
+    assert!(
+        report.grace_overrun >= Duration::from_millis(40),
+        "{:?}",
+        report.grace_overrun
+    );
+}
+
+#[test]
+fn curved_polling_lands_on_the_deadline() {
+    let mut mock = Mock::new(&[1], &[]);
+
+    // As above, but the polls close in on the end of the grace period
+    // rather than overshoot it
+    let report = gracekill(&mut mock)
+        .poll_interval(Duration::from_millis(60))
+        .poll_schedule(PollSchedule::Curve)
+        .run(&[1]);
+
+    assert_eq!(report.killed(), 1);
+    assert!(
+        report.grace_overrun < Duration::from_millis(30),
+        "{:?}",
+        report.grace_overrun
+    );
+}
+
+#[test]
+fn quorum_cuts_the_grace_period_short_for_the_stragglers() {
+    let mut mock = Mock::new(&[4], &[]);
+
+    // Three of the four are already gone, which is the quorum
+    let report = gracekill(&mut mock)
+        .grace(Duration::from_secs(30))
+        .ignore_missing(true)
+        .quorum(75, Duration::from_millis(20))
+        .run(&[1, 2, 3, 4]);
+
+    assert_eq!(report.graceful(), 3);
+    assert_eq!(report.outcome(4), Some(&Outcome::Killed));
+    assert!(report.quorum_reached.is_some());
+    assert!(
+        report.elapsed < Duration::from_secs(1),
+        "{:?}",
+        report.elapsed
+    );
+
+    // Short of it, the straggler gets the whole grace period
+    let mut mock = Mock::new(&[3, 4], &[]);
+    let report = gracekill(&mut mock)
+        .ignore_missing(true)
+        .quorum(75, Duration::ZERO)
+        .run(&[1, 2, 3, 4]);
+
+    assert_eq!(report.killed(), 2);
+    assert_eq!(report.quorum_reached, None);
+    assert!(report.elapsed >= Duration::from_millis(20));
+}
+
+#[test]
+fn cancelling_mid_wait_stops_before_the_kill() {
+    let mut mock = Mock::new(&[1, 2], &[]);
+    let token = CancelToken::new();
+    let canceller = {
+        let token = token.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            token.cancel();
+        })
+    };
+
+    let report = gracekill(&mut mock)
+        .grace(Duration::from_secs(10))
+        .escalate([(Signal::Int, Duration::from_secs(10))])
+        .cancel_token(token)
+        .run(&[1, 2]);
+    canceller.join().unwrap();
+
+    assert!(report.cancelled);
+    assert!(
+        report.elapsed < Duration::from_secs(5),
+        "{:?}",
+        report.elapsed
+    );
+    assert_eq!(
+        report.outcomes,
+        vec![(1, Outcome::Survived), (2, Outcome::Survived)]
+    );
+    assert_eq!(mock.sent, vec![(1, Signal::Term), (2, Signal::Term)]);
+}
+
+/// Processes that exit on the graceful signal, which the kernel can't queue
+/// the first `full_for` times it is sent to each of them.
+struct QueueFull {
+    alive: HashSet<u32>,
+    full_for: usize,
+    attempts: Vec<(u32, Signal)>,
+}
+
+impl QueueFull {
+    fn new(alive: &[u32], full_for: usize) -> Self {
+        Self {
+            alive: alive.iter().copied().collect(),
+            full_for,
+            attempts: Vec::new(),
+        }
+    }
+}
+
+impl ProcessController for QueueFull {
+    fn send_signal(&mut self, pid: u32, signal: Signal) -> Result<(), SignalError> {
+        if !self.alive.contains(&pid) {
+            return Err(SignalError::NotFound);
+        }
+        self.attempts.push((pid, signal));
+        let sent = self.attempts.iter().filter(|&&(p, _)| p == pid).count();
+        if sent <= self.full_for {
+            return Err(SignalError::QueueFull);
+        }
+        self.alive.remove(&pid);
+        Ok(())
+    }
+
+    fn is_running(&mut self, pid: u32) -> bool {
+        self.alive.contains(&pid)
+    }
+}
+
+#[test]
+fn signals_the_kernel_cannot_queue_are_retried() {
+    let mut mock = QueueFull::new(&[1, 2], 2);
+
+    let report = GraceKill::with_controller(&mut mock)
+        .grace(Duration::from_millis(20))
+        .poll_interval(Duration::from_millis(5))
+        .run(&[1, 2]);
+
+    assert_eq!(
+        report.outcomes,
+        vec![(1, Outcome::Graceful), (2, Outcome::Graceful)]
+    );
+    assert_eq!(
+        mock.attempts,
+        vec![
+            (1, Signal::Term),
+            (1, Signal::Term),
+            (1, Signal::Term),
+            (2, Signal::Term),
+            (2, Signal::Term),
+            (2, Signal::Term),
+        ]
+    );
+}
+
+#[test]
+fn signals_the_kernel_never_queues_are_reported_as_failed() {
+    let mut mock = QueueFull::new(&[1], usize::MAX);
+
+    let report = GraceKill::with_controller(&mut mock)
+        .grace(Duration::from_millis(20))
+        .poll_interval(Duration::from_millis(5))
+        .run(&[1]);
+
+    assert_eq!(
+        report.outcomes,
+        vec![(1, Outcome::SignalFailed(SignalError::QueueFull))]
+    );
+    // The first attempt and three retries
+    assert_eq!(mock.attempts, vec![(1, Signal::Term); 4]);
+}
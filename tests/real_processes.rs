@@ -0,0 +1,3211 @@
+//! Runs graceful kills against real child processes.
+
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+use gracekill::{
+    ExitStatus, GraceKill, MissingHandler, Outcome, ProcessController, Signal, SignalError,
+    Survival, System,
+};
+
+const GRACE: Duration = Duration::from_millis(500);
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Spawns `sh -c script` and returns its PID once the script has set itself
+/// up, signalled by printing a line. The child is reaped by the kill itself,
+/// through `GraceKill::exit_status`, or by the test with `reap`.
+#[allow(clippy::zombie_processes)] // reaped by the kill, or by the test
+fn spawn(script: &str) -> u32 {
+    let mut child = Command::new("sh")
+        .args(["-c", script])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("failed to spawn sh");
+    let mut ready = String::new();
+    BufReader::new(child.stdout.take().expect("stdout is piped"))
+        .read_line(&mut ready)
+        .expect("failed to read from child");
+    child.id()
+}
+
+/// Waits for a child that said it was ready just before an `exec` to have
+/// made it, going by its command name.
+#[cfg(target_os = "linux")]
+fn wait_for_exec(pid: u32, name: &str) {
+    while std::fs::read_to_string(format!("/proc/{pid}/comm"))
+        .is_ok_and(|comm| comm.trim_end() != name)
+    {
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Waits for a child killed by something other than a `GraceKill` here.
+fn reap(pid: u32) {
+    let pid = nix::unistd::Pid::from_raw(i32::try_from(pid).unwrap());
+    nix::sys::wait::waitpid(pid, None).expect("failed to reap child");
+}
+
+fn gracekill() -> GraceKill {
+    GraceKill::new()
+        .grace(GRACE)
+        .poll_interval(POLL_INTERVAL)
+        // Reaps the children, which would otherwise linger as zombies that
+        // still look alive to kill(pid, 0)
+        .exit_status(true)
+}
+
+#[test]
+fn cooperative_process_exits_within_grace() {
+    let pid = spawn("echo ready; exec sleep 30");
+
+    let report = gracekill().run(&[pid]);
+
+    assert_eq!(report.outcome(pid), Some(&Outcome::Graceful));
+    assert_eq!(report.exit_status(pid), Some(ExitStatus::Signaled(15)));
+    assert!(report.elapsed < GRACE);
+    assert!(report.all_graceful());
+}
+
+#[test]
+fn uncooperative_process_is_killed_after_grace() {
+    // An ignored disposition survives exec, so sleep ignores SIGTERM too
+    let pid = spawn("trap '' TERM; echo ready; exec sleep 30");
+
+    let report = gracekill().run(&[pid]);
+
+    assert_eq!(report.outcome(pid), Some(&Outcome::Killed));
+    assert_eq!(report.exit_status(pid), Some(ExitStatus::Signaled(9)));
+    assert!(report.elapsed >= GRACE);
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn survivor_ignoring_the_signal_is_classified_before_the_kill() {
+    let ignoring = spawn("trap '' TERM; echo ready; exec sleep 30");
+    let slow = spawn("trap 'exec sleep 30' TERM; echo ready; while :; do sleep 0.01; done");
+
+    let report = gracekill().run(&[ignoring, slow]);
+
+    assert_eq!(report.outcome(ignoring), Some(&Outcome::Killed));
+    assert_eq!(report.survival(ignoring), Some(Survival::IgnoredSignal));
+    assert_eq!(report.survival(slow), Some(Survival::SlowShutdown));
+}
+
+/// Starts `gracekill --as-victim` with `args` and returns its PID once it
+/// has said it is ready and, for `--stop` or `--uninterruptible`, is in
+/// `state`.
+#[cfg(target_os = "linux")]
+#[allow(clippy::zombie_processes)] // reaped by the kill
+fn spawn_victim(args: &[&str], state: char) -> u32 {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_gracekill"))
+        .arg("--as-victim")
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("failed to spawn the victim");
+    let mut ready = String::new();
+    BufReader::new(child.stdout.take().expect("stdout is piped"))
+        .read_line(&mut ready)
+        .expect("failed to read from the victim");
+    assert_eq!(ready, format!("ready {}\n", child.id()));
+    while gracekill::procfs::stat(child.id()).is_ok_and(|stat| stat.state != state) {
+        std::thread::sleep(POLL_INTERVAL);
+    }
+    child.id()
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn victims_outlive_their_signal_the_way_they_were_told_to() {
+    let uninterruptible = spawn_victim(&["--catch", "TERM", "--uninterruptible", "30s"], 'D');
+    let stopped = spawn_victim(&["--catch", "TERM", "--stop"], 'T');
+    let exiting = spawn_victim(&["--exit-on", "TERM=50ms"], 'S');
+
+    let report = gracekill().run(&[uninterruptible, stopped, exiting]);
+
+    assert_eq!(report.outcome(uninterruptible), Some(&Outcome::Killed));
+    assert_eq!(
+        report.survival(uninterruptible),
+        Some(Survival::Uninterruptible)
+    );
+    assert_eq!(report.outcome(stopped), Some(&Outcome::Killed));
+    assert_eq!(report.survival(stopped), Some(Survival::Stopped));
+    assert_eq!(report.outcome(exiting), Some(&Outcome::Graceful));
+    assert_eq!(report.exit_status(exiting), Some(ExitStatus::Code(0)));
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn process_without_handler_is_killed_at_once_with_require_handler() {
+    let pid = spawn("trap '' TERM; echo ready; exec sleep 30");
+
+    let report = gracekill()
+        .require_handler(MissingHandler::Escalate)
+        .run(&[pid]);
+
+    assert_eq!(report.outcome(pid), Some(&Outcome::Killed));
+    assert_eq!(report.exit_status(pid), Some(ExitStatus::Signaled(9)));
+    assert!(report.elapsed < GRACE);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn stalled_process_is_killed_without_waiting_out_the_extension() {
+    let pid = spawn("trap '' TERM; echo ready; exec sleep 30");
+
+    let report = gracekill()
+        .extend_if_progressing(Duration::from_secs(30))
+        .run(&[pid]);
+
+    assert_eq!(report.outcome(pid), Some(&Outcome::Killed));
+    assert!(report.elapsed < Duration::from_secs(10));
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn spinning_process_is_killed_once_it_has_used_its_cpu_time_grace() {
+    let pid = spawn("trap '' TERM; echo ready; while :; do :; done");
+
+    let report = gracekill()
+        .grace(Duration::from_secs(30))
+        .cpu_time_grace(Duration::from_millis(200))
+        .run(&[pid]);
+
+    assert_eq!(report.outcome(pid), Some(&Outcome::Killed));
+    assert!(report.elapsed < Duration::from_secs(10));
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn zombie_is_not_signalled_with_skip_exiting() {
+    let pid = spawn("echo ready");
+    while gracekill::procfs::stat(pid).is_ok_and(|stat| stat.state != 'Z') {
+        std::thread::sleep(POLL_INTERVAL);
+    }
+
+    let report = GraceKill::new()
+        .grace(GRACE)
+        .poll_interval(POLL_INTERVAL)
+        .skip_exiting(true)
+        .run(&[pid]);
+    reap(pid);
+
+    assert_eq!(report.outcome(pid), Some(&Outcome::Graceful));
+    assert!(report.elapsed < GRACE);
+}
+
+#[test]
+fn zero_grace_without_kill_still_polls_once() {
+    let pid = spawn("echo ready; exec sleep 30");
+
+    let report = gracekill().grace(Duration::ZERO).no_kill(true).run(&[pid]);
+
+    assert_eq!(report.outcome(pid), Some(&Outcome::Graceful));
+}
+
+#[test]
+fn nonexistent_pid_is_not_found() {
+    // Beyond the kernel's largest possible pid_max (2^22)
+    let pid = 1 << 30;
+
+    let report = gracekill().run(&[pid]);
+
+    assert_eq!(
+        report.outcome(pid),
+        Some(&Outcome::SignalFailed(SignalError::NotFound))
+    );
+    assert_eq!(report.signalled(), 0);
+}
+
+#[test]
+fn process_we_may_not_signal_is_still_running() {
+    // SAFETY: geteuid has no preconditions
+    if unsafe { libc::geteuid() } != 0 {
+        return;
+    }
+    let pid = spawn("echo ready; exec sleep 30");
+
+    // A child that gives up root can no longer signal the target, and must
+    // still see it as running
+    // SAFETY: the child only makes system calls before it exits
+    let checker = match unsafe { nix::unistd::fork() }.expect("failed to fork") {
+        nix::unistd::ForkResult::Child => {
+            // SAFETY: setuid and _exit are async-signal-safe
+            unsafe {
+                if libc::setuid(65534) != 0 {
+                    libc::_exit(2);
+                }
+                let denied =
+                    System.send_signal(pid, Signal::Term) == Err(SignalError::PermissionDenied);
+                libc::_exit(if denied && System.is_running(pid) {
+                    0
+                } else {
+                    1
+                });
+            }
+        }
+        nix::unistd::ForkResult::Parent { child } => child,
+    };
+    let status = nix::sys::wait::waitpid(checker, None).expect("failed to reap checker");
+
+    nix::sys::signal::kill(
+        nix::unistd::Pid::from_raw(i32::try_from(pid).unwrap()),
+        nix::sys::signal::Signal::SIGKILL,
+    )
+    .unwrap();
+    reap(pid);
+    assert_eq!(status, nix::sys::wait::WaitStatus::Exited(checker, 0));
+}
+
+#[test]
+fn mixed_targets_each_get_their_own_outcome() {
+    let cooperative = spawn("echo ready; exec sleep 30");
+    let uncooperative = spawn("trap '' TERM; echo ready; exec sleep 30");
+
+    let report = gracekill().run(&[cooperative, uncooperative]);
+
+    assert_eq!(
+        report.outcomes,
+        vec![
+            (cooperative, Outcome::Graceful),
+            (uncooperative, Outcome::Killed),
+        ]
+    );
+    assert_eq!(report.summary(), "1 exited gracefully, 1 killed, 0 failed");
+}
+
+#[test]
+fn cli_exits_with_2_when_nothing_could_be_signalled() {
+    let status = Command::new(env!("CARGO_BIN_EXE_gracekill"))
+        .args(["-q", "-g", "1", "1073741824"])
+        .status()
+        .expect("failed to run gracekill");
+
+    assert_eq!(status.code(), Some(2));
+}
+
+#[test]
+fn cli_exits_with_3_when_sigkill_was_required_and_asked_to() {
+    let pid = spawn("trap '' TERM; echo ready; exec sleep 30");
+
+    let status = Command::new(env!("CARGO_BIN_EXE_gracekill"))
+        .args([
+            "-q",
+            "--exit-non-zero-if-sigkill-required",
+            "--max-grace",
+            "300ms",
+        ])
+        .arg(pid.to_string())
+        .status()
+        .expect("failed to run gracekill");
+    reap(pid);
+
+    assert_eq!(status.code(), Some(3));
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn cli_refuses_to_signal_its_own_ancestor() {
+    let status = Command::new(env!("CARGO_BIN_EXE_gracekill"))
+        .args(["-q", "-g", "0"])
+        .arg(std::process::id().to_string())
+        .stderr(Stdio::null())
+        .status()
+        .expect("failed to run gracekill");
+
+    assert_eq!(status.code(), Some(1));
+}
+
+#[test]
+fn cli_counts_missing_targets_as_exited_with_ignore_missing() {
+    let status = Command::new(env!("CARGO_BIN_EXE_gracekill"))
+        .args(["-q", "--ignore-missing", "-g", "1", "1073741824"])
+        .status()
+        .expect("failed to run gracekill");
+
+    assert_eq!(status.code(), Some(0));
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn cli_error_exit_writes_out_held_log_lines() {
+    let output = Command::new(env!("CARGO_BIN_EXE_gracekill"))
+        .args(["--quiet-on-success", "--session", "1073741824"])
+        .output()
+        .expect("failed to run gracekill");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    let (id, rest) = stderr
+        .strip_prefix("[gracekill ")
+        .and_then(|rest| rest.split_once("] "))
+        .expect("log line has an operation ID");
+
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(id.len(), 6);
+    assert!(id.chars().all(|c| c.is_ascii_hexdigit()));
+    let pid_max = gracekill::procfs::pid_max().expect("failed to read pid_max");
+    assert_eq!(
+        rest,
+        format!(
+            "PID(s) 1073741824 can never exist, being at or above the system's pid_max of {pid_max}. \
+             Use --strict-pids to refuse them\n\
+             [gracekill {id}] Session 1073741824 has no processes\n\
+             Error: No processes to signal\n"
+        )
+    );
+}
+
+#[test]
+fn cli_reaps_killed_children_with_reap() {
+    // gracekill takes over the shell's children, one ignoring SIGTERM and one
+    // that isn't targeted and must be left running
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(r#"sleep 2 & (trap "" TERM; exec sleep 30) & exec "$0" -g 1 --reap $!"#)
+        .arg(env!("CARGO_BIN_EXE_gracekill"))
+        .output()
+        .expect("failed to run gracekill");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert!(stderr.contains("killed by signal SIGKILL"), "{stderr}");
+    assert!(
+        stderr.contains("Reaped 1 defunct child process(es)"),
+        "{stderr}"
+    );
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn cli_lists_the_threads_of_a_process() {
+    let pid = spawn("echo ready; exec sleep 30");
+    wait_for_exec(pid, "sleep");
+    let output = Command::new(env!("CARGO_BIN_EXE_gracekill"))
+        .args(["--list-threads", &pid.to_string()])
+        .output()
+        .expect("failed to run gracekill");
+    let target = nix::unistd::Pid::from_raw(i32::try_from(pid).unwrap());
+    nix::sys::signal::kill(target, nix::sys::signal::Signal::SIGKILL).unwrap();
+    reap(pid);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert_eq!(output.status.code(), Some(0));
+    let mut lines = stdout.lines();
+    assert!(lines.next().is_some_and(|header| header.starts_with("TID")));
+    let thread = lines.next().expect("the process has a thread");
+    assert!(thread.starts_with(&format!("{pid} ")), "{stdout}");
+    assert!(thread.contains("sleep"), "{stdout}");
+}
+
+#[test]
+fn cli_runs_the_on_timeout_command_before_the_kill() {
+    let hook = std::env::temp_dir().join(format!("gracekill-hook-{}.sh", std::process::id()));
+    std::fs::write(
+        &hook,
+        "echo \"survivors: $* remaining: $GRACEKILL_REMAINING_MS\"\n",
+    )
+    .unwrap();
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(r#"(trap "" TERM; exec sleep 30) & exec "$0" -g 1 --reap --on-timeout "sh $1" $!"#)
+        .arg(env!("CARGO_BIN_EXE_gracekill"))
+        .arg(&hook)
+        .output()
+        .expect("failed to run gracekill");
+    let _ = std::fs::remove_file(&hook);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert_eq!(output.status.code(), Some(0));
+    let ran = stderr.find("survivors: ").expect("the command ran");
+    let kill = stderr.find("Sent SIGKILL").expect("the target was killed");
+    assert!(ran < kill, "{stderr}");
+    assert!(stderr.contains("The --on-timeout command finished"));
+    // Just started, with most of the default 10s limit left
+    let remaining: u64 = stderr[ran..]
+        .lines()
+        .next()
+        .and_then(|line| line.rsplit("remaining: ").next())
+        .and_then(|ms| ms.parse().ok())
+        .expect("GRACEKILL_REMAINING_MS is set");
+    assert!((5000..=10_000).contains(&remaining), "{stderr}");
+}
+
+#[test]
+fn cli_logs_exit_latency_statistics_with_stats() {
+    // gracekill takes over the shell's children, reaping them as they exit
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(r#"sleep 30 & a=$!; sleep 30 & exec "$0" --exit-status --stats --output-pid-status-json $a,$!"#)
+        .arg(env!("CARGO_BIN_EXE_gracekill"))
+        .output()
+        .expect("failed to run gracekill");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert!(
+        stderr.contains("Exit latency over 2 graceful exit(s): min "),
+        "{stderr}"
+    );
+    assert!(
+        stdout.contains(r#""stats":{"graceful_exits":2,"min_ms":"#),
+        "{stdout}"
+    );
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn cli_writes_durations_in_the_chosen_format() {
+    let pid = spawn("echo ready; exec sleep 30");
+    let kill_after = |format: &str| {
+        let output = Command::new(env!("CARGO_BIN_EXE_gracekill"))
+            .args(["--dry-run", "--escalate", "TERM:61s,INT:2500ms,KILL"])
+            .args(["--duration-format", format, &pid.to_string()])
+            .output()
+            .expect("failed to run gracekill");
+        let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+        stderr
+            .split("to any still running after ")
+            .nth(1)
+            .and_then(|rest| rest.lines().next())
+            .map_or_else(|| panic!("{stderr}"), str::to_string)
+    };
+
+    assert_eq!(kill_after("short"), "63.5s");
+    assert_eq!(kill_after("clock"), "01:03.500");
+    assert_eq!(kill_after("ms"), "63500ms");
+    let target = nix::unistd::Pid::from_raw(i32::try_from(pid).unwrap());
+    nix::sys::signal::kill(target, nix::sys::signal::Signal::SIGKILL).unwrap();
+    reap(pid);
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn cli_estimates_the_timeline_without_signalling_with_dry_run() {
+    let default = spawn("echo ready; exec sleep 30");
+    let ignoring = spawn("trap '' TERM; echo ready; exec sleep 30");
+    let output = Command::new(env!("CARGO_BIN_EXE_gracekill"))
+        .args(["--dry-run", "--simulate-timing", "-g", "10"])
+        .arg(default.to_string())
+        .arg(ignoring.to_string())
+        .output()
+        .expect("failed to run gracekill");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert!(stderr.contains(&format!("+0s: PID {default} ")), "{stderr}");
+    assert!(
+        stderr.contains(&format!("+10s: PID {ignoring} ")),
+        "{stderr}"
+    );
+    assert!(stderr.contains("exits on SIGTERM, which it doesn't handle"));
+    assert!(stderr.contains("is sent SIGKILL as it ignores SIGTERM"));
+    assert!(stderr.contains("Estimated total duration: 10s"), "{stderr}");
+    assert!(output.stdout.is_empty());
+    for pid in [default, ignoring] {
+        let target = nix::unistd::Pid::from_raw(i32::try_from(pid).unwrap());
+        nix::sys::signal::kill(target, nix::sys::signal::Signal::SIGKILL).unwrap();
+        // Still running until now, so killed by this SIGKILL
+        assert!(matches!(
+            nix::sys::wait::waitpid(target, None),
+            Ok(nix::sys::wait::WaitStatus::Signaled(
+                _,
+                nix::sys::signal::Signal::SIGKILL,
+                _
+            ))
+        ));
+    }
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn cli_refuses_to_signal_a_pid_running_another_command_with_expect_cmd() {
+    let expected = spawn("echo ready; exec sleep 30");
+    let other = spawn("echo ready; exec sleep 30");
+    let output = Command::new(env!("CARGO_BIN_EXE_gracekill"))
+        .arg("-g")
+        .arg("1")
+        .arg("--expect-cmd")
+        .arg(format!("{expected}=sleep 30"))
+        .arg("--expect-cmd")
+        .arg(format!("{other}=nginx"))
+        .arg(expected.to_string())
+        .arg(other.to_string())
+        .output()
+        .expect("failed to run gracekill");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert!(
+        stderr.contains(&format!(
+            "PID {other} runs 'sleep 30', which doesn't contain the expected 'nginx', refusing to signal it"
+        )),
+        "{stderr}"
+    );
+    assert!(stderr.contains(&format!("Sent SIGTERM to PID {expected}")));
+    assert!(stderr.contains(", 1 failed"), "{stderr}");
+    reap(expected);
+    let target = nix::unistd::Pid::from_raw(i32::try_from(other).unwrap());
+    nix::sys::signal::kill(target, nix::sys::signal::Signal::SIGKILL).unwrap();
+    // Never signalled, so still running until now
+    assert!(matches!(
+        nix::sys::wait::waitpid(target, None),
+        Ok(nix::sys::wait::WaitStatus::Signaled(
+            _,
+            nix::sys::signal::Signal::SIGKILL,
+            _
+        ))
+    ));
+}
+
+#[test]
+fn cli_simulates_scripted_exits() {
+    let output = Command::new(env!("CARGO_BIN_EXE_gracekill"))
+        .args([
+            "--summary-only",
+            "-g",
+            "1",
+            "--exit-non-zero-if-sigkill-required",
+            "--simulate-exit-after",
+            "100=200ms",
+            "100",
+            "101",
+        ])
+        .output()
+        .expect("failed to run gracekill");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert_eq!(output.status.code(), Some(3));
+    assert!(stderr.contains("Summary: 1 exited gracefully, 1 killed, 0 failed"));
+}
+
+#[test]
+fn cli_ends_with_the_result_line() {
+    let output = Command::new(env!("CARGO_BIN_EXE_gracekill"))
+        .args([
+            "-g",
+            "1",
+            "--simulate-exit-after",
+            "100=200ms",
+            "100",
+            "101",
+        ])
+        .output()
+        .expect("failed to run gracekill");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    let last = stdout.lines().last().expect("something is printed");
+    assert!(
+        last.starts_with("RESULT graceful=1 killed=1 failed=0 elapsed_ms="),
+        "{stdout}"
+    );
+
+    let quiet = Command::new(env!("CARGO_BIN_EXE_gracekill"))
+        .args(["-q", "-g", "1", "--simulate-exit-after", "100=200ms", "100"])
+        .output()
+        .expect("failed to run gracekill");
+    assert!(quiet.stdout.is_empty());
+}
+
+/// The `--chunk-size` the streaming tests read PIDs with.
+const STREAM_CHUNK: usize = 100;
+
+/// Streams `count` simulated PIDs through `--stream`, returning the exit
+/// code and the peak memory use of gracekill in KB.
+#[allow(clippy::zombie_processes)] // reaped with wait4, for its resource usage
+fn stream_simulated(count: u32) -> (i32, i64) {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_gracekill"))
+        .args(["--stream", "--simulate", "-g", "0", "-q"])
+        .args(["--chunk-size", &STREAM_CHUNK.to_string()])
+        .stdin(Stdio::piped())
+        .spawn()
+        .expect("failed to run gracekill");
+    let stdin = child.stdin.take().expect("stdin is piped");
+    let writer = std::thread::spawn(move || {
+        // A line at a time, so that the test doesn't hold them all either
+        let mut stdin = std::io::BufWriter::new(stdin);
+        for pid in 1..=count {
+            writeln!(stdin, "{pid}").expect("failed to write PIDs");
+        }
+        stdin.flush().expect("failed to write PIDs");
+    });
+    let mut status = 0;
+    // SAFETY: a zeroed rusage is valid, and wait4 fills it in
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    let pid = libc::pid_t::try_from(child.id()).expect("PID fits");
+    // SAFETY: waits for our own child, writing only to the locals given
+    assert_eq!(
+        unsafe { libc::wait4(pid, &raw mut status, 0, &raw mut usage) },
+        pid
+    );
+    writer.join().expect("writer panicked");
+    (libc::WEXITSTATUS(status), usage.ru_maxrss)
+}
+
+#[test]
+fn cli_streams_pids_from_stdin_in_flat_memory() {
+    let (small_code, small) = stream_simulated(10_000);
+    let (large_code, large) = stream_simulated(200_000);
+
+    // Simulated targets ignore SIGTERM, so every one is killed
+    assert_eq!((small_code, large_code), (0, 0));
+    // Twenty times the PIDs, but never more than a chunk in memory: a
+    // generous 4KB per PID of the chunk is all the peak may grow by
+    let bound = i64::try_from(STREAM_CHUNK * 4).expect("bound fits");
+    assert!(
+        large - small < bound,
+        "peak memory grew from {small}KB to {large}KB, more than {bound}KB"
+    );
+}
+
+#[test]
+fn cli_takes_kill_style_signals_with_kill_compat() {
+    let sent = |program: &std::path::Path, args: &[&str]| {
+        let output = Command::new(program)
+            .args(args)
+            .args(["--simulate", "-g", "0", "100"])
+            .output()
+            .expect("failed to run gracekill");
+        let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+        assert_eq!(output.status.code(), Some(0), "{stderr}");
+        stderr
+    };
+    let gracekill = std::path::Path::new(env!("CARGO_BIN_EXE_gracekill"));
+
+    assert!(sent(gracekill, &["--kill-compat", "-INT"]).contains("Sent SIGINT to PID 100"));
+    assert!(sent(gracekill, &["--kill-compat", "-s", "1"]).contains("Sent SIGHUP to PID 100"));
+
+    // Under the name gracekill-kill, without the flag
+    let dir = std::env::temp_dir().join(format!("gracekill-kill-{}", std::process::id()));
+    let _ = std::fs::create_dir(&dir);
+    let link = dir.join("gracekill-kill");
+    let _ = std::fs::remove_file(&link);
+    std::os::unix::fs::symlink(gracekill, &link).unwrap();
+    let stderr = sent(&link, &["-10"]);
+    let _ = std::fs::remove_dir_all(&dir);
+    assert!(stderr.contains("Sent SIGUSR1 to PID 100"), "{stderr}");
+
+    let unknown = Command::new(gracekill)
+        .args(["--kill-compat", "-77", "--simulate", "100"])
+        .output()
+        .expect("failed to run gracekill");
+    assert_eq!(unknown.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&unknown.stderr).contains("Unknown signal number"));
+}
+
+#[test]
+fn cli_carries_on_once_stdout_is_closed() {
+    let run = |epipe: &str| {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_gracekill"))
+            .args(["--print-commands", "--epipe", epipe, "-g", "1"])
+            .args(["--simulate-exit-after", "100=200ms", "100", "101"])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .expect("failed to run gracekill");
+        // The reader goes away before the kill is over
+        drop(child.stdout.take());
+        child
+            .wait_with_output()
+            .expect("failed to wait for gracekill")
+    };
+
+    let ignored = run("ignore");
+    let stderr = String::from_utf8_lossy(&ignored.stderr);
+    assert_eq!(ignored.status.code(), Some(0), "{stderr}");
+    assert!(stderr.contains("stdout was closed, carrying on without writing to it"));
+    assert!(stderr.contains("Summary: 1 exited gracefully, 1 killed, 0 failed"));
+
+    let aborted = run("abort");
+    let stderr = String::from_utf8_lossy(&aborted.stderr);
+    assert_eq!(aborted.status.code(), Some(141), "{stderr}");
+    assert!(stderr.contains("stdout was closed, aborting the run"));
+    assert!(!stderr.contains("Summary"));
+}
+
+#[test]
+fn cli_prints_assignments_to_eval_with_shell_vars() {
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(
+            r#"eval "$("$0" --shell-vars -g 1 --forbid-signal-to 101 --simulate 100 101)"
+            echo "$GRACEKILL_KILLED|$GRACEKILL_SURVIVORS|$GRACEKILL_SUMMARY""#,
+        )
+        .arg(env!("CARGO_BIN_EXE_gracekill"))
+        .output()
+        .expect("failed to run gracekill");
+
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "1|101|0 exited gracefully, 1 killed, 0 failed, 1 left running\n"
+    );
+}
+
+#[test]
+fn cli_accepts_targets_over_listen_socket() {
+    let path = std::env::temp_dir().join(format!("gracekill-listen-{}.sock", std::process::id()));
+    let child = Command::new(env!("CARGO_BIN_EXE_gracekill"))
+        .args(["--summary-only", "--simulate", "-g", "1", "--listen"])
+        .arg(&path)
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("failed to run gracekill");
+    let mut stream = loop {
+        if let Ok(stream) = UnixStream::connect(&path) {
+            break stream;
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    };
+    stream
+        .write_all(b"100\nbogus\n")
+        .expect("failed to send PIDs");
+    drop(stream);
+    let output = child
+        .wait_with_output()
+        .expect("failed to wait for gracekill");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert!(stderr.contains("Summary: 0 exited gracefully, 1 killed, 0 failed"));
+    assert!(!path.exists());
+}
+
+#[test]
+fn cli_runs_plan_with_per_target_settings() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_gracekill"))
+        .args([
+            "--simulate",
+            "--simulate-exit-after",
+            "100=50ms",
+            "--plan",
+            "-",
+        ])
+        .stdin(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("failed to run gracekill");
+    child
+        .stdin
+        .take()
+        .expect("stdin is piped")
+        .write_all(br#"{"targets": [{"pid": 100, "grace": "1s"}, {"pid": 101, "signal": "INT", "grace": 0.2}]}"#)
+        .expect("failed to send plan");
+    let output = child
+        .wait_with_output()
+        .expect("failed to wait for gracekill");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert!(stderr.contains("Sent SIGINT to PID 101"));
+    assert!(stderr.contains("1 exited gracefully, 1 killed, 0 failed"));
+}
+
+#[test]
+fn cli_reports_plan_errors_by_field_path() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_gracekill"))
+        .args(["--plan", "-"])
+        .stdin(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("failed to run gracekill");
+    child
+        .stdin
+        .take()
+        .expect("stdin is piped")
+        .write_all(br#"{"targets": [{"pid": 100}, {"pid": 101, "grace": true}]}"#)
+        .expect("failed to send plan");
+    let output = child
+        .wait_with_output()
+        .expect("failed to wait for gracekill");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr.contains(
+        "Invalid plan '-': targets[1].grace: expected a duration such as \"30s\" or a number of seconds, got true"
+    ));
+}
+
+#[test]
+fn cli_writes_signalled_pids_to_report_fd() {
+    let output = Command::new(env!("CARGO_BIN_EXE_gracekill"))
+        .args([
+            "-q",
+            "--simulate",
+            "--simulate-exit-after",
+            "100=50ms",
+            "-g",
+            "1",
+            "--report-pids-fd",
+            "1",
+            "100",
+            "101",
+        ])
+        .output()
+        .expect("failed to run gracekill");
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "100 graceful\n101 killed\n"
+    );
+}
+
+#[test]
+fn cli_reports_nagios_status() {
+    let output = Command::new(env!("CARGO_BIN_EXE_gracekill"))
+        .args([
+            "-q",
+            "--simulate",
+            "--simulate-exit-after",
+            "100=50ms",
+            "-g",
+            "1",
+            "--nagios",
+            "100",
+            "101",
+        ])
+        .output()
+        .expect("failed to run gracekill");
+
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "WARNING: 1 exited gracefully, 1 killed, 0 failed | graceful=1 killed=1 failed=0\n"
+    );
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn cli_refuses_pids_above_pid_max_with_strict_pids() {
+    let output = Command::new(env!("CARGO_BIN_EXE_gracekill"))
+        .args(["--strict-pids", "-g", "1", "1073741824"])
+        .output()
+        .expect("failed to run gracekill");
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(
+        String::from_utf8_lossy(&output.stderr)
+            .starts_with("Error: PID(s) 1073741824 can never exist")
+    );
+}
+
+#[test]
+fn cli_points_inotify_liveness_at_pidfd() {
+    let output = Command::new(env!("CARGO_BIN_EXE_gracekill"))
+        .args(["--liveness", "inotify", "-g", "1", "1073741824"])
+        .output()
+        .expect("failed to run gracekill");
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Use --liveness pidfd"));
+}
+
+#[test]
+fn cli_tails_log_lines_written_during_the_kill() {
+    let path = std::env::temp_dir().join(format!("gracekill-tail-{}.log", std::process::id()));
+    std::fs::write(&path, "before the kill\n").expect("failed to create log");
+    let mut child = Command::new(env!("CARGO_BIN_EXE_gracekill"))
+        .args(["--simulate", "--simulate-exit-after", "100=1s", "--tail"])
+        .arg(&path)
+        .arg("100")
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("failed to run gracekill");
+    let mut lines = BufReader::new(child.stderr.take().expect("stderr is piped")).lines();
+    for line in lines.by_ref() {
+        if line
+            .expect("failed to read from gracekill")
+            .contains("Sent SIGTERM")
+        {
+            break;
+        }
+    }
+    std::fs::OpenOptions::new()
+        .append(true)
+        .open(&path)
+        .and_then(|mut log| log.write_all(b"shutting down\n"))
+        .expect("failed to append to log");
+    let rest: Vec<String> = lines.map(|line| line.expect("failed to read")).collect();
+    child.wait().expect("failed to wait for gracekill");
+    std::fs::remove_file(&path).expect("failed to remove log");
+
+    let tailed: Vec<&String> = rest.iter().filter(|line| line.contains(".log: ")).collect();
+    assert_eq!(tailed.len(), 1, "{rest:?}");
+    assert!(tailed[0].ends_with(".log: shutting down"));
+}
+
+#[test]
+fn cli_coalesces_repeated_log_lines() {
+    let path = std::env::temp_dir().join(format!("gracekill-coalesce-{}.log", std::process::id()));
+    std::fs::write(&path, "").expect("failed to create log");
+    let mut child = Command::new(env!("CARGO_BIN_EXE_gracekill"))
+        .args([
+            "--simulate",
+            "--simulate-exit-after",
+            "100=1s",
+            "--coalesce-logs",
+        ])
+        .arg("--tail")
+        .arg(&path)
+        .arg("100")
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("failed to run gracekill");
+    let mut lines = BufReader::new(child.stderr.take().expect("stderr is piped")).lines();
+    for line in lines.by_ref() {
+        if line
+            .expect("failed to read from gracekill")
+            .contains("Sent SIGTERM")
+        {
+            break;
+        }
+    }
+    std::fs::OpenOptions::new()
+        .append(true)
+        .open(&path)
+        .and_then(|mut log| {
+            log.write_all(b"retrying\nretrying\nretrying\nretrying\nshutting down\n")
+        })
+        .expect("failed to append to log");
+    let rest: Vec<String> = lines.map(|line| line.expect("failed to read")).collect();
+    child.wait().expect("failed to wait for gracekill");
+    std::fs::remove_file(&path).expect("failed to remove log");
+
+    let tailed: Vec<&str> = rest
+        .iter()
+        .filter_map(|line| line.split_once(".log: ").map(|(_, tailed)| tailed))
+        .collect();
+    assert_eq!(
+        tailed,
+        ["retrying", "retrying (repeated 3 times)", "shutting down"],
+        "{rest:?}"
+    );
+}
+
+#[test]
+fn cli_writes_a_trace_of_each_signal_and_outcome() {
+    let pid = spawn("trap '' TERM; echo ready; exec sleep 30");
+    let path = std::env::temp_dir().join(format!("gracekill-trace-{}.log", std::process::id()));
+    let reaper = std::thread::spawn(move || reap(pid));
+    let status = Command::new(env!("CARGO_BIN_EXE_gracekill"))
+        .args(["-q", "-g", "1", "--trace-file"])
+        .arg(&path)
+        .arg(pid.to_string())
+        .status()
+        .expect("failed to run gracekill");
+    reaper.join().unwrap();
+    let trace = std::fs::read_to_string(&path).expect("the trace was written");
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(status.code(), Some(0));
+    assert!(
+        trace.starts_with("# gracekill trace v1 started_unix_ms="),
+        "{trace}"
+    );
+    // Without the milliseconds, or the polls in between
+    let events: Vec<&str> = trace
+        .lines()
+        .skip(1)
+        .filter_map(|line| line.split_once(' ').map(|(_, event)| event))
+        .filter(|event| !event.starts_with("poll "))
+        .collect();
+    assert_eq!(
+        events,
+        [
+            format!("run pids={pid}"),
+            format!("signal pid={pid} signal=SIGTERM result=ok"),
+            format!("escalate pid={pid} from=SIGTERM to=SIGKILL"),
+            format!("signal pid={pid} signal=SIGKILL result=ok"),
+            format!("outcome pid={pid} outcome=killed"),
+            format!("survival pid={pid} reason=ignored_signal"),
+        ],
+        "{trace}"
+    );
+    assert!(trace.contains(&format!(" poll running={pid}\n")), "{trace}");
+}
+
+#[test]
+fn cli_spaces_signals_out_with_rate() {
+    let pids: Vec<u32> = (0..4).map(|_| spawn("echo ready; exec sleep 30")).collect();
+    let path = std::env::temp_dir().join(format!("gracekill-rate-{}.log", std::process::id()));
+    let reapers: Vec<_> = pids
+        .iter()
+        .map(|&pid| std::thread::spawn(move || reap(pid)))
+        .collect();
+    let status = Command::new(env!("CARGO_BIN_EXE_gracekill"))
+        .args(["-q", "-g", "5", "--rate", "4", "--trace-file"])
+        .arg(&path)
+        .args(pids.iter().map(u32::to_string))
+        .status()
+        .expect("failed to run gracekill");
+    for reaper in reapers {
+        reaper.join().unwrap();
+    }
+    let trace = std::fs::read_to_string(&path).expect("the trace was written");
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(status.code(), Some(0));
+    // When each SIGTERM went out, in milliseconds
+    let sent: Vec<u64> = trace
+        .lines()
+        .filter(|line| line.contains(" signal=SIGTERM "))
+        .filter_map(|line| line.split_once(' ')?.0.parse().ok())
+        .collect();
+    assert_eq!(sent.len(), 4, "{trace}");
+    // Four a second is one every 250ms, give or take the clock
+    for pair in sent.windows(2) {
+        assert!(pair[1] - pair[0] >= 240, "{trace}");
+    }
+}
+
+#[test]
+fn cli_warns_once_about_targets_slow_to_exit() {
+    let quick = spawn("echo ready; exec sleep 30");
+    let slow = spawn("trap '' TERM; echo ready; exec sleep 30");
+    let reapers = [quick, slow].map(|pid| std::thread::spawn(move || reap(pid)));
+    let output = Command::new(env!("CARGO_BIN_EXE_gracekill"))
+        .args(["-g", "1", "--warn-long-exit", "300ms"])
+        .args([quick.to_string(), slow.to_string()])
+        .output()
+        .expect("failed to run gracekill");
+    for reaper in reapers {
+        reaper.join().unwrap();
+    }
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(0), "{stderr}");
+    assert_eq!(stderr.matches("slow to exit").count(), 1, "{stderr}");
+    assert!(
+        stderr.contains(&format!("PID {slow} is slow to exit: still running")),
+        "{stderr}"
+    );
+    // Only a warning: the kill goes on as usual
+    assert!(stderr.contains("1 exited gracefully, 1 killed"), "{stderr}");
+}
+
+#[test]
+fn cli_reads_as_much_about_targets_as_enrich_says() {
+    let run = |enrich: &str| {
+        let pid = spawn("echo ready; exec sleep 30");
+        let path = std::env::temp_dir().join(format!(
+            "gracekill-enrich-{enrich}-{}.csv",
+            std::process::id()
+        ));
+        let reaper = std::thread::spawn(move || reap(pid));
+        let output = Command::new(env!("CARGO_BIN_EXE_gracekill"))
+            .args(["-g", "5", "--enrich", enrich, "--report"])
+            .arg(&path)
+            .arg(pid.to_string())
+            .output()
+            .expect("failed to run gracekill");
+        reaper.join().unwrap();
+        let report = std::fs::read_to_string(&path).expect("the report was written");
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(output.status.code(), Some(0));
+        let row = report
+            .lines()
+            .nth(1)
+            .expect("a row for the target")
+            .to_string();
+        (
+            pid,
+            String::from_utf8_lossy(&output.stderr).into_owned(),
+            row,
+        )
+    };
+
+    let (pid, stderr, row) = run("off");
+    assert!(
+        row.starts_with(&format!("{pid},,SIGTERM,graceful,")),
+        "{row}"
+    );
+    assert!(!stderr.contains("cmdline"), "{stderr}");
+    let (pid, stderr, row) = run("basic");
+    assert!(
+        row.starts_with(&format!("{pid},sleep,SIGTERM,graceful,")),
+        "{row}"
+    );
+    assert!(!stderr.contains("cmdline"), "{stderr}");
+    let (pid, stderr, row) = run("full");
+    assert!(
+        row.starts_with(&format!("{pid},sleep,SIGTERM,graceful,")),
+        "{row}"
+    );
+    assert!(
+        stderr.contains(&format!("PID {pid} (sleep): state S, uid ")),
+        "{stderr}"
+    );
+    assert!(stderr.contains("cmdline 'sleep 30'"), "{stderr}");
+}
+
+#[test]
+fn cli_writes_errors_as_json_with_error_format() {
+    let run = |args: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_gracekill"))
+            .args(["-q", "--error-format", "json"])
+            .args(args)
+            .output()
+            .expect("failed to run gracekill");
+        String::from_utf8_lossy(&output.stderr).into_owned()
+    };
+
+    assert_eq!(
+        run(&["abc"]),
+        "{\"error\":\"Invalid PID at argument 4: 'abc'\",\"kind\":\"parse\"}\n"
+    );
+    assert!(run(&["1073741824"]).ends_with(
+        "{\"error\":\"Failed to send the graceful signal to PID 1073741824: Process not found\",\
+         \"kind\":\"not_found\",\"pid\":1073741824}\n"
+    ));
+}
+
+#[test]
+fn cli_shows_the_effective_config_and_where_it_came_from() {
+    let output = Command::new(env!("CARGO_BIN_EXE_gracekill"))
+        .args(["--show-config", "-s", "INT", "1073741824"])
+        .env("GRACEKILL_LOG", "warn")
+        .output()
+        .expect("failed to run gracekill");
+
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains(r#""signal":{"value":"SIGINT","source":"flag"}"#));
+    assert!(stdout.contains(r#""grace_period_ms":{"value":25000,"source":"default"}"#));
+    assert!(stdout.contains(r#""log_level":{"value":"warn","source":"env"}"#));
+}
+
+#[test]
+fn cli_takes_negative_ids_after_the_separator_as_process_groups() {
+    let run = |args: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_gracekill"))
+            .args(args)
+            .output()
+            .expect("failed to run gracekill");
+        (
+            output.status.code(),
+            String::from_utf8_lossy(&output.stdout).into_owned(),
+            String::from_utf8_lossy(&output.stderr).into_owned(),
+        )
+    };
+
+    let (code, stdout, stderr) = run(&["--show-config", "--", "-1073741824"]);
+    assert_eq!(code, Some(0), "{stderr}");
+    assert!(stdout.contains(r#""pids":{"value":[],"#), "{stdout}");
+    assert!(
+        stdout.contains(r#""groups":{"value":[1073741824],"#),
+        "{stdout}"
+    );
+
+    // Before the separator, it is an option
+    let (code, _, stderr) = run(&["--show-config", "-1073741824"]);
+    assert_eq!(code, Some(1), "{stderr}");
+    assert!(
+        stderr.contains("Unknown option at argument 2: '-1073741824'"),
+        "{stderr}"
+    );
+
+    let (code, stdout, stderr) = run(&["--show-config", "--"]);
+    assert_eq!(code, Some(0), "{stderr}");
+    assert!(stdout.contains(r#""pids":{"value":[],"#), "{stdout}");
+    assert!(stdout.contains(r#""groups":{"value":[],"#), "{stdout}");
+    let (code, _, stderr) = run(&["--"]);
+    assert_eq!(code, Some(1), "{stderr}");
+    assert!(stderr.contains("No PIDs provided"), "{stderr}");
+
+    let (code, stdout, stderr) = run(&[
+        "--show-config",
+        "1073741823",
+        "--",
+        "1073741824,-1073741825",
+        "-1073741826",
+    ]);
+    assert_eq!(code, Some(0), "{stderr}");
+    assert!(
+        stdout.contains(r#""pids":{"value":[1073741823,1073741824],"#),
+        "{stdout}"
+    );
+    assert!(
+        stdout.contains(r#""groups":{"value":[1073741825,1073741826],"#),
+        "{stdout}"
+    );
+}
+
+#[test]
+fn cli_parses_every_field_of_a_stage_block() {
+    let config = |stages: &[&str]| {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_gracekill"));
+        command.args(["--show-config", "-g", "7"]);
+        for stage in stages {
+            command.args(["--stage", stage]);
+        }
+        let output = command
+            .arg("1073741824")
+            .env("GRACEKILL_LOG", "error")
+            .output()
+            .expect("failed to run gracekill");
+        (
+            output.status.code(),
+            String::from_utf8_lossy(&output.stdout).into_owned(),
+            String::from_utf8_lossy(&output.stderr).into_owned(),
+        )
+    };
+
+    let (code, stdout, _) = config(&["stage{signal=INT,grace=10s,retries=2,jitter=1s}"]);
+    assert_eq!(code, Some(0));
+    assert!(
+        stdout.contains(r#""grace_period_ms":{"value":10000,"#),
+        "{stdout}"
+    );
+    assert!(
+        stdout.contains(r#""signal":{"value":"SIGINT","#),
+        "{stdout}"
+    );
+    assert!(stdout.contains(r#""retries":{"value":2,"#), "{stdout}");
+    assert!(stdout.contains(r#""jitter_ms":{"value":1000,"#), "{stdout}");
+    assert!(stdout.contains(r#""escalate":{"value":[],"#), "{stdout}");
+
+    // Several blocks in one value and across values, keys in any order,
+    // spaces around everything, and the grace falling back to -g
+    let (code, stdout, _) = config(&[
+        " stage { jitter = 250ms , signal = TERM } , {signal=QUIT,grace=2s}",
+        "stage{retries=3,signal=USR1}",
+    ]);
+    assert_eq!(code, Some(0));
+    assert!(
+        stdout.contains(r#""signal":{"value":"SIGTERM","#),
+        "{stdout}"
+    );
+    assert!(
+        stdout.contains(r#""grace_period_ms":{"value":7000,"#),
+        "{stdout}"
+    );
+    assert!(stdout.contains(r#""jitter_ms":{"value":250,"#), "{stdout}");
+    assert!(
+        stdout.contains(
+            r#""escalate":{"value":[{"signal":"SIGQUIT","grace_ms":2000,"retries":0,"jitter_ms":0},{"signal":"SIGUSR1","grace_ms":7000,"retries":3,"jitter_ms":0}],"#
+        ),
+        "{stdout}"
+    );
+
+    for (stage, reason) in [
+        ("", "no stages"),
+        ("stage{}", "every stage needs a signal"),
+        ("stage{grace=1s}", "every stage needs a signal"),
+        ("stage{signal=INT", "missing '}'"),
+        ("signal=INT", "expected stage{KEY=VALUE,...}"),
+        ("stage{signal=INT}junk", "expected stage{KEY=VALUE,...}"),
+        ("stage{signal=INT},", "expected a stage after ','"),
+        ("stage{signal=INT,,grace=1s}", "empty field"),
+        ("stage{signal}", "expected KEY=VALUE, got 'signal'"),
+        ("stage{signal=INT,signal=TERM}", "'signal' given twice"),
+        ("stage{signal=INT,retries=-1}", "invalid retries: '-1'"),
+        ("stage{signal=INT,grace=soon}", "soon"),
+        ("stage{signal=NOPE}", "NOPE"),
+        ("stage{signal=INT,delay=1s}", "unknown key 'delay'"),
+    ] {
+        let (code, _, stderr) = config(&[stage]);
+        assert_eq!(code, Some(1), "{stage}");
+        assert!(
+            stderr.contains(&format!("Invalid --stage at argument 5: '{stage}' (")),
+            "{stderr}"
+        );
+        assert!(stderr.contains(reason), "{stage}: {stderr}");
+    }
+
+    let output = Command::new(env!("CARGO_BIN_EXE_gracekill"))
+        .args([
+            "--escalate",
+            "TERM,KILL",
+            "--stage",
+            "stage{signal=INT}",
+            "1",
+        ])
+        .output()
+        .expect("failed to run gracekill");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(
+        String::from_utf8_lossy(&output.stderr)
+            .contains("--stage cannot be combined with --escalate")
+    );
+}
+
+#[test]
+#[cfg(feature = "shutdown-url")]
+fn cli_leaves_targets_alone_that_exit_on_the_shutdown_url() {
+    use std::net::TcpListener;
+
+    let pid = spawn("trap '' TERM; echo ready; exec sleep 30");
+    let listener = TcpListener::bind("127.0.0.1:0").expect("failed to listen");
+    let url = format!(
+        "http://{}/shutdown",
+        listener.local_addr().expect("listener has an address")
+    );
+    // The endpoint shuts the target down the only way it can be
+    let server = std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().expect("no shutdown request");
+        let mut request = String::new();
+        BufReader::new(&stream)
+            .read_line(&mut request)
+            .expect("failed to read the request");
+        stream
+            .write_all(b"HTTP/1.1 202 Accepted\r\nContent-Length: 0\r\n\r\n")
+            .expect("failed to answer");
+        let target = nix::unistd::Pid::from_raw(i32::try_from(pid).unwrap());
+        nix::sys::signal::kill(target, nix::sys::signal::Signal::SIGKILL).unwrap();
+        reap(pid);
+        request
+    });
+
+    let output = Command::new(env!("CARGO_BIN_EXE_gracekill"))
+        .args(["-g", "1", "--shutdown-wait", "5s", "--shutdown-url", &url])
+        .arg(pid.to_string())
+        .output()
+        .expect("failed to run gracekill");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert_eq!(server.join().unwrap(), "POST /shutdown HTTP/1.1\r\n");
+    assert_eq!(output.status.code(), Some(0));
+    assert!(stderr.contains(&format!("Process {pid} exited after the shutdown request")));
+    assert!(!stderr.contains("Sent SIGTERM"));
+}
+
+#[test]
+#[cfg(feature = "otlp")]
+fn cli_sends_a_span_per_target_to_the_otlp_endpoint() {
+    use std::io::Read;
+    use std::net::TcpListener;
+
+    let pid = spawn("echo ready; exec sleep 30");
+    let listener = TcpListener::bind("127.0.0.1:0").expect("failed to listen");
+    let url = format!(
+        "http://{}",
+        listener.local_addr().expect("listener has an address")
+    );
+    let collector = std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().expect("no export request");
+        let mut reader = BufReader::new(&stream);
+        let mut head = String::new();
+        let mut length = 0;
+        loop {
+            let mut line = String::new();
+            reader
+                .read_line(&mut line)
+                .expect("failed to read the request");
+            if let Some(value) = line.strip_prefix("Content-Length: ") {
+                length = value.trim().parse().expect("bad Content-Length");
+            }
+            if line == "\r\n" {
+                break;
+            }
+            head.push_str(&line);
+        }
+        let mut body = vec![0; length];
+        reader
+            .read_exact(&mut body)
+            .expect("failed to read the body");
+        stream
+            .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n")
+            .expect("failed to answer");
+        (head, String::from_utf8(body).expect("body is UTF-8"))
+    });
+    let reaper = std::thread::spawn(move || reap(pid));
+
+    let output = Command::new(env!("CARGO_BIN_EXE_gracekill"))
+        .args(["-g", "5", "--otlp-endpoint", &url])
+        .arg(pid.to_string())
+        .env(
+            "TRACEPARENT",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+        )
+        .output()
+        .expect("failed to run gracekill");
+    reaper.join().unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    let (head, body) = collector.join().unwrap();
+    assert!(head.starts_with("POST /v1/traces HTTP/1.1\r\n"), "{head}");
+    assert_eq!(output.status.code(), Some(0), "{stderr}");
+    assert!(
+        body.contains(r#""traceId":"4bf92f3577b34da6a3ce929d0e0e4736""#),
+        "{body}"
+    );
+    assert!(
+        body.contains(r#""parentSpanId":"00f067aa0ba902b7""#),
+        "{body}"
+    );
+    assert!(
+        body.contains(&format!(
+            r#"{{"key":"process.pid","value":{{"intValue":"{pid}"}}}},{{"key":"gracekill.outcome","value":{{"stringValue":"graceful"}}}}"#
+        )),
+        "{body}"
+    );
+    assert!(
+        body.contains(r#"{"key":"gracekill.graceful","value":{"intValue":"1"}}"#),
+        "{body}"
+    );
+}
+
+#[test]
+#[cfg(not(feature = "otlp"))]
+fn cli_refuses_an_otlp_endpoint_without_the_feature() {
+    let output = Command::new(env!("CARGO_BIN_EXE_gracekill"))
+        .args(["--otlp-endpoint", "http://localhost:4318", "1"])
+        .output()
+        .expect("failed to run gracekill");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert_eq!(output.status.code(), Some(1), "{stderr}");
+    assert!(
+        stderr.contains("needs gracekill built with the otlp feature"),
+        "{stderr}"
+    );
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn cli_shuts_down_a_target_through_its_stdin() {
+    // Only the command makes it exit; its stdin is held open throughout
+    let mut child = Command::new("sh")
+        .args([
+            "-c",
+            "trap '' TERM; echo ready; while read line; do [ \"$line\" = quit ] && exit 0; done",
+        ])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("failed to spawn sh");
+    let mut ready = String::new();
+    BufReader::new(child.stdout.take().expect("stdout is piped"))
+        .read_line(&mut ready)
+        .expect("failed to read from child");
+    let pid = child.id();
+    let stdin = child.stdin.take();
+    let reaper = std::thread::spawn(move || child.wait().expect("failed to reap child"));
+
+    let output = Command::new(env!("CARGO_BIN_EXE_gracekill"))
+        .args([
+            "-g",
+            "1",
+            "--stdin-wait",
+            "5s",
+            "--stdin-cmd",
+            "hello\\nquit\\n",
+        ])
+        .arg(pid.to_string())
+        .output()
+        .expect("failed to run gracekill");
+    let status = reaper.join().unwrap();
+    drop(stdin);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert_eq!(output.status.code(), Some(0), "{stderr}");
+    assert!(status.success());
+    assert!(
+        stderr.contains(&format!("Process {pid} exited after the stdin command")),
+        "{stderr}"
+    );
+    assert!(!stderr.contains("Sent SIGTERM"), "{stderr}");
+}
+
+#[test]
+fn cli_waits_for_another_run_holding_a_target_lock() {
+    use std::os::fd::AsRawFd;
+
+    let pid = spawn("echo ready; exec sleep 30");
+    let dir = std::env::temp_dir().join(format!("gracekill-locks-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).expect("failed to create the lock directory");
+    // Another run, as far as gracekill can tell
+    let mut lock = std::fs::File::create(dir.join(format!("{pid}.lock"))).unwrap();
+    writeln!(lock, "4242").unwrap();
+    // SAFETY: the fd is open for as long as `lock` is
+    assert_eq!(unsafe { libc::flock(lock.as_raw_fd(), libc::LOCK_EX) }, 0);
+    let run = |wait: bool| {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_gracekill"));
+        if !wait {
+            command.arg("--no-wait-lock");
+        }
+        command
+            .args(["-g", "5", &pid.to_string()])
+            .env("GRACEKILL_LOCK_DIR", &dir)
+            .output()
+            .expect("failed to run gracekill")
+    };
+
+    let refused = run(false);
+    let releaser = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(300));
+        drop(lock);
+    });
+    let reaper = std::thread::spawn(move || reap(pid));
+    let waited = run(true);
+    releaser.join().unwrap();
+    reaper.join().unwrap();
+    let left = dir.join(format!("{pid}.lock")).exists();
+    std::fs::remove_dir_all(&dir).expect("failed to remove the lock directory");
+
+    let stderr = String::from_utf8_lossy(&refused.stderr);
+    assert_eq!(refused.status.code(), Some(1), "{stderr}");
+    assert!(
+        stderr.contains(&format!("PID {pid} is locked by gracekill PID 4242")),
+        "{stderr}"
+    );
+    let stderr = String::from_utf8_lossy(&waited.stderr);
+    assert_eq!(waited.status.code(), Some(0), "{stderr}");
+    assert!(stderr.contains("waiting for it to finish"), "{stderr}");
+    assert!(stderr.contains("1 exited gracefully"), "{stderr}");
+    assert!(!left, "the lock file outlived the run");
+}
+
+#[test]
+fn cli_kills_targets_it_cannot_lock() {
+    let pid = spawn("echo ready; exec sleep 30");
+    // Lock files can't be created under a regular file, even by root
+    let dir = std::env::temp_dir().join(format!("gracekill-not-a-dir-{}", std::process::id()));
+    std::fs::write(&dir, "").expect("failed to create the file");
+    let reaper = std::thread::spawn(move || reap(pid));
+    let output = Command::new(env!("CARGO_BIN_EXE_gracekill"))
+        .args(["-g", "5", "-v", &pid.to_string()])
+        .env("GRACEKILL_LOCK_DIR", &dir)
+        .output()
+        .expect("failed to run gracekill");
+    reaper.join().unwrap();
+    std::fs::remove_file(&dir).expect("failed to remove the file");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(0), "{stderr}");
+    assert!(
+        stderr.contains(&format!("Cannot lock PID {pid}")),
+        "{stderr}"
+    );
+    assert!(stderr.contains("1 exited gracefully"), "{stderr}");
+}
+
+#[test]
+fn cli_treats_a_past_by_time_as_the_configured_action() {
+    let pid = spawn("trap '' TERM; echo ready; exec sleep 30");
+    let by = |action: &str| {
+        Command::new(env!("CARGO_BIN_EXE_gracekill"))
+            .args(["-q", "--by", "2020-01-01T00:00:00Z", "--if-past", action])
+            .arg(pid.to_string())
+            .status()
+            .expect("failed to run gracekill")
+    };
+
+    assert_eq!(by("error").code(), Some(1));
+    let start = std::time::Instant::now();
+    assert_eq!(by("kill").code(), Some(0));
+    reap(pid);
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn cli_takes_a_clock_time_that_just_passed_as_tomorrow() {
+    // Two minutes ago in local time, as HH:MM
+    let ago = libc::time_t::try_from(
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            - 120,
+    )
+    .unwrap();
+    // SAFETY: localtime_r only reads ago and writes the tm we own
+    let tm = unsafe {
+        let mut tm: libc::tm = std::mem::zeroed();
+        assert!(!libc::localtime_r(&raw const ago, &raw mut tm).is_null());
+        tm
+    };
+    let clock = format!("{:02}:{:02}", tm.tm_hour, tm.tm_min);
+    let output = Command::new(env!("CARGO_BIN_EXE_gracekill"))
+        .args(["--show-config", "--by", &clock, "--if-past", "error", "100"])
+        .output()
+        .expect("failed to run gracekill");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(0), "{stderr}");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let grace: u64 = stdout
+        .split(r#""grace_period_ms":{"value":"#)
+        .nth(1)
+        .and_then(|rest| rest.split(',').next())
+        .and_then(|value| value.parse().ok())
+        .expect("the grace period is shown");
+    // Tomorrow at that time, give or take a change to or from summer time
+    assert!(grace > 22 * 3_600_000, "{grace}ms");
+}
+
+#[test]
+fn cli_checks_that_the_escalate_waits_fit_before_the_by_time() {
+    let pid = spawn("echo ready; exec sleep 30");
+    // Far off, but not the thousand years the long waits add up to
+    let by = |ladder: &str, over_budget: &str| {
+        Command::new(env!("CARGO_BIN_EXE_gracekill"))
+            .args(["--dry-run", "--by", "2999-01-01T00:00:00Z"])
+            .args(["--escalate", ladder, "--if-over-budget", over_budget])
+            .arg(pid.to_string())
+            .output()
+            .expect("failed to run gracekill")
+    };
+
+    let output = by("TERM:100ms,INT:200ms,KILL", "error");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(stderr.contains("still running after 300ms"), "{stderr}");
+
+    let output = by("TERM:100ms,INT:9999999h,KILL", "error");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr.contains("more than the"), "{stderr}");
+    assert!(!stderr.contains("Would send"), "{stderr}");
+
+    let output = by("TERM:100ms,INT:9999999h,KILL", "clamp");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(stderr.contains("shortening the last wait"), "{stderr}");
+
+    // Only the last wait is ever shortened
+    let output = by("TERM:9999999h,INT:100ms,KILL", "clamp");
+    assert_eq!(output.status.code(), Some(1));
+
+    // The longest waits accepted still add up, and are over budget
+    let huge = "TERM:87600000h,INT:87600000h,KILL";
+    for over_budget in ["error", "clamp"] {
+        let output = by(huge, over_budget);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert_eq!(output.status.code(), Some(1), "{stderr}");
+        assert!(stderr.contains("more than the"), "{stderr}");
+    }
+
+    let target = nix::unistd::Pid::from_raw(i32::try_from(pid).unwrap());
+    nix::sys::signal::kill(target, nix::sys::signal::Signal::SIGKILL).unwrap();
+    reap(pid);
+}
+
+#[test]
+fn cli_carries_on_in_the_background_with_daemonize() {
+    let pid = spawn("echo ready; exec sleep 30");
+    let log = std::env::temp_dir().join(format!("gracekill-daemon-{}.log", std::process::id()));
+    let output = Command::new(env!("CARGO_BIN_EXE_gracekill"))
+        .arg("--daemonize")
+        .arg("--log-file")
+        .arg(&log)
+        .arg(pid.to_string())
+        .output()
+        .expect("failed to run gracekill");
+    assert_eq!(output.status.code(), Some(0));
+    let background: u32 = String::from_utf8_lossy(&output.stdout)
+        .trim()
+        .parse()
+        .expect("the background PID is printed");
+    assert_ne!(background, pid);
+    reap(pid);
+
+    let mut logged = String::new();
+    for _ in 0..50 {
+        logged = std::fs::read_to_string(&log).unwrap_or_default();
+        if logged.contains("Summary") {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    std::fs::remove_file(&log).expect("failed to remove log");
+    assert!(
+        logged.contains(&format!("Sent SIGTERM to PID {pid}")),
+        "{logged}"
+    );
+    assert!(logged.contains("Summary"), "{logged}");
+}
+
+#[test]
+fn cli_hands_the_kill_off_with_background_escalate() {
+    let pid = spawn("trap '' TERM; echo ready; exec sleep 30");
+    let log = std::env::temp_dir().join(format!("gracekill-escalate-{}.log", std::process::id()));
+    let started = std::time::Instant::now();
+    let output = Command::new(env!("CARGO_BIN_EXE_gracekill"))
+        .args(["-g", "2", "--background-escalate", "--log-file"])
+        .arg(&log)
+        .arg(pid.to_string())
+        .output()
+        .expect("failed to run gracekill");
+    assert!(started.elapsed() < Duration::from_secs(2));
+    assert_eq!(output.status.code(), Some(0));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains(&format!("Sent SIGTERM to PID {pid}")),
+        "{stderr}"
+    );
+    assert!(
+        stderr.contains("handing the wait and the kill off"),
+        "{stderr}"
+    );
+    let background: u32 = String::from_utf8_lossy(&output.stdout)
+        .trim()
+        .parse()
+        .expect("the background PID is printed");
+    assert_ne!(background, pid);
+    reap(pid);
+
+    let mut logged = String::new();
+    for _ in 0..50 {
+        logged = std::fs::read_to_string(&log).unwrap_or_default();
+        if logged.contains("RESULT") {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    std::fs::remove_file(&log).expect("failed to remove log");
+    assert!(
+        logged.contains(&format!("Sent SIGKILL to PID {pid}")),
+        "{logged}"
+    );
+    assert!(logged.contains("RESULT graceful=0 killed=1"), "{logged}");
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn snapshot_records_each_target_before_the_kill() {
+    let pid = spawn("echo ready; exec sleep 30");
+
+    let report = gracekill().snapshot(true).run(&[pid, 999_999_999]);
+
+    assert_eq!(report.snapshots.len(), 1);
+    let snapshot = report
+        .snapshot(pid)
+        .expect("the running target is recorded");
+    assert!(
+        snapshot.command.contains("sleep 30"),
+        "{}",
+        snapshot.command
+    );
+    assert_eq!(
+        Some(snapshot.uid),
+        gracekill::procfs::uid(std::process::id()).ok()
+    );
+    assert!(snapshot.rss > 0);
+    assert_eq!(report.outcome(pid), Some(&Outcome::Graceful));
+}
+
+#[test]
+#[cfg(all(feature = "container", target_os = "linux"))]
+fn cli_targets_a_container_and_its_descendants() {
+    use std::os::unix::net::UnixListener;
+
+    let pid = spawn("sleep 30 & echo ready; wait");
+    let path = std::env::temp_dir().join(format!("gracekill-docker-{}.sock", std::process::id()));
+    let listener = UnixListener::bind(&path).expect("failed to listen");
+    let runtime = std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().expect("no inspect request");
+        let mut request = String::new();
+        let mut reader = BufReader::new(&stream);
+        while reader
+            .read_line(&mut request)
+            .expect("failed to read the request")
+            > 2
+        {}
+        let body = format!(r#"{{"Id":"0123abcd","State":{{"Running":true,"Pid":{pid}}}}}"#);
+        write!(
+            stream,
+            "HTTP/1.0 200 OK\r\nContent-Type: application/json\r\n\r\n{body}"
+        )
+        .expect("failed to answer");
+        request
+    });
+    let reaper = std::thread::spawn(move || reap(pid));
+
+    let output = Command::new(env!("CARGO_BIN_EXE_gracekill"))
+        .args(["-g", "5", "--container", "web", "--container-socket"])
+        .arg(&path)
+        .output()
+        .expect("failed to run gracekill");
+    reaper.join().unwrap();
+    std::fs::remove_file(&path).expect("failed to remove socket");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    let request = runtime.join().unwrap();
+    assert!(
+        request.starts_with("GET /containers/web/json HTTP/1.0\r\n"),
+        "{request:?} {stderr}"
+    );
+    assert_eq!(output.status.code(), Some(0), "{stderr}");
+    assert!(
+        stderr.contains(&format!(
+            "Container web has main PID {pid} and 2 process(es) in all"
+        )),
+        "{stderr}"
+    );
+    assert!(stderr.contains("2 exited gracefully"), "{stderr}");
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn cli_signals_children_before_their_parent() {
+    let parent = spawn("sleep 30 & echo ready; wait");
+    let child = gracekill::procfs::descendants(parent)[0];
+    let reaper = std::thread::spawn(move || reap(parent));
+
+    let output = Command::new(env!("CARGO_BIN_EXE_gracekill"))
+        .args(["-g", "5", &format!("{parent},{child}")])
+        .output()
+        .expect("failed to run gracekill");
+    reaper.join().unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert_eq!(output.status.code(), Some(0), "{stderr}");
+    let first = stderr
+        .lines()
+        .find(|line| line.contains(" to PID ") || line.contains("before its own"))
+        .unwrap_or_default();
+    assert!(
+        first.ends_with(&format!("Sent SIGTERM to PID {child}")),
+        "{stderr}"
+    );
+    assert!(stderr.contains("2 exited gracefully"), "{stderr}");
+}
+
+#[test]
+fn cli_skips_targets_that_dont_parse_with_skip_invalid() {
+    let run = |extra: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_gracekill"))
+            .args(["--simulate", "--simulate-exit-after", "100=0s", "100,1O1"])
+            .args(extra)
+            .output()
+            .expect("failed to run gracekill")
+    };
+
+    assert_eq!(run(&[]).status.code(), Some(1));
+    let output = run(&["--skip-invalid"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(0), "{stderr}");
+    assert!(
+        stderr.contains("Invalid PID at argument 4: '1O1', skipping it"),
+        "{stderr}"
+    );
+    assert!(stderr.contains("Skipped 1 invalid target(s)"), "{stderr}");
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn cli_stretches_the_grace_period_at_most_fourfold_with_load_aware() {
+    let output = Command::new(env!("CARGO_BIN_EXE_gracekill"))
+        .args(["--show-config", "--load-aware", "-g", "10", "1073741824"])
+        .output()
+        .expect("failed to run gracekill");
+
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let grace: u64 = stdout
+        .split(r#""grace_period_ms":{"value":"#)
+        .nth(1)
+        .and_then(|rest| rest.split(',').next())
+        .and_then(|value| value.parse().ok())
+        .expect("the grace period is shown");
+    assert!((10_000..=40_000).contains(&grace), "{grace}");
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn cli_refuses_to_freeze_a_target_sharing_its_cgroup() {
+    // Spawned from here, the target shares the cgroup the tests run in,
+    // which is either the root one or one gracekill would be frozen in too
+    let pid = spawn("trap '' TERM; echo ready; exec sleep 30");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_gracekill"))
+        .args(["--freeze", "-g", "0"])
+        .arg(pid.to_string())
+        .output()
+        .expect("failed to run gracekill");
+    let target = nix::unistd::Pid::from_raw(i32::try_from(pid).unwrap());
+    let untouched = nix::sys::signal::kill(target, None).is_ok();
+    nix::sys::signal::kill(target, nix::sys::signal::Signal::SIGKILL).unwrap();
+    reap(pid);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(untouched);
+}
+
+#[test]
+fn cli_hands_survivors_off_to_the_reaper() {
+    let socket =
+        std::env::temp_dir().join(format!("gracekill-handoff-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&socket);
+    let listener = std::os::unix::net::UnixListener::bind(&socket).unwrap();
+    let reaper = std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut received = String::new();
+        std::io::Read::read_to_string(&mut stream, &mut received).unwrap();
+        received
+    });
+    let ignoring = spawn("trap '' TERM; echo ready; exec sleep 30");
+    let output = Command::new(env!("CARGO_BIN_EXE_gracekill"))
+        .args(["-g", "1", "--handoff"])
+        .arg(&socket)
+        .arg(ignoring.to_string())
+        .output()
+        .expect("failed to run gracekill");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let _ = std::fs::remove_file(&socket);
+
+    assert_eq!(reaper.join().unwrap(), format!("{ignoring}\n"));
+    assert!(
+        stderr.contains("Handed 1 survivor(s) off to the reaper"),
+        "{stderr}"
+    );
+    let target = nix::unistd::Pid::from_raw(i32::try_from(ignoring).unwrap());
+    // Left to the reaper, so still running until now
+    nix::sys::signal::kill(target, nix::sys::signal::Signal::SIGKILL).unwrap();
+    reap(ignoring);
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn cli_keeps_killing_named_processes_with_watch() {
+    // A name of our own, so that nothing else is mistaken for the targets
+    let name = format!("gkwatch{}", std::process::id());
+    let link = std::env::temp_dir().join(&name);
+    let _ = std::fs::remove_file(&link);
+    std::os::unix::fs::symlink(
+        ["/bin/sleep", "/usr/bin/sleep"]
+            .into_iter()
+            .find(|path| std::path::Path::new(path).exists())
+            .unwrap(),
+        &link,
+    )
+    .unwrap();
+    let watch = Command::new(env!("CARGO_BIN_EXE_gracekill"))
+        .args(["--watch", "--name", &name, "--scan-interval", "50ms"])
+        .args(["-g", "1"])
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("failed to run gracekill");
+
+    for _ in 0..2 {
+        let pid = spawn(&format!("echo ready; exec {} 30", link.display()));
+        // Returns once the watch has killed it
+        reap(pid);
+    }
+    let watcher = nix::unistd::Pid::from_raw(i32::try_from(watch.id()).unwrap());
+    nix::sys::signal::kill(watcher, nix::sys::signal::Signal::SIGINT).unwrap();
+    let output = watch.wait_with_output().unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let _ = std::fs::remove_file(&link);
+
+    assert_eq!(output.status.code(), Some(0), "{stderr}");
+    assert!(stderr.contains("Watch ended after 2 kill(s)"), "{stderr}");
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn cli_kills_the_processes_holding_a_file() {
+    let file = std::env::temp_dir().join(format!("gracekill-holding-{}", std::process::id()));
+    std::fs::write(&file, "").unwrap();
+    // Held through two descriptors, but targeted once
+    let holder = spawn(&format!(
+        "exec 3<'{0}' 4<'{0}'; echo ready; exec sleep 30",
+        file.display()
+    ));
+    let reaper = std::thread::spawn(move || reap(holder));
+
+    let output = Command::new(env!("CARGO_BIN_EXE_gracekill"))
+        .args(["-g", "5", "--holding"])
+        .arg(&file)
+        .output()
+        .expect("failed to run gracekill");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    reaper.join().unwrap();
+    let _ = std::fs::remove_file(&file);
+
+    assert_eq!(output.status.code(), Some(0), "{stderr}");
+    assert!(
+        stderr.contains(&format!("Found 1 process(es) holding '{}'", file.display())),
+        "{stderr}"
+    );
+    assert!(stderr.contains("1 exited gracefully"), "{stderr}");
+}
+
+#[test]
+fn cli_emits_a_script_that_replays_the_kill() {
+    let pid = spawn("trap '' TERM; echo ready; while :; do sleep 0.05; done");
+    let script = std::env::temp_dir().join(format!("gracekill-script-{}.sh", std::process::id()));
+    let output = Command::new(env!("CARGO_BIN_EXE_gracekill"))
+        .args([
+            "-g",
+            "1",
+            "--escalate",
+            "TERM:1s,INT:300ms,KILL",
+            "--emit-script",
+        ])
+        .arg(&script)
+        .arg(pid.to_string())
+        .output()
+        .expect("failed to run gracekill");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(0), "{stderr}");
+    assert!(!stderr.contains("Sent SIGTERM"), "{stderr}");
+    let written = std::fs::read_to_string(&script).expect("the script is written");
+    for line in [
+        "#!/usr/bin/env bash",
+        &format!("targets='{pid}'"),
+        &format!("kill -TERM {pid} 2>/dev/null"),
+        "wait_for 1000 $targets",
+        "    kill -INT $remaining 2>/dev/null",
+        "    wait_for 300 $remaining",
+        &format!("remaining=$(alive {pid})"),
+        "[ -n \"$remaining\" ] && kill -KILL $remaining 2>/dev/null",
+    ] {
+        assert!(written.lines().any(|l| l == line), "{line}\n{written}");
+    }
+
+    let run = Command::new("bash")
+        .arg(&script)
+        .output()
+        .expect("failed to run the script");
+    reap(pid);
+    let _ = std::fs::remove_file(&script);
+    assert_eq!(
+        run.status.code(),
+        Some(0),
+        "{}",
+        String::from_utf8_lossy(&run.stderr)
+    );
+}
+
+#[test]
+fn cli_asks_before_signalling_more_than_the_confirm_count() {
+    let pids: Vec<u32> = (0..3).map(|_| spawn("echo ready; exec sleep 30")).collect();
+    let targets = pids
+        .iter()
+        .map(u32::to_string)
+        .collect::<Vec<_>>()
+        .join(",");
+    let run = |extra: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_gracekill"))
+            .args(["-g", "1", "--confirm-count", "2"])
+            .args(extra)
+            .arg(&targets)
+            .stdin(Stdio::null())
+            .output()
+            .expect("failed to run gracekill")
+    };
+
+    let refused = run(&[]);
+    let stderr = String::from_utf8_lossy(&refused.stderr);
+    assert_eq!(refused.status.code(), Some(1), "{stderr}");
+    assert!(
+        stderr.contains("About to signal 3 process(es), more than --confirm-count 2"),
+        "{stderr}"
+    );
+    assert!(stderr.contains("Pass --yes"), "{stderr}");
+    assert!(!stderr.contains("Sent SIGTERM"), "{stderr}");
+
+    let confirmed = run(&["--yes"]);
+    let stderr = String::from_utf8_lossy(&confirmed.stderr);
+    for &pid in &pids {
+        reap(pid);
+    }
+    assert_eq!(confirmed.status.code(), Some(0), "{stderr}");
+    for pid in &pids {
+        assert!(
+            stderr.contains(&format!("Sent SIGTERM to PID {pid}")),
+            "{stderr}"
+        );
+    }
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn cli_never_kills_the_targets_in_the_protect_file() {
+    let file = std::env::temp_dir().join(format!("gracekill-protect-{}", std::process::id()));
+    std::fs::write(&file, "# only gracefully\n\n  sl*p  \n").unwrap();
+    let protected = spawn("trap '' TERM; echo ready; exec sleep 30");
+    wait_for_exec(protected, "sleep");
+    let unprotected = spawn("trap '' TERM; echo ready; while :; do sleep 0.05; done");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_gracekill"))
+        .args(["-g", "1", "--protect-file"])
+        .arg(&file)
+        .arg(format!("{protected},{unprotected}"))
+        .output()
+        .expect("failed to run gracekill");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    reap(unprotected);
+    let still_running = std::fs::metadata(format!("/proc/{protected}")).is_ok();
+    nix::sys::signal::kill(
+        nix::unistd::Pid::from_raw(i32::try_from(protected).unwrap()),
+        nix::sys::signal::Signal::SIGKILL,
+    )
+    .unwrap();
+    reap(protected);
+    let _ = std::fs::remove_file(&file);
+
+    assert!(still_running, "{stderr}");
+    assert!(
+        stderr.contains(&format!(
+            "Policy forbids sending SIGKILL to protected PID {protected}"
+        )),
+        "{stderr}"
+    );
+    assert!(
+        stderr.contains(&format!("Sent SIGKILL to PID {unprotected}")),
+        "{stderr}"
+    );
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn cli_never_kills_protect_file_targets_grouped_by_policy() {
+    let file =
+        std::env::temp_dir().join(format!("gracekill-protect-policy-{}", std::process::id()));
+    std::fs::write(&file, "sleep\n").unwrap();
+    let protected = spawn("trap '' TERM; echo ready; exec sleep 30");
+    wait_for_exec(protected, "sleep");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_gracekill"))
+        .args(["--policy", "sl*:TERM:300ms", "--protect-file"])
+        .arg(&file)
+        .arg(protected.to_string())
+        .output()
+        .expect("failed to run gracekill");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let still_running = std::fs::metadata(format!("/proc/{protected}")).is_ok();
+    nix::sys::signal::kill(
+        nix::unistd::Pid::from_raw(i32::try_from(protected).unwrap()),
+        nix::sys::signal::Signal::SIGKILL,
+    )
+    .unwrap();
+    reap(protected);
+    let _ = std::fs::remove_file(&file);
+
+    assert!(still_running, "{stderr}");
+    assert!(
+        stderr.contains(&format!(
+            "Policy forbids sending SIGKILL to protected PID {protected}"
+        )),
+        "{stderr}"
+    );
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn cli_kills_the_orphans_a_target_leaves_behind() {
+    let parent = spawn("sleep 30 & echo ready; exec sleep 30");
+    let orphan: u32 = std::fs::read_to_string(format!("/proc/{parent}/task/{parent}/children"))
+        .ok()
+        .and_then(|children| children.split_whitespace().next()?.parse().ok())
+        .expect("the parent has a child");
+    let reaper = std::thread::spawn(move || reap(parent));
+    let output = Command::new(env!("CARGO_BIN_EXE_gracekill"))
+        .args(["-g", "5", "--kill-orphans-after-parent"])
+        .args([parent.to_string(), parent.to_string()])
+        .output()
+        .expect("failed to run gracekill");
+    reaper.join().unwrap();
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(0), "{stderr}");
+    assert!(
+        stderr.contains(&format!(
+            "Orphan cleanup: PID {parent} left 1 process(es) reparented to init: {orphan}"
+        )),
+        "{stderr}"
+    );
+    assert!(
+        stderr.contains(&format!("Sent SIGTERM to PID {orphan}")),
+        "{stderr}"
+    );
+    assert!(
+        stderr.contains("Orphan cleanup: 1 exited gracefully"),
+        "{stderr}"
+    );
+    assert!(stderr.contains("Summary: 2 exited gracefully"), "{stderr}");
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn cli_translates_pids_from_another_pid_namespace() {
+    // SAFETY: geteuid has no preconditions
+    if unsafe { libc::geteuid() } != 0 {
+        return;
+    }
+    // A namespace whose init, the shell, waits for sleep, PID 2 inside it
+    let mut child = Command::new("unshare")
+        .args(["--pid", "--fork", "sh", "-c", "sleep 30 & echo $!; wait"])
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("failed to run unshare");
+    let mut inner = String::new();
+    BufReader::new(child.stdout.take().expect("stdout is piped"))
+        .read_line(&mut inner)
+        .expect("failed to read from the namespace");
+    let child_of = |pid: u32| -> u32 {
+        std::fs::read_to_string(format!("/proc/{pid}/task/{pid}/children"))
+            .ok()
+            .and_then(|children| children.split_whitespace().next()?.parse().ok())
+            .expect("the child is running")
+    };
+    let init = child_of(child.id());
+    let sleep = child_of(init);
+    let output = Command::new(env!("CARGO_BIN_EXE_gracekill"))
+        .args([
+            "-g",
+            "5",
+            "--pid-namespace",
+            &init.to_string(),
+            inner.trim(),
+        ])
+        .output()
+        .expect("failed to run gracekill");
+    let _ = child.kill();
+    let _ = child.wait();
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(inner.trim(), "2");
+    assert_eq!(output.status.code(), Some(0), "{stderr}");
+    // Signalled by its PID out here
+    assert!(
+        stderr.contains(&format!("Sent SIGTERM to PID {sleep}")),
+        "{stderr}"
+    );
+    assert!(stderr.contains("1 exited gracefully"), "{stderr}");
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn cli_targets_the_holder_of_a_port_in_its_network_namespace() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    // The child holds the listening socket as its stdin
+    let mut child = Command::new("sh")
+        .args(["-c", "echo ready; exec sleep 30"])
+        .stdin(std::os::fd::OwnedFd::from(listener))
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("failed to spawn sh");
+    BufReader::new(child.stdout.take().unwrap())
+        .read_line(&mut String::new())
+        .unwrap();
+    let pid = child.id();
+    wait_for_exec(pid, "sleep");
+    let dry_run = |extra: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_gracekill"))
+            .args(["--dry-run", "--port", &port.to_string()])
+            .args(extra)
+            .output()
+            .expect("failed to run gracekill")
+    };
+    let plain = dry_run(&[]);
+    // Entering a namespace needs CAP_SYS_ADMIN, even to stay in the same one
+    let root = unsafe { libc::geteuid() } == 0;
+    let in_namespace = root.then(|| dry_run(&["--netns", &pid.to_string()]));
+    let _ = child.kill();
+    let _ = child.wait();
+
+    let would_send = format!("Would send SIGTERM to PID {pid} (sleep)");
+    let stderr = String::from_utf8_lossy(&plain.stderr);
+    assert_eq!(plain.status.code(), Some(0), "{stderr}");
+    assert!(
+        stderr.contains(&format!("Found 1 process(es) with a socket on port {port}")),
+        "{stderr}"
+    );
+    assert!(stderr.contains(&would_send), "{stderr}");
+    if let Some(output) = in_namespace {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert_eq!(output.status.code(), Some(0), "{stderr}");
+        assert!(
+            stderr.contains(&format!("on port {port} in network namespace '{pid}'")),
+            "{stderr}"
+        );
+        assert!(stderr.contains(&would_send), "{stderr}");
+    }
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn cli_raises_the_core_limit_before_aborting() {
+    // Catches SIGABRT as well, to outlive the kill for the limit to be read
+    let pid = spawn(&format!(
+        "ulimit -S -c 0; exec {} --as-victim --catch TERM --catch ABRT",
+        env!("CARGO_BIN_EXE_gracekill")
+    ));
+    let soft_limit = || {
+        std::fs::read_to_string(format!("/proc/{pid}/limits"))
+            .unwrap()
+            .lines()
+            .find_map(|line| {
+                line.strip_prefix("Max core file size")
+                    .and_then(|limits| limits.split_whitespace().next().map(String::from))
+            })
+            .unwrap()
+    };
+    assert_eq!(soft_limit(), "0");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_gracekill"))
+        .args(["-g", "1", "--abort-with-core", &pid.to_string()])
+        .output()
+        .expect("failed to run gracekill");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let raised = soft_limit();
+    nix::sys::signal::kill(
+        nix::unistd::Pid::from_raw(i32::try_from(pid).unwrap()),
+        nix::sys::signal::Signal::SIGKILL,
+    )
+    .unwrap();
+    reap(pid);
+
+    assert_ne!(raised, "0", "{stderr}");
+    assert!(
+        stderr.contains(&format!(
+            "Raised the core size limit of PID {pid} from 0 bytes"
+        )),
+        "{stderr}"
+    );
+    assert!(
+        stderr.contains(&format!("Sent SIGABRT to PID {pid}")),
+        "{stderr}"
+    );
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn cli_reports_targets_drifting_between_selection_and_signal() {
+    let file = std::env::temp_dir().join(format!("gracekill-drift-{}", std::process::id()));
+    std::fs::write(&file, "").unwrap();
+    let holder = |file: &std::path::Path| {
+        spawn(&format!(
+            "exec 3<'{}'; echo ready; exec sleep 30",
+            file.display()
+        ))
+    };
+    let vanishing = holder(&file);
+    let reaper = std::thread::spawn(move || reap(vanishing));
+
+    // --drain-idle holds off the signals while the targets change
+    let mut run = Command::new(env!("CARGO_BIN_EXE_gracekill"))
+        .args([
+            "-g",
+            "5",
+            "--ignore-missing",
+            "--rescan",
+            "--drain-idle",
+            "1s",
+        ])
+        .arg("--holding")
+        .arg(&file)
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("failed to run gracekill");
+    let mut stderr = BufReader::new(run.stderr.take().unwrap());
+    let mut lines = String::new();
+    while !lines.contains("holding") {
+        assert_ne!(stderr.read_line(&mut lines).unwrap(), 0, "{lines}");
+    }
+    let appearing = holder(&file);
+    let vanishing_pid = nix::unistd::Pid::from_raw(i32::try_from(vanishing).unwrap());
+    nix::sys::signal::kill(vanishing_pid, nix::sys::signal::Signal::SIGKILL).unwrap();
+    reaper.join().unwrap();
+    std::io::Read::read_to_string(&mut stderr, &mut lines).unwrap();
+    let status = run.wait().unwrap();
+    let appearing_pid = nix::unistd::Pid::from_raw(i32::try_from(appearing).unwrap());
+    nix::sys::signal::kill(appearing_pid, nix::sys::signal::Signal::SIGKILL).unwrap();
+    reap(appearing);
+    let _ = std::fs::remove_file(&file);
+
+    assert_eq!(status.code(), Some(0), "{lines}");
+    assert!(
+        lines.contains(&format!(
+            "Drift since selection: 1 vanished before signal ({vanishing}), 1 appeared after selection ({appearing})"
+        )),
+        "{lines}"
+    );
+}
+
+#[test]
+fn cli_execs_the_replacement_once_the_kill_is_clean() {
+    let run = |script: &str, args: &[&str]| {
+        let pid = spawn(script);
+        let reaper = std::thread::spawn(move || reap(pid));
+        let output = Command::new(env!("CARGO_BIN_EXE_gracekill"))
+            .args(args)
+            .args(["--then-exec", "echo replaced", &pid.to_string()])
+            .output()
+            .expect("failed to run gracekill");
+        reaper.join().unwrap();
+        output
+    };
+
+    let clean = run("echo ready; exec sleep 30", &["-g", "5"]);
+    let stdout = String::from_utf8_lossy(&clean.stdout);
+    assert_eq!(clean.status.code(), Some(0), "{stdout}");
+    assert!(stdout.ends_with("replaced\n"), "{stdout}");
+
+    // Killed, with exit code 3
+    let killed = run(
+        "trap '' TERM; echo ready; exec sleep 30",
+        &["-g", "1", "--exit-non-zero-if-sigkill-required"],
+    );
+    let stderr = String::from_utf8_lossy(&killed.stderr);
+    assert_eq!(killed.status.code(), Some(3), "{stderr}");
+    assert!(!String::from_utf8_lossy(&killed.stdout).contains("replaced"));
+    assert!(
+        stderr.contains("Not running --then-exec 'echo replaced': the kill ended with exit code 3"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn cli_reads_exit_codes_from_expect_exit_file() {
+    let dir = std::env::temp_dir();
+    let file = |name: &str| dir.join(format!("gracekill-exit-{name}-{}", std::process::id()));
+    let (clean, unclean, missing) = (file("clean"), file("unclean"), file("missing"));
+    for path in [&clean, &unclean, &missing] {
+        let _ = std::fs::remove_file(path);
+    }
+    let writes = |code: i32, path: &std::path::Path| {
+        spawn(&format!(
+            "trap 'echo {code} > {}; exit 0' TERM; echo ready; while :; do sleep 0.05; done",
+            path.display()
+        ))
+    };
+    let targets = [
+        writes(0, &clean),
+        writes(3, &unclean),
+        writes(0, std::path::Path::new("/dev/null")),
+    ];
+    let output = Command::new(env!("CARGO_BIN_EXE_gracekill"))
+        .args(["-g", "1"])
+        .arg(format!(
+            "--expect-exit-file={}={}",
+            targets[0],
+            clean.display()
+        ))
+        .arg(format!(
+            "--expect-exit-file={}={}",
+            targets[1],
+            unclean.display()
+        ))
+        .arg(format!(
+            "--expect-exit-file={}={}",
+            targets[2],
+            missing.display()
+        ))
+        .arg(targets.map(|pid| pid.to_string()).join(","))
+        .output()
+        .expect("failed to run gracekill");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    for path in [&clean, &unclean] {
+        let _ = std::fs::remove_file(path);
+    }
+
+    assert_eq!(output.status.code(), Some(0), "{stderr}");
+    assert!(
+        stderr.contains(&format!("PID {} reported exit code 0", targets[0])),
+        "{stderr}"
+    );
+    assert!(stderr.contains("a clean shutdown"), "{stderr}");
+    assert!(
+        stderr.contains(&format!("PID {} reported exit code 3", targets[1])),
+        "{stderr}"
+    );
+    assert!(stderr.contains("an unclean shutdown"), "{stderr}");
+    assert!(
+        stderr.contains(&format!("PID {} left no exit code", targets[2])),
+        "{stderr}"
+    );
+    // Exited on SIGTERM, then killed as zombies that still look alive
+    for pid in targets {
+        reap(pid);
+    }
+}
+
+#[test]
+fn cli_leaves_output_alone_with_reset_tty_off_a_terminal() {
+    let pid = spawn("echo ready; exec sleep 30");
+    let output = Command::new(env!("CARGO_BIN_EXE_gracekill"))
+        .args(["-g", "1", "--reset-tty"])
+        .arg(pid.to_string())
+        .output()
+        .expect("failed to run gracekill");
+    reap(pid);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert!(!output.stdout.contains(&0x1b));
+    assert!(!output.stderr.contains(&0x1b));
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn cli_takes_grace_periods_from_the_environment_with_grace_from_env() {
+    let own = spawn("trap '' TERM; echo ready; GK_GRACE=200ms exec sleep 30");
+    let global = spawn("trap '' TERM; echo ready; exec sleep 30");
+    let output = Command::new(env!("CARGO_BIN_EXE_gracekill"))
+        .args(["-g", "1", "--grace-from-env", "GK_GRACE"])
+        .arg(format!("{own},{global}"))
+        .output()
+        .expect("failed to run gracekill");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    for pid in [own, global] {
+        reap(pid);
+    }
+
+    assert_eq!(output.status.code(), Some(0), "{stderr}");
+    assert!(
+        stderr.contains(&format!(
+            "PID {own} sets GK_GRACE=200ms, a 200ms grace period"
+        )),
+        "{stderr}"
+    );
+    assert!(
+        stderr.contains("from GK_GRACE=200ms for 1 process(es) with 200ms grace period"),
+        "{stderr}"
+    );
+    assert!(
+        stderr.contains("from the command line for 1 process(es) with 1s grace period"),
+        "{stderr}"
+    );
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn cli_escalates_targets_with_their_own_grace_from_env() {
+    let pid = spawn("trap '' TERM USR1; echo ready; GK_GRACE=200ms exec sleep 30");
+    let output = Command::new(env!("CARGO_BIN_EXE_gracekill"))
+        .args(["--grace-from-env", "GK_GRACE"])
+        .args(["--escalate", "TERM:5s,USR1:200ms,KILL"])
+        .arg(pid.to_string())
+        .output()
+        .expect("failed to run gracekill");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    reap(pid);
+
+    assert!(
+        stderr.contains("from GK_GRACE=200ms for 1 process(es) with 200ms grace period"),
+        "{stderr}"
+    );
+    assert!(
+        stderr.contains(&format!("Sent SIGUSR1 to PID {pid}")),
+        "{stderr}"
+    );
+    assert!(
+        stderr.contains(&format!("Sent SIGKILL to PID {pid}")),
+        "{stderr}"
+    );
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn cli_applies_the_first_matching_policy_to_each_target() {
+    let dir = std::env::temp_dir().join(format!("gracekill-policy-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let sleep = ["/bin/sleep", "/usr/bin/sleep"]
+        .into_iter()
+        .find(|path| std::path::Path::new(path).exists())
+        .unwrap();
+    for name in ["gk-web-1", "gk-batch-1"] {
+        let _ = std::fs::remove_file(dir.join(name));
+        std::os::unix::fs::symlink(sleep, dir.join(name)).unwrap();
+    }
+    let web = spawn(&format!("echo ready; exec {}/gk-web-1 30", dir.display()));
+    let batch = spawn(&format!("echo ready; exec {}/gk-batch-1 30", dir.display()));
+    let other = spawn("echo ready; exec sleep 30");
+    wait_for_exec(web, "gk-web-1");
+    wait_for_exec(batch, "gk-batch-1");
+    let output = Command::new(env!("CARGO_BIN_EXE_gracekill"))
+        .args(["-g", "1", "--policy", "gk-web-*:INT:2s"])
+        .args(["--policy", "gk-batch-?:HUP:3s", "--policy", "gk-*:QUIT:4s"])
+        .arg(format!("{web},{batch},{other}"))
+        .output()
+        .expect("failed to run gracekill");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    for pid in [web, batch, other] {
+        reap(pid);
+    }
+    let _ = std::fs::remove_dir_all(&dir);
+
+    assert_eq!(output.status.code(), Some(0), "{stderr}");
+    assert!(
+        stderr.contains(&format!(
+            "PID {web} (gk-web-1) matches --policy 'gk-web-*': SIGINT with a 2s grace period"
+        )),
+        "{stderr}"
+    );
+    assert!(
+        stderr.contains(&format!(
+            "PID {batch} (gk-batch-1) matches --policy 'gk-batch-?': SIGHUP with a 3s grace period"
+        )),
+        "{stderr}"
+    );
+    assert!(
+        stderr.contains(&format!("Sent SIGINT to PID {web}")),
+        "{stderr}"
+    );
+    assert!(
+        stderr.contains(&format!("Sent SIGHUP to PID {batch}")),
+        "{stderr}"
+    );
+    assert!(
+        stderr.contains(&format!("Sent SIGTERM to PID {other}")),
+        "{stderr}"
+    );
+    assert!(
+        stderr.contains("from the command line for 1 process(es) with 1s grace period"),
+        "{stderr}"
+    );
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn cli_keeps_escalation_and_protections_for_policy_targets() {
+    let protected = spawn("trap '' TERM USR1; echo ready; exec sleep 30");
+    let escalated = spawn("trap '' TERM USR1; echo ready; exec sleep 30");
+    wait_for_exec(protected, "sleep");
+    wait_for_exec(escalated, "sleep");
+    let output = Command::new(env!("CARGO_BIN_EXE_gracekill"))
+        .args(["--policy", "sleep:TERM:300ms"])
+        .args(["--escalate", "TERM:300ms,USR1:300ms,KILL"])
+        .args(["--forbid-signal-to", &protected.to_string()])
+        .arg(format!("{protected},{escalated}"))
+        .output()
+        .expect("failed to run gracekill");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    reap(escalated);
+    let still_running = std::fs::metadata(format!("/proc/{protected}")).is_ok();
+    nix::sys::signal::kill(
+        nix::unistd::Pid::from_raw(i32::try_from(protected).unwrap()),
+        nix::sys::signal::Signal::SIGKILL,
+    )
+    .unwrap();
+    reap(protected);
+
+    assert!(still_running, "{stderr}");
+    assert!(
+        stderr.contains(&format!("Sent SIGUSR1 to PID {escalated}")),
+        "{stderr}"
+    );
+    assert!(
+        stderr.contains(&format!("Sent SIGKILL to PID {escalated}")),
+        "{stderr}"
+    );
+    assert!(
+        !stderr.contains(&format!("Sent SIGKILL to PID {protected}")),
+        "{stderr}"
+    );
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn cli_skips_targets_below_the_thresholds() {
+    let old = spawn("echo ready; exec sleep 30");
+    std::thread::sleep(Duration::from_millis(2500));
+    let young = spawn("echo ready; exec sleep 30");
+    let output = Command::new(env!("CARGO_BIN_EXE_gracekill"))
+        .args(["-g", "1", "--if-age-above", "1s", "--if-rss-above", "1K"])
+        .arg(format!("{old},{young}"))
+        .output()
+        .expect("failed to run gracekill");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    reap(old);
+    let _ = nix::sys::signal::kill(
+        nix::unistd::Pid::from_raw(i32::try_from(young).unwrap()),
+        nix::sys::signal::Signal::SIGKILL,
+    );
+    reap(young);
+
+    assert_eq!(output.status.code(), Some(0), "{stderr}");
+    assert!(
+        stderr.contains(&format!("Skipping PID {young}: age ")),
+        "{stderr}"
+    );
+    assert!(stderr.contains(" is not above 1s"), "{stderr}");
+    assert!(
+        stderr.contains("1 of 2 target(s) over the threshold"),
+        "{stderr}"
+    );
+    assert!(
+        stderr.contains(&format!("Sent SIGTERM to PID {old}")),
+        "{stderr}"
+    );
+    assert!(!stderr.contains(&format!("to PID {young}")), "{stderr}");
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn cli_kills_only_targets_new_since_the_saved_set() {
+    let path = std::env::temp_dir().join(format!("gracekill-set-{}", std::process::id()));
+    let old = spawn("echo ready; exec sleep 30");
+    let saved = Command::new(env!("CARGO_BIN_EXE_gracekill"))
+        .arg("--save-set")
+        .arg(&path)
+        .arg(old.to_string())
+        .output()
+        .expect("failed to run gracekill");
+    let saved_stderr = String::from_utf8_lossy(&saved.stderr);
+    let new = spawn("echo ready; exec sleep 30");
+    let output = Command::new(env!("CARGO_BIN_EXE_gracekill"))
+        .args(["-g", "1", "--kill-new-since"])
+        .arg(&path)
+        .arg(format!("{old},{new}"))
+        .output()
+        .expect("failed to run gracekill");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let contents = std::fs::read_to_string(&path).unwrap_or_default();
+    let _ = std::fs::remove_file(&path);
+    reap(new);
+    let _ = nix::sys::signal::kill(
+        nix::unistd::Pid::from_raw(i32::try_from(old).unwrap()),
+        nix::sys::signal::Signal::SIGKILL,
+    );
+    reap(old);
+
+    assert_eq!(saved.status.code(), Some(0), "{saved_stderr}");
+    assert!(!saved_stderr.contains("Sent"), "{saved_stderr}");
+    assert!(
+        contents.starts_with("# gracekill process set\nboot_id "),
+        "{contents}"
+    );
+    assert!(contents.contains(&format!("\n{old} ")), "{contents}");
+    assert_eq!(output.status.code(), Some(0), "{stderr}");
+    assert!(
+        stderr.contains("1 of 2 target(s) are new since process set"),
+        "{stderr}"
+    );
+    assert!(
+        stderr.contains(&format!("Sent SIGTERM to PID {new}")),
+        "{stderr}"
+    );
+    assert!(!stderr.contains(&format!("to PID {old}")), "{stderr}");
+}
+
+#[test]
+fn cli_runs_each_command_written_to_the_fifo() {
+    let path = std::env::temp_dir().join(format!("gracekill-fifo-{}", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    assert!(
+        Command::new("mkfifo")
+            .arg(&path)
+            .status()
+            .unwrap()
+            .success()
+    );
+    let first = spawn("echo ready; exec sleep 30");
+    let second = spawn("echo ready; exec sleep 30");
+    let gracekill = Command::new(env!("CARGO_BIN_EXE_gracekill"))
+        .arg("--fifo")
+        .arg(&path)
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("failed to run gracekill");
+    // Each write opens the FIFO anew, so the second comes after a writer
+    // has gone
+    std::fs::write(&path, format!("pids={first} grace=2s\nnot a command\n")).unwrap();
+    std::fs::write(&path, format!("pids={second} signal=INT")).unwrap();
+    reap(first);
+    reap(second);
+    let _ = nix::sys::signal::kill(
+        nix::unistd::Pid::from_raw(i32::try_from(gracekill.id()).unwrap()),
+        nix::sys::signal::Signal::SIGTERM,
+    );
+    let output = gracekill.wait_with_output().unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let _ = std::fs::remove_file(&path);
+
+    assert_eq!(output.status.code(), Some(0), "{stderr}");
+    assert!(
+        stderr.contains(&format!("Sent SIGTERM to PID {first}")),
+        "{stderr}"
+    );
+    assert!(
+        stderr.contains(&format!("Sent SIGINT to PID {second}")),
+        "{stderr}"
+    );
+    assert!(stderr.contains("Ignoring command 2 on"), "{stderr}");
+    assert!(stderr.contains("after 2 operation(s)"), "{stderr}");
+}
+
+#[test]
+fn cli_notifies_systemd_of_progress_with_sd_notify() {
+    let path = std::env::temp_dir().join(format!("gracekill-notify-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let systemd = std::os::unix::net::UnixDatagram::bind(&path).unwrap();
+    systemd
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    let pid = spawn("trap '' TERM; echo ready; exec sleep 30");
+    let output = Command::new(env!("CARGO_BIN_EXE_gracekill"))
+        .args(["-g", "1", "--sd-notify", &pid.to_string()])
+        .env("NOTIFY_SOCKET", &path)
+        .output()
+        .expect("failed to run gracekill");
+    reap(pid);
+    let mut messages = Vec::new();
+    let mut buffer = [0; 4096];
+    while let Ok(length) = systemd.recv(&mut buffer) {
+        let message = String::from_utf8_lossy(&buffer[..length]).into_owned();
+        let last = message.starts_with("STOPPING=1");
+        messages.push(message);
+        if last {
+            break;
+        }
+    }
+    let _ = std::fs::remove_file(&path);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert!(
+        messages[0]
+            .starts_with("EXTEND_TIMEOUT_USEC=15000000\nSTATUS=Waiting for 1 process(es) to exit"),
+        "{messages:?}"
+    );
+    assert_eq!(
+        messages.last().map(String::as_str),
+        Some("STOPPING=1\nSTATUS=Stopped: 0 exited gracefully, 1 killed, 0 failed")
+    );
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn stat_shows_a_core_dump_in_progress() {
+    // Fields 3 to 22, with PF_DUMPCORE among the flags in field 9
+    let line = |state: char, flags: u64| {
+        format!("42 (big (worker)) {state} 1 42 42 0 -1 {flags} 0 0 0 0 5 6 0 0 20 0 8 0 100")
+    };
+    let dumping = gracekill::procfs::Stat::parse(&line('D', 0x4040_0204)).unwrap();
+    assert_eq!(dumping.comm, "big (worker)");
+    assert!(dumping.dumping_core());
+    assert!(
+        !gracekill::procfs::Stat::parse(&line('S', 0x4040_0000))
+            .unwrap()
+            .dumping_core()
+    );
+    // A zombie has finished with its core dump
+    assert!(
+        !gracekill::procfs::Stat::parse(&line('Z', 0x4040_0204))
+            .unwrap()
+            .dumping_core()
+    );
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn survivors_are_classified_from_their_state_and_signal_masks() {
+    use gracekill::procfs::{SignalMasks, Stat, classify_survivor};
+
+    let stat = |state: char| {
+        Stat::parse(&format!(
+            "42 (worker) {state} 1 42 42 0 -1 4194560 0 0 0 0 5 6 0 0 20 0 1 0 100"
+        ))
+        .unwrap()
+    };
+    // SIGTERM is bit 15, 0x4000; SIGINT bit 2, 0x2
+    let masks = |blocked: &str, ignored: &str| {
+        SignalMasks::parse(&format!(
+            "Name:\tworker\nSigPnd:\t0000000000000000\nSigBlk:\t{blocked}\nSigIgn:\t{ignored}\nSigCgt:\t0000000000000000\n"
+        ))
+        .unwrap()
+    };
+    let none = masks("0000000000000000", "0000000000000000");
+    let term_ignored = masks("0000000000000000", "0000000000004000");
+    let term_blocked = masks("0000000000004000", "0000000000000000");
+
+    let cases = [
+        ('S', term_ignored, Survival::IgnoredSignal),
+        ('R', term_blocked, Survival::BlockedSignal),
+        ('D', none, Survival::Uninterruptible),
+        // Asleep in the kernel or stopped, the masks don't matter yet
+        ('D', term_ignored, Survival::Uninterruptible),
+        ('T', none, Survival::Stopped),
+        ('t', term_blocked, Survival::Stopped),
+        ('R', none, Survival::SlowShutdown),
+        (
+            'S',
+            masks("0000000000000000", "0000000000000002"),
+            Survival::SlowShutdown,
+        ),
+    ];
+    for (state, masks, expected) in cases {
+        assert_eq!(
+            classify_survivor(&stat(state), &masks, Signal::Term),
+            expected,
+            "state {state}, {masks:?}"
+        );
+    }
+    assert_eq!(
+        classify_survivor(
+            &stat('S'),
+            &masks("0000000000000000", "0000000000000002"),
+            Signal::Int
+        ),
+        Survival::IgnoredSignal
+    );
+}
+
+#[test]
+fn cli_passes_its_selftest() {
+    let output = Command::new(env!("CARGO_BIN_EXE_gracekill"))
+        .arg("--selftest")
+        .output()
+        .expect("failed to run gracekill");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert_eq!(output.status.code(), Some(0), "{stdout}{stderr}");
+    assert!(
+        stdout.lines().all(|line| line.starts_with("ok ")),
+        "{stdout}"
+    );
+    assert!(stdout.contains("Escalated to SIGKILL"), "{stdout}");
+    assert!(
+        stdout.contains("Confirmed the child killed by signal SIGKILL"),
+        "{stdout}"
+    );
+    assert!(stderr.contains("Self-test passed"), "{stderr}");
+}
+
+#[test]
+fn cli_takes_the_target_and_its_settings_from_a_discover_sidecar() {
+    let dir = std::env::temp_dir().join(format!("gracekill-discover-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let sidecar = dir.join("shutdown.conf");
+    let pid = spawn("trap '' INT; echo ready; exec sleep 30");
+    std::fs::write(dir.join("app.pid"), format!("{pid}\n")).unwrap();
+    std::fs::write(
+        &sidecar,
+        "# written by the app\npid_file = app.pid\nsignal = INT\ngrace = 300ms\n",
+    )
+    .unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_gracekill"))
+        .args(["-g", "20", "--discover"])
+        .arg(&sidecar)
+        .output()
+        .expect("failed to run gracekill");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    reap(pid);
+
+    std::fs::write(&sidecar, "pid_file = app.pid\ngrace = soon\n").unwrap();
+    let invalid = Command::new(env!("CARGO_BIN_EXE_gracekill"))
+        .arg("--discover")
+        .arg(&sidecar)
+        .output()
+        .expect("failed to run gracekill");
+    let _ = std::fs::remove_dir_all(&dir);
+
+    assert_eq!(output.status.code(), Some(0), "{stderr}");
+    assert!(
+        stderr.contains(&format!(
+            "declares PID {pid} with signal SIGINT, 300ms grace period"
+        )),
+        "{stderr}"
+    );
+    assert!(
+        stderr.contains(&format!("Sent SIGINT to PID {pid}")),
+        "{stderr}"
+    );
+    assert!(stderr.contains("sending SIGKILL"), "{stderr}");
+    assert_eq!(invalid.status.code(), Some(1));
+    assert!(
+        String::from_utf8_lossy(&invalid.stderr).contains(": line 2: "),
+        "{}",
+        String::from_utf8_lossy(&invalid.stderr)
+    );
+}
+
+#[test]
+fn cli_prints_a_grace_table_before_signalling() {
+    let pid = spawn("echo ready; while :; do sleep 0.05; done");
+    let table = Command::new(env!("CARGO_BIN_EXE_gracekill"))
+        .args(["--grace-table", "--escalate", "INT:3s,USR1:4s,KILL"])
+        .arg(pid.to_string())
+        .output()
+        .expect("failed to run gracekill");
+    let proceeded = Command::new(env!("CARGO_BIN_EXE_gracekill"))
+        .args(["--grace-table", "--proceed", "-g", "1"])
+        .arg(pid.to_string())
+        .output()
+        .expect("failed to run gracekill");
+    reap(pid);
+
+    let stdout = String::from_utf8_lossy(&table.stdout);
+    let stderr = String::from_utf8_lossy(&table.stderr);
+    assert_eq!(table.status.code(), Some(0), "{stderr}");
+    assert!(!stderr.contains("Sent SIGINT"), "{stderr}");
+    let mut lines = stdout.lines();
+    let header: Vec<&str> = lines
+        .next()
+        .unwrap_or_default()
+        .split("  ")
+        .map(str::trim)
+        .filter(|cell| !cell.is_empty())
+        .collect();
+    assert_eq!(
+        header,
+        ["PID", "COMMAND", "SIGNAL", "GRACE", "KILL AT"],
+        "{stdout}"
+    );
+    let row = lines.next().unwrap_or_default();
+    assert!(row.starts_with(&format!("{pid} ")), "{stdout}");
+    assert!(row.contains(" SIGINT "), "{stdout}");
+    assert!(row.contains(" 7s "), "{stdout}");
+    assert!(row.ends_with("(+7s)"), "{stdout}");
+
+    let stderr = String::from_utf8_lossy(&proceeded.stderr);
+    assert!(
+        String::from_utf8_lossy(&proceeded.stdout)
+            .lines()
+            .any(|line| line.starts_with(&format!("{pid} "))),
+        "{stderr}"
+    );
+    assert!(
+        stderr.contains(&format!("Sent SIGTERM to PID {pid}")),
+        "{stderr}"
+    );
+}
+
+const LOG_STRESS_CHILD: &str = "GRACEKILL_TEST_LOG_STRESS_CHILD";
+const LOG_STRESS_THREADS: usize = 8;
+const LOG_STRESS_LINES: usize = 500;
+const LOG_STRESS_SHELL_LINES: usize = 500;
+
+/// Logs from many threads at once while a child process writes to the same
+/// stderr, when run as the child of the test below; otherwise does nothing.
+#[test]
+fn log_stress_child() {
+    if std::env::var_os(LOG_STRESS_CHILD).is_none() {
+        return;
+    }
+    let shell = Command::new("sh")
+        .arg("-c")
+        .arg(format!(
+            "i=0; while [ $i -lt {LOG_STRESS_SHELL_LINES} ]; do \
+             echo \"shell line $i\" >&2; i=$((i + 1)); done"
+        ))
+        .spawn()
+        .expect("failed to spawn sh");
+    let threads: Vec<_> = (0..LOG_STRESS_THREADS)
+        .map(|thread| {
+            std::thread::spawn(move || {
+                for line in 0..LOG_STRESS_LINES {
+                    let message = format!("thread {thread} line {line} {}", "x".repeat(200));
+                    if line % 2 == 0 {
+                        gracekill::log::log(&message);
+                    } else {
+                        gracekill::log::warn(&message);
+                    }
+                }
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().expect("logging thread panicked");
+    }
+    let mut shell = shell;
+    shell.wait().expect("failed to wait for sh");
+}
+
+#[test]
+fn lines_logged_from_many_threads_come_out_whole() {
+    if std::env::var_os(LOG_STRESS_CHILD).is_some() {
+        return;
+    }
+    let child = Command::new(std::env::current_exe().expect("no test binary"))
+        .args([
+            "--exact",
+            "log_stress_child",
+            "--nocapture",
+            "--test-threads=1",
+        ])
+        .env(LOG_STRESS_CHILD, "1")
+        .env_remove("GRACEKILL_LOG")
+        .output()
+        .expect("failed to rerun the test binary");
+    let stderr = String::from_utf8_lossy(&child.stderr);
+    assert!(child.status.success(), "{stderr}");
+
+    let padding = "x".repeat(200);
+    let mut seen = vec![[false; LOG_STRESS_LINES]; LOG_STRESS_THREADS];
+    let mut shell_lines = 0;
+    for line in stderr.lines() {
+        if line.starts_with("shell line ") {
+            shell_lines += 1;
+            continue;
+        }
+        let message = line
+            .strip_prefix("[gracekill] ")
+            .unwrap_or_else(|| panic!("line not prefixed: {line:?}"));
+        let fields: Vec<&str> = message.split(' ').collect();
+        let ["thread", thread, "line", number, rest] = fields[..] else {
+            panic!("line not whole: {line:?}");
+        };
+        assert_eq!(rest, padding, "line not whole: {line:?}");
+        let thread: usize = thread.parse().expect("bad thread number");
+        let number: usize = number.parse().expect("bad line number");
+        assert!(!seen[thread][number], "line logged twice: {line:?}");
+        seen[thread][number] = true;
+    }
+    assert!(
+        seen.iter().flatten().all(|&seen| seen),
+        "lines went missing"
+    );
+    assert_eq!(shell_lines, LOG_STRESS_SHELL_LINES, "{stderr}");
+}
+
+/// Real processes whose PID `pid`, once killed, is handed to a new process,
+/// as the kernel would after wrapping around. Needs root, to write
+/// `ns_last_pid`.
+#[cfg(target_os = "linux")]
+struct Reused {
+    pid: u32,
+    successor: Option<u32>,
+}
+
+#[cfg(target_os = "linux")]
+impl ProcessController for Reused {
+    fn send_signal(&mut self, pid: u32, signal: Signal) -> Result<(), SignalError> {
+        System.send_signal(pid, signal)?;
+        if pid == self.pid && signal == Signal::Kill {
+            reap(pid);
+            // Anything else forking in the meantime takes the PID first
+            for _ in 0..10 {
+                std::fs::write("/proc/sys/kernel/ns_last_pid", (pid - 1).to_string())
+                    .expect("failed to write ns_last_pid");
+                let successor = spawn("echo ready; exec sleep 30");
+                if successor == pid {
+                    self.successor = Some(successor);
+                    break;
+                }
+                let _ = System.send_signal(successor, Signal::Kill);
+                reap(successor);
+            }
+        }
+        Ok(())
+    }
+
+    fn is_running(&mut self, pid: u32) -> bool {
+        System.is_running(pid)
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn a_pid_reused_after_the_kill_is_not_a_survivor() {
+    if unsafe { libc::geteuid() } != 0 {
+        return;
+    }
+    let pid = spawn("trap '' TERM; echo ready; exec sleep 30");
+    let mut controller = Reused {
+        pid,
+        successor: None,
+    };
+
+    let report = GraceKill::with_controller(&mut controller)
+        .grace(GRACE)
+        .poll_interval(POLL_INTERVAL)
+        .run(&[pid]);
+
+    let successor = controller.successor.expect("the PID was never reused");
+    let _ = System.send_signal(successor, Signal::Kill);
+    reap(successor);
+    assert_eq!(report.outcome(pid), Some(&Outcome::Killed));
+    assert!(report.lingering.is_empty(), "{:?}", report.lingering);
+}